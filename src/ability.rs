@@ -0,0 +1,457 @@
+//! Active abilities, defined in `assets/data/abilities.ron`.
+//!
+//! An ability is built out of data rather than code: what it can be aimed
+//! at and how far, the shape of the area it covers around that point, whom
+//! in the area it affects, and a list of [effects](AbilityEffect) applied
+//! in order. The effects are the primitives the executor knows how to
+//! apply: damage, healing, pushing units away, bringing on new units,
+//! status effects, and changing the terrain. Each class lists the abilities
+//! it can use, and using one ends the unit's turn and puts the ability on
+//! cooldown.
+//!
+//! Whether an ability can be aimed at a tile is decided here, so the action
+//! menu never offers what the executor would turn down.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::class::UnitClass;
+use crate::data::{load_data_file, Layered};
+use crate::grid::{GridMap, GridPosition};
+use crate::status::StatusEffect;
+use crate::unit::{Faction, TurnStatus};
+
+/// Key of an ability in the [`AbilityCatalog`], e.g. `"fireball"`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+#[serde(transparent)]
+pub struct AbilityId(pub String);
+
+/// What an ability is aimed at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Targeting {
+    /// The unit using it, wherever it stands.
+    Caster,
+    /// A unit in range that the ability [affects](Affects).
+    #[default]
+    Unit,
+    /// Any tile in range.
+    Tile,
+}
+
+/// Whom an ability's unit effects land on, seen from the unit using it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Affects {
+    #[default]
+    Enemies,
+    /// The unit's own side, itself included.
+    Allies,
+    Everyone,
+}
+
+impl Affects {
+    pub fn includes(self, user: Faction, other: Faction) -> bool {
+        match self {
+            Affects::Enemies => user != other,
+            Affects::Allies => user == other,
+            Affects::Everyone => true,
+        }
+    }
+}
+
+/// The tiles an ability covers around the tile it is aimed at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Shape {
+    /// Just the tile.
+    #[default]
+    Single,
+    /// Every tile within this many steps.
+    Diamond(u32),
+    /// Every tile within this many tiles in both directions, corners
+    /// included.
+    Square(u32),
+    /// The tile and this many tiles out from it along its row and column.
+    Cross(u32),
+    /// This many tiles in a straight line out from the unit, toward the
+    /// tile. The tile has to be in line with the unit.
+    Line(u32),
+}
+
+/// One thing an ability does, applied to every tile or affected unit in its
+/// area.
+#[derive(Clone, Debug, Deserialize)]
+pub enum AbilityEffect {
+    /// Deals this much damage, less the unit's defense, and never less
+    /// than 1.
+    Damage(u32),
+    /// Restores up to this much HP.
+    Heal(u32),
+    /// Pushes units up to this many tiles straight away from the unit
+    /// using the ability, stopping short of anything in the way.
+    Push(u32),
+    /// Brings on a new unit of the user's side, on every free tile it can
+    /// stand on. New units can't act until their side's next turn.
+    Spawn(UnitClass),
+    ApplyStatus(StatusEffect),
+    /// Turns the ground into the named terrain, built in or from
+    /// `tiles.ron`. Doors, bridges, and levers are left alone, as is the
+    /// ground under a unit when the new terrain can't be stood on.
+    ModifyTerrain(String),
+}
+
+fn default_range() -> u32 {
+    1
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AbilityDef {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub targeting: Targeting,
+    /// How far from the unit the ability can be aimed. Unused when it
+    /// targets the caster.
+    #[serde(default = "default_range")]
+    pub range: u32,
+    #[serde(default)]
+    pub shape: Shape,
+    #[serde(default)]
+    pub affects: Affects,
+    pub effects: Vec<AbilityEffect>,
+    /// How many of the unit's turns go by before it can use the ability
+    /// again; 0 means every turn.
+    #[serde(default)]
+    pub cooldown: u32,
+}
+
+/// Every ability definition, loaded from `assets/data/abilities.ron`.
+#[derive(Resource, Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AbilityCatalog {
+    pub abilities: HashMap<AbilityId, AbilityDef>,
+    /// The abilities each class can use, in the order they're offered.
+    pub classes: HashMap<UnitClass, Vec<AbilityId>>,
+}
+
+impl AbilityCatalog {
+    pub fn get(&self, id: &AbilityId) -> Option<&AbilityDef> {
+        self.abilities.get(id)
+    }
+
+    /// The abilities `class` can use, skipping ids missing from the catalog.
+    pub fn of_class(
+        &self,
+        class: UnitClass,
+    ) -> impl Iterator<Item = (usize, &AbilityId, &AbilityDef)> {
+        self.classes
+            .get(&class)
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(slot, id)| Some((slot, id, self.get(id)?)))
+    }
+
+    /// The ability in `slot` of `class`'s list.
+    pub fn slot(&self, class: UnitClass, slot: usize) -> Option<(&AbilityId, &AbilityDef)> {
+        let id = self.classes.get(&class)?.get(slot)?;
+        Some((id, self.get(id)?))
+    }
+}
+
+impl Layered for AbilityCatalog {
+    fn ids(&self) -> Vec<String> {
+        let abilities = self.abilities.keys().map(|id| id.0.clone());
+        let classes = self
+            .classes
+            .keys()
+            .map(|class| format!("{class:?} abilities"));
+        abilities.chain(classes).collect()
+    }
+
+    fn overlay(&mut self, layer: Self) {
+        self.abilities.extend(layer.abilities);
+        self.classes.extend(layer.classes);
+    }
+}
+
+pub fn load_ability_catalog() -> AbilityCatalog {
+    load_data_file("abilities.ron")
+}
+
+/// Turns left before each ability a unit used can be used again, counting
+/// the one it is taking. An ability is ready once its count runs out.
+#[derive(Component, Clone, Debug, Default, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct Cooldowns(pub HashMap<AbilityId, u32>);
+
+impl Cooldowns {
+    pub fn left(&self, id: &AbilityId) -> u32 {
+        self.0.get(id).copied().unwrap_or(0)
+    }
+
+    /// Counts down every cooldown at the start of the unit's turn.
+    pub fn tick(&mut self) {
+        self.0.retain(|_, turns| {
+            *turns = turns.saturating_sub(1);
+            *turns > 0
+        });
+    }
+}
+
+/// The step from `from` toward `to` when they're in the same row or
+/// column.
+fn step_toward(from: GridPosition, to: GridPosition) -> Option<(i32, i32)> {
+    (from != to && (from.x == to.x || from.y == to.y))
+        .then(|| ((to.x - from.x).signum(), (to.y - from.y).signum()))
+}
+
+/// The way a unit at `pos` is pushed by an ability used from `from`: along
+/// whichever of the row or column it is further out on.
+pub fn push_direction(from: GridPosition, pos: GridPosition) -> Option<(i32, i32)> {
+    let (dx, dy) = (pos.x - from.x, pos.y - from.y);
+    match (dx, dy) {
+        (0, 0) => None,
+        _ if dx.abs() >= dy.abs() => Some((dx.signum(), 0)),
+        _ => Some((0, dy.signum())),
+    }
+}
+
+/// Every tile on the map that an ability used from `from` and aimed at
+/// `at` covers, in a fixed order.
+pub fn area(
+    shape: Shape,
+    map: &GridMap,
+    from: GridPosition,
+    at: GridPosition,
+) -> Vec<GridPosition> {
+    let around = |reach: u32, covers: &dyn Fn(i32, i32) -> bool| {
+        let reach = reach as i32;
+        let mut tiles = Vec::new();
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                if covers(dx, dy) {
+                    tiles.push(GridPosition::new(at.x + dx, at.y + dy));
+                }
+            }
+        }
+        tiles
+    };
+    let tiles = match shape {
+        Shape::Single => vec![at],
+        Shape::Diamond(reach) => around(reach, &|dx, dy| dx.abs() + dy.abs() <= reach as i32),
+        Shape::Square(reach) => around(reach, &|_, _| true),
+        Shape::Cross(reach) => around(reach, &|dx, dy| dx == 0 || dy == 0),
+        Shape::Line(length) => match step_toward(from, at) {
+            Some((dx, dy)) => (1..=length as i32)
+                .map(|step| GridPosition::new(from.x + dx * step, from.y + dy * step))
+                .collect(),
+            None => Vec::new(),
+        },
+    };
+    tiles
+        .into_iter()
+        .filter(|pos| map.in_bounds(*pos))
+        .collect()
+}
+
+/// Why a unit can't use an ability, or not where it was aimed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AbilityError {
+    OffMap,
+    /// The ability only affects the unit using it.
+    NotOnSelf,
+    OutOfRange,
+    NoUnit,
+    /// The unit aimed at is on a side the ability doesn't affect.
+    Unaffected,
+    /// A line ability aimed off the unit's row and column.
+    NotInLine,
+    AlreadyActed,
+    OnCooldown,
+}
+
+impl AbilityError {
+    pub fn reason(self) -> &'static str {
+        match self {
+            AbilityError::OffMap => "target is off the map",
+            AbilityError::NotOnSelf => "ability can only be used on the unit",
+            AbilityError::OutOfRange => "target is out of range",
+            AbilityError::NoUnit => "no unit there",
+            AbilityError::Unaffected => "ability doesn't affect that unit",
+            AbilityError::NotInLine => "target is not in line with the unit",
+            AbilityError::AlreadyActed => "unit has already acted",
+            AbilityError::OnCooldown => "ability is on cooldown",
+        }
+    }
+}
+
+impl fmt::Display for AbilityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.reason())
+    }
+}
+
+/// Why an ability can't be used where it was aimed.
+pub fn check_target(
+    ability: &AbilityDef,
+    map: &GridMap,
+    from: GridPosition,
+    faction: Faction,
+    at: GridPosition,
+    target: Option<Faction>,
+) -> Result<(), AbilityError> {
+    if !map.in_bounds(at) {
+        return Err(AbilityError::OffMap);
+    }
+    match ability.targeting {
+        Targeting::Caster if at != from => return Err(AbilityError::NotOnSelf),
+        Targeting::Caster => {}
+        Targeting::Unit | Targeting::Tile if from.distance(at) > ability.range => {
+            return Err(AbilityError::OutOfRange);
+        }
+        Targeting::Unit => {
+            let target = target.ok_or(AbilityError::NoUnit)?;
+            if !ability.affects.includes(faction, target) {
+                return Err(AbilityError::Unaffected);
+            }
+        }
+        Targeting::Tile => {}
+    }
+    if matches!(ability.shape, Shape::Line(_)) && step_toward(from, at).is_none() {
+        return Err(AbilityError::NotInLine);
+    }
+    Ok(())
+}
+
+/// Why a unit can't use `id` right now, wherever it is aimed.
+pub fn check_ready(
+    id: &AbilityId,
+    status: &TurnStatus,
+    cooldowns: &Cooldowns,
+) -> Result<(), AbilityError> {
+    if status.has_acted {
+        return Err(AbilityError::AlreadyActed);
+    }
+    if cooldowns.left(id) > 0 {
+        return Err(AbilityError::OnCooldown);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ability(fields: &str) -> AbilityDef {
+        ron::from_str(&format!(
+            "(name: \"\", description: \"\", effects: [], {fields})"
+        ))
+        .unwrap()
+    }
+
+    fn tiles(coords: &[(i32, i32)]) -> Vec<GridPosition> {
+        coords
+            .iter()
+            .map(|&(x, y)| GridPosition::new(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn each_shape_covers_its_tiles_and_stops_at_the_map_edge() {
+        let map = GridMap::new(5, 5);
+        let cases = [
+            (Shape::Single, (0, 0), (2, 2), tiles(&[(2, 2)])),
+            (
+                Shape::Diamond(1),
+                (0, 0),
+                (2, 2),
+                tiles(&[(2, 1), (1, 2), (2, 2), (3, 2), (2, 3)]),
+            ),
+            (
+                Shape::Square(1),
+                (2, 2),
+                (0, 0),
+                tiles(&[(0, 0), (1, 0), (0, 1), (1, 1)]),
+            ),
+            (
+                Shape::Cross(1),
+                (2, 2),
+                (4, 4),
+                tiles(&[(4, 3), (3, 4), (4, 4)]),
+            ),
+            (
+                Shape::Line(3),
+                (1, 2),
+                (2, 2),
+                tiles(&[(2, 2), (3, 2), (4, 2)]),
+            ),
+            (Shape::Line(3), (3, 2), (4, 2), tiles(&[(4, 2)])),
+            (Shape::Line(3), (2, 2), (2, 0), tiles(&[(2, 1), (2, 0)])),
+            (Shape::Line(3), (2, 2), (3, 3), Vec::new()),
+        ];
+        for (shape, (fx, fy), (ax, ay), expected) in cases {
+            let from = GridPosition::new(fx, fy);
+            let at = GridPosition::new(ax, ay);
+            assert_eq!(area(shape, &map, from, at), expected, "{shape:?} at {at:?}");
+        }
+    }
+
+    #[test]
+    fn pushes_go_along_the_row_or_column_the_unit_is_further_out_on() {
+        let from = GridPosition::new(2, 2);
+        let cases = [
+            ((4, 3), Some((1, 0))),
+            ((1, 0), Some((0, -1))),
+            ((3, 3), Some((1, 0))),
+            ((2, 2), None),
+        ];
+        for ((x, y), expected) in cases {
+            let pos = GridPosition::new(x, y);
+            assert_eq!(push_direction(from, pos), expected, "{pos:?}");
+        }
+    }
+
+    #[test]
+    fn a_line_has_to_be_aimed_along_a_row_or_column() {
+        let map = GridMap::new(5, 5);
+        let line = ability("targeting: Tile, range: 3, shape: Line(3)");
+        let from = GridPosition::new(1, 1);
+
+        assert_eq!(
+            check_target(
+                &line,
+                &map,
+                from,
+                Faction::Player,
+                GridPosition::new(2, 2),
+                None
+            ),
+            Err(AbilityError::NotInLine)
+        );
+        assert_eq!(
+            check_target(
+                &line,
+                &map,
+                from,
+                Faction::Player,
+                GridPosition::new(1, 3),
+                None
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn an_ability_for_allies_cannot_be_aimed_at_an_enemy() {
+        let map = GridMap::new(5, 5);
+        let blessing = ability("targeting: Unit, range: 2, affects: Allies");
+        let from = GridPosition::new(1, 1);
+        let at = GridPosition::new(2, 1);
+        let aim = |target| check_target(&blessing, &map, from, Faction::Player, at, Some(target));
+
+        assert_eq!(aim(Faction::Enemy), Err(AbilityError::Unaffected));
+        assert_eq!(aim(Faction::Player), Ok(()));
+    }
+}
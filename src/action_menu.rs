@@ -0,0 +1,615 @@
+//! The action menu shown next to the selected unit, with its Items submenu
+//! and the prompt shown while an ability is being aimed.
+//!
+//! Every entry can be picked from the keyboard: Items, Capture, Back, and
+//! Wait have keys of their own, and the rest are handed the menu entry keys
+//! in the order they are listed, with the key shown in front of each.
+//!
+//! Like the rest of player input, menu entries only write [`GameCommand`]s.
+
+use bevy::prelude::*;
+
+use crate::ability::{AbilityCatalog, Cooldowns};
+use crate::bindings::{ActionInput, InputAction, InputMap};
+use crate::chest::Chest;
+use crate::class::UnitClass;
+use crate::command::GameCommand;
+use crate::grid::{GridMap, GridPosition, TileType};
+use crate::input::{ButtonActivated, GameAction};
+use crate::items::{EquipSlot, Equipment, Inventory, ItemCatalog, ItemKind};
+use crate::locale::Locale;
+use crate::rescue::Rescue;
+use crate::selection::SelectedUnit;
+use crate::state::AppState;
+use crate::stealth::Stealth;
+use crate::structure::Structure;
+use crate::unit::{Faction, Stats, Unit, UnitId, UnitIndex};
+
+const PANEL_COLOR: Color = Color::srgba(0.08, 0.08, 0.12, 0.9);
+const ENTRY_COLOR: Color = Color::srgb(0.22, 0.22, 0.30);
+
+/// Which page of the action menu is open.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ActionMenuMode {
+    #[default]
+    Main,
+    Items,
+    /// Picking where to use the ability in this slot of the unit's class's
+    /// list.
+    Aim(usize),
+}
+
+impl ActionMenuMode {
+    /// The ability being aimed, if any.
+    pub fn aiming(self) -> Option<usize> {
+        match self {
+            ActionMenuMode::Aim(slot) => Some(slot),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ActionMenu;
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionButton {
+    Items,
+    Heal(UnitId),
+    PickUp(UnitId),
+    DropOff(GridPosition),
+    Operate(GridPosition),
+    OpenChest,
+    Hide,
+    Capture,
+    Wait,
+    Back,
+    Ability(usize),
+    Use(usize),
+    Equip(usize),
+    Unequip(EquipSlot),
+    Discard(usize),
+}
+
+impl ActionButton {
+    fn hotkey(self) -> Option<InputAction> {
+        match self {
+            ActionButton::Items => Some(InputAction::Items),
+            ActionButton::Capture => Some(InputAction::Capture),
+            ActionButton::Back => Some(InputAction::Back),
+            // Picked through `GameAction::Confirm` instead.
+            ActionButton::Wait
+            | ActionButton::Heal(_)
+            | ActionButton::PickUp(_)
+            | ActionButton::DropOff(_)
+            | ActionButton::Operate(_)
+            | ActionButton::OpenChest
+            | ActionButton::Hide
+            | ActionButton::Ability(_)
+            | ActionButton::Use(_)
+            | ActionButton::Equip(_)
+            | ActionButton::Unequip(_)
+            | ActionButton::Discard(_) => None,
+        }
+    }
+}
+
+/// The menu entry key handed to an entry without a key of its own.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct EntryKey(pub InputAction);
+
+/// Hands out the menu entry keys as entries are added, and labels each entry
+/// with its key.
+struct EntryKeys<'a> {
+    bindings: &'a InputMap,
+    free: std::slice::Iter<'static, InputAction>,
+}
+
+impl<'a> EntryKeys<'a> {
+    fn new(bindings: &'a InputMap) -> Self {
+        Self {
+            bindings,
+            free: InputAction::ENTRIES.iter(),
+        }
+    }
+}
+
+fn spawn_entry(
+    parent: &mut ChildSpawnerCommands,
+    keys: &mut EntryKeys,
+    button: ActionButton,
+    label: String,
+) {
+    // Wait is labelled with the confirm key, which picks it.
+    let own = match button {
+        ActionButton::Wait => Some(InputAction::Confirm),
+        _ => button.hotkey(),
+    };
+    let entry_key = own.is_none().then(|| keys.free.next().copied()).flatten();
+    let label = match own.or(entry_key) {
+        Some(action) => format!("[{}] {label}", keys.bindings.hint(action)),
+        None => label,
+    };
+    let mut entry = parent.spawn((
+        button,
+        Button,
+        Node {
+            padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+            ..default()
+        },
+        BackgroundColor(ENTRY_COLOR),
+    ));
+    entry.with_child((
+        Text::new(label),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+    ));
+    if let Some(key) = entry_key {
+        entry.insert(EntryKey(key));
+    }
+}
+
+/// Rebuilds the menu when the selection or the open page changes, or when
+/// something that decides which entries to show (inventory, equipment,
+/// positions, HP, carried units, stealth, cooldowns, structures, chests,
+/// doors) changes.
+pub fn update_action_menu_system(
+    mut commands: Commands,
+    selected: Res<SelectedUnit>,
+    mut mode: ResMut<ActionMenuMode>,
+    (catalog, abilities): (Res<ItemCatalog>, Res<AbilityCatalog>),
+    (bindings, locale): (Res<InputMap>, Res<Locale>),
+    map: Res<GridMap>,
+    index: Res<UnitIndex>,
+    units: Query<(&Inventory, &Equipment, &UnitClass, &Cooldowns), With<Unit>>,
+    board: Query<(&UnitId, &GridPosition, &Faction, &Stats, &Rescue), With<Unit>>,
+    watchers: Query<
+        (
+            &GridPosition,
+            &Faction,
+            &UnitClass,
+            &Stats,
+            &Rescue,
+            &Stealth,
+        ),
+        With<Unit>,
+    >,
+    changed: Query<
+        (),
+        (
+            With<Unit>,
+            Or<(
+                Changed<Inventory>,
+                Changed<Equipment>,
+                Changed<GridPosition>,
+                Changed<Stats>,
+                Changed<Rescue>,
+                Changed<Stealth>,
+                Changed<Cooldowns>,
+            )>,
+        ),
+    >,
+    structures: Query<(&GridPosition, &Structure), Without<Unit>>,
+    changed_structures: Query<(), Changed<Structure>>,
+    chests: Query<(&GridPosition, &Chest), Changed<Chest>>,
+    all_chests: Query<(&GridPosition, &Chest)>,
+    menus: Query<Entity, With<ActionMenu>>,
+) {
+    if selected.is_changed() && *mode != ActionMenuMode::Main {
+        *mode = ActionMenuMode::Main;
+    }
+    if !selected.is_changed()
+        && !mode.is_changed()
+        && changed.is_empty()
+        && changed_structures.is_empty()
+        && chests.is_empty()
+        && !map.is_changed()
+        && !locale.is_changed()
+    {
+        return;
+    }
+    for menu in &menus {
+        commands.entity(menu).despawn();
+    }
+
+    let Some(entity) = selected.0 else {
+        return;
+    };
+    let (Ok((inventory, equipment, class, cooldowns)), Ok((&id, &pos, &faction, _, rescue))) =
+        (units.get(entity), board.get(entity))
+    else {
+        return;
+    };
+    let mut patients: Vec<(UnitId, &Stats)> = class
+        .heal_range()
+        .map(|range| {
+            board
+                .iter()
+                .filter(|(other, other_pos, other_faction, stats, other_rescue)| {
+                    **other != id
+                        && **other_faction == faction
+                        && stats.hp < stats.max_hp
+                        && !other_rescue.is_carried()
+                        && pos.distance(**other_pos) <= range
+                })
+                .map(|(&other, _, _, stats, _)| (other, stats))
+                .collect()
+        })
+        .unwrap_or_default();
+    patients.sort_by_key(|(other, _)| *other);
+    // While carrying, offer every free tile next to the unit to drop the
+    // ally on; otherwise offer to pick up any adjacent ally.
+    let mut rescuable: Vec<UnitId> = Vec::new();
+    let mut drop_tiles: Vec<GridPosition> = Vec::new();
+    if rescue.carrying.is_some() {
+        drop_tiles = [(0, -1), (-1, 0), (1, 0), (0, 1)]
+            .into_iter()
+            .map(|(dx, dy)| GridPosition::new(pos.x + dx, pos.y + dy))
+            .filter(|tile| map.is_walkable(*tile) && !index.is_occupied(*tile))
+            .collect();
+    } else {
+        rescuable = pos
+            .neighbors()
+            .into_iter()
+            .filter_map(|tile| board.get(index.at(tile)?).ok())
+            .filter(|(_, _, other_faction, _, other_rescue)| {
+                **other_faction == faction && other_rescue.carrying.is_none()
+            })
+            .map(|(&other, ..)| other)
+            .collect();
+        rescuable.sort();
+    }
+    let operable: Vec<(GridPosition, &str)> = std::iter::once(pos)
+        .chain(pos.neighbors())
+        .filter_map(|tile| {
+            let label = match map.tile(tile)? {
+                TileType::Door { open: false } => "Open door at ({}, {})",
+                TileType::Door { open: true } => "Close door at ({}, {})",
+                TileType::Lever => "Pull lever at ({}, {})",
+                _ => return None,
+            };
+            Some((tile, label))
+        })
+        .collect();
+    let chest = all_chests
+        .iter()
+        .find(|(chest_pos, chest)| **chest_pos == pos && !chest.opened)
+        .map(|(_, chest)| chest);
+    // Hiding needs a stealthy class and no enemy close enough to notice.
+    let can_hide = class.stealthy()
+        && watchers
+            .get(entity)
+            .is_ok_and(|(.., stealth)| !stealth.hidden)
+        && !watchers.iter().any(
+            |(other_pos, other_faction, other_class, stats, other_rescue, _)| {
+                *other_faction != faction
+                    && stats.is_alive()
+                    && !other_rescue.is_carried()
+                    && pos.distance(*other_pos) <= other_class.detection_range()
+            },
+        );
+    let capturable = structures
+        .iter()
+        .find(|(structure_pos, structure)| {
+            **structure_pos == pos && structure.owner != Some(faction)
+        })
+        .map(|(_, structure)| structure);
+
+    let mut keys = EntryKeys::new(&bindings);
+    commands
+        .spawn((
+            ActionMenu,
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            BackgroundColor(PANEL_COLOR),
+            DespawnOnExit(AppState::GamePlay),
+        ))
+        .with_children(|menu| match *mode {
+            ActionMenuMode::Main => {
+                for (patient, stats) in &patients {
+                    spawn_entry(
+                        menu,
+                        &mut keys,
+                        ActionButton::Heal(*patient),
+                        locale.fill(
+                            "Heal unit {} ({}/{})",
+                            &[&patient.0, &stats.hp, &stats.max_hp],
+                        ),
+                    );
+                }
+                if let Some(carried) = rescue.carrying {
+                    menu.spawn((
+                        Text::new(locale.fill("Carrying unit {}", &[&carried.0])),
+                        TextFont {
+                            font_size: 14.0,
+                            ..default()
+                        },
+                    ));
+                }
+                for other in &rescuable {
+                    spawn_entry(
+                        menu,
+                        &mut keys,
+                        ActionButton::PickUp(*other),
+                        locale.fill("Rescue unit {}", &[&other.0]),
+                    );
+                }
+                for tile in &drop_tiles {
+                    spawn_entry(
+                        menu,
+                        &mut keys,
+                        ActionButton::DropOff(*tile),
+                        locale.fill("Drop at ({}, {})", &[&tile.x, &tile.y]),
+                    );
+                }
+                for (tile, label) in &operable {
+                    spawn_entry(
+                        menu,
+                        &mut keys,
+                        ActionButton::Operate(*tile),
+                        locale.fill(label, &[&tile.x, &tile.y]),
+                    );
+                }
+                if chest.is_some() {
+                    spawn_entry(
+                        menu,
+                        &mut keys,
+                        ActionButton::OpenChest,
+                        locale.tr("Open chest").to_string(),
+                    );
+                }
+                for (slot, ability_id, ability) in abilities.of_class(*class) {
+                    match cooldowns.left(ability_id) {
+                        0 => spawn_entry(
+                            menu,
+                            &mut keys,
+                            ActionButton::Ability(slot),
+                            ability.name.clone(),
+                        ),
+                        turns => {
+                            menu.spawn((
+                                Text::new(
+                                    locale.fill("{} (ready in {})", &[&ability.name, &turns]),
+                                ),
+                                TextFont {
+                                    font_size: 14.0,
+                                    ..default()
+                                },
+                            ));
+                        }
+                    }
+                }
+                if can_hide {
+                    spawn_entry(
+                        menu,
+                        &mut keys,
+                        ActionButton::Hide,
+                        locale.tr("Hide").to_string(),
+                    );
+                }
+                if let Some(structure) = capturable {
+                    let progress = match structure.capture {
+                        Some((by, progress)) if by == id => progress,
+                        _ => 0,
+                    };
+                    spawn_entry(
+                        menu,
+                        &mut keys,
+                        ActionButton::Capture,
+                        locale.fill(
+                            "Capture {} ({}/{})",
+                            &[
+                                &locale.tr(&format!("{:?}", structure.kind)),
+                                &progress,
+                                &structure.kind.capture_turns(),
+                            ],
+                        ),
+                    );
+                }
+                spawn_entry(
+                    menu,
+                    &mut keys,
+                    ActionButton::Items,
+                    locale.tr("Items").to_string(),
+                );
+                spawn_entry(
+                    menu,
+                    &mut keys,
+                    ActionButton::Wait,
+                    locale.tr("Wait").to_string(),
+                );
+            }
+            ActionMenuMode::Items => {
+                for slot in [EquipSlot::Weapon, EquipSlot::Armor] {
+                    let equipped = equipment.get(slot);
+                    menu.spawn((
+                        Text::new(format!(
+                            "{}: {}",
+                            locale.tr(&format!("{slot:?}")),
+                            equipped
+                                .map_or(locale.tr("none").to_string(), |item| catalog.label(item))
+                        )),
+                        TextFont {
+                            font_size: 14.0,
+                            ..default()
+                        },
+                    ));
+                    if equipped.is_some() {
+                        spawn_entry(
+                            menu,
+                            &mut keys,
+                            ActionButton::Unequip(slot),
+                            locale.tr("Unequip").to_string(),
+                        );
+                    }
+                }
+                if inventory.items.is_empty() {
+                    menu.spawn(Text::new(locale.tr("No items")));
+                }
+                for (slot, carried) in inventory.items.iter().enumerate() {
+                    let item = catalog.get(&carried.id);
+                    menu.spawn((
+                        Text::new(format!(
+                            "{} - {}",
+                            catalog.label(carried),
+                            item.map_or("", |item| item.description.as_str())
+                        )),
+                        TextFont {
+                            font_size: 14.0,
+                            ..default()
+                        },
+                    ));
+                    if item.is_some_and(|item| item.effect.is_some()) {
+                        spawn_entry(
+                            menu,
+                            &mut keys,
+                            ActionButton::Use(slot),
+                            locale.tr("Use").to_string(),
+                        );
+                    }
+                    if item.is_some_and(|item| item.kind.equip_slot().is_some()) {
+                        spawn_entry(
+                            menu,
+                            &mut keys,
+                            ActionButton::Equip(slot),
+                            locale.tr("Equip").to_string(),
+                        );
+                    }
+                    if item.is_none_or(|item| item.kind != ItemKind::Quest) {
+                        spawn_entry(
+                            menu,
+                            &mut keys,
+                            ActionButton::Discard(slot),
+                            locale.tr("Discard").to_string(),
+                        );
+                    }
+                }
+                spawn_entry(
+                    menu,
+                    &mut keys,
+                    ActionButton::Back,
+                    locale.tr("Back").to_string(),
+                );
+            }
+            ActionMenuMode::Aim(slot) => {
+                if let Some((_, ability)) = abilities.slot(*class, slot) {
+                    menu.spawn((
+                        Text::new(format!("{} - {}", ability.name, ability.description)),
+                        TextFont {
+                            font_size: 14.0,
+                            ..default()
+                        },
+                    ));
+                }
+                menu.spawn((
+                    Text::new(locale.tr("Pick a target")),
+                    TextFont {
+                        font_size: 14.0,
+                        ..default()
+                    },
+                ));
+                spawn_entry(
+                    menu,
+                    &mut keys,
+                    ActionButton::Back,
+                    locale.tr("Back").to_string(),
+                );
+            }
+        });
+}
+
+/// Runs the entry that was clicked, pressed through focus, or picked by its
+/// key. Confirming picks Wait.
+pub fn action_menu_input_system(
+    input: ActionInput,
+    mut actions: MessageReader<GameAction>,
+    mut activated: MessageReader<ButtonActivated>,
+    selected: Res<SelectedUnit>,
+    mut mode: ResMut<ActionMenuMode>,
+    units: Query<&UnitId, With<Unit>>,
+    buttons: Query<(&ActionButton, &Interaction), Changed<Interaction>>,
+    visible: Query<(&ActionButton, Option<&EntryKey>)>,
+    mut orders: MessageWriter<GameCommand>,
+) {
+    let Some(&unit) = selected.0.and_then(|entity| units.get(entity).ok()) else {
+        return;
+    };
+
+    let clicked = buttons
+        .iter()
+        .find(|(_, interaction)| **interaction == Interaction::Pressed)
+        .map(|(button, _)| *button);
+    let activated = activated
+        .read()
+        .find_map(|ButtonActivated(entity)| visible.get(*entity).ok())
+        .map(|(button, _)| *button);
+    let pressed = visible
+        .iter()
+        .find(|(button, entry_key)| {
+            button
+                .hotkey()
+                .or(entry_key.map(|key| key.0))
+                .is_some_and(|action| input.just_pressed(action))
+        })
+        .map(|(button, _)| *button);
+    let confirmed = actions
+        .read()
+        .any(|action| *action == GameAction::Confirm)
+        .then_some(ActionButton::Wait)
+        .filter(|wait| visible.iter().any(|(button, _)| button == wait));
+
+    match clicked.or(activated).or(pressed).or(confirmed) {
+        Some(ActionButton::Items) => *mode = ActionMenuMode::Items,
+        Some(ActionButton::Back) => *mode = ActionMenuMode::Main,
+        Some(ActionButton::Ability(slot)) => *mode = ActionMenuMode::Aim(slot),
+        Some(ActionButton::Heal(target)) => {
+            orders.write(GameCommand::Heal {
+                healer: unit,
+                target,
+            });
+        }
+        Some(ActionButton::PickUp(target)) => {
+            orders.write(GameCommand::PickUp { unit, target });
+        }
+        Some(ActionButton::DropOff(to)) => {
+            orders.write(GameCommand::DropOff { unit, to });
+        }
+        Some(ActionButton::Operate(at)) => {
+            orders.write(GameCommand::Operate { unit, at });
+        }
+        Some(ActionButton::OpenChest) => {
+            orders.write(GameCommand::OpenChest { unit });
+        }
+        Some(ActionButton::Hide) => {
+            orders.write(GameCommand::Hide { unit });
+        }
+        Some(ActionButton::Capture) => {
+            orders.write(GameCommand::Capture { unit });
+        }
+        Some(ActionButton::Wait) => {
+            orders.write(GameCommand::Wait { unit });
+        }
+        Some(ActionButton::Use(slot)) => {
+            orders.write(GameCommand::UseItem { unit, slot });
+        }
+        Some(ActionButton::Equip(slot)) => {
+            orders.write(GameCommand::Equip { unit, slot });
+        }
+        Some(ActionButton::Unequip(slot)) => {
+            orders.write(GameCommand::Unequip { unit, slot });
+        }
+        Some(ActionButton::Discard(slot)) => {
+            orders.write(GameCommand::DiscardItem { unit, slot });
+        }
+        None => {}
+    }
+}
@@ -0,0 +1,1039 @@
+//! Turn logic for computer-controlled sides: the enemy unless a person is
+//! seated at it, and with `--autoplay` the player's side as well.
+//!
+//! Each side the AI plays has an [`AiLevel`]. An easy AI spreads its attacks
+//! around and neither retreats nor drinks potions to stay alive, and a hard
+//! one closes in on the weakest target rather than the nearest.
+//!
+//! The AI acts one unit at a time on a short timer so the player can follow
+//! along, and like player input it only ever writes [`GameCommand`]s.
+//!
+//! At the start of each round every unit that still has to act is planned
+//! at once, in parallel over a read-only copy of the board, so a large army
+//! doesn't slow the phase down. The plans are then carried out one command
+//! at a time in id order. A plan that the units acting before it have ruled
+//! out, say by taking its tile or felling its target, is thrown away and
+//! that unit is planned again from the board as it now stands.
+//!
+//! A unit whose command the executor turns down anyway waits out the rest
+//! of the turn rather than trying again, and a turned-down recruit ends the
+//! recruiting until the side's next turn, so the AI can't get stuck asking
+//! for the same thing forever.
+//!
+//! A scenario can give single units a standing [`AiOrder`] that overrides
+//! their usual plans, such as a boss that never leaves its throne room.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use bevy::tasks::{ComputeTaskPool, ParallelSlice, TaskPool};
+use serde::{Deserialize, Serialize};
+
+use crate::chest::{Chest, Loot};
+use crate::class::{Experience, UnitClass};
+use crate::command::{CommandRejected, GameCommand};
+use crate::constants::{ENEMY_ACTION_DELAY, ENEMY_TURN_DELAY};
+use crate::economy::{Treasury, RECRUITABLE};
+use crate::grid::{reachable_tiles, GridMap, GridPosition, PathCache, TileType};
+use crate::items::{
+    can_attack, effective_stats, ConsumableEffect, Equipment, Inventory, ItemCatalog, ItemDef,
+    ItemKind, INVENTORY_SLOTS,
+};
+use crate::leader::AuraBonus;
+use crate::rescue::Rescue;
+use crate::rules::{validate_attack, validate_move, AttackAttempt};
+use crate::seats::{Controller, Seats};
+use crate::status::StatusEffects;
+use crate::stealth::Stealth;
+use crate::structure::Structure;
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::{AttackRange, Faction, MoveRange, Stats, TurnStatus, Unit, UnitId, UnitIndex};
+
+/// How long the AI waits before acting, in seconds: once as its turn
+/// begins, so the turn change can be read, and before each action, so each
+/// can be followed.
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct AiPacing {
+    pub turn_start: f32,
+    pub per_action: f32,
+}
+
+impl AiPacing {
+    /// No waiting at all, for headless runs.
+    pub const INSTANT: Self = Self {
+        turn_start: 0.0,
+        per_action: 0.0,
+    };
+}
+
+impl Default for AiPacing {
+    fn default() -> Self {
+        Self {
+            turn_start: ENEMY_TURN_DELAY,
+            per_action: ENEMY_ACTION_DELAY,
+        }
+    }
+}
+
+/// Counts down to the AI's next action.
+#[derive(Resource)]
+pub struct AiTimer(pub Timer);
+
+impl Default for AiTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(ENEMY_ACTION_DELAY, TimerMode::Once))
+    }
+}
+
+/// How well the AI plays a side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AiLevel {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl AiLevel {
+    pub const ALL: [AiLevel; 3] = [AiLevel::Easy, AiLevel::Normal, AiLevel::Hard];
+}
+
+/// A standing order for one unit, vetoing or replacing what the AI would
+/// otherwise do with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AiOrder {
+    /// Never moves, but still fights whatever comes into reach.
+    Hold,
+    /// Never ends a move more than `radius` tiles from `at`.
+    Guard { at: GridPosition, radius: u32 },
+    /// Heads for `at` rather than the enemy, fighting whatever comes into
+    /// reach on the way, and carries on as usual once there.
+    Advance(GridPosition),
+    /// Only ever waits.
+    Idle,
+}
+
+/// The standing order of each unit that has one, given by the map's
+/// scenario script. Cleared as each battle starts.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct AiOrders(pub HashMap<UnitId, AiOrder>);
+
+pub fn reset_ai_orders(mut orders: ResMut<AiOrders>) {
+    orders.0.clear();
+}
+
+/// The next command of each unit planned this round, waiting its turn.
+#[derive(Resource, Default)]
+pub struct AiPlans {
+    /// The turn number and side the plans were made for.
+    turn: Option<(u32, Faction)>,
+    commands: HashMap<UnitId, GameCommand>,
+    /// Units whose command was turned down this turn, left to sit it out.
+    benched: HashSet<UnitId>,
+    /// Whether a recruit was turned down this turn.
+    recruit_refused: bool,
+}
+
+impl AiPlans {
+    /// Takes note of a command the executor turned down while `side` was
+    /// acting, so it isn't asked for again this turn.
+    fn refuse(&mut self, side: Faction, command: GameCommand) {
+        match command {
+            GameCommand::Recruit { faction, .. } if faction == side => {
+                self.recruit_refused = true;
+            }
+            _ => {
+                if let Some(unit) = command.unit() {
+                    self.commands.remove(&unit);
+                    self.benched.insert(unit);
+                }
+            }
+        }
+    }
+}
+
+/// What planning needs to know about a unit.
+#[derive(Clone, Debug)]
+struct AiUnit {
+    id: UnitId,
+    faction: Faction,
+    pos: GridPosition,
+    stats: Stats,
+    status: TurnStatus,
+    class: UnitClass,
+    /// Tiles it can walk, less while carrying an ally.
+    move_range: u32,
+    /// Its range with equipment and bonuses.
+    attack_range: AttackRange,
+    armed: bool,
+    hidden: bool,
+    carried: bool,
+    /// The first promotion it qualifies for.
+    promotion: Option<UnitClass>,
+    /// Inventory slot of its first healing item.
+    healing_slot: Option<usize>,
+    /// Inventory slot of its first weapon, while it has none equipped.
+    spare_weapon: Option<usize>,
+    /// Whether it has a free inventory slot.
+    room: bool,
+    order: Option<AiOrder>,
+}
+
+impl AiUnit {
+    /// Whether its order lets it end a move on `tile`.
+    fn may_stand(&self, tile: GridPosition) -> bool {
+        match self.order {
+            Some(AiOrder::Hold | AiOrder::Idle) => tile == self.pos,
+            Some(AiOrder::Guard { at, radius }) => tile.distance(at) <= radius,
+            Some(AiOrder::Advance(_)) | None => true,
+        }
+    }
+}
+
+/// The battle as the AI sees it, gathered from the world before planning.
+/// Planning only reads it, so any number of units can be planned at once.
+struct AiBoard<'a> {
+    side: Faction,
+    level: AiLevel,
+    map: &'a GridMap,
+    /// Every unit standing, carried ones included.
+    units: Vec<AiUnit>,
+    /// The opponents that can be attacked, weakest first.
+    targets: Vec<(UnitId, GridPosition, u32)>,
+    /// Every tile a unit stands on.
+    occupied: HashSet<GridPosition>,
+    structures: Vec<(GridPosition, Structure)>,
+    chests: Vec<(GridPosition, Chest)>,
+    /// The side's gold, for recruiting.
+    gold: u32,
+    /// The side's units that are to act no more this turn.
+    benched: HashSet<UnitId>,
+}
+
+type AiUnitQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static UnitId,
+        &'static Faction,
+        &'static GridPosition,
+        &'static UnitClass,
+        &'static Experience,
+        &'static Stats,
+        &'static TurnStatus,
+        &'static MoveRange,
+        &'static AttackRange,
+        &'static Equipment,
+        &'static StatusEffects,
+        &'static AuraBonus,
+        &'static Inventory,
+        &'static Stealth,
+        &'static Rescue,
+    ),
+    With<Unit>,
+>;
+
+impl<'a> AiBoard<'a> {
+    fn gather(
+        side: Faction,
+        level: AiLevel,
+        map: &'a GridMap,
+        catalog: &ItemCatalog,
+        orders: &AiOrders,
+        index: &UnitIndex,
+        units: &AiUnitQuery,
+        structures: &Query<(&GridPosition, &Structure), Without<Unit>>,
+        chests: &Query<(&GridPosition, &Chest), Without<Unit>>,
+        gold: u32,
+    ) -> Self {
+        let first_item = |inventory: &Inventory, wanted: fn(&ItemDef) -> bool| {
+            inventory
+                .items
+                .iter()
+                .position(|item| catalog.get(&item.id).is_some_and(wanted))
+        };
+        let units: Vec<AiUnit> = units
+            .iter()
+            .map(
+                |(
+                    &id,
+                    &faction,
+                    &pos,
+                    &class,
+                    experience,
+                    stats,
+                    &status,
+                    &move_range,
+                    &attack_range,
+                    equipment,
+                    effects,
+                    aura,
+                    inventory,
+                    stealth,
+                    rescue,
+                )| {
+                    let (_, attack_range) =
+                        effective_stats(stats, attack_range, equipment, effects, aura, catalog);
+                    AiUnit {
+                        id,
+                        faction,
+                        pos,
+                        stats: *stats,
+                        status,
+                        class,
+                        move_range: rescue.move_range(move_range),
+                        attack_range,
+                        armed: can_attack(class, equipment),
+                        hidden: stealth.hidden,
+                        carried: rescue.is_carried(),
+                        promotion: class
+                            .promotions()
+                            .first()
+                            .copied()
+                            .filter(|&into| class.can_promote(experience, into)),
+                        healing_slot: first_item(inventory, |item| {
+                            matches!(item.effect, Some(ConsumableEffect::Heal(_)))
+                        }),
+                        spare_weapon: first_item(inventory, |item| item.kind == ItemKind::Weapon)
+                            .filter(|_| equipment.weapon.is_none()),
+                        room: inventory.items.len() < INVENTORY_SLOTS,
+                        order: orders.0.get(&id).copied(),
+                    }
+                },
+            )
+            .filter(|unit| unit.stats.is_alive())
+            .collect();
+        Self::new(
+            side,
+            level,
+            map,
+            units,
+            index.occupied(),
+            structures
+                .iter()
+                .map(|(pos, structure)| (*pos, structure.clone()))
+                .collect(),
+            chests
+                .iter()
+                .map(|(pos, chest)| (*pos, chest.clone()))
+                .collect(),
+            gold,
+        )
+    }
+
+    fn new(
+        side: Faction,
+        level: AiLevel,
+        map: &'a GridMap,
+        units: Vec<AiUnit>,
+        occupied: HashSet<GridPosition>,
+        structures: Vec<(GridPosition, Structure)>,
+        chests: Vec<(GridPosition, Chest)>,
+        gold: u32,
+    ) -> Self {
+        let mut targets: Vec<(UnitId, GridPosition, u32)> = units
+            .iter()
+            .filter(|unit| unit.faction == side.opponent() && !unit.hidden && !unit.carried)
+            .map(|unit| (unit.id, unit.pos, unit.stats.hp))
+            .collect();
+        // Prefer finishing off weakened units; ids break ties deterministically.
+        targets.sort_by_key(|&(id, _, hp)| (hp, id));
+
+        Self {
+            side,
+            level,
+            map,
+            units,
+            targets,
+            occupied,
+            structures,
+            chests,
+            gold,
+            benched: HashSet::new(),
+        }
+    }
+
+    /// The side's next command: a recruit while there is gold and room for
+    /// one, then each ready unit's plan in id order, then the end of the
+    /// turn.
+    fn next_command(&self, plans: &mut AiPlans, paths: &mut PathCache) -> GameCommand {
+        if let Some(recruit) = self.recruit().filter(|_| !plans.recruit_refused) {
+            return recruit;
+        }
+        let Some(actor) = self.ready().next() else {
+            plans.commands.clear();
+            return GameCommand::EndTurn;
+        };
+        let planned = plans
+            .commands
+            .remove(&actor.id)
+            .filter(|command| self.still_valid(command))
+            .filter(|command| {
+                actor.order != Some(AiOrder::Idle) || matches!(command, GameCommand::Wait { .. })
+            });
+        match planned {
+            Some(command) => command,
+            // Everyone planned last round has acted: plan the next round.
+            None if plans.commands.is_empty() => {
+                self.chart(paths);
+                plans.commands = self.plan_all(paths);
+                plans
+                    .commands
+                    .remove(&actor.id)
+                    .unwrap_or_else(|| self.plan(actor, paths))
+            }
+            None => {
+                if !actor.status.has_moved {
+                    let (id, pos, range) = (actor.id, actor.pos, actor.move_range);
+                    if paths.get(id, pos, range).is_none() {
+                        let tiles = reachable_tiles(self.map, pos, range, &self.occupied);
+                        paths.insert(id, pos, range, tiles);
+                    }
+                }
+                self.plan(actor, paths)
+            }
+        }
+    }
+
+    /// The priciest recruit the side can afford, on its first free site.
+    fn recruit(&self) -> Option<GameCommand> {
+        let at = self
+            .structures
+            .iter()
+            .filter(|(site, structure)| {
+                structure.kind.can_recruit()
+                    && structure.owner == Some(self.side)
+                    && !self.occupied.contains(site)
+            })
+            .map(|(site, _)| *site)
+            .min_by_key(|site| (site.y, site.x))?;
+        let class = RECRUITABLE
+            .into_iter()
+            .rev()
+            .find(|class| class.cost() <= self.gold)?;
+        Some(GameCommand::Recruit {
+            faction: self.side,
+            class,
+            at,
+        })
+    }
+
+    fn unit(&self, id: UnitId) -> Option<&AiUnit> {
+        self.units.iter().find(|unit| unit.id == id)
+    }
+
+    /// The side's units that still have to act this turn, in id order.
+    fn ready(&self) -> impl Iterator<Item = &AiUnit> {
+        let mut ready: Vec<&AiUnit> = self
+            .units
+            .iter()
+            .filter(|unit| {
+                unit.faction == self.side
+                    && !unit.status.has_acted
+                    && !unit.carried
+                    && !self.benched.contains(&unit.id)
+            })
+            .collect();
+        ready.sort_by_key(|unit| unit.id);
+        ready.into_iter()
+    }
+
+    /// Works out where each ready unit that can still move may walk, for
+    /// any not already in `paths`, spread over the compute threads.
+    fn chart(&self, paths: &mut PathCache) {
+        let uncharted: Vec<&AiUnit> = self
+            .ready()
+            .filter(|unit| {
+                !unit.status.has_moved && paths.get(unit.id, unit.pos, unit.move_range).is_none()
+            })
+            .collect();
+        let pool = ComputeTaskPool::get_or_init(TaskPool::default);
+        let charted = uncharted.par_splat_map(pool, None, |_, units| {
+            units
+                .iter()
+                .map(|unit| {
+                    let tiles =
+                        reachable_tiles(self.map, unit.pos, unit.move_range, &self.occupied);
+                    (unit.id, unit.pos, unit.move_range, tiles)
+                })
+                .collect::<Vec<_>>()
+        });
+        for (id, pos, range, tiles) in charted.into_iter().flatten() {
+            paths.insert(id, pos, range, tiles);
+        }
+    }
+
+    /// Plans every ready unit's next command, spread over the compute
+    /// threads.
+    fn plan_all(&self, paths: &PathCache) -> HashMap<UnitId, GameCommand> {
+        let ready: Vec<&AiUnit> = self.ready().collect();
+        let pool = ComputeTaskPool::get_or_init(TaskPool::default);
+        ready
+            .par_splat_map(pool, None, |_, units| {
+                units
+                    .iter()
+                    .map(|unit| (unit.id, self.plan(unit, paths)))
+                    .collect::<Vec<_>>()
+            })
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Where `unit` can walk, from `paths` when it has been worked out
+    /// already.
+    fn reachable<'p>(&self, unit: &AiUnit, paths: &'p PathCache) -> Cow<'p, HashSet<GridPosition>> {
+        match paths.get(unit.id, unit.pos, unit.move_range) {
+            Some(tiles) => Cow::Borrowed(tiles),
+            None => Cow::Owned(reachable_tiles(
+                self.map,
+                unit.pos,
+                unit.move_range,
+                &self.occupied,
+            )),
+        }
+    }
+
+    /// Whether a command planned earlier in the round still holds up now
+    /// that other units have acted. Only orders that depend on where other
+    /// units stand, or on standing orders a script may have changed since,
+    /// are checked; the executor turns down anything else that went stale.
+    fn still_valid(&self, command: &GameCommand) -> bool {
+        match *command {
+            GameCommand::Move { unit, to } => self.unit(unit).is_some_and(|unit| {
+                unit.may_stand(to)
+                    && validate_move(
+                        self.map,
+                        &self.occupied,
+                        unit.pos,
+                        unit.move_range,
+                        &unit.status,
+                        to,
+                    )
+                    .is_ok()
+            }),
+            GameCommand::Attack { attacker, target } => {
+                let target = self.targets.iter().find(|(id, ..)| *id == target);
+                match (self.unit(attacker), target) {
+                    (Some(unit), Some(&(_, at, _))) => validate_attack(AttackAttempt {
+                        faction: self.side,
+                        status: &unit.status,
+                        armed: unit.armed,
+                        range: unit.attack_range,
+                        from: unit.pos,
+                        target: self.side.opponent(),
+                        hidden: false,
+                        at,
+                    })
+                    .is_ok(),
+                    _ => false,
+                }
+            }
+            GameCommand::Heal { healer, target } => match (self.unit(healer), self.unit(target)) {
+                (Some(healer), Some(patient)) => {
+                    patient.stats.hp < patient.stats.max_hp
+                        && !patient.carried
+                        && healer
+                            .class
+                            .heal_range()
+                            .is_some_and(|range| healer.pos.distance(patient.pos) <= range)
+                }
+                _ => false,
+            },
+            _ => true,
+        }
+    }
+
+    /// The next command for `unit`, from the board as it stands.
+    fn plan(&self, unit: &AiUnit, paths: &PathCache) -> GameCommand {
+        let side = self.side;
+        let careful = self.level != AiLevel::Easy;
+        let AiUnit {
+            id,
+            pos,
+            stats,
+            status,
+            ..
+        } = *unit;
+
+        if unit.order == Some(AiOrder::Idle) {
+            return GameCommand::Wait { unit: id };
+        }
+
+        // Promote as soon as possible, always taking the first listed path.
+        if let Some(class) = unit.promotion {
+            return GameCommand::Promote { unit: id, class };
+        }
+
+        // Drink a healing item once at half health or below.
+        if let Some(slot) = unit
+            .healing_slot
+            .filter(|_| careful && stats.hp * 2 <= stats.max_hp)
+        {
+            return GameCommand::UseItem { unit: id, slot };
+        }
+
+        // Healers mend the most wounded ally in reach before thinking about attacks.
+        if let Some(range) = unit.class.heal_range() {
+            let patient = self
+                .units
+                .iter()
+                .filter(|ally| {
+                    ally.id != id
+                        && ally.faction == side
+                        && !ally.carried
+                        && ally.stats.hp < ally.stats.max_hp
+                        && pos.distance(ally.pos) <= range
+                })
+                .min_by_key(|ally| (ally.stats.hp * 100 / ally.stats.max_hp, ally.id));
+            if let Some(patient) = patient {
+                return GameCommand::Heal {
+                    healer: id,
+                    target: patient.id,
+                };
+            }
+        }
+
+        // Loot any chest the unit happens to stand on.
+        let on_chest = self.chests.iter().any(|(chest_pos, chest)| {
+            *chest_pos == pos && !chest.opened && (unit.room || matches!(chest.loot, Loot::Gold(_)))
+        });
+        if on_chest {
+            return GameCommand::OpenChest { unit: id };
+        }
+
+        // Keep capturing anything the unit already stands on.
+        let on_capturable = self.structures.iter().any(|(structure_pos, structure)| {
+            *structure_pos == pos && structure.owner != Some(side)
+        });
+        if on_capturable {
+            return GameCommand::Capture { unit: id };
+        }
+
+        // Replace a broken weapon with the first spare one in the inventory.
+        if let Some(slot) = unit.spare_weapon {
+            return GameCommand::Equip { unit: id, slot };
+        }
+
+        // Badly hurt units fall back to the nearest healing structure they can
+        // reach this turn, and stay there while it patches them up.
+        let heals_at = |tile: GridPosition| {
+            self.structures.iter().any(|(structure_pos, structure)| {
+                *structure_pos == tile
+                    && structure.owner == Some(side)
+                    && structure.kind.heal_percent() > 0
+            })
+        };
+        if careful && stats.hp * 2 <= stats.max_hp && !status.has_moved && !heals_at(pos) {
+            let refuge = self
+                .reachable(unit, paths)
+                .iter()
+                .copied()
+                .filter(|&tile| heals_at(tile) && unit.may_stand(tile))
+                .min_by_key(|&tile| (pos.distance(tile), tile.y, tile.x));
+            if let Some(to) = refuge {
+                return GameCommand::Move { unit: id, to };
+            }
+        }
+
+        // An easy AI goes for the healthiest target in reach, not the weakest.
+        let mut targets: Vec<_> = self.targets.iter().collect();
+        if !careful {
+            targets.reverse();
+        }
+        if let Some(&&(target, ..)) = targets.iter().find(|(_, target_pos, _)| {
+            validate_attack(AttackAttempt {
+                faction: side,
+                status: &status,
+                armed: unit.armed,
+                range: unit.attack_range,
+                from: pos,
+                target: side.opponent(),
+                hidden: false,
+                at: *target_pos,
+            })
+            .is_ok()
+        }) {
+            return GameCommand::Attack {
+                attacker: id,
+                target,
+            };
+        }
+
+        // Open any closed door next to the unit to clear the way forward.
+        let door = pos
+            .neighbors()
+            .into_iter()
+            .find(|&tile| self.map.tile(tile) == Some(TileType::Door { open: false }));
+        if let Some(at) = door.filter(|_| !self.targets.is_empty()) {
+            return GameCommand::Operate { unit: id, at };
+        }
+
+        let recovering = stats.hp * 2 <= stats.max_hp && heals_at(pos);
+        // A unit sent somewhere keeps going until it gets there.
+        let heading = match unit.order {
+            Some(AiOrder::Advance(at)) if at != pos => Some(at),
+            _ => None,
+        };
+        if status.has_moved || (self.targets.is_empty() && heading.is_none()) || recovering {
+            // Stealthy units with nothing to hit slip back out of sight.
+            let watched = self.units.iter().any(|other| {
+                other.faction == side.opponent()
+                    && !other.carried
+                    && other.pos.distance(pos) <= other.class.detection_range()
+            });
+            return if unit.class.stealthy() && !unit.hidden && !watched {
+                GameCommand::Hide { unit: id }
+            } else {
+                GameCommand::Wait { unit: id }
+            };
+        }
+
+        let goals: Vec<GridPosition> = match (heading, self.level) {
+            (Some(at), _) => vec![at],
+            // A hard AI closes in on the weakest target together.
+            (None, AiLevel::Hard) => vec![self.targets[0].1],
+            (None, _) => self.targets.iter().map(|&(_, at, _)| at).collect(),
+        };
+        let distance_to_goal = |tile: GridPosition| {
+            goals
+                .iter()
+                .map(|&goal| tile.distance(goal))
+                .min()
+                .unwrap_or(u32::MAX)
+        };
+        let destination = self
+            .reachable(unit, paths)
+            .iter()
+            .copied()
+            .filter(|&tile| unit.may_stand(tile))
+            .min_by_key(|&tile| (distance_to_goal(tile), tile.y, tile.x))
+            .unwrap_or(pos);
+        if destination == pos {
+            GameCommand::Wait { unit: id }
+        } else {
+            GameCommand::Move {
+                unit: id,
+                to: destination,
+            }
+        }
+    }
+}
+
+pub fn ai_movement_system(
+    time: Res<Time>,
+    (mut timer, pacing): (ResMut<AiTimer>, Res<AiPacing>),
+    mut plans: ResMut<AiPlans>,
+    turn: Res<CurrentTurn>,
+    outcome: Res<BattleOutcome>,
+    seats: Res<Seats>,
+    map: Res<GridMap>,
+    catalog: Res<ItemCatalog>,
+    standing: Res<AiOrders>,
+    index: Res<UnitIndex>,
+    mut paths: ResMut<PathCache>,
+    units: AiUnitQuery,
+    structures: Query<(&GridPosition, &Structure), Without<Unit>>,
+    chests: Query<(&GridPosition, &Chest), Without<Unit>>,
+    treasury: Res<Treasury>,
+    (mut orders, mut rejected): (MessageWriter<GameCommand>, MessageReader<CommandRejected>),
+) {
+    // Read every frame, so only this turn's refusals are left to look at.
+    let refused: Vec<GameCommand> = rejected.read().map(|rejected| rejected.command).collect();
+    let side = turn.faction;
+    let Controller::Ai(level) = seats.controller(side) else {
+        return;
+    };
+    if outcome.0.is_some() {
+        return;
+    }
+    if plans.turn != Some((turn.number, side)) {
+        plans.turn = Some((turn.number, side));
+        plans.commands.clear();
+        plans.benched.clear();
+        plans.recruit_refused = false;
+        timer.0 = Timer::from_seconds(pacing.turn_start, TimerMode::Once);
+    }
+    for command in refused {
+        plans.refuse(side, command);
+    }
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    timer.0 = Timer::from_seconds(pacing.per_action, TimerMode::Once);
+
+    let mut board = AiBoard::gather(
+        side,
+        level,
+        &map,
+        &catalog,
+        &standing,
+        &index,
+        &units,
+        &structures,
+        &chests,
+        treasury.get(side),
+    );
+    board.benched.clone_from(&plans.benched);
+    orders.write(board.next_command(&mut plans, &mut paths));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structure::StructureKind;
+
+    fn knight(id: u32, faction: Faction, x: i32, y: i32) -> AiUnit {
+        let class = UnitClass::Knight;
+        AiUnit {
+            id: UnitId(id),
+            faction,
+            pos: GridPosition::new(x, y),
+            stats: class.base_stats(),
+            status: TurnStatus::default(),
+            class,
+            move_range: class.move_range().0,
+            attack_range: class.attack_range(),
+            armed: true,
+            hidden: false,
+            carried: false,
+            promotion: None,
+            healing_slot: None,
+            spare_weapon: None,
+            room: true,
+            order: None,
+        }
+    }
+
+    fn wounded(mut unit: AiUnit, hp: u32) -> AiUnit {
+        unit.stats.hp = hp;
+        unit
+    }
+
+    fn ordered(mut unit: AiUnit, order: AiOrder) -> AiUnit {
+        unit.order = Some(order);
+        unit
+    }
+
+    /// The enemy's view of `units` on `map`.
+    fn board(
+        map: &GridMap,
+        level: AiLevel,
+        units: Vec<AiUnit>,
+        structures: Vec<(GridPosition, Structure)>,
+        gold: u32,
+    ) -> AiBoard<'_> {
+        let occupied = units.iter().map(|unit| unit.pos).collect();
+        AiBoard::new(
+            Faction::Enemy,
+            level,
+            map,
+            units,
+            occupied,
+            structures,
+            Vec::new(),
+            gold,
+        )
+    }
+
+    fn next(board: &AiBoard) -> GameCommand {
+        board.next_command(&mut AiPlans::default(), &mut PathCache::default())
+    }
+
+    fn attack(attacker: u32, target: u32) -> GameCommand {
+        GameCommand::Attack {
+            attacker: UnitId(attacker),
+            target: UnitId(target),
+        }
+    }
+
+    #[test]
+    fn the_weakest_target_in_reach_is_attacked_unless_the_ai_is_easy() {
+        let map = GridMap::new(8, 8);
+        let units = vec![
+            knight(1, Faction::Enemy, 2, 2),
+            knight(2, Faction::Player, 3, 2),
+            wounded(knight(3, Faction::Player, 1, 2), 5),
+        ];
+
+        let normal = board(&map, AiLevel::Normal, units.clone(), Vec::new(), 0);
+        assert_eq!(next(&normal), attack(1, 3));
+        let easy = board(&map, AiLevel::Easy, units, Vec::new(), 0);
+        assert_eq!(next(&easy), attack(1, 2));
+    }
+
+    #[test]
+    fn ties_go_to_the_lower_id() {
+        let map = GridMap::new(8, 8);
+        let units = vec![
+            knight(1, Faction::Enemy, 2, 2),
+            knight(3, Faction::Player, 1, 2),
+            knight(2, Faction::Player, 3, 2),
+            knight(4, Faction::Player, 2, 3),
+        ];
+        let board = board(&map, AiLevel::Normal, units, Vec::new(), 0);
+
+        assert_eq!(next(&board), attack(1, 2));
+    }
+
+    #[test]
+    fn the_side_recruits_before_its_units_act() {
+        let map = GridMap::new(8, 8);
+        let site = GridPosition::new(6, 6);
+        let fort = Structure {
+            kind: StructureKind::Fort,
+            owner: Some(Faction::Enemy),
+            home: Some(Faction::Enemy),
+            capture: None,
+        };
+        let units = vec![
+            knight(1, Faction::Enemy, 2, 2),
+            knight(2, Faction::Player, 3, 2),
+        ];
+        let board = board(&map, AiLevel::Normal, units, vec![(site, fort)], 1000);
+
+        assert!(matches!(
+            next(&board),
+            GameCommand::Recruit { faction: Faction::Enemy, at, .. } if at == site
+        ));
+
+        // Once a recruit is turned down the units get on with the turn.
+        let mut plans = AiPlans::default();
+        plans.refuse(Faction::Enemy, next(&board));
+        assert_eq!(
+            board.next_command(&mut plans, &mut PathCache::default()),
+            attack(1, 2)
+        );
+    }
+
+    #[test]
+    fn a_unit_turned_down_sits_out_the_rest_of_the_turn() {
+        let map = GridMap::new(8, 8);
+        let units = vec![
+            knight(1, Faction::Enemy, 2, 2),
+            knight(2, Faction::Player, 3, 2),
+        ];
+        let mut board = board(&map, AiLevel::Normal, units, Vec::new(), 0);
+        let mut plans = AiPlans::default();
+        let mut paths = PathCache::default();
+        let command = board.next_command(&mut plans, &mut paths);
+
+        plans.refuse(Faction::Enemy, command);
+        board.benched.clone_from(&plans.benched);
+
+        assert_eq!(
+            board.next_command(&mut plans, &mut paths),
+            GameCommand::EndTurn
+        );
+    }
+
+    #[test]
+    fn a_held_unit_fights_what_is_in_reach_but_never_moves() {
+        let map = GridMap::new(8, 8);
+        let held = ordered(knight(1, Faction::Enemy, 2, 2), AiOrder::Hold);
+        assert!(held.may_stand(held.pos));
+        assert!(!held.may_stand(GridPosition::new(2, 3)));
+
+        let in_reach = vec![held.clone(), knight(2, Faction::Player, 3, 2)];
+        let fighting = board(&map, AiLevel::Normal, in_reach, Vec::new(), 0);
+        assert_eq!(next(&fighting), attack(1, 2));
+
+        let far_off = vec![held, knight(2, Faction::Player, 6, 6)];
+        let waiting = board(&map, AiLevel::Normal, far_off, Vec::new(), 0);
+        assert_eq!(next(&waiting), GameCommand::Wait { unit: UnitId(1) });
+    }
+
+    #[test]
+    fn a_guarding_unit_never_ends_a_move_outside_its_radius() {
+        let map = GridMap::new(8, 8);
+        let post = GridPosition::new(1, 1);
+        let guard = ordered(
+            knight(1, Faction::Enemy, 1, 1),
+            AiOrder::Guard {
+                at: post,
+                radius: 1,
+            },
+        );
+        assert!(guard.may_stand(GridPosition::new(2, 1)));
+        assert!(!guard.may_stand(GridPosition::new(3, 1)));
+
+        let units = vec![guard, knight(2, Faction::Player, 6, 6)];
+        let board = board(&map, AiLevel::Normal, units, Vec::new(), 0);
+        let GameCommand::Move { to, .. } = next(&board) else {
+            panic!("expected the guard to close in");
+        };
+        assert!(to.distance(post) <= 1);
+        // A move planned before the order came in no longer holds.
+        assert!(!board.still_valid(&GameCommand::Move {
+            unit: UnitId(1),
+            to: GridPosition::new(3, 1),
+        }));
+    }
+
+    #[test]
+    fn an_advancing_unit_heads_for_its_spot_then_for_the_enemy() {
+        let map = GridMap::new(8, 8);
+        let spot = GridPosition::new(4, 0);
+
+        let units = vec![
+            ordered(knight(1, Faction::Enemy, 4, 4), AiOrder::Advance(spot)),
+            knight(2, Faction::Player, 4, 7),
+        ];
+        let setting_out = board(&map, AiLevel::Normal, units, Vec::new(), 0);
+        let GameCommand::Move { to, .. } = next(&setting_out) else {
+            panic!("expected the unit to advance");
+        };
+        assert!(to.distance(spot) < 4);
+
+        let units = vec![
+            ordered(knight(1, Faction::Enemy, 4, 0), AiOrder::Advance(spot)),
+            knight(2, Faction::Player, 4, 7),
+        ];
+        let arrived = board(&map, AiLevel::Normal, units, Vec::new(), 0);
+        let GameCommand::Move { to, .. } = next(&arrived) else {
+            panic!("expected the unit to carry on at the enemy");
+        };
+        assert!(to.y > 0);
+    }
+
+    #[test]
+    fn an_idle_unit_only_waits() {
+        let map = GridMap::new(8, 8);
+        let units = vec![
+            ordered(knight(1, Faction::Enemy, 2, 2), AiOrder::Idle),
+            knight(2, Faction::Player, 3, 2),
+        ];
+        let board = board(&map, AiLevel::Normal, units, Vec::new(), 0);
+        assert_eq!(next(&board), GameCommand::Wait { unit: UnitId(1) });
+
+        // Even a plan made before the order came in is dropped.
+        let mut plans = AiPlans::default();
+        plans.commands.insert(UnitId(1), attack(1, 2));
+        assert_eq!(
+            board.next_command(&mut plans, &mut PathCache::default()),
+            GameCommand::Wait { unit: UnitId(1) }
+        );
+    }
+
+    #[test]
+    fn plans_come_out_the_same_every_run() {
+        let map = GridMap::new(12, 12);
+        let units: Vec<AiUnit> = (0..6)
+            .map(|i| knight(i + 1, Faction::Enemy, 1 + i as i32, 1))
+            .chain((0..4).map(|i| knight(i + 10, Faction::Player, 2 + 2 * i as i32, 10)))
+            .collect();
+        let plan = || {
+            let board = board(&map, AiLevel::Normal, units.clone(), Vec::new(), 0);
+            let mut paths = PathCache::default();
+            board.chart(&mut paths);
+            board.plan_all(&paths)
+        };
+
+        let first = plan();
+        assert_eq!(first.len(), 6);
+        for _ in 0..5 {
+            assert_eq!(plan(), first);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Unit animation: walking between tiles and cycling sprite-sheet frames.
+//!
+//! The executor moves units instantly; their sprites catch up with a short
+//! [`MoveTween`]. Each unit's [`UnitAnimation`] is walking while a tween
+//! runs, attacking briefly after the battle log records it swinging, and
+//! idle otherwise. Frames only show when the unit sheet is loaded. Fallen
+//! units fade and shrink away before they are despawned.
+
+use std::ops::Range;
+
+use bevy::prelude::*;
+
+use crate::battle_log::{BattleEvent, BattleLog};
+use crate::class::UnitClass;
+use crate::constants::*;
+use crate::grid::GridPosition;
+use crate::sprites::SpriteAtlases;
+use crate::unit::{Unit, UnitId};
+
+/// Seconds a unit takes to walk to its new tile.
+const WALK_SECONDS: f32 = 0.2;
+/// Seconds each animation frame stays up.
+const FRAME_SECONDS: f32 = 0.15;
+/// Seconds the attack animation plays for.
+const ATTACK_SECONDS: f32 = 0.4;
+/// Seconds a fallen unit takes to fade away.
+const DEATH_SECONDS: f32 = 0.6;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnimationState {
+    #[default]
+    Idle,
+    Walking,
+    Attacking,
+}
+
+impl AnimationState {
+    /// Columns of a class's row in the unit sheet that this state cycles
+    /// through.
+    pub fn frames(self) -> Range<usize> {
+        match self {
+            AnimationState::Idle => 0..2,
+            AnimationState::Walking => 2..6,
+            AnimationState::Attacking => 6..8,
+        }
+    }
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct UnitAnimation {
+    pub state: AnimationState,
+    frame: usize,
+    timer: Timer,
+    /// Time left of an attack in progress.
+    attack: Option<Timer>,
+}
+
+impl Default for UnitAnimation {
+    fn default() -> Self {
+        Self {
+            state: AnimationState::Idle,
+            frame: 0,
+            timer: Timer::from_seconds(FRAME_SECONDS, TimerMode::Repeating),
+            attack: None,
+        }
+    }
+}
+
+/// A fallen unit on its way out. The executor takes away its [`Unit`]
+/// marker when it dies, so nothing in the battle sees it any more; only the
+/// sprite lingers until the fade is done.
+#[derive(Component, Clone, Debug)]
+pub struct Dying(Timer);
+
+impl Default for Dying {
+    fn default() -> Self {
+        Self(Timer::from_seconds(DEATH_SECONDS, TimerMode::Once))
+    }
+}
+
+/// A unit sprite sliding from where it was drawn to its current tile.
+#[derive(Component, Clone, Debug)]
+pub struct MoveTween {
+    from: Vec3,
+    to: Vec3,
+    timer: Timer,
+}
+
+/// Starts a walk to the new tile whenever a unit's grid position changes.
+pub fn sync_unit_transforms(
+    mut commands: Commands,
+    units: Query<(Entity, &GridPosition, &Transform), (With<Unit>, Changed<GridPosition>)>,
+) {
+    for (entity, pos, transform) in &units {
+        let to = pos.to_world(UNIT_Z);
+        if transform.translation != to {
+            commands.entity(entity).insert(MoveTween {
+                from: transform.translation,
+                to,
+                timer: Timer::from_seconds(WALK_SECONDS, TimerMode::Once),
+            });
+        }
+    }
+}
+
+/// Slides walking units along, finishing the walk when the tween runs out.
+pub fn tween_units_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut units: Query<(Entity, &mut MoveTween, &mut Transform), With<Unit>>,
+) {
+    for (entity, mut tween, mut transform) in &mut units {
+        tween.timer.tick(time.delta());
+        transform.translation = tween.from.lerp(tween.to, tween.timer.fraction());
+        if tween.timer.is_finished() {
+            commands.entity(entity).remove::<MoveTween>();
+        }
+    }
+}
+
+/// Plays the attack animation for every attacker the battle log has
+/// recorded since the last frame.
+pub fn start_attack_animations_system(
+    mut seen: Local<usize>,
+    history: Res<BattleLog>,
+    mut units: Query<(&UnitId, &mut UnitAnimation), With<Unit>>,
+) {
+    for (_, event) in history.read_new(&mut seen) {
+        let (BattleEvent::Attacked { attacker, .. } | BattleEvent::Missed { attacker, .. }) = event
+        else {
+            continue;
+        };
+        if let Some((_, mut animation)) = units.iter_mut().find(|(id, _)| *id == attacker) {
+            animation.attack = Some(Timer::from_seconds(ATTACK_SECONDS, TimerMode::Once));
+        }
+    }
+}
+
+/// Picks each unit's animation state and steps its frames, drawing them
+/// from the unit sheet when there is one.
+pub fn animate_units_system(
+    time: Res<Time>,
+    atlases: Res<SpriteAtlases>,
+    mut units: Query<(&UnitClass, &mut UnitAnimation, Has<MoveTween>, &mut Sprite), With<Unit>>,
+) {
+    for (&class, mut animation, walking, mut sprite) in &mut units {
+        let animation = &mut *animation;
+        if let Some(attack) = &mut animation.attack {
+            attack.tick(time.delta());
+            if attack.is_finished() {
+                animation.attack = None;
+            }
+        }
+        let state = if animation.attack.is_some() {
+            AnimationState::Attacking
+        } else if walking {
+            AnimationState::Walking
+        } else {
+            AnimationState::Idle
+        };
+        let frames = state.frames();
+        if state != animation.state {
+            animation.state = state;
+            animation.frame = frames.start;
+            animation.timer.reset();
+        } else if animation.timer.tick(time.delta()).just_finished() {
+            animation.frame += 1;
+            if !frames.contains(&animation.frame) {
+                animation.frame = frames.start;
+            }
+        }
+
+        if let Some((atlas, frame)) = atlases.unit(class, animation.frame) {
+            atlas.apply(&mut sprite, frame);
+        }
+    }
+}
+
+/// Fades and shrinks fallen units, despawning them once the fade is done.
+pub fn animate_deaths_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut dying: Query<(Entity, &mut Dying, &mut Sprite, &mut Transform)>,
+) {
+    for (entity, mut dying, mut sprite, mut transform) in &mut dying {
+        dying.0.tick(time.delta());
+        let left = 1.0 - dying.0.fraction();
+        sprite.color = sprite.color.with_alpha(left);
+        transform.scale = Vec3::splat(left);
+        if dying.0.is_finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,425 @@
+//! Written announcements of what happens in battle, for players who can't
+//! easily follow it on the board.
+//!
+//! With announcements on (A on the main menu), selecting a unit, resting
+//! the cursor on a tile, and every event the [`BattleLog`] records that the
+//! side on screen can see are announced in a log at the bottom of the
+//! screen. In screen reader mode the log is also a live region, so the
+//! system's screen reader reads each announcement out as it arrives.
+//!
+//! Any system can announce something by writing an [`Announcement`].
+
+use std::collections::VecDeque;
+use std::fmt::{Debug, Write};
+
+use accesskit::{Live, Node as AccessNode, Role};
+use bevy::a11y::AccessibilityNode;
+use bevy::prelude::*;
+
+use crate::battle_log::{BattleEvent, BattleLog};
+use crate::chest::Loot;
+use crate::class::UnitClass;
+use crate::constants::{ANNOUNCEMENT_HISTORY, ANNOUNCE_TILE_SECONDS};
+use crate::facing::Flank;
+use crate::fire::Fires;
+use crate::grid::{tile_info, GridMap, GridPosition};
+use crate::hud::{HudEvent, HudLabel, HudSources};
+use crate::input::GridCursor;
+use crate::locale::Locale;
+use crate::seats::Viewer;
+use crate::selection::SelectedUnit;
+use crate::settings::{AnnouncementMode, Settings};
+use crate::structure::Structure;
+use crate::tiles::TileRegistry;
+use crate::trap::TrapKind;
+use crate::turn::TimeOfDay;
+use crate::unit::{Faction, Stats, Unit, UnitId, UnitIndex};
+use crate::vision::FactionVision;
+
+/// A line to announce.
+#[derive(Message, Clone, Debug, PartialEq, Eq)]
+pub struct Announcement(pub String);
+
+/// The latest announcements, oldest first. Empty while announcements are
+/// off.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnnouncementLog(pub VecDeque<String>);
+
+/// The announcement log on screen, and the live region screen readers
+/// follow.
+#[derive(Component)]
+pub struct AnnouncementPanel;
+
+type UnitInfo<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static UnitId,
+        &'static Faction,
+        &'static UnitClass,
+        &'static Stats,
+        &'static GridPosition,
+    ),
+    With<Unit>,
+>;
+
+/// A unit as announcements name it, e.g. `"Enemy Archer (unit 5), 8/10 HP"`.
+fn describe_unit(
+    id: UnitId,
+    faction: Faction,
+    class: UnitClass,
+    stats: &Stats,
+    locale: &Locale,
+) -> String {
+    let name = |value: &dyn Debug| locale.tr(&format!("{value:?}")).to_string();
+    locale.fill(
+        "{} {} (unit {}), {}/{} HP",
+        &[
+            &name(&faction),
+            &name(&class),
+            &id.0,
+            &stats.hp,
+            &stats.max_hp,
+        ],
+    )
+}
+
+/// An event as announcements tell it: the battle log's line for it, in the
+/// player's language.
+fn describe_event(event: &BattleEvent, locale: &Locale) -> String {
+    let name = |value: &dyn Debug| locale.tr(&format!("{value:?}")).to_string();
+    match event {
+        BattleEvent::Moved { unit, from, to } => locale.fill(
+            "Unit {} moved from ({}, {}) to ({}, {})",
+            &[&unit.0, &from.x, &from.y, &to.x, &to.y],
+        ),
+        BattleEvent::Attacked {
+            attacker,
+            target,
+            roll,
+            damage,
+            hp_left,
+            flank,
+        } => locale.fill(
+            match flank {
+                Flank::Front => "Unit {} attacked unit {} (roll +{}) for {} damage, {} HP left",
+                Flank::Side => {
+                    "Unit {} attacked unit {} from the side (roll +{}) for {} damage, {} HP left"
+                }
+                Flank::Rear => {
+                    "Unit {} attacked unit {} from behind (roll +{}) for {} damage, {} HP left"
+                }
+            },
+            &[&attacker.0, &target.0, roll, damage, hp_left],
+        ),
+        BattleEvent::Missed { attacker, target } => locale.fill(
+            "Unit {} attacked unit {} but missed",
+            &[&attacker.0, &target.0],
+        ),
+        BattleEvent::Mended {
+            healer,
+            target,
+            amount,
+            hp,
+        } => locale.fill(
+            "Unit {} healed unit {} for {} HP ({} HP)",
+            &[&healer.0, &target.0, amount, hp],
+        ),
+        BattleEvent::ChestOpened { unit, loot } => match loot {
+            Loot::Item(item) => locale.fill("Unit {} found {} in a chest", &[&unit.0, &item.0]),
+            Loot::Gold(amount) => {
+                locale.fill("Unit {} found {} gold in a chest", &[&unit.0, amount])
+            }
+        },
+        BattleEvent::DoorToggled { unit, at, open } => locale.fill(
+            if *open {
+                "Unit {} opened the door at ({}, {})"
+            } else {
+                "Unit {} closed the door at ({}, {})"
+            },
+            &[&unit.0, &at.x, &at.y],
+        ),
+        BattleEvent::BridgeToggled { unit, at, lowered } => locale.fill(
+            if *lowered {
+                "Unit {} lowered the bridge at ({}, {})"
+            } else {
+                "Unit {} raised the bridge at ({}, {})"
+            },
+            &[&unit.0, &at.x, &at.y],
+        ),
+        BattleEvent::Ignited { at } => locale.fill("Fire broke out at ({}, {})", &[&at.x, &at.y]),
+        BattleEvent::Burned { unit, damage, hp } => locale.fill(
+            "Unit {} took {} fire damage ({} HP)",
+            &[&unit.0, damage, hp],
+        ),
+        BattleEvent::TrapSprung { unit, kind, hp } => match kind {
+            TrapKind::Spikes(_) => {
+                locale.fill("Unit {} stepped on a spike trap ({} HP)", &[&unit.0, hp])
+            }
+            TrapKind::Snare => locale.fill("Unit {} was caught in a snare", &[&unit.0]),
+            TrapKind::Alarm => locale.fill("Unit {} set off an alarm", &[&unit.0]),
+        },
+        BattleEvent::TrapSpotted { unit, at } => locale.fill(
+            "Unit {} spotted a trap at ({}, {})",
+            &[&unit.0, &at.x, &at.y],
+        ),
+        BattleEvent::PickedUp { unit, target } => {
+            locale.fill("Unit {} picked up unit {}", &[&unit.0, &target.0])
+        }
+        BattleEvent::DroppedOff { unit, target, at } => locale.fill(
+            "Unit {} dropped off unit {} at ({}, {})",
+            &[&unit.0, &target.0, &at.x, &at.y],
+        ),
+        BattleEvent::Recruited {
+            unit,
+            faction,
+            class,
+        } => locale.fill(
+            "{} recruited a {} as unit {}",
+            &[&name(faction), &name(class), &unit.0],
+        ),
+        BattleEvent::IncomeCollected { faction, amount } => {
+            locale.fill("{} collected {} gold", &[&name(faction), amount])
+        }
+        BattleEvent::Capturing {
+            unit,
+            progress,
+            needed,
+        } => locale.fill("Unit {} is capturing ({}/{})", &[&unit.0, progress, needed]),
+        BattleEvent::Captured { unit, structure } => {
+            locale.fill("Unit {} captured a {}", &[&unit.0, &name(structure)])
+        }
+        BattleEvent::Waited { unit } => locale.fill("Unit {} waited", &[&unit.0]),
+        BattleEvent::Hid { unit } => locale.fill("Unit {} slipped out of sight", &[&unit.0]),
+        BattleEvent::Revealed { unit } => locale.fill("Unit {} was revealed", &[&unit.0]),
+        BattleEvent::Died { unit } => locale.fill("Unit {} was defeated", &[&unit.0]),
+        BattleEvent::LeveledUp { unit, level } => {
+            locale.fill("Unit {} reached level {}", &[&unit.0, level])
+        }
+        BattleEvent::Promoted { unit, class } => {
+            locale.fill("Unit {} was promoted to {}", &[&unit.0, &name(class)])
+        }
+        BattleEvent::ItemDiscarded { unit, item } => {
+            locale.fill("Unit {} discarded {}", &[&unit.0, &item.0])
+        }
+        BattleEvent::ItemUsed { unit, item } => locale.fill("Unit {} used {}", &[&unit.0, &item.0]),
+        BattleEvent::Healed { unit, amount, hp } => {
+            locale.fill("Unit {} recovered {} HP ({} HP)", &[&unit.0, amount, hp])
+        }
+        BattleEvent::StatusInflicted { unit, status } => locale.fill(
+            "Unit {} is afflicted with {}",
+            &[&unit.0, &locale.tr(status.label())],
+        ),
+        BattleEvent::Poisoned { unit, damage, hp } => locale.fill(
+            "Unit {} took {} poison damage ({} HP)",
+            &[&unit.0, damage, hp],
+        ),
+        BattleEvent::Stunned { unit } => locale.fill("Unit {} is stunned", &[&unit.0]),
+        BattleEvent::WeaponBroke { unit, item } => {
+            locale.fill("Unit {}'s {} broke", &[&unit.0, &item.0])
+        }
+        BattleEvent::Equipped { unit, item } => {
+            locale.fill("Unit {} equipped {}", &[&unit.0, &item.0])
+        }
+        BattleEvent::Unequipped { unit, item } => {
+            locale.fill("Unit {} unequipped {}", &[&unit.0, &item.0])
+        }
+        BattleEvent::TurnStarted { faction, number } => locale.fill(
+            "Turn {} ({}): {} phase begins",
+            &[number, &name(&TimeOfDay::of_turn(*number)), &name(faction)],
+        ),
+        BattleEvent::AbilityUsed { unit, ability, at } => locale.fill(
+            "Unit {} used {} at ({}, {})",
+            &[&unit.0, &ability.0, &at.x, &at.y],
+        ),
+        BattleEvent::Struck { unit, damage, hp } => {
+            locale.fill("Unit {} took {} damage ({} HP)", &[&unit.0, damage, hp])
+        }
+        BattleEvent::Pushed { unit, from, to } => locale.fill(
+            "Unit {} was pushed from ({}, {}) to ({}, {})",
+            &[&unit.0, &from.x, &from.y, &to.x, &to.y],
+        ),
+        BattleEvent::TerrainChanged { at, terrain } => locale.fill(
+            "The ground at ({}, {}) became {}",
+            &[&at.x, &at.y, &locale.tr(terrain)],
+        ),
+        BattleEvent::Arrived {
+            unit,
+            faction,
+            class,
+        } => locale.fill(
+            "A {} arrived for {} as unit {}",
+            &[&name(class), &name(faction), &unit.0],
+        ),
+        BattleEvent::Said { .. } => event.to_string(),
+        BattleEvent::Rewarded { faction, amount } => {
+            locale.fill("{} was rewarded {} gold", &[&name(faction), amount])
+        }
+        BattleEvent::BattleEnded { winner } => {
+            locale.fill("Battle over, {} wins", &[&name(winner)])
+        }
+    }
+}
+
+/// Announces each event the battle log records, unless it is the doing of
+/// a unit the side on screen can't see.
+pub fn announce_battle_events_system(
+    mut seen: Local<usize>,
+    history: Res<BattleLog>,
+    vision: Res<FactionVision>,
+    viewer: Res<Viewer>,
+    units: Query<(Entity, &UnitId, &Faction)>,
+    locale: Res<Locale>,
+    mut announcements: MessageWriter<Announcement>,
+) {
+    for (_, event) in history.read_new(&mut seen) {
+        let hidden = event.actor().is_some_and(|actor| {
+            units.iter().any(|(entity, id, faction)| {
+                *id == actor && !vision.can_see(viewer.0, entity, *faction)
+            })
+        });
+        if !hidden {
+            announcements.write(Announcement(describe_event(event, &locale)));
+        }
+    }
+}
+
+/// Announces the unit the player selects.
+pub fn announce_selection_system(
+    selected: Res<SelectedUnit>,
+    units: UnitInfo,
+    locale: Res<Locale>,
+    mut announcements: MessageWriter<Announcement>,
+) {
+    let Some(Ok((_, &id, &faction, &class, stats, pos))) = selected.0.map(|unit| units.get(unit))
+    else {
+        return;
+    };
+    announcements.write(Announcement(locale.fill(
+        "Selected {} at ({}, {})",
+        &[
+            &describe_unit(id, faction, class, stats, &locale),
+            &pos.x,
+            &pos.y,
+        ],
+    )));
+}
+
+/// Announces what is on the tile under the cursor once it has rested there
+/// a moment: the ground, and the unit standing on it if the side on screen
+/// can see it.
+pub fn announce_tile_system(
+    time: Res<Time>,
+    cursor: Res<GridCursor>,
+    mut resting: Local<Option<(GridPosition, Timer)>>,
+    (map, tiles, fires): (Res<GridMap>, Res<TileRegistry>, Res<Fires>),
+    structures: Query<(&GridPosition, &Structure)>,
+    (index, vision, viewer): (Res<UnitIndex>, Res<FactionVision>, Res<Viewer>),
+    units: UnitInfo,
+    locale: Res<Locale>,
+    mut announcements: MessageWriter<Announcement>,
+) {
+    if cursor.is_changed() {
+        *resting = cursor.0.map(|pos| {
+            (
+                pos,
+                Timer::from_seconds(ANNOUNCE_TILE_SECONDS, TimerMode::Once),
+            )
+        });
+    }
+    let Some((pos, timer)) = &mut *resting else {
+        return;
+    };
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Some(info) = tile_info(&map, &tiles, &fires, &structures, &locale, *pos) else {
+        return;
+    };
+    let mut line = format!("({}, {}): {info}", pos.x, pos.y);
+    let standing = index.at(*pos).and_then(|entity| units.get(entity).ok());
+    if let Some((entity, &id, &faction, &class, stats, _)) = standing {
+        if vision.can_see(viewer.0, entity, faction) {
+            let _ = write!(
+                line,
+                ", {}",
+                describe_unit(id, faction, class, stats, &locale)
+            );
+        }
+    }
+    announcements.write(Announcement(line));
+}
+
+/// Adds new announcements to the log, keeping the latest few, and passes
+/// the newest to the screen reader when it is following.
+pub fn collect_announcements_system(
+    settings: Res<Settings>,
+    mut incoming: MessageReader<Announcement>,
+    mut log: ResMut<AnnouncementLog>,
+    mut panel: Single<&mut AccessibilityNode, With<AnnouncementPanel>>,
+) {
+    let mode = settings.announcements;
+    if settings.is_changed() {
+        panel.set_live(if mode == AnnouncementMode::ScreenReader {
+            Live::Polite
+        } else {
+            Live::Off
+        });
+    }
+    if mode == AnnouncementMode::Off {
+        incoming.clear();
+        if !log.0.is_empty() {
+            log.0.clear();
+        }
+        return;
+    }
+    let mut newest = None;
+    for Announcement(line) in incoming.read() {
+        log.0.push_back(line.clone());
+        newest = Some(line);
+    }
+    while log.0.len() > ANNOUNCEMENT_HISTORY {
+        log.0.pop_front();
+    }
+    if let Some(line) = newest {
+        panel.set_label(line.as_str());
+    }
+}
+
+pub fn clear_announcements(mut log: ResMut<AnnouncementLog>) {
+    log.0.clear();
+}
+
+pub fn setup_announcement_ui(mut commands: Commands) {
+    let mut region = AccessNode::new(Role::Log);
+    region.set_live(Live::Off);
+    commands.spawn((
+        AnnouncementPanel,
+        HudLabel {
+            events: &[HudEvent::Announcements],
+            write: write_announcements,
+        },
+        AccessibilityNode(region),
+        Text::new(""),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Percent(30.0),
+            right: Val::Percent(30.0),
+            ..default()
+        },
+    ));
+}
+
+/// The latest announcements, a line each, newest at the bottom.
+fn write_announcements(hud: &HudSources, text: &mut String, _color: &mut Color) {
+    for line in &hud.announcements.0 {
+        let _ = writeln!(text, "{line}");
+    }
+}
@@ -0,0 +1,124 @@
+//! Sound effects for the core actions: selecting a unit, its steps, and the
+//! battle's [`Cue`]s (attacks, hits, misses, falls, new turns, and the
+//! rest).
+//!
+//! Sounds are optional, like art: each effect is read from
+//! `assets/sounds/<name>.ogg`, or a mod's copy of it, and an effect with no
+//! file stays silent. Handles are loaded once at startup into
+//! [`SoundEffects`]. Battle sounds are played for the same cues sound
+//! indicators flash up for, so the two always agree, and nothing a unit
+//! the side on screen can't see does makes a sound.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::animation::MoveTween;
+use crate::cues::Cue;
+use crate::mods::resolve_asset;
+use crate::selection::SelectedUnit;
+use crate::unit::Unit;
+
+/// A sound effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sfx {
+    Select,
+    Step,
+    Cue(Cue),
+}
+
+impl Sfx {
+    pub const ALL: [Sfx; 11] = [
+        Sfx::Select,
+        Sfx::Step,
+        Sfx::Cue(Cue::Attack),
+        Sfx::Cue(Cue::Hit),
+        Sfx::Cue(Cue::Miss),
+        Sfx::Cue(Cue::Death),
+        Sfx::Cue(Cue::Turn),
+        Sfx::Cue(Cue::Trap),
+        Sfx::Cue(Cue::Heal),
+        Sfx::Cue(Cue::LevelUp),
+        Sfx::Cue(Cue::Capture),
+    ];
+
+    /// Where the effect is read from, under `assets/`.
+    fn path(self) -> &'static str {
+        match self {
+            Sfx::Select => "sounds/select.ogg",
+            Sfx::Step => "sounds/step.ogg",
+            Sfx::Cue(Cue::Attack) => "sounds/attack.ogg",
+            Sfx::Cue(Cue::Hit) => "sounds/hit.ogg",
+            Sfx::Cue(Cue::Miss) => "sounds/miss.ogg",
+            Sfx::Cue(Cue::Death) => "sounds/death.ogg",
+            Sfx::Cue(Cue::Turn) => "sounds/turn.ogg",
+            Sfx::Cue(Cue::Trap) => "sounds/trap.ogg",
+            Sfx::Cue(Cue::Heal) => "sounds/heal.ogg",
+            Sfx::Cue(Cue::LevelUp) => "sounds/level_up.ogg",
+            Sfx::Cue(Cue::Capture) => "sounds/capture.ogg",
+        }
+    }
+}
+
+/// Every sound effect that has a file.
+#[derive(Resource, Default)]
+pub struct SoundEffects(HashMap<Sfx, Handle<AudioSource>>);
+
+impl SoundEffects {
+    /// Plays `sfx` once, if it has a file.
+    pub fn play(&self, commands: &mut Commands, sfx: Sfx) {
+        if let Some(sound) = self.0.get(&sfx) {
+            commands.spawn((AudioPlayer(sound.clone()), PlaybackSettings::DESPAWN));
+        }
+    }
+}
+
+pub fn load_sound_effects(asset_server: Res<AssetServer>, mut sounds: ResMut<SoundEffects>) {
+    for sfx in Sfx::ALL {
+        let (source, file) = resolve_asset(sfx.path());
+        if file.exists() {
+            sounds.0.insert(sfx, asset_server.load(source));
+        }
+    }
+    if sounds.0.is_empty() {
+        info!("No sound effects in assets/sounds, playing none");
+    }
+}
+
+/// Plays each battle cue's sound.
+pub fn play_cue_sounds_system(
+    mut commands: Commands,
+    sounds: Res<SoundEffects>,
+    mut cues: MessageReader<Cue>,
+) {
+    for &cue in cues.read() {
+        sounds.play(&mut commands, Sfx::Cue(cue));
+    }
+}
+
+/// Plays the selection sound when a unit is picked out.
+pub fn play_selection_sound_system(
+    mut commands: Commands,
+    sounds: Res<SoundEffects>,
+    selected: Res<SelectedUnit>,
+    mut last: Local<Option<Entity>>,
+) {
+    if selected.0 != *last && selected.0.is_some() {
+        sounds.play(&mut commands, Sfx::Select);
+    }
+    *last = selected.0;
+}
+
+/// Plays a step as each unit on screen sets off for a new tile.
+pub fn play_step_sounds_system(
+    mut commands: Commands,
+    sounds: Res<SoundEffects>,
+    walkers: Query<&Visibility, (With<Unit>, Added<MoveTween>)>,
+) {
+    if walkers
+        .iter()
+        .any(|visibility| *visibility != Visibility::Hidden)
+    {
+        sounds.play(&mut commands, Sfx::Step);
+    }
+}
@@ -0,0 +1,480 @@
+//! Human-readable history of everything that happened in a battle.
+//!
+//! The command executor records a [`BattleEvent`] for every state change.
+//! The history can be exported to a text file (F8, or automatically when the
+//! battle ends) to attach to bug reports and balance feedback.
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+
+use crate::ability::AbilityId;
+#[cfg(feature = "render")]
+use crate::bindings::{ActionInput, InputAction};
+use crate::chest::Loot;
+use crate::class::UnitClass;
+use crate::facing::Flank;
+use crate::grid::GridPosition;
+use crate::items::ItemId;
+use crate::status::StatusKind;
+use crate::structure::StructureKind;
+use crate::trap::TrapKind;
+#[cfg(feature = "render")]
+use crate::turn::BattleOutcome;
+use crate::turn::TimeOfDay;
+use crate::unit::{Faction, UnitId};
+
+const BATTLE_LOG_DIR: &str = "battle_logs";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BattleEvent {
+    Moved {
+        unit: UnitId,
+        from: GridPosition,
+        to: GridPosition,
+    },
+    Attacked {
+        attacker: UnitId,
+        target: UnitId,
+        roll: u32,
+        damage: u32,
+        hp_left: u32,
+        flank: Flank,
+    },
+    Missed {
+        attacker: UnitId,
+        target: UnitId,
+    },
+    Mended {
+        healer: UnitId,
+        target: UnitId,
+        amount: u32,
+        hp: u32,
+    },
+    ChestOpened {
+        unit: UnitId,
+        loot: Loot,
+    },
+    DoorToggled {
+        unit: UnitId,
+        at: GridPosition,
+        open: bool,
+    },
+    BridgeToggled {
+        unit: UnitId,
+        at: GridPosition,
+        lowered: bool,
+    },
+    Ignited {
+        at: GridPosition,
+    },
+    Burned {
+        unit: UnitId,
+        damage: u32,
+        hp: u32,
+    },
+    TrapSprung {
+        unit: UnitId,
+        kind: TrapKind,
+        hp: u32,
+    },
+    TrapSpotted {
+        unit: UnitId,
+        at: GridPosition,
+    },
+    PickedUp {
+        unit: UnitId,
+        target: UnitId,
+    },
+    DroppedOff {
+        unit: UnitId,
+        target: UnitId,
+        at: GridPosition,
+    },
+    Recruited {
+        unit: UnitId,
+        faction: Faction,
+        class: UnitClass,
+    },
+    IncomeCollected {
+        faction: Faction,
+        amount: u32,
+    },
+    Capturing {
+        unit: UnitId,
+        progress: u32,
+        needed: u32,
+    },
+    Captured {
+        unit: UnitId,
+        structure: StructureKind,
+    },
+    Waited {
+        unit: UnitId,
+    },
+    Hid {
+        unit: UnitId,
+    },
+    Revealed {
+        unit: UnitId,
+    },
+    Died {
+        unit: UnitId,
+    },
+    LeveledUp {
+        unit: UnitId,
+        level: u32,
+    },
+    Promoted {
+        unit: UnitId,
+        class: UnitClass,
+    },
+    ItemDiscarded {
+        unit: UnitId,
+        item: ItemId,
+    },
+    ItemUsed {
+        unit: UnitId,
+        item: ItemId,
+    },
+    Healed {
+        unit: UnitId,
+        amount: u32,
+        hp: u32,
+    },
+    Equipped {
+        unit: UnitId,
+        item: ItemId,
+    },
+    WeaponBroke {
+        unit: UnitId,
+        item: ItemId,
+    },
+    StatusInflicted {
+        unit: UnitId,
+        status: StatusKind,
+    },
+    Poisoned {
+        unit: UnitId,
+        damage: u32,
+        hp: u32,
+    },
+    Stunned {
+        unit: UnitId,
+    },
+    Unequipped {
+        unit: UnitId,
+        item: ItemId,
+    },
+    TurnStarted {
+        faction: Faction,
+        number: u32,
+    },
+    AbilityUsed {
+        unit: UnitId,
+        ability: AbilityId,
+        at: GridPosition,
+    },
+    /// Damage dealt by an ability.
+    Struck {
+        unit: UnitId,
+        damage: u32,
+        hp: u32,
+    },
+    Pushed {
+        unit: UnitId,
+        from: GridPosition,
+        to: GridPosition,
+    },
+    TerrainChanged {
+        at: GridPosition,
+        terrain: String,
+    },
+    /// A unit a scenario script or an ability brought onto the board.
+    Arrived {
+        unit: UnitId,
+        faction: Faction,
+        class: UnitClass,
+    },
+    /// A line of a scenario script's dialogue.
+    Said {
+        speaker: String,
+        text: String,
+    },
+    /// Gold a scenario script handed out.
+    Rewarded {
+        faction: Faction,
+        amount: u32,
+    },
+    BattleEnded {
+        winner: Faction,
+    },
+}
+
+impl BattleEvent {
+    /// The unit that chose to do this, for events that are a unit's action
+    /// rather than something that happened to it.
+    pub fn actor(&self) -> Option<UnitId> {
+        match *self {
+            BattleEvent::Moved { unit, .. }
+            | BattleEvent::ChestOpened { unit, .. }
+            | BattleEvent::DoorToggled { unit, .. }
+            | BattleEvent::BridgeToggled { unit, .. }
+            | BattleEvent::PickedUp { unit, .. }
+            | BattleEvent::DroppedOff { unit, .. }
+            | BattleEvent::Capturing { unit, .. }
+            | BattleEvent::Waited { unit }
+            | BattleEvent::Hid { unit }
+            | BattleEvent::ItemUsed { unit, .. }
+            | BattleEvent::Equipped { unit, .. }
+            | BattleEvent::AbilityUsed { unit, .. } => Some(unit),
+            BattleEvent::Attacked { attacker, .. } | BattleEvent::Missed { attacker, .. } => {
+                Some(attacker)
+            }
+            BattleEvent::Mended { healer, .. } => Some(healer),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BattleEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BattleEvent::Moved { unit, from, to } => write!(
+                f,
+                "Unit {} moved from ({}, {}) to ({}, {})",
+                unit.0, from.x, from.y, to.x, to.y
+            ),
+            BattleEvent::Attacked {
+                attacker,
+                target,
+                roll,
+                damage,
+                hp_left,
+                flank,
+            } => {
+                write!(f, "Unit {} attacked unit {}", attacker.0, target.0)?;
+                match flank {
+                    Flank::Front => {}
+                    Flank::Side => write!(f, " from the side")?,
+                    Flank::Rear => write!(f, " from behind")?,
+                }
+                write!(f, " (roll +{roll}) for {damage} damage, {hp_left} HP left")
+            }
+            BattleEvent::Missed { attacker, target } => write!(
+                f,
+                "Unit {} attacked unit {} but missed",
+                attacker.0, target.0
+            ),
+            BattleEvent::Mended {
+                healer,
+                target,
+                amount,
+                hp,
+            } => write!(
+                f,
+                "Unit {} healed unit {} for {amount} HP ({hp} HP)",
+                healer.0, target.0
+            ),
+            BattleEvent::ChestOpened { unit, loot } => match loot {
+                Loot::Item(item) => write!(f, "Unit {} found {} in a chest", unit.0, item.0),
+                Loot::Gold(amount) => {
+                    write!(f, "Unit {} found {amount} gold in a chest", unit.0)
+                }
+            },
+            BattleEvent::DoorToggled { unit, at, open } => write!(
+                f,
+                "Unit {} {} the door at ({}, {})",
+                unit.0,
+                if *open { "opened" } else { "closed" },
+                at.x,
+                at.y
+            ),
+            BattleEvent::BridgeToggled { unit, at, lowered } => write!(
+                f,
+                "Unit {} {} the bridge at ({}, {})",
+                unit.0,
+                if *lowered { "lowered" } else { "raised" },
+                at.x,
+                at.y
+            ),
+            BattleEvent::Ignited { at } => write!(f, "Fire broke out at ({}, {})", at.x, at.y),
+            BattleEvent::Burned { unit, damage, hp } => {
+                write!(f, "Unit {} took {damage} fire damage ({hp} HP)", unit.0)
+            }
+            BattleEvent::TrapSprung { unit, kind, hp } => match kind {
+                TrapKind::Spikes(_) => {
+                    write!(f, "Unit {} stepped on a spike trap ({hp} HP)", unit.0)
+                }
+                TrapKind::Snare => write!(f, "Unit {} was caught in a snare", unit.0),
+                TrapKind::Alarm => write!(f, "Unit {} set off an alarm", unit.0),
+            },
+            BattleEvent::TrapSpotted { unit, at } => {
+                write!(f, "Unit {} spotted a trap at ({}, {})", unit.0, at.x, at.y)
+            }
+            BattleEvent::PickedUp { unit, target } => {
+                write!(f, "Unit {} picked up unit {}", unit.0, target.0)
+            }
+            BattleEvent::DroppedOff { unit, target, at } => write!(
+                f,
+                "Unit {} dropped off unit {} at ({}, {})",
+                unit.0, target.0, at.x, at.y
+            ),
+            BattleEvent::Recruited {
+                unit,
+                faction,
+                class,
+            } => write!(f, "{faction:?} recruited a {class:?} as unit {}", unit.0),
+            BattleEvent::IncomeCollected { faction, amount } => {
+                write!(f, "{faction:?} collected {amount} gold")
+            }
+            BattleEvent::Capturing {
+                unit,
+                progress,
+                needed,
+            } => write!(f, "Unit {} is capturing ({progress}/{needed})", unit.0),
+            BattleEvent::Captured { unit, structure } => {
+                write!(f, "Unit {} captured a {structure:?}", unit.0)
+            }
+            BattleEvent::Waited { unit } => write!(f, "Unit {} waited", unit.0),
+            BattleEvent::Hid { unit } => write!(f, "Unit {} slipped out of sight", unit.0),
+            BattleEvent::Revealed { unit } => write!(f, "Unit {} was revealed", unit.0),
+            BattleEvent::Died { unit } => write!(f, "Unit {} was defeated", unit.0),
+            BattleEvent::LeveledUp { unit, level } => {
+                write!(f, "Unit {} reached level {level}", unit.0)
+            }
+            BattleEvent::Promoted { unit, class } => {
+                write!(f, "Unit {} was promoted to {class:?}", unit.0)
+            }
+            BattleEvent::ItemDiscarded { unit, item } => {
+                write!(f, "Unit {} discarded {}", unit.0, item.0)
+            }
+            BattleEvent::ItemUsed { unit, item } => {
+                write!(f, "Unit {} used {}", unit.0, item.0)
+            }
+            BattleEvent::Healed { unit, amount, hp } => {
+                write!(f, "Unit {} recovered {amount} HP ({hp} HP)", unit.0)
+            }
+            BattleEvent::StatusInflicted { unit, status } => {
+                write!(f, "Unit {} is afflicted with {}", unit.0, status.label())
+            }
+            BattleEvent::Poisoned { unit, damage, hp } => {
+                write!(f, "Unit {} took {damage} poison damage ({hp} HP)", unit.0)
+            }
+            BattleEvent::Stunned { unit } => write!(f, "Unit {} is stunned", unit.0),
+            BattleEvent::WeaponBroke { unit, item } => {
+                write!(f, "Unit {}'s {} broke", unit.0, item.0)
+            }
+            BattleEvent::Equipped { unit, item } => {
+                write!(f, "Unit {} equipped {}", unit.0, item.0)
+            }
+            BattleEvent::Unequipped { unit, item } => {
+                write!(f, "Unit {} unequipped {}", unit.0, item.0)
+            }
+            BattleEvent::TurnStarted { faction, number } => write!(
+                f,
+                "Turn {number} ({:?}): {faction:?} phase begins",
+                TimeOfDay::of_turn(*number)
+            ),
+            BattleEvent::AbilityUsed { unit, ability, at } => write!(
+                f,
+                "Unit {} used {} at ({}, {})",
+                unit.0, ability.0, at.x, at.y
+            ),
+            BattleEvent::Struck { unit, damage, hp } => {
+                write!(f, "Unit {} took {damage} damage ({hp} HP)", unit.0)
+            }
+            BattleEvent::Pushed { unit, from, to } => write!(
+                f,
+                "Unit {} was pushed from ({}, {}) to ({}, {})",
+                unit.0, from.x, from.y, to.x, to.y
+            ),
+            BattleEvent::TerrainChanged { at, terrain } => {
+                write!(f, "The ground at ({}, {}) became {terrain}", at.x, at.y)
+            }
+            BattleEvent::Arrived {
+                unit,
+                faction,
+                class,
+            } => write!(f, "A {class:?} arrived for {faction:?} as unit {}", unit.0),
+            BattleEvent::Said { speaker, text } => write!(f, "{speaker}: {text}"),
+            BattleEvent::Rewarded { faction, amount } => {
+                write!(f, "{faction:?} was rewarded {amount} gold")
+            }
+            BattleEvent::BattleEnded { winner } => write!(f, "Battle over, {winner:?} wins"),
+        }
+    }
+}
+
+/// Every event of the current battle, tagged with the turn it happened on.
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct BattleLog {
+    pub seed: u64,
+    pub entries: Vec<(u32, BattleEvent)>,
+}
+
+impl BattleLog {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, turn: u32, event: BattleEvent) {
+        info!("{event}");
+        self.entries.push((turn, event));
+    }
+
+    /// Entries recorded since a reader last looked, moving its cursor `seen`
+    /// to the end. A log shorter than the cursor is a new battle's, so it
+    /// is read from the start.
+    pub fn read_new(&self, seen: &mut usize) -> &[(u32, BattleEvent)] {
+        if self.entries.len() < *seen {
+            *seen = 0;
+        }
+        let new = &self.entries[*seen..];
+        *seen = self.entries.len();
+        new
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Battle log (seed {})\n\n", self.seed);
+        for (turn, event) in &self.entries {
+            text.push_str(&format!("[Turn {turn:>3}] {event}\n"));
+        }
+        text
+    }
+}
+
+/// Writes the log to `battle_logs/battle-<unix time>.txt` and returns the path.
+pub fn export_battle_log(log: &BattleLog) -> Result<PathBuf, String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let path = PathBuf::from(BATTLE_LOG_DIR).join(format!("battle-{timestamp}.txt"));
+    fs::create_dir_all(BATTLE_LOG_DIR).map_err(|err| err.to_string())?;
+    fs::write(&path, log.to_text()).map_err(|err| err.to_string())?;
+    Ok(path)
+}
+
+/// Exports the battle log on F8, and automatically once the battle is decided.
+#[cfg(feature = "render")]
+pub fn export_battle_log_system(
+    input: ActionInput,
+    outcome: Res<BattleOutcome>,
+    log: Res<BattleLog>,
+) {
+    let battle_just_ended = outcome.is_changed() && outcome.0.is_some();
+    if !input.just_pressed(InputAction::ExportLog) && !battle_just_ended {
+        return;
+    }
+
+    match export_battle_log(&log) {
+        Ok(path) => info!("Exported battle log to {}", path.display()),
+        Err(err) => error!("Failed to export battle log: {err}"),
+    }
+}
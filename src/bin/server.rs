@@ -0,0 +1,104 @@
+//! The dedicated server for online battles; see [`bevy_game::server`].
+
+use std::env;
+use std::process;
+use std::time::Duration;
+
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+
+use bevy_game::command::fresh_seed;
+use bevy_game::launch::LaunchOptions;
+use bevy_game::plugins::{AiPlugin, BattlePlugin};
+use bevy_game::seats::{Controller, Seats};
+use bevy_game::server::{Server, ServerPlugin};
+
+const USAGE: &str = "\
+Usage: bevy-game-server --port <PORT> [OPTIONS]
+
+Options:
+  --port <PORT>  Wait for the two players to join on PORT
+  --seed <SEED>  Fight the battle with this seed instead of a random one
+  -h, --help     Print this help";
+
+/// How often the server steps the battle.
+const TICK: Duration = Duration::from_millis(16);
+
+fn parse(args: impl IntoIterator<Item = String>) -> Result<Option<(u16, Option<u64>)>, String> {
+    let mut port = None;
+    let mut seed = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                let value = args.next().ok_or("--port needs a value")?;
+                port = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid port: {value}"))?,
+                );
+            }
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a value")?;
+                seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid seed: {value}"))?,
+                );
+            }
+            "-h" | "--help" => return Ok(None),
+            _ => return Err(format!("unknown argument: {arg}")),
+        }
+    }
+    let port = port.ok_or("--port is required")?;
+    Ok(Some((port, seed)))
+}
+
+fn main() {
+    let (port, seed) = match parse(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            process::exit(2);
+        }
+    };
+    let seed = seed.unwrap_or_else(fresh_seed);
+
+    println!("Waiting for two players on port {port}...");
+    let server = match Server::open(port, seed) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("Couldn't seat both players: {err}");
+            process::exit(1);
+        }
+    };
+    println!("Both players have joined; playing seed {seed}");
+
+    App::new()
+        .add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(TICK)),
+            StatesPlugin,
+            LogPlugin::default(),
+            BattlePlugin,
+            AiPlugin,
+            ServerPlugin,
+        ))
+        // Straight into the battle, and out again once it is decided.
+        .insert_resource(LaunchOptions {
+            seed: Some(seed),
+            headless: true,
+            ..default()
+        })
+        .insert_resource(Seats {
+            player: Controller::Remote,
+            enemy: Controller::Remote,
+        })
+        .insert_resource(server)
+        .run();
+}
@@ -0,0 +1,564 @@
+//! Rebindable controls: which keys and buttons trigger each [`InputAction`].
+//!
+//! Every action has default bindings. The player's changes are kept in the
+//! settings file as overrides on top of them, so an action they never
+//! touched picks up new defaults. Systems ask [`ActionInput`] whether an
+//! action was pressed instead of reading keys and buttons themselves.
+
+use std::collections::BTreeMap;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Something a key, mouse button, or gamepad button can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum InputAction {
+    Select,
+    Cancel,
+    EndTurn,
+    NextUnit,
+    PreviousUnit,
+    MenuFocus,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    DragPan,
+    Items,
+    Confirm,
+    Capture,
+    Back,
+    Choice1,
+    Choice2,
+    Choice3,
+    Choice4,
+    SaveReplay,
+    PlayReplay,
+    ExportLog,
+    Suspend,
+    ReplayPlayPause,
+    ReplayStep,
+    ReplayExit,
+    UiSmaller,
+    UiLarger,
+    VolumeDown,
+    VolumeUp,
+    Diagnostics,
+    Chat,
+    CursorUp,
+    CursorDown,
+    CursorLeft,
+    CursorRight,
+    FocusNext,
+    FocusPrevious,
+    Entry1,
+    Entry2,
+    Entry3,
+    Entry4,
+    Entry5,
+    Entry6,
+    Entry7,
+    Entry8,
+    Entry9,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 47] = [
+        InputAction::Select,
+        InputAction::Cancel,
+        InputAction::EndTurn,
+        InputAction::NextUnit,
+        InputAction::PreviousUnit,
+        InputAction::MenuFocus,
+        InputAction::PanUp,
+        InputAction::PanDown,
+        InputAction::PanLeft,
+        InputAction::PanRight,
+        InputAction::DragPan,
+        InputAction::Items,
+        InputAction::Confirm,
+        InputAction::Capture,
+        InputAction::Back,
+        InputAction::Choice1,
+        InputAction::Choice2,
+        InputAction::Choice3,
+        InputAction::Choice4,
+        InputAction::SaveReplay,
+        InputAction::PlayReplay,
+        InputAction::ExportLog,
+        InputAction::Suspend,
+        InputAction::ReplayPlayPause,
+        InputAction::ReplayStep,
+        InputAction::ReplayExit,
+        InputAction::UiSmaller,
+        InputAction::UiLarger,
+        InputAction::VolumeDown,
+        InputAction::VolumeUp,
+        InputAction::Diagnostics,
+        InputAction::Chat,
+        InputAction::CursorUp,
+        InputAction::CursorDown,
+        InputAction::CursorLeft,
+        InputAction::CursorRight,
+        InputAction::FocusNext,
+        InputAction::FocusPrevious,
+        InputAction::Entry1,
+        InputAction::Entry2,
+        InputAction::Entry3,
+        InputAction::Entry4,
+        InputAction::Entry5,
+        InputAction::Entry6,
+        InputAction::Entry7,
+        InputAction::Entry8,
+        InputAction::Entry9,
+    ];
+
+    /// The numbered picks in the promotion and recruit panels, in order.
+    pub const CHOICES: [InputAction; 4] = [
+        InputAction::Choice1,
+        InputAction::Choice2,
+        InputAction::Choice3,
+        InputAction::Choice4,
+    ];
+
+    /// The action menu's entries without an action of their own, in the
+    /// order they are listed.
+    pub const ENTRIES: [InputAction; 9] = [
+        InputAction::Entry1,
+        InputAction::Entry2,
+        InputAction::Entry3,
+        InputAction::Entry4,
+        InputAction::Entry5,
+        InputAction::Entry6,
+        InputAction::Entry7,
+        InputAction::Entry8,
+        InputAction::Entry9,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            InputAction::Select => "Select / confirm",
+            InputAction::Cancel => "Cancel",
+            InputAction::EndTurn => "End turn",
+            InputAction::NextUnit => "Next unit",
+            InputAction::PreviousUnit => "Previous unit",
+            InputAction::MenuFocus => "Jump to menu",
+            InputAction::PanUp => "Pan up",
+            InputAction::PanDown => "Pan down",
+            InputAction::PanLeft => "Pan left",
+            InputAction::PanRight => "Pan right",
+            InputAction::DragPan => "Drag to pan",
+            InputAction::Items => "Items",
+            InputAction::Confirm => "Confirm (wait)",
+            InputAction::Capture => "Capture",
+            InputAction::Back => "Back",
+            InputAction::Choice1 => "Choice 1",
+            InputAction::Choice2 => "Choice 2",
+            InputAction::Choice3 => "Choice 3",
+            InputAction::Choice4 => "Choice 4",
+            InputAction::SaveReplay => "Save replay",
+            InputAction::PlayReplay => "Play replay",
+            InputAction::ExportLog => "Export battle log",
+            InputAction::Suspend => "Suspend battle",
+            InputAction::ReplayPlayPause => "Replay play / pause",
+            InputAction::ReplayStep => "Replay step",
+            InputAction::ReplayExit => "Leave replay",
+            InputAction::UiSmaller => "Shrink UI",
+            InputAction::UiLarger => "Grow UI",
+            InputAction::VolumeDown => "Volume down",
+            InputAction::VolumeUp => "Volume up",
+            InputAction::Diagnostics => "Performance overlay",
+            InputAction::Chat => "Chat",
+            InputAction::CursorUp => "Cursor up",
+            InputAction::CursorDown => "Cursor down",
+            InputAction::CursorLeft => "Cursor left",
+            InputAction::CursorRight => "Cursor right",
+            InputAction::FocusNext => "Next button",
+            InputAction::FocusPrevious => "Previous button",
+            InputAction::Entry1 => "Menu entry 1",
+            InputAction::Entry2 => "Menu entry 2",
+            InputAction::Entry3 => "Menu entry 3",
+            InputAction::Entry4 => "Menu entry 4",
+            InputAction::Entry5 => "Menu entry 5",
+            InputAction::Entry6 => "Menu entry 6",
+            InputAction::Entry7 => "Menu entry 7",
+            InputAction::Entry8 => "Menu entry 8",
+            InputAction::Entry9 => "Menu entry 9",
+        }
+    }
+
+    pub fn default_bindings(self) -> Vec<Binding> {
+        use Binding::{Gamepad as Pad, Key, Mouse};
+        match self {
+            InputAction::Select => vec![
+                Mouse(MouseButton::Left),
+                Key(KeyCode::KeyF),
+                Pad(GamepadButton::South),
+            ],
+            InputAction::Cancel => vec![
+                Mouse(MouseButton::Right),
+                Key(KeyCode::Escape),
+                Pad(GamepadButton::East),
+            ],
+            InputAction::EndTurn => vec![Key(KeyCode::Enter), Pad(GamepadButton::Start)],
+            InputAction::NextUnit => vec![Key(KeyCode::KeyE), Pad(GamepadButton::RightTrigger)],
+            InputAction::PreviousUnit => vec![Key(KeyCode::KeyQ), Pad(GamepadButton::LeftTrigger)],
+            InputAction::MenuFocus => vec![Key(KeyCode::KeyM), Pad(GamepadButton::West)],
+            InputAction::PanUp => vec![Key(KeyCode::KeyW), Key(KeyCode::ArrowUp)],
+            InputAction::PanDown => vec![Key(KeyCode::KeyS), Key(KeyCode::ArrowDown)],
+            InputAction::PanLeft => vec![Key(KeyCode::KeyA), Key(KeyCode::ArrowLeft)],
+            InputAction::PanRight => vec![Key(KeyCode::KeyD), Key(KeyCode::ArrowRight)],
+            InputAction::DragPan => vec![Mouse(MouseButton::Middle)],
+            InputAction::Items => vec![Key(KeyCode::Tab)],
+            InputAction::Confirm => vec![Key(KeyCode::Space), Pad(GamepadButton::North)],
+            InputAction::Capture => vec![Key(KeyCode::KeyC)],
+            InputAction::Back => vec![Key(KeyCode::Backspace)],
+            InputAction::Choice1 => vec![Key(KeyCode::Digit1)],
+            InputAction::Choice2 => vec![Key(KeyCode::Digit2)],
+            InputAction::Choice3 => vec![Key(KeyCode::Digit3)],
+            InputAction::Choice4 => vec![Key(KeyCode::Digit4)],
+            InputAction::SaveReplay => vec![Key(KeyCode::F5)],
+            InputAction::PlayReplay => vec![Key(KeyCode::F9)],
+            InputAction::ExportLog => vec![Key(KeyCode::F8)],
+            InputAction::Suspend => vec![Key(KeyCode::F10)],
+            InputAction::ReplayPlayPause => vec![Key(KeyCode::Space)],
+            InputAction::ReplayStep => vec![Key(KeyCode::Period)],
+            InputAction::ReplayExit => vec![Key(KeyCode::Escape)],
+            InputAction::UiSmaller => vec![Key(KeyCode::Minus)],
+            InputAction::UiLarger => vec![Key(KeyCode::Equal)],
+            InputAction::VolumeDown => vec![Key(KeyCode::BracketLeft)],
+            InputAction::VolumeUp => vec![Key(KeyCode::BracketRight)],
+            InputAction::Diagnostics => vec![Key(KeyCode::F3)],
+            InputAction::Chat => vec![Key(KeyCode::KeyT)],
+            InputAction::CursorUp => vec![Key(KeyCode::KeyI)],
+            InputAction::CursorDown => vec![Key(KeyCode::KeyK)],
+            InputAction::CursorLeft => vec![Key(KeyCode::KeyJ)],
+            InputAction::CursorRight => vec![Key(KeyCode::KeyL)],
+            InputAction::FocusNext => vec![Key(KeyCode::PageDown), Pad(GamepadButton::DPadDown)],
+            InputAction::FocusPrevious => vec![Key(KeyCode::PageUp), Pad(GamepadButton::DPadUp)],
+            InputAction::Entry1 => vec![Key(KeyCode::KeyZ)],
+            InputAction::Entry2 => vec![Key(KeyCode::KeyX)],
+            InputAction::Entry3 => vec![Key(KeyCode::KeyV)],
+            InputAction::Entry4 => vec![Key(KeyCode::KeyB)],
+            InputAction::Entry5 => vec![Key(KeyCode::KeyN)],
+            InputAction::Entry6 => vec![Key(KeyCode::KeyR)],
+            InputAction::Entry7 => vec![Key(KeyCode::KeyY)],
+            InputAction::Entry8 => vec![Key(KeyCode::KeyU)],
+            InputAction::Entry9 => vec![Key(KeyCode::KeyO)],
+        }
+    }
+}
+
+/// Keys that can be bound. Bevy's key codes can't be saved directly, so
+/// bindings are written by name and read back through this list.
+const BINDABLE_KEYS: &[KeyCode] = &[
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::Space,
+    KeyCode::Enter,
+    KeyCode::Escape,
+    KeyCode::Tab,
+    KeyCode::Backspace,
+    KeyCode::Delete,
+    KeyCode::Insert,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::Backslash,
+    KeyCode::Semicolon,
+    KeyCode::Quote,
+    KeyCode::Backquote,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::NumpadEnter,
+];
+
+const BINDABLE_MOUSE_BUTTONS: &[MouseButton] = &[
+    MouseButton::Left,
+    MouseButton::Right,
+    MouseButton::Middle,
+    MouseButton::Back,
+    MouseButton::Forward,
+];
+
+const BINDABLE_GAMEPAD_BUTTONS: &[GamepadButton] = &[
+    GamepadButton::South,
+    GamepadButton::East,
+    GamepadButton::North,
+    GamepadButton::West,
+    GamepadButton::LeftTrigger,
+    GamepadButton::RightTrigger,
+    GamepadButton::LeftTrigger2,
+    GamepadButton::RightTrigger2,
+    GamepadButton::Select,
+    GamepadButton::Start,
+    GamepadButton::LeftThumb,
+    GamepadButton::RightThumb,
+    GamepadButton::DPadUp,
+    GamepadButton::DPadDown,
+    GamepadButton::DPadLeft,
+    GamepadButton::DPadRight,
+];
+
+/// A physical key or button. Saved as `"Key:KeyW"`, `"Mouse:Middle"`, or
+/// `"Pad:South"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+impl Binding {
+    /// Whether this binding can be written to and read back from the
+    /// settings file.
+    pub fn is_bindable(self) -> bool {
+        match self {
+            Binding::Key(key) => BINDABLE_KEYS.contains(&key),
+            Binding::Mouse(button) => BINDABLE_MOUSE_BUTTONS.contains(&button),
+            Binding::Gamepad(button) => BINDABLE_GAMEPAD_BUTTONS.contains(&button),
+        }
+    }
+
+    pub fn is_gamepad(self) -> bool {
+        matches!(self, Binding::Gamepad(_))
+    }
+
+    /// A short name to show in the controls menu.
+    pub fn label(self) -> String {
+        match self {
+            Binding::Key(key) => {
+                let name = format!("{key:?}");
+                match name.strip_prefix("Key").or(name.strip_prefix("Digit")) {
+                    Some(short) => short.to_string(),
+                    None => name,
+                }
+            }
+            Binding::Mouse(button) => format!("Mouse {button:?}"),
+            Binding::Gamepad(button) => format!("Pad {button:?}"),
+        }
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> Self {
+        match binding {
+            Binding::Key(key) => format!("Key:{key:?}"),
+            Binding::Mouse(button) => format!("Mouse:{button:?}"),
+            Binding::Gamepad(button) => format!("Pad:{button:?}"),
+        }
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        fn find<T: Copy + std::fmt::Debug>(options: &[T], name: &str) -> Option<T> {
+            options
+                .iter()
+                .copied()
+                .find(|option| format!("{option:?}") == name)
+        }
+        let binding = match text.split_once(':') {
+            Some(("Key", name)) => find(BINDABLE_KEYS, name).map(Binding::Key),
+            Some(("Mouse", name)) => find(BINDABLE_MOUSE_BUTTONS, name).map(Binding::Mouse),
+            Some(("Pad", name)) => find(BINDABLE_GAMEPAD_BUTTONS, name).map(Binding::Gamepad),
+            _ => None,
+        };
+        binding.ok_or_else(|| format!("unknown binding {text:?}"))
+    }
+}
+
+/// The player's rebound actions. Actions missing here use their defaults.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputMap(BTreeMap<InputAction, Vec<Binding>>);
+
+impl InputMap {
+    pub fn bindings(&self, action: InputAction) -> Vec<Binding> {
+        self.0
+            .get(&action)
+            .cloned()
+            .unwrap_or_else(|| action.default_bindings())
+    }
+
+    /// Binds `action` to `binding` in place of its other bindings from the
+    /// same kind of device, so rebinding a key keeps its gamepad button and
+    /// the other way round.
+    pub fn rebind(&mut self, action: InputAction, binding: Binding) {
+        let mut bindings: Vec<Binding> = self
+            .bindings(action)
+            .into_iter()
+            .filter(|other| other.is_gamepad() != binding.is_gamepad())
+            .collect();
+        bindings.push(binding);
+        if bindings == action.default_bindings() {
+            self.0.remove(&action);
+        } else {
+            self.0.insert(action, bindings);
+        }
+    }
+}
+
+impl InputMap {
+    /// The binding to show next to an action in menus: its first key or
+    /// mouse button, falling back to a gamepad button.
+    pub fn hint(&self, action: InputAction) -> String {
+        let bindings = self.bindings(action);
+        bindings
+            .iter()
+            .find(|binding| !binding.is_gamepad())
+            .or(bindings.first())
+            .map_or("-".to_string(), |binding| binding.label())
+    }
+}
+
+/// Reads actions through the player's [`InputMap`].
+#[derive(SystemParam)]
+pub struct ActionInput<'w, 's> {
+    map: Res<'w, InputMap>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    gamepads: Query<'w, 's, &'static Gamepad>,
+}
+
+impl ActionInput<'_, '_> {
+    fn any(&self, action: InputAction, check: impl Fn(Binding) -> bool) -> bool {
+        self.map.bindings(action).into_iter().any(check)
+    }
+
+    pub fn pressed(&self, action: InputAction) -> bool {
+        self.any(action, |binding| match binding {
+            Binding::Key(key) => self.keys.pressed(key),
+            Binding::Mouse(button) => self.mouse.pressed(button),
+            Binding::Gamepad(button) => self.gamepads.iter().any(|pad| pad.pressed(button)),
+        })
+    }
+
+    pub fn just_pressed(&self, action: InputAction) -> bool {
+        self.any(action, |binding| match binding {
+            Binding::Key(key) => self.keys.just_pressed(key),
+            Binding::Mouse(button) => self.mouse.just_pressed(button),
+            Binding::Gamepad(button) => self.gamepads.iter().any(|pad| pad.just_pressed(button)),
+        })
+    }
+
+    /// Like [`ActionInput::just_pressed`], but only counting mouse and
+    /// keyboard bindings.
+    pub fn just_pressed_on_mouse_or_keyboard(&self, action: InputAction) -> bool {
+        self.any(action, |binding| match binding {
+            Binding::Key(key) => self.keys.just_pressed(key),
+            Binding::Mouse(button) => self.mouse.just_pressed(button),
+            Binding::Gamepad(_) => false,
+        })
+    }
+
+    /// Like [`ActionInput::just_pressed`], but only counting keyboard
+    /// bindings.
+    pub fn just_pressed_on_keyboard(&self, action: InputAction) -> bool {
+        self.any(action, |binding| match binding {
+            Binding::Key(key) => self.keys.just_pressed(key),
+            Binding::Mouse(_) | Binding::Gamepad(_) => false,
+        })
+    }
+
+    /// Like [`ActionInput::just_pressed`], but only counting gamepad
+    /// bindings.
+    pub fn just_pressed_on_gamepad(&self, action: InputAction) -> bool {
+        self.any(action, |binding| match binding {
+            Binding::Gamepad(button) => self.gamepads.iter().any(|pad| pad.just_pressed(button)),
+            Binding::Key(_) | Binding::Mouse(_) => false,
+        })
+    }
+
+    /// The first bindable key or button pressed this frame, for rebinding.
+    pub fn any_just_pressed(&self) -> Option<Binding> {
+        let key = self.keys.get_just_pressed().map(|key| Binding::Key(*key));
+        let mouse = self
+            .mouse
+            .get_just_pressed()
+            .map(|button| Binding::Mouse(*button));
+        let pad = self
+            .gamepads
+            .iter()
+            .flat_map(|pad| pad.get_just_pressed())
+            .map(|button| Binding::Gamepad(*button));
+        key.chain(mouse)
+            .chain(pad)
+            .find(|binding| binding.is_bindable())
+    }
+}
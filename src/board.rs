@@ -0,0 +1,1609 @@
+//! The battle as plain data, and everything a command does to it.
+//!
+//! A [`Board`] holds the units, structures, traps, chests, and terrain,
+//! along with the turn, gold, fire, rolls, and battle log, as ordinary
+//! values. [`Board::apply`] plays a [`GameCommand`] out on it, or turns it
+//! down with a [`Rejection`], without touching a world or an app. The
+//! command executor copies the battle into a board, applies each command,
+//! and copies the result back, so AI search, servers, and tests can play
+//! the same rules on boards of their own.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use bevy::log::warn;
+
+use crate::ability::{
+    area, check_ready, check_target, push_direction, AbilityCatalog, AbilityEffect, Affects,
+    Cooldowns,
+};
+use crate::battle_log::{BattleEvent, BattleLog};
+use crate::chest::{Chest, Loot};
+use crate::class::{
+    heal_amount, level_up, promote, Experience, UnitClass, XP_PER_ATTACK, XP_PER_HEAL, XP_PER_KILL,
+};
+use crate::command::{GameCommand, Rejection};
+use crate::economy::{NextUnitId, Treasury, RECRUITABLE};
+use crate::facing::Facing;
+use crate::fire::{Fires, FIRE_DAMAGE};
+use crate::gate::lever_bridge;
+use crate::grid::{GridMap, GridPosition, TileType};
+use crate::items::{
+    can_attack, effective_stats, ConsumableEffect, EquipSlot, Equipment, Inventory, ItemCatalog,
+    ItemKind, StatBonus, INVENTORY_SLOTS,
+};
+use crate::leader::{aura_at, AuraBonus, Leader};
+use crate::rescue::Rescue;
+use crate::rules::{
+    check_turn, next_turn, strike, validate_attack, validate_move, AttackAttempt, AttackRoll,
+    Combatant, GameRng, Strike,
+};
+use crate::status::{StatusEffect, StatusEffects, StatusKind};
+use crate::stealth::Stealth;
+use crate::structure::{terrain_at, Structure};
+use crate::tiles::TileRegistry;
+use crate::trap::{Trap, TrapKind, TRAP_SENSE_RADIUS};
+use crate::turn::CurrentTurn;
+use crate::unit::{AttackRange, Faction, MoveRange, Stats, TurnStatus, UnitId};
+use crate::vision::{Flare, Flares};
+
+/// Everything the rules read or change on a unit. Fallen units stay on the
+/// board with no hp until whoever holds it takes them off.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoardUnit {
+    pub id: UnitId,
+    pub faction: Faction,
+    pub class: UnitClass,
+    pub pos: GridPosition,
+    pub stats: Stats,
+    pub status: TurnStatus,
+    pub move_range: MoveRange,
+    pub attack_range: AttackRange,
+    pub experience: Experience,
+    pub inventory: Inventory,
+    pub equipment: Equipment,
+    pub effects: StatusEffects,
+    pub leader: Option<Leader>,
+    pub aura: AuraBonus,
+    pub rescue: Rescue,
+    pub stealth: Stealth,
+    pub facing: Facing,
+    pub cooldowns: Cooldowns,
+}
+
+impl BoardUnit {
+    /// A fresh level 1 unit with its class's stats and nothing in its
+    /// packs, ready to act.
+    pub fn new(id: UnitId, faction: Faction, class: UnitClass, pos: GridPosition) -> Self {
+        Self {
+            id,
+            faction,
+            class,
+            pos,
+            stats: class.base_stats(),
+            status: TurnStatus::default(),
+            move_range: class.move_range(),
+            attack_range: class.attack_range(),
+            experience: Experience::default(),
+            inventory: Inventory::default(),
+            equipment: Equipment::default(),
+            effects: StatusEffects::default(),
+            leader: None,
+            aura: AuraBonus::default(),
+            rescue: Rescue::default(),
+            stealth: Stealth {
+                hidden: class.stealthy(),
+            },
+            facing: Facing::for_faction(faction),
+            cooldowns: Cooldowns::default(),
+        }
+    }
+}
+
+/// A unit recruited or called up by an ability, waiting to be put on the
+/// board. It can't act until its side's next turn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Arrival {
+    pub unit: UnitId,
+    pub faction: Faction,
+    pub class: UnitClass,
+    pub at: GridPosition,
+}
+
+/// The game data commands are played out with.
+#[derive(Clone, Copy)]
+pub struct Catalogs<'a> {
+    pub items: &'a ItemCatalog,
+    pub abilities: &'a AbilityCatalog,
+    pub tiles: &'a TileRegistry,
+}
+
+/// A battle in progress, as plain values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Board {
+    pub units: Vec<BoardUnit>,
+    pub structures: Vec<(GridPosition, Structure)>,
+    pub traps: Vec<(GridPosition, Trap)>,
+    pub chests: Vec<(GridPosition, Chest)>,
+    pub map: GridMap,
+    pub fires: Fires,
+    pub flares: Flares,
+    pub turn: CurrentTurn,
+    pub treasury: Treasury,
+    pub rng: GameRng,
+    pub history: BattleLog,
+    pub next_id: NextUnitId,
+    /// Units brought on since the board was set up, which only join
+    /// [`units`](Self::units) once the holder places them.
+    pub arrivals: Vec<Arrival>,
+}
+
+impl Board {
+    /// Plays `command` out, or leaves the board as it was and says why not.
+    pub fn apply(&mut self, command: GameCommand, data: Catalogs) -> Result<(), Rejection> {
+        // Positions may have changed with the previous command.
+        self.refresh_auras();
+        // Some commands only find out they can't go on partway through, so
+        // a rejected one is undone from a copy. The log can be long and is
+        // only ever added to, so it is cut back rather than copied.
+        let history = std::mem::take(&mut self.history);
+        let before = self.clone();
+        self.history = history;
+        let recorded = self.history.entries.len();
+        let result = self.play(command, data);
+        if result.is_err() {
+            let mut history = std::mem::take(&mut self.history);
+            history.entries.truncate(recorded);
+            *self = Board { history, ..before };
+        }
+        result
+    }
+
+    fn play(&mut self, command: GameCommand, data: Catalogs) -> Result<(), Rejection> {
+        match command {
+            GameCommand::Move { unit, to } => self.apply_move(unit, to),
+            GameCommand::Attack { attacker, target } => {
+                self.apply_attack(data.items, attacker, target)
+            }
+            GameCommand::Heal { healer, target } => self.apply_heal(healer, target),
+            GameCommand::PickUp { unit, target } => self.apply_pick_up(unit, target),
+            GameCommand::DropOff { unit, to } => self.apply_drop_off(unit, to),
+            GameCommand::OpenChest { unit } => self.apply_open_chest(data.items, unit),
+            GameCommand::Operate { unit, at } => self.apply_operate(unit, at),
+            GameCommand::Recruit { faction, class, at } => self.apply_recruit(faction, class, at),
+            GameCommand::Capture { unit } => self.apply_capture(unit),
+            GameCommand::Hide { unit } => self.apply_hide(unit),
+            GameCommand::Wait { unit } => self.apply_wait(unit),
+            GameCommand::DiscardItem { unit, slot } => self.apply_discard(data.items, unit, slot),
+            GameCommand::UseItem { unit, slot } => self.apply_use_item(data.items, unit, slot),
+            GameCommand::Equip { unit, slot } => self.apply_equip(data.items, unit, slot),
+            GameCommand::Unequip { unit, slot } => self.apply_unequip(unit, slot),
+            GameCommand::UseAbility { unit, slot, at } => self.apply_ability(data, unit, slot, at),
+            GameCommand::Promote { unit, class } => self.apply_promote(unit, class),
+            GameCommand::EndTurn => {
+                self.apply_end_turn();
+                Ok(())
+            }
+        }
+    }
+
+    /// The unit with `id`, if it stands on the board.
+    fn find(&self, id: UnitId) -> Option<usize> {
+        self.units
+            .iter()
+            .position(|unit| unit.id == id && unit.stats.is_alive() && !unit.rescue.is_carried())
+    }
+
+    fn find_carried(&self, id: UnitId) -> Option<usize> {
+        self.units
+            .iter()
+            .position(|unit| unit.id == id && unit.rescue.is_carried())
+    }
+
+    /// The tiles units stand on, apart from the unit at `except`.
+    fn occupied(&self, except: Option<usize>) -> HashSet<GridPosition> {
+        self.units
+            .iter()
+            .enumerate()
+            .filter(|&(index, unit)| {
+                Some(index) != except && unit.stats.is_alive() && !unit.rescue.is_carried()
+            })
+            .map(|(_, unit)| unit.pos)
+            .collect()
+    }
+
+    /// The units standing in `area` that an ability used by `faction`
+    /// affects.
+    fn affected(&self, area: &[GridPosition], affects: Affects, faction: Faction) -> Vec<usize> {
+        area.iter()
+            .filter_map(|pos| {
+                self.units.iter().position(|other| {
+                    other.pos == *pos
+                        && other.stats.is_alive()
+                        && !other.rescue.is_carried()
+                        && affects.includes(faction, other.faction)
+                })
+            })
+            .collect()
+    }
+
+    /// Recomputes every unit's [`AuraBonus`] from current positions.
+    pub fn refresh_auras(&mut self) {
+        let leaders: Vec<_> = self
+            .units
+            .iter()
+            .filter(|unit| unit.stats.is_alive() && !unit.rescue.is_carried())
+            .filter_map(|unit| Some((unit.id, unit.pos, unit.faction, unit.leader?)))
+            .collect();
+        for unit in &mut self.units {
+            unit.aura = aura_at(unit.id, unit.pos, unit.faction, &leaders);
+        }
+    }
+
+    fn apply_move(&mut self, unit: UnitId, to: GridPosition) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let blocked = self.occupied(Some(index));
+        let mover = &self.units[index];
+
+        check_turn(mover.faction, &self.turn)?;
+        let range = mover.rescue.move_range(mover.move_range);
+        validate_move(&self.map, &blocked, mover.pos, range, &mover.status, to)?;
+        let passenger = mover
+            .rescue
+            .carrying
+            .map(|passenger| {
+                self.find_carried(passenger)
+                    .ok_or(Rejection::CarriedUnitMissing)
+            })
+            .transpose()?;
+        let mover = &mut self.units[index];
+
+        self.history.record(
+            self.turn.number,
+            BattleEvent::Moved {
+                unit,
+                from: mover.pos,
+                to,
+            },
+        );
+        // Walking off a structure abandons any capture in progress there.
+        for (pos, structure) in &mut self.structures {
+            if *pos == mover.pos && structure.capture.is_some_and(|(by, _)| by == unit) {
+                structure.capture = None;
+            }
+        }
+        if let Some(facing) = Facing::toward(mover.pos, to) {
+            mover.facing = facing;
+        }
+        mover.pos = to;
+        mover.status.has_moved = true;
+
+        let (faction, spots_traps) = (mover.faction, mover.class.spots_traps());
+        if let Some(passenger) = passenger {
+            self.units[passenger].pos = to;
+        }
+
+        for trap in 0..self.traps.len() {
+            let (pos, trap) = &mut self.traps[trap];
+            if trap.sprung || trap.owner == faction {
+                continue;
+            }
+            if *pos == to {
+                trap.sprung = true;
+                trap.revealed = true;
+                let kind = trap.kind;
+                self.spring_trap(index, kind);
+            } else if spots_traps && !trap.revealed && pos.distance(to) <= TRAP_SENSE_RADIUS {
+                trap.revealed = true;
+                self.history.record(
+                    self.turn.number,
+                    BattleEvent::TrapSpotted { unit, at: *pos },
+                );
+            }
+        }
+        self.reveal_spotted();
+        Ok(())
+    }
+
+    /// Reveals every hidden unit within detection range of a unit of the
+    /// other side: next to it, or further for classes with true sight.
+    fn reveal_spotted(&mut self) {
+        let on_board: Vec<(GridPosition, Faction, u32)> = self
+            .units
+            .iter()
+            .filter(|unit| unit.stats.is_alive() && !unit.rescue.is_carried())
+            .map(|unit| (unit.pos, unit.faction, unit.class.detection_range()))
+            .collect();
+        for unit in &mut self.units {
+            if !unit.stealth.hidden || unit.rescue.is_carried() {
+                continue;
+            }
+            let spotted = on_board.iter().any(|(pos, faction, range)| {
+                *faction != unit.faction && pos.distance(unit.pos) <= *range
+            });
+            if spotted {
+                unit.stealth.hidden = false;
+                self.history
+                    .record(self.turn.number, BattleEvent::Revealed { unit: unit.id });
+            }
+        }
+    }
+
+    fn spring_trap(&mut self, victim: usize, kind: TrapKind) {
+        let victim = &mut self.units[victim];
+        let owner = victim.faction.opponent();
+        match kind {
+            TrapKind::Spikes(damage) => {
+                victim.stats.hp -= damage.min(victim.stats.hp.saturating_sub(1));
+            }
+            TrapKind::Snare => victim.effects.0.push(StatusEffect {
+                kind: StatusKind::Snare,
+                turns: 1,
+            }),
+            TrapKind::Alarm => {}
+        }
+        self.history.record(
+            self.turn.number,
+            BattleEvent::TrapSprung {
+                unit: victim.id,
+                kind,
+                hp: victim.stats.hp,
+            },
+        );
+
+        // The alarm boosts the trap owner's units through their next turn.
+        if kind == TrapKind::Alarm {
+            for unit in &mut self.units {
+                if unit.faction == owner && unit.stats.is_alive() {
+                    unit.effects.0.push(StatusEffect {
+                        kind: StatusKind::Boost(StatBonus {
+                            attack: 1,
+                            defense: 0,
+                            range: 0,
+                        }),
+                        turns: 2,
+                    });
+                }
+            }
+        }
+    }
+
+    fn apply_attack(
+        &mut self,
+        catalog: &ItemCatalog,
+        attacker: UnitId,
+        target: UnitId,
+    ) -> Result<(), Rejection> {
+        let attacker = self.find(attacker).ok_or(Rejection::UnknownUnit)?;
+        let target = self.find(target).ok_or(Rejection::UnknownTarget)?;
+        let [attacker, defender] = self
+            .units
+            .get_disjoint_mut([attacker, target])
+            .map_err(|_| Rejection::InvalidPair)?;
+        let number = self.turn.number;
+
+        check_turn(attacker.faction, &self.turn)?;
+        let (attacker_stats, attacker_range) = effective_stats(
+            &attacker.stats,
+            attacker.attack_range,
+            &attacker.equipment,
+            &attacker.effects,
+            &attacker.aura,
+            catalog,
+        );
+        let (defender_stats, _) = effective_stats(
+            &defender.stats,
+            defender.attack_range,
+            &defender.equipment,
+            &defender.effects,
+            &defender.aura,
+            catalog,
+        );
+        validate_attack(AttackAttempt {
+            faction: attacker.faction,
+            status: &attacker.status,
+            armed: can_attack(attacker.class, &attacker.equipment),
+            range: attacker_range,
+            from: attacker.pos,
+            target: defender.faction,
+            hidden: defender.stealth.hidden,
+            at: defender.pos,
+        })?;
+        let structure = self
+            .structures
+            .iter()
+            .find(|(pos, _)| *pos == defender.pos)
+            .map(|(_, structure)| structure.kind);
+        let terrain = terrain_at(&self.map, structure, defender.pos);
+
+        // Attacking gives the attacker away, whether or not it lands.
+        if attacker.stealth.hidden {
+            attacker.stealth.hidden = false;
+            self.history
+                .record(number, BattleEvent::Revealed { unit: attacker.id });
+        }
+        let flank = defender.facing.flank(defender.pos, attacker.pos);
+        if let Some(facing) = Facing::toward(attacker.pos, defender.pos) {
+            attacker.facing = facing;
+        }
+
+        let strike = strike(
+            Combatant {
+                stats: &attacker_stats,
+                class: attacker.class,
+                pos: attacker.pos,
+                moved: attacker.status.has_moved,
+            },
+            Combatant {
+                stats: &defender_stats,
+                class: defender.class,
+                pos: defender.pos,
+                moved: defender.status.has_moved,
+            },
+            terrain,
+            self.turn.time_of_day(),
+            &mut self.rng,
+        );
+        let Strike::Hit(AttackRoll { roll, damage }) = strike else {
+            self.history.record(
+                number,
+                BattleEvent::Missed {
+                    attacker: attacker.id,
+                    target: defender.id,
+                },
+            );
+            attacker.status.has_moved = true;
+            attacker.status.has_acted = true;
+            wear_weapon(attacker, number, &mut self.history);
+            return Ok(());
+        };
+        defender.stats.hp = defender.stats.hp.saturating_sub(damage);
+        attacker.status.has_moved = true;
+        attacker.status.has_acted = true;
+        self.history.record(
+            number,
+            BattleEvent::Attacked {
+                attacker: attacker.id,
+                target: defender.id,
+                roll,
+                damage,
+                hp_left: defender.stats.hp,
+                flank,
+            },
+        );
+
+        let weapon = attacker
+            .equipment
+            .weapon
+            .as_ref()
+            .and_then(|weapon| catalog.get(&weapon.id));
+        if weapon.is_some_and(|weapon| weapon.ignites) && self.fires.ignite(&self.map, defender.pos)
+        {
+            self.history
+                .record(number, BattleEvent::Ignited { at: defender.pos });
+        }
+        let inflicts = weapon.and_then(|weapon| weapon.inflicts);
+        if let Some(effect) = inflicts.filter(|_| defender.stats.is_alive()) {
+            defender.effects.0.push(effect);
+            self.history.record(
+                number,
+                BattleEvent::StatusInflicted {
+                    unit: defender.id,
+                    status: effect.kind,
+                },
+            );
+        }
+
+        wear_weapon(attacker, number, &mut self.history);
+
+        let mut xp = XP_PER_ATTACK;
+        if !defender.stats.is_alive() {
+            self.history
+                .record(number, BattleEvent::Died { unit: defender.id });
+            xp += XP_PER_KILL;
+        }
+        let fallen_carrier = (!defender.stats.is_alive())
+            .then_some(defender.id)
+            .filter(|_| defender.rescue.carrying.is_some());
+        grant_xp(attacker, xp, number, &mut self.history);
+
+        if let Some(carrier) = fallen_carrier {
+            self.set_down_passenger(carrier);
+        }
+        Ok(())
+    }
+
+    /// Leaves whoever a fallen unit was carrying standing where it fell.
+    fn set_down_passenger(&mut self, carrier: UnitId) {
+        for unit in &mut self.units {
+            if unit.rescue.carried_by == Some(carrier) {
+                unit.rescue.carried_by = None;
+            }
+        }
+    }
+
+    fn apply_heal(&mut self, healer: UnitId, target: UnitId) -> Result<(), Rejection> {
+        let healer = self.find(healer).ok_or(Rejection::UnknownUnit)?;
+        let target = self.find(target).ok_or(Rejection::UnknownTarget)?;
+        let [healer, patient] = self
+            .units
+            .get_disjoint_mut([healer, target])
+            .map_err(|_| Rejection::InvalidPair)?;
+
+        check_turn(healer.faction, &self.turn)?;
+        if healer.faction != patient.faction {
+            return Err(Rejection::HealEnemy);
+        }
+        if healer.status.has_acted {
+            return Err(Rejection::AlreadyActed);
+        }
+        let range = healer.class.heal_range().ok_or(Rejection::CannotHeal)?;
+        if healer.pos.distance(patient.pos) > range {
+            return Err(Rejection::OutOfRange);
+        }
+        if patient.stats.hp >= patient.stats.max_hp {
+            return Err(Rejection::FullHealth);
+        }
+
+        let amount = patient.stats.heal(heal_amount(&healer.stats));
+        healer.status.has_moved = true;
+        healer.status.has_acted = true;
+        self.history.record(
+            self.turn.number,
+            BattleEvent::Mended {
+                healer: healer.id,
+                target: patient.id,
+                amount,
+                hp: patient.stats.hp,
+            },
+        );
+        grant_xp(healer, XP_PER_HEAL, self.turn.number, &mut self.history);
+        Ok(())
+    }
+
+    fn apply_pick_up(&mut self, unit: UnitId, target: UnitId) -> Result<(), Rejection> {
+        let carrier = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let passenger = self.find(target).ok_or(Rejection::UnknownTarget)?;
+        let [carrier, passenger] = self
+            .units
+            .get_disjoint_mut([carrier, passenger])
+            .map_err(|_| Rejection::InvalidPair)?;
+
+        check_turn(carrier.faction, &self.turn)?;
+        if carrier.faction != passenger.faction {
+            return Err(Rejection::CarryEnemy);
+        }
+        if carrier.status.has_acted {
+            return Err(Rejection::AlreadyActed);
+        }
+        if carrier.pos.distance(passenger.pos) != 1 {
+            return Err(Rejection::NotAdjacent);
+        }
+        if carrier.rescue.carrying.is_some() || passenger.rescue.carrying.is_some() {
+            return Err(Rejection::AlreadyCarrying);
+        }
+
+        carrier.rescue.carrying = Some(target);
+        carrier.status.has_moved = true;
+        carrier.status.has_acted = true;
+        passenger.rescue.carried_by = Some(unit);
+        passenger.pos = carrier.pos;
+        self.history
+            .record(self.turn.number, BattleEvent::PickedUp { unit, target });
+        Ok(())
+    }
+
+    fn apply_drop_off(&mut self, unit: UnitId, to: GridPosition) -> Result<(), Rejection> {
+        let carrier = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let blocked = self.occupied(Some(carrier));
+        let target = self.units[carrier]
+            .rescue
+            .carrying
+            .ok_or(Rejection::NotCarrying)?;
+        let passenger = self
+            .find_carried(target)
+            .ok_or(Rejection::CarriedUnitMissing)?;
+        let [carrier, passenger] = self
+            .units
+            .get_disjoint_mut([carrier, passenger])
+            .map_err(|_| Rejection::InvalidPair)?;
+
+        check_turn(carrier.faction, &self.turn)?;
+        if carrier.status.has_acted {
+            return Err(Rejection::AlreadyActed);
+        }
+        if carrier.pos.distance(to) != 1 || !self.map.is_walkable(to) || blocked.contains(&to) {
+            return Err(Rejection::CannotDrop);
+        }
+
+        carrier.rescue.carrying = None;
+        carrier.status.has_moved = true;
+        carrier.status.has_acted = true;
+        passenger.rescue.carried_by = None;
+        passenger.pos = to;
+        self.history.record(
+            self.turn.number,
+            BattleEvent::DroppedOff {
+                unit,
+                target,
+                at: to,
+            },
+        );
+        self.reveal_spotted();
+        Ok(())
+    }
+
+    fn apply_operate(&mut self, unit: UnitId, at: GridPosition) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let mut occupied = self.occupied(Some(index));
+        let operator = &mut self.units[index];
+        occupied.insert(operator.pos);
+
+        check_turn(operator.faction, &self.turn)?;
+        if operator.status.has_acted {
+            return Err(Rejection::AlreadyActed);
+        }
+        if operator.pos.distance(at) > 1 {
+            return Err(Rejection::TooFar);
+        }
+
+        match self.map.tile(at) {
+            Some(TileType::Door { open }) => {
+                if open && occupied.contains(&at) {
+                    return Err(Rejection::DoorwayBlocked);
+                }
+                self.map.set_tile(at, TileType::Door { open: !open });
+                self.history.record(
+                    self.turn.number,
+                    BattleEvent::DoorToggled {
+                        unit,
+                        at,
+                        open: !open,
+                    },
+                );
+            }
+            Some(TileType::Lever) => {
+                let bridge = lever_bridge(at).ok_or(Rejection::LeverNotConnected)?;
+                let Some(TileType::Bridge { lowered }) = self.map.tile(bridge) else {
+                    return Err(Rejection::LeverNotConnected);
+                };
+                if lowered && occupied.contains(&bridge) {
+                    return Err(Rejection::BridgeBlocked);
+                }
+                self.map
+                    .set_tile(bridge, TileType::Bridge { lowered: !lowered });
+                self.history.record(
+                    self.turn.number,
+                    BattleEvent::BridgeToggled {
+                        unit,
+                        at: bridge,
+                        lowered: !lowered,
+                    },
+                );
+            }
+            _ => return Err(Rejection::NothingToOperate),
+        }
+        operator.status.has_moved = true;
+        operator.status.has_acted = true;
+        Ok(())
+    }
+
+    fn apply_recruit(
+        &mut self,
+        faction: Faction,
+        class: UnitClass,
+        at: GridPosition,
+    ) -> Result<(), Rejection> {
+        if faction != self.turn.faction {
+            return Err(Rejection::OutOfTurn);
+        }
+        if !RECRUITABLE.contains(&class) {
+            return Err(Rejection::NotRecruitable);
+        }
+        let site = self.structures.iter().any(|(pos, structure)| {
+            *pos == at && structure.kind.can_recruit() && structure.owner == Some(faction)
+        });
+        if !site {
+            return Err(Rejection::NotRecruitSite);
+        }
+        if self
+            .units
+            .iter()
+            .any(|unit| unit.pos == at && unit.stats.is_alive())
+        {
+            return Err(Rejection::SiteOccupied);
+        }
+        let gold = self.treasury.get_mut(faction);
+        if *gold < class.cost() {
+            return Err(Rejection::NotEnoughGold);
+        }
+
+        *gold -= class.cost();
+        let unit = UnitId(self.next_id.0);
+        self.next_id.0 += 1;
+        self.arrivals.push(Arrival {
+            unit,
+            faction,
+            class,
+            at,
+        });
+        self.history.record(
+            self.turn.number,
+            BattleEvent::Recruited {
+                unit,
+                faction,
+                class,
+            },
+        );
+        Ok(())
+    }
+
+    fn apply_open_chest(&mut self, catalog: &ItemCatalog, unit: UnitId) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let opener = &mut self.units[index];
+
+        check_turn(opener.faction, &self.turn)?;
+        if opener.status.has_acted {
+            return Err(Rejection::AlreadyActed);
+        }
+        let (_, chest) = self
+            .chests
+            .iter_mut()
+            .find(|(pos, chest)| *pos == opener.pos && !chest.opened)
+            .ok_or(Rejection::NoChest)?;
+
+        match &chest.loot {
+            Loot::Item(id) => {
+                if opener.inventory.items.len() >= INVENTORY_SLOTS {
+                    return Err(Rejection::InventoryFull);
+                }
+                opener.inventory.items.push(catalog.instance(id.clone()));
+            }
+            Loot::Gold(amount) => *self.treasury.get_mut(opener.faction) += amount,
+        }
+        chest.opened = true;
+        opener.status.has_moved = true;
+        opener.status.has_acted = true;
+        self.history.record(
+            self.turn.number,
+            BattleEvent::ChestOpened {
+                unit,
+                loot: chest.loot.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    fn apply_capture(&mut self, unit: UnitId) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let capturer = &mut self.units[index];
+
+        check_turn(capturer.faction, &self.turn)?;
+        if capturer.status.has_acted {
+            return Err(Rejection::AlreadyActed);
+        }
+        let (_, structure) = self
+            .structures
+            .iter_mut()
+            .find(|(pos, _)| *pos == capturer.pos)
+            .ok_or(Rejection::NoStructure)?;
+        if structure.owner == Some(capturer.faction) {
+            return Err(Rejection::AlreadyOwned);
+        }
+
+        let progress = match structure.capture {
+            Some((by, progress)) if by == unit => progress + 1,
+            _ => 1,
+        };
+        let needed = structure.kind.capture_turns();
+        capturer.status.has_moved = true;
+        capturer.status.has_acted = true;
+        if progress >= needed {
+            structure.owner = Some(capturer.faction);
+            structure.capture = None;
+            self.history.record(
+                self.turn.number,
+                BattleEvent::Captured {
+                    unit,
+                    structure: structure.kind,
+                },
+            );
+        } else {
+            structure.capture = Some((unit, progress));
+            self.history.record(
+                self.turn.number,
+                BattleEvent::Capturing {
+                    unit,
+                    progress,
+                    needed,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn apply_hide(&mut self, unit: UnitId) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let pos = self.units[index].pos;
+        let watched = self.units.iter().any(|other| {
+            other.faction != self.turn.faction
+                && other.stats.is_alive()
+                && !other.rescue.is_carried()
+                && other.pos.distance(pos) <= other.class.detection_range()
+        });
+        let hider = &mut self.units[index];
+
+        check_turn(hider.faction, &self.turn)?;
+        if hider.status.has_acted {
+            return Err(Rejection::AlreadyActed);
+        }
+        if !hider.class.stealthy() {
+            return Err(Rejection::CannotHide);
+        }
+        if hider.stealth.hidden {
+            return Err(Rejection::AlreadyHidden);
+        }
+        if watched {
+            return Err(Rejection::Watched);
+        }
+        hider.stealth.hidden = true;
+        hider.status.has_moved = true;
+        hider.status.has_acted = true;
+        self.history
+            .record(self.turn.number, BattleEvent::Hid { unit });
+        Ok(())
+    }
+
+    fn apply_wait(&mut self, unit: UnitId) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let waiter = &mut self.units[index];
+
+        check_turn(waiter.faction, &self.turn)?;
+        waiter.status.has_moved = true;
+        waiter.status.has_acted = true;
+        self.history
+            .record(self.turn.number, BattleEvent::Waited { unit });
+        Ok(())
+    }
+
+    fn apply_discard(
+        &mut self,
+        catalog: &ItemCatalog,
+        unit: UnitId,
+        slot: usize,
+    ) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let owner = &mut self.units[index];
+
+        check_turn(owner.faction, &self.turn)?;
+        let item = owner
+            .inventory
+            .items
+            .get(slot)
+            .ok_or(Rejection::EmptySlot)?;
+        if catalog
+            .get(&item.id)
+            .is_some_and(|item| item.kind == ItemKind::Quest)
+        {
+            return Err(Rejection::QuestItem);
+        }
+
+        let item = owner.inventory.remove(slot).unwrap().id;
+        self.history
+            .record(self.turn.number, BattleEvent::ItemDiscarded { unit, item });
+        Ok(())
+    }
+
+    fn apply_use_item(
+        &mut self,
+        catalog: &ItemCatalog,
+        unit: UnitId,
+        slot: usize,
+    ) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let user = &mut self.units[index];
+
+        check_turn(user.faction, &self.turn)?;
+        if user.status.has_acted {
+            return Err(Rejection::AlreadyActed);
+        }
+        let item = user.inventory.items.get(slot).ok_or(Rejection::EmptySlot)?;
+        let effect = catalog
+            .get(&item.id)
+            .and_then(|item| item.effect)
+            .ok_or(Rejection::NotUsable)?;
+        match effect {
+            ConsumableEffect::Heal(_) if user.stats.hp >= user.stats.max_hp => {
+                return Err(Rejection::FullHealth);
+            }
+            ConsumableEffect::Cure if !user.effects.0.iter().any(|e| e.kind.is_harmful()) => {
+                return Err(Rejection::NothingToCure);
+            }
+            _ => {}
+        }
+
+        let item = user.inventory.remove(slot).unwrap().id;
+        user.status.has_moved = true;
+        user.status.has_acted = true;
+        self.history
+            .record(self.turn.number, BattleEvent::ItemUsed { unit, item });
+        match effect {
+            ConsumableEffect::Heal(amount) => {
+                let amount = user.stats.heal(amount);
+                self.history.record(
+                    self.turn.number,
+                    BattleEvent::Healed {
+                        unit,
+                        amount,
+                        hp: user.stats.hp,
+                    },
+                );
+            }
+            ConsumableEffect::Buff { bonus, turns } => user.effects.0.push(StatusEffect {
+                kind: StatusKind::Boost(bonus),
+                turns,
+            }),
+            ConsumableEffect::Cure => {
+                user.effects.cure();
+            }
+            ConsumableEffect::Reveal { radius, turns } => self.flares.0.push(Flare {
+                faction: user.faction,
+                at: user.pos,
+                radius,
+                turns,
+            }),
+        }
+        Ok(())
+    }
+
+    fn apply_equip(
+        &mut self,
+        catalog: &ItemCatalog,
+        unit: UnitId,
+        slot: usize,
+    ) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let owner = &mut self.units[index];
+
+        check_turn(owner.faction, &self.turn)?;
+        let item = owner
+            .inventory
+            .items
+            .get(slot)
+            .ok_or(Rejection::EmptySlot)?;
+        let equip_slot = catalog
+            .get(&item.id)
+            .and_then(|item| item.kind.equip_slot())
+            .ok_or(Rejection::NotEquippable)?;
+
+        let item = owner.inventory.remove(slot).unwrap();
+        let id = item.id.clone();
+        if let Some(previous) = owner.equipment.get_mut(equip_slot).replace(item) {
+            owner.inventory.items.insert(slot, previous);
+        }
+        self.history
+            .record(self.turn.number, BattleEvent::Equipped { unit, item: id });
+        Ok(())
+    }
+
+    fn apply_unequip(&mut self, unit: UnitId, slot: EquipSlot) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let owner = &mut self.units[index];
+
+        check_turn(owner.faction, &self.turn)?;
+        if owner.inventory.items.len() >= INVENTORY_SLOTS {
+            return Err(Rejection::InventoryFull);
+        }
+        let item = owner
+            .equipment
+            .get_mut(slot)
+            .take()
+            .ok_or(Rejection::NothingEquipped)?;
+
+        let id = item.id.clone();
+        owner.inventory.items.push(item);
+        self.history
+            .record(self.turn.number, BattleEvent::Unequipped { unit, item: id });
+        Ok(())
+    }
+
+    fn apply_ability(
+        &mut self,
+        data: Catalogs,
+        unit: UnitId,
+        slot: usize,
+        at: GridPosition,
+    ) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let user = &self.units[index];
+        let (faction, from) = (user.faction, user.pos);
+
+        check_turn(faction, &self.turn)?;
+        let (id, ability) = data
+            .abilities
+            .slot(user.class, slot)
+            .ok_or(Rejection::NoSuchAbility)?;
+        check_ready(id, &user.status, &user.cooldowns)?;
+        // Hidden enemies can't be picked out.
+        let target = self
+            .units
+            .iter()
+            .find(|other| other.pos == at && other.stats.is_alive() && !other.rescue.is_carried())
+            .filter(|other| !other.stealth.hidden || other.faction == faction)
+            .map(|other| other.faction);
+        check_target(ability, &self.map, from, faction, at, target)?;
+
+        let number = self.turn.number;
+        let user = &mut self.units[index];
+        user.status.has_moved = true;
+        user.status.has_acted = true;
+        if ability.cooldown > 0 {
+            // The count goes down as each of the unit's turns starts, the
+            // next one included, so it starts a turn higher.
+            user.cooldowns.0.insert(id.clone(), ability.cooldown + 1);
+        }
+        if let Some(facing) = Facing::toward(from, at) {
+            user.facing = facing;
+        }
+        // Using an ability gives the unit away, like attacking does.
+        if user.stealth.hidden {
+            user.stealth.hidden = false;
+            self.history.record(number, BattleEvent::Revealed { unit });
+        }
+        self.history.record(
+            number,
+            BattleEvent::AbilityUsed {
+                unit,
+                ability: id.clone(),
+                at,
+            },
+        );
+
+        let area = area(ability.shape, &self.map, from, at);
+        let mut xp = 0;
+        // Who is affected is worked out again for each effect, as earlier
+        // ones move and fell units.
+        for effect in &ability.effects {
+            match effect {
+                AbilityEffect::Damage(amount) => {
+                    for target in self.affected(&area, ability.affects, faction) {
+                        let victim = &mut self.units[target];
+                        let damage = amount.saturating_sub(victim.stats.defense).max(1);
+                        victim.stats.hp = victim.stats.hp.saturating_sub(damage);
+                        self.history.record(
+                            number,
+                            BattleEvent::Struck {
+                                unit: victim.id,
+                                damage,
+                                hp: victim.stats.hp,
+                            },
+                        );
+                        xp = xp.max(XP_PER_ATTACK);
+                        if victim.stats.is_alive() {
+                            continue;
+                        }
+                        let fallen = victim.id;
+                        self.history
+                            .record(number, BattleEvent::Died { unit: fallen });
+                        xp += XP_PER_KILL;
+                        if victim.rescue.carrying.is_some() {
+                            self.set_down_passenger(fallen);
+                        }
+                    }
+                }
+                AbilityEffect::Heal(amount) => {
+                    for target in self.affected(&area, ability.affects, faction) {
+                        let patient = &mut self.units[target];
+                        let amount = patient.stats.heal(*amount);
+                        if amount > 0 {
+                            self.history.record(
+                                number,
+                                BattleEvent::Mended {
+                                    healer: unit,
+                                    target: patient.id,
+                                    amount,
+                                    hp: patient.stats.hp,
+                                },
+                            );
+                            xp = xp.max(XP_PER_HEAL);
+                        }
+                    }
+                }
+                AbilityEffect::Push(distance) => {
+                    // The furthest go first, so they don't block the nearest.
+                    let mut targets = self.affected(&area, ability.affects, faction);
+                    targets.sort_by_key(|&target| Reverse(self.units[target].pos.distance(from)));
+                    for target in targets {
+                        let blocked = self.occupied(Some(target));
+                        let pushed = &mut self.units[target];
+                        let start = pushed.pos;
+                        let Some((dx, dy)) = push_direction(from, start) else {
+                            continue;
+                        };
+                        let mut to = start;
+                        for _ in 0..*distance {
+                            let next = GridPosition::new(to.x + dx, to.y + dy);
+                            if !self.map.is_walkable(next) || blocked.contains(&next) {
+                                break;
+                            }
+                            to = next;
+                        }
+                        if to == start {
+                            continue;
+                        }
+                        pushed.pos = to;
+                        self.history.record(
+                            number,
+                            BattleEvent::Pushed {
+                                unit: pushed.id,
+                                from: start,
+                                to,
+                            },
+                        );
+                        if let Some(passenger) = pushed.rescue.carrying {
+                            let passenger = self
+                                .find_carried(passenger)
+                                .ok_or(Rejection::CarriedUnitMissing)?;
+                            self.units[passenger].pos = to;
+                        }
+                    }
+                }
+                AbilityEffect::Spawn(class) => {
+                    let mut occupied = self.occupied(None);
+                    for &pos in &area {
+                        if !self.map.is_walkable(pos) || !occupied.insert(pos) {
+                            continue;
+                        }
+                        let arrival = UnitId(self.next_id.0);
+                        self.next_id.0 += 1;
+                        self.arrivals.push(Arrival {
+                            unit: arrival,
+                            faction,
+                            class: *class,
+                            at: pos,
+                        });
+                        self.history.record(
+                            number,
+                            BattleEvent::Arrived {
+                                unit: arrival,
+                                faction,
+                                class: *class,
+                            },
+                        );
+                    }
+                }
+                AbilityEffect::ApplyStatus(status) => {
+                    for target in self.affected(&area, ability.affects, faction) {
+                        let victim = &mut self.units[target];
+                        victim.effects.0.push(*status);
+                        self.history.record(
+                            number,
+                            BattleEvent::StatusInflicted {
+                                unit: victim.id,
+                                status: status.kind,
+                            },
+                        );
+                    }
+                }
+                AbilityEffect::ModifyTerrain(name) => {
+                    let Some(terrain) = data.tiles.find(name) else {
+                        warn!(
+                            "{} changes the ground into unknown terrain {name:?}",
+                            ability.name
+                        );
+                        continue;
+                    };
+                    let occupied = self.occupied(None);
+                    for &pos in &area {
+                        let Some(tile) = self.map.tile(pos) else {
+                            continue;
+                        };
+                        let changeable = matches!(
+                            tile,
+                            TileType::Grass
+                                | TileType::Forest
+                                | TileType::Water
+                                | TileType::Mountain
+                                | TileType::Custom(_)
+                        );
+                        let stranded = !terrain.is_walkable() && occupied.contains(&pos);
+                        if tile == terrain || !changeable || stranded {
+                            continue;
+                        }
+                        self.map.set_tile(pos, terrain);
+                        self.history.record(
+                            number,
+                            BattleEvent::TerrainChanged {
+                                at: pos,
+                                terrain: data.tiles.label(terrain).to_string(),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        if xp > 0 {
+            grant_xp(&mut self.units[index], xp, number, &mut self.history);
+        }
+        self.reveal_spotted();
+        Ok(())
+    }
+
+    fn apply_promote(&mut self, unit: UnitId, class: UnitClass) -> Result<(), Rejection> {
+        let index = self.find(unit).ok_or(Rejection::UnknownUnit)?;
+        let promoted = &mut self.units[index];
+
+        check_turn(promoted.faction, &self.turn)?;
+        if !promoted.class.can_promote(&promoted.experience, class) {
+            return Err(Rejection::CannotPromote);
+        }
+
+        promote(
+            promoted.class,
+            class,
+            &mut promoted.stats,
+            &mut promoted.move_range,
+            &mut promoted.attack_range,
+        );
+        promoted.class = class;
+        self.history
+            .record(self.turn.number, BattleEvent::Promoted { unit, class });
+        Ok(())
+    }
+
+    /// Hands the turn over and runs the upkeep of the side whose turn it
+    /// now is: income, flares, fire, status effects, and healing on owned
+    /// structures.
+    fn apply_end_turn(&mut self) {
+        self.turn = next_turn(self.turn);
+        let turn = self.turn;
+        self.history.record(
+            turn.number,
+            BattleEvent::TurnStarted {
+                faction: turn.faction,
+                number: turn.number,
+            },
+        );
+
+        let income: u32 = self
+            .structures
+            .iter()
+            .filter(|(_, structure)| structure.owner == Some(turn.faction))
+            .map(|(_, structure)| structure.kind.income())
+            .sum();
+        if income > 0 {
+            *self.treasury.get_mut(turn.faction) += income;
+            self.history.record(
+                turn.number,
+                BattleEvent::IncomeCollected {
+                    faction: turn.faction,
+                    amount: income,
+                },
+            );
+        }
+        self.flares.burn_down(turn.faction);
+        // Fires spread and burn down once per round.
+        if turn.faction == Faction::Player {
+            for at in self.fires.advance(&self.map, &mut self.rng) {
+                self.history
+                    .record(turn.number, BattleEvent::Ignited { at });
+            }
+        }
+        for unit in &mut self.units {
+            unit.status = TurnStatus::default();
+            if unit.faction != turn.faction {
+                continue;
+            }
+            unit.cooldowns.tick();
+
+            let upkeep = unit.effects.upkeep(&mut unit.stats, &mut unit.status);
+            if upkeep.poison_damage > 0 {
+                self.history.record(
+                    turn.number,
+                    BattleEvent::Poisoned {
+                        unit: unit.id,
+                        damage: upkeep.poison_damage,
+                        hp: unit.stats.hp,
+                    },
+                );
+            }
+            if upkeep.stunned {
+                self.history
+                    .record(turn.number, BattleEvent::Stunned { unit: unit.id });
+            }
+            if self.fires.is_burning(unit.pos) && !unit.rescue.is_carried() {
+                let damage = FIRE_DAMAGE.min(unit.stats.hp.saturating_sub(1));
+                unit.stats.hp -= damage;
+                self.history.record(
+                    turn.number,
+                    BattleEvent::Burned {
+                        unit: unit.id,
+                        damage,
+                        hp: unit.stats.hp,
+                    },
+                );
+            }
+
+            let heal_percent = self
+                .structures
+                .iter()
+                .find(|(pos, structure)| *pos == unit.pos && structure.owner == Some(turn.faction))
+                .map_or(0, |(_, structure)| structure.kind.heal_percent());
+            if heal_percent > 0 {
+                let restore = (unit.stats.max_hp * heal_percent / 100).max(1);
+                let amount = unit.stats.heal(restore);
+                if amount > 0 {
+                    self.history.record(
+                        turn.number,
+                        BattleEvent::Healed {
+                            unit: unit.id,
+                            amount,
+                            hp: unit.stats.hp,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Spends one use of the attacker's weapon, destroying it when it runs out.
+fn wear_weapon(unit: &mut BoardUnit, turn: u32, history: &mut BattleLog) {
+    let Some(weapon) = unit.equipment.weapon.as_mut() else {
+        return;
+    };
+    let Some(uses) = weapon.uses.as_mut() else {
+        return;
+    };
+    *uses = uses.saturating_sub(1);
+    if *uses == 0 {
+        let item = unit.equipment.weapon.take().unwrap().id;
+        history.record(
+            turn,
+            BattleEvent::WeaponBroke {
+                unit: unit.id,
+                item,
+            },
+        );
+    }
+}
+
+fn grant_xp(unit: &mut BoardUnit, xp: u32, turn: u32, history: &mut BattleLog) {
+    for _ in 0..unit.experience.gain(xp) {
+        level_up(&mut unit.stats);
+        history.record(
+            turn,
+            BattleEvent::LeveledUp {
+                unit: unit.id,
+                level: unit.experience.level,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ability::AbilityError;
+    use crate::structure::StructureKind;
+
+    fn fort(owner: Faction) -> (GridPosition, Structure) {
+        let structure = Structure {
+            kind: StructureKind::Fort,
+            owner: Some(owner),
+            home: Some(owner),
+            capture: None,
+        };
+        (GridPosition::new(1, 1), structure)
+    }
+
+    fn recruit(class: UnitClass) -> GameCommand {
+        GameCommand::Recruit {
+            faction: Faction::Player,
+            class,
+            at: GridPosition::new(1, 1),
+        }
+    }
+
+    fn apply(board: &mut Board, command: GameCommand) -> Result<(), Rejection> {
+        let (items, abilities, tiles) = Default::default();
+        let data = Catalogs {
+            items: &items,
+            abilities: &abilities,
+            tiles: &tiles,
+        };
+        board.apply(command, data)
+    }
+
+    #[test]
+    fn recruits_are_paid_for_and_left_for_the_holder_to_place() {
+        let mut board = Board {
+            structures: vec![fort(Faction::Player)],
+            treasury: Treasury {
+                player: 300,
+                enemy: 0,
+            },
+            ..Board::default()
+        };
+
+        assert_eq!(apply(&mut board, recruit(UnitClass::Archer)), Ok(()));
+
+        assert_eq!(board.treasury.player, 300 - UnitClass::Archer.cost());
+        assert_eq!(
+            board.arrivals,
+            [Arrival {
+                unit: UnitId(0),
+                faction: Faction::Player,
+                class: UnitClass::Archer,
+                at: GridPosition::new(1, 1),
+            }]
+        );
+    }
+
+    #[test]
+    fn recruiting_needs_an_owned_fort() {
+        let mut board = Board {
+            structures: vec![fort(Faction::Enemy)],
+            ..Board::default()
+        };
+        assert_eq!(
+            apply(&mut board, recruit(UnitClass::Archer)),
+            Err(Rejection::NotRecruitSite)
+        );
+        assert!(board.arrivals.is_empty());
+    }
+
+    #[test]
+    fn a_new_turn_pays_the_side_taking_it() {
+        let mut board = Board {
+            structures: vec![fort(Faction::Enemy)],
+            ..Board::default()
+        };
+        let gold = board.treasury.enemy;
+
+        assert_eq!(apply(&mut board, GameCommand::EndTurn), Ok(()));
+
+        assert_eq!(board.turn.faction, Faction::Enemy);
+        assert_eq!(board.treasury.enemy, gold + StructureKind::Fort.income());
+    }
+
+    /// A board with a player knight at (1, 1) and an enemy knight at
+    /// (2, 1) said to be carrying a unit that isn't there.
+    fn carrying_a_missing_unit(carrier: Faction) -> Board {
+        let mut units = vec![
+            BoardUnit::new(
+                UnitId(0),
+                Faction::Player,
+                UnitClass::Knight,
+                GridPosition::new(1, 1),
+            ),
+            BoardUnit::new(
+                UnitId(1),
+                Faction::Enemy,
+                UnitClass::Knight,
+                GridPosition::new(2, 1),
+            ),
+        ];
+        let index = if carrier == Faction::Player { 0 } else { 1 };
+        units[index].rescue.carrying = Some(UnitId(7));
+        Board {
+            units,
+            ..Board::default()
+        }
+    }
+
+    #[test]
+    fn a_move_turned_down_leaves_the_board_as_it_was() {
+        let mut board = carrying_a_missing_unit(Faction::Player);
+        board.refresh_auras();
+        let before = board.clone();
+
+        let command = GameCommand::Move {
+            unit: UnitId(0),
+            to: GridPosition::new(1, 2),
+        };
+        assert_eq!(
+            apply(&mut board, command),
+            Err(Rejection::CarriedUnitMissing)
+        );
+        assert_eq!(board, before);
+    }
+
+    #[test]
+    fn an_ability_turned_down_partway_through_is_undone() {
+        let abilities: AbilityCatalog = ron::from_str(
+            r#"(
+                abilities: {
+                    "shove": (
+                        name: "Shove",
+                        description: "",
+                        effects: [Damage(1), Push(2)],
+                        cooldown: 2,
+                    ),
+                },
+                classes: { Knight: ["shove"] },
+            )"#,
+        )
+        .unwrap();
+        let (items, tiles) = Default::default();
+        let data = Catalogs {
+            items: &items,
+            abilities: &abilities,
+            tiles: &tiles,
+        };
+        let mut board = carrying_a_missing_unit(Faction::Enemy);
+        board.refresh_auras();
+        let before = board.clone();
+
+        // The shove lands and pushes before finding the carried unit gone.
+        let command = GameCommand::UseAbility {
+            unit: UnitId(0),
+            slot: 0,
+            at: GridPosition::new(2, 1),
+        };
+        assert_eq!(
+            board.apply(command, data),
+            Err(Rejection::CarriedUnitMissing)
+        );
+        assert_eq!(board, before);
+    }
+
+    #[test]
+    fn a_cooldown_of_one_skips_the_units_next_turn() {
+        let at = GridPosition::new(1, 1);
+        let mut board = Board {
+            units: vec![
+                BoardUnit::new(UnitId(0), Faction::Player, UnitClass::Knight, at),
+                BoardUnit::new(
+                    UnitId(1),
+                    Faction::Enemy,
+                    UnitClass::Knight,
+                    GridPosition::new(6, 6),
+                ),
+            ],
+            map: GridMap::new(8, 8),
+            ..Board::default()
+        };
+        let abilities: AbilityCatalog = ron::from_str(
+            r#"(
+                abilities: {
+                    "second_wind": (
+                        name: "Second Wind",
+                        description: "",
+                        targeting: Caster,
+                        effects: [Heal(1)],
+                        cooldown: 1,
+                    ),
+                },
+                classes: { Knight: ["second_wind"] },
+            )"#,
+        )
+        .unwrap();
+        let (items, tiles) = Default::default();
+        let data = Catalogs {
+            items: &items,
+            abilities: &abilities,
+            tiles: &tiles,
+        };
+        let use_it = GameCommand::UseAbility {
+            unit: UnitId(0),
+            slot: 0,
+            at,
+        };
+        let next_player_turn = |board: &mut Board| {
+            for _ in 0..2 {
+                assert_eq!(board.apply(GameCommand::EndTurn, data), Ok(()));
+            }
+        };
+
+        assert_eq!(board.apply(use_it, data), Ok(()));
+        next_player_turn(&mut board);
+        assert_eq!(
+            board.apply(use_it, data),
+            Err(Rejection::Ability(AbilityError::OnCooldown))
+        );
+        next_player_turn(&mut board);
+        assert_eq!(board.apply(use_it, data), Ok(()));
+    }
+}
@@ -0,0 +1,262 @@
+//! Camera setup, keyboard, edge, and drag panning, mouse-wheel zoom, and
+//! following enemy units as they act, kept over the map.
+
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::battle_log::BattleLog;
+use crate::bindings::{ActionInput, InputAction};
+use crate::constants::*;
+use crate::grid::{GridMap, GridPosition};
+use crate::seats::Viewer;
+use crate::selection::pointer_over_ui;
+use crate::settings::Settings;
+use crate::unit::{Faction, Unit, UnitId};
+use crate::vision::FactionVision;
+
+/// Pixels of smooth (touchpad) scrolling that count as one wheel notch.
+const PIXELS_PER_NOTCH: f32 = 40.0;
+/// Wheel notches a second a fully pulled gamepad trigger zooms by.
+const GAMEPAD_ZOOM_SPEED: f32 = 6.0;
+
+/// The camera's current zoom, as its projection scale.
+pub fn zoom(projection: &Projection) -> f32 {
+    match projection {
+        Projection::Orthographic(ortho) => ortho.scale,
+        _ => 1.0,
+    }
+}
+
+pub fn setup_camera(mut commands: Commands, map: Res<GridMap>) {
+    let center = map.center();
+    commands.spawn((Camera2d, Transform::from_xyz(center.x, center.y, 100.0)));
+}
+
+/// Starts each battle over the army of the side on screen, which on a large
+/// map can be far from its middle. [`clamp_camera_system`] still centers a map that
+/// fits on screen.
+pub fn frame_player_units(
+    viewer: Res<Viewer>,
+    units: Query<(&GridPosition, &Faction), With<Unit>>,
+    mut camera: Single<&mut Transform, With<Camera2d>>,
+) {
+    let tiles = units
+        .iter()
+        .filter(|(_, faction)| **faction == viewer.0)
+        .map(|(pos, _)| pos.to_world(0.0).truncate());
+    let Some((min, max)) = tiles.fold(None, |bounds: Option<(Vec2, Vec2)>, tile| {
+        Some(bounds.map_or((tile, tile), |(min, max)| (min.min(tile), max.max(tile))))
+    }) else {
+        return;
+    };
+    camera.translation = ((min + max) / 2.0).extend(camera.translation.z);
+}
+
+/// Pans on WASD, the arrow keys, or a gamepad's right stick, and when the
+/// cursor rests at the edge of the window if edge scrolling is on.
+pub fn camera_pan_system(
+    time: Res<Time>,
+    input: ActionInput,
+    settings: Res<Settings>,
+    gamepads: Query<&Gamepad>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    mut camera: Single<&mut Transform, With<Camera2d>>,
+) {
+    let mut direction = Vec2::ZERO;
+    if input.pressed(InputAction::PanUp) {
+        direction.y += 1.0;
+    }
+    if input.pressed(InputAction::PanDown) {
+        direction.y -= 1.0;
+    }
+    if input.pressed(InputAction::PanLeft) {
+        direction.x -= 1.0;
+    }
+    if input.pressed(InputAction::PanRight) {
+        direction.x += 1.0;
+    }
+
+    let mut delta = direction.normalize_or_zero() * CAMERA_PAN_SPEED;
+    for gamepad in &gamepads {
+        delta += gamepad.right_stick().clamp_length_max(1.0) * CAMERA_PAN_SPEED;
+    }
+
+    let cursor = window.cursor_position().filter(|_| window.focused);
+    if let Some(cursor) = cursor.filter(|_| settings.edge_scroll) {
+        let size = window.size();
+        let mut edge = Vec2::ZERO;
+        if cursor.x < EDGE_SCROLL_ZONE {
+            edge.x -= 1.0;
+        } else if cursor.x > size.x - EDGE_SCROLL_ZONE {
+            edge.x += 1.0;
+        }
+        // Window coordinates grow downward.
+        if cursor.y < EDGE_SCROLL_ZONE {
+            edge.y += 1.0;
+        } else if cursor.y > size.y - EDGE_SCROLL_ZONE {
+            edge.y -= 1.0;
+        }
+        delta += edge.normalize_or_zero() * EDGE_SCROLL_SPEED;
+    }
+
+    camera.translation += (delta * time.delta_secs()).extend(0.0);
+}
+
+/// Drags the board along with the cursor while the drag button (the middle
+/// mouse button by default) is held.
+pub fn camera_drag_system(
+    input: ActionInput,
+    motion: Res<AccumulatedMouseMotion>,
+    camera: Single<(&mut Transform, &Projection), With<Camera2d>>,
+) {
+    if !input.pressed(InputAction::DragPan) || motion.delta == Vec2::ZERO {
+        return;
+    }
+    let (mut transform, projection) = camera.into_inner();
+    let scale = zoom(projection);
+    // Screen y grows downward; the board should follow the cursor.
+    let delta = motion.delta * Vec2::new(-1.0, 1.0) * scale;
+    transform.translation += delta.extend(0.0);
+}
+
+/// Zooms in and out on the mouse wheel, keeping the point under the cursor
+/// fixed on screen, or on a gamepad's triggers around the middle of the
+/// screen.
+pub fn camera_zoom_system(
+    time: Res<Time>,
+    scroll: Res<AccumulatedMouseScroll>,
+    gamepads: Query<&Gamepad>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    interactions: Query<&Interaction>,
+    camera: Single<(&mut Transform, &mut Projection), With<Camera2d>>,
+) {
+    let triggers: f32 = gamepads
+        .iter()
+        .map(|gamepad| {
+            let pull = |trigger| gamepad.get(trigger).unwrap_or(0.0);
+            pull(GamepadButton::RightTrigger2) - pull(GamepadButton::LeftTrigger2)
+        })
+        .sum();
+    let wheel = if pointer_over_ui(&interactions) {
+        0.0
+    } else {
+        match scroll.unit {
+            MouseScrollUnit::Line => scroll.delta.y,
+            MouseScrollUnit::Pixel => scroll.delta.y / PIXELS_PER_NOTCH,
+        }
+    };
+    let notches = wheel + triggers * GAMEPAD_ZOOM_SPEED * time.delta_secs();
+    if notches == 0.0 {
+        return;
+    }
+    let (mut transform, mut projection) = camera.into_inner();
+    let Projection::Orthographic(ortho) = &mut *projection else {
+        return;
+    };
+    let scale =
+        (ortho.scale * (-notches * CAMERA_ZOOM_STEP).exp()).clamp(CAMERA_MIN_ZOOM, CAMERA_MAX_ZOOM);
+    if scale == ortho.scale {
+        return;
+    }
+
+    if let Some(cursor) = window.cursor_position().filter(|_| wheel != 0.0) {
+        // How far the cursor is from the middle of the screen, in world
+        // units at the old zoom; it should be the same point at the new one.
+        let offset = (cursor - window.size() / 2.0) * Vec2::new(1.0, -1.0);
+        let anchor = transform.translation.truncate() + offset * ortho.scale;
+        let center = anchor - offset * scale;
+        transform.translation = center.extend(transform.translation.z);
+    }
+    ortho.scale = scale;
+}
+
+/// Keeps the view from straying more than [`CAMERA_EDGE_MARGIN`] past the
+/// edges of the map. When the whole map fits on screen along an axis, the
+/// camera stays centered on it instead.
+/// Whether the camera moved or zoomed, the window changed, or the map did,
+/// since the last check.
+pub fn view_changed(
+    map: Res<GridMap>,
+    window: Query<(), (With<PrimaryWindow>, Changed<Window>)>,
+    camera: Query<
+        (),
+        (
+            With<Camera2d>,
+            Or<(Changed<Transform>, Changed<Projection>)>,
+        ),
+    >,
+) -> bool {
+    map.is_changed() || !window.is_empty() || !camera.is_empty()
+}
+
+pub fn clamp_camera_system(
+    map: Res<GridMap>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&mut Transform, &Projection), With<Camera2d>>,
+) {
+    let (mut transform, projection) = camera.into_inner();
+    let scale = zoom(projection);
+    let half_view = window.size() / 2.0 * scale;
+    let half_map = map.world_size() / 2.0 + CAMERA_EDGE_MARGIN;
+    let slack = (half_map - half_view).max(Vec2::ZERO);
+    let center = map.center();
+    let clamped = transform
+        .translation
+        .truncate()
+        .clamp(center - slack, center + slack);
+    if clamped != transform.translation.truncate() {
+        transform.translation = clamped.extend(transform.translation.z);
+    }
+}
+
+/// The glide in progress toward an enemy unit that just acted.
+pub struct CameraGlide {
+    from: Vec2,
+    to: Vec2,
+    timer: Timer,
+}
+
+/// Glides the camera to each enemy unit the side on screen can see as it
+/// takes an action, so the enemy phase doesn't play out off-screen.
+pub fn camera_follow_system(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    history: Res<BattleLog>,
+    vision: Res<FactionVision>,
+    viewer: Res<Viewer>,
+    mut seen: Local<usize>,
+    mut glide: Local<Option<CameraGlide>>,
+    units: Query<(Entity, &UnitId, &Faction, &GridPosition), With<Unit>>,
+    mut camera: Single<&mut Transform, With<Camera2d>>,
+) {
+    let acted = history
+        .read_new(&mut seen)
+        .iter()
+        .rev()
+        .find_map(|(_, event)| event.actor());
+    let target = acted.and_then(|actor| {
+        units.iter().find(|(entity, id, faction, _)| {
+            **id == actor && **faction != viewer.0 && vision.can_see(viewer.0, *entity, **faction)
+        })
+    });
+    if let Some((_, _, _, pos)) = target.filter(|_| settings.camera_follow) {
+        *glide = Some(CameraGlide {
+            from: camera.translation.truncate(),
+            to: pos.to_world(0.0).truncate(),
+            timer: Timer::from_seconds(CAMERA_FOLLOW_SECONDS, TimerMode::Once),
+        });
+    }
+
+    let Some(active) = &mut *glide else {
+        return;
+    };
+    active.timer.tick(time.delta());
+    // Ease out so the camera settles gently on the unit.
+    let t = 1.0 - (1.0 - active.timer.fraction()).powi(2);
+    let at = active.from.lerp(active.to, t);
+    camera.translation = at.extend(camera.translation.z);
+    if active.timer.is_finished() {
+        *glide = None;
+    }
+}
@@ -0,0 +1,194 @@
+//! Campaign progress, persisted to a profile file between play sessions.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::class::UnitClass;
+use crate::economy::{Treasury, STARTING_GOLD};
+use crate::seats::Seats;
+use crate::turn::BattleOutcome;
+use crate::unit::{Faction, Stats, Unit, UnitId};
+
+/// Identifier of the battle currently being fought.
+#[derive(Resource, Clone, Debug)]
+pub struct CurrentScenario(pub String);
+
+impl Default for CurrentScenario {
+    fn default() -> Self {
+        Self("skirmish".to_string())
+    }
+}
+
+/// A home-side unit that survived a completed scenario.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RosterEntry {
+    pub id: UnitId,
+    pub class: UnitClass,
+    pub stats: Stats,
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CampaignProgress {
+    pub completed_scenarios: Vec<String>,
+    pub roster: Vec<RosterEntry>,
+    /// Gold the home side ended its last victory with, and starts the next
+    /// campaign battle with.
+    pub gold: u32,
+}
+
+impl Default for CampaignProgress {
+    fn default() -> Self {
+        Self {
+            completed_scenarios: Vec::new(),
+            roster: Vec::new(),
+            gold: STARTING_GOLD,
+        }
+    }
+}
+
+impl CampaignProgress {
+    /// The gold each side starts a campaign battle with: the home side
+    /// brings what it has saved up, and the other side starts as usual.
+    pub fn starting_treasury(&self, home: Faction) -> Treasury {
+        let mut treasury = Treasury::default();
+        *treasury.get_mut(home) = self.gold;
+        treasury
+    }
+}
+
+/// Whether a battle fought from these seats is part of the campaign:
+/// someone at this device plays the [home](Seats::home) side alone against
+/// the AI. Hotseat and online battles are between two people, and AI-vs-AI
+/// matches have no one to credit.
+pub fn is_campaign_battle(seats: &Seats) -> bool {
+    seats.is_local(seats.home()) && !seats.hotseat() && !seats.online()
+}
+
+/// `<data dir>/bevy-game/profile.ron`, e.g. `~/.local/share/bevy-game/profile.ron` on Linux.
+pub fn profile_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("bevy-game").join("profile.ron"))
+}
+
+/// Reads the campaign profile, starting a fresh campaign if none exists yet.
+pub fn load_campaign() -> CampaignProgress {
+    profile_path().map_or_else(CampaignProgress::default, |path| read_campaign(&path))
+}
+
+pub fn save_campaign(progress: &CampaignProgress) -> Result<(), String> {
+    let path = profile_path().ok_or("no data directory on this platform")?;
+    write_campaign(progress, &path)
+}
+
+/// Reads the profile at `path`. Fields missing from profiles written by
+/// older versions take their starting values.
+pub fn read_campaign(path: &Path) -> CampaignProgress {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return CampaignProgress::default();
+    };
+    ron::from_str(&contents).unwrap_or_else(|err| {
+        warn!("Ignoring invalid profile {}: {err}", path.display());
+        CampaignProgress::default()
+    })
+}
+
+pub fn write_campaign(progress: &CampaignProgress, path: &Path) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+    let contents = ron::ser::to_string_pretty(progress, PrettyConfig::default())
+        .map_err(|err| err.to_string())?;
+    fs::write(path, contents).map_err(|err| err.to_string())
+}
+
+/// On a campaign victory, marks the scenario completed, records the
+/// survivors and the gold left over, and writes the profile immediately so
+/// quitting afterwards loses nothing. Only a win by the
+/// [home](Seats::home) side counts.
+pub fn record_campaign_progress_system(
+    outcome: Res<BattleOutcome>,
+    seats: Res<Seats>,
+    scenario: Res<CurrentScenario>,
+    treasury: Res<Treasury>,
+    mut progress: ResMut<CampaignProgress>,
+    units: Query<(&UnitId, &UnitClass, &Faction, &Stats), With<Unit>>,
+) {
+    let home = seats.home();
+    if !outcome.is_changed() || outcome.0 != Some(home) || !is_campaign_battle(&seats) {
+        return;
+    }
+
+    if !progress.completed_scenarios.contains(&scenario.0) {
+        progress.completed_scenarios.push(scenario.0.clone());
+    }
+    let mut roster: Vec<RosterEntry> = units
+        .iter()
+        .filter(|(_, _, faction, stats)| **faction == home && stats.is_alive())
+        .map(|(&id, &class, _, &stats)| RosterEntry { id, class, stats })
+        .collect();
+    roster.sort_by_key(|entry| entry.id);
+    progress.roster = roster;
+    progress.gold = treasury.get(home);
+
+    match save_campaign(&progress) {
+        Ok(()) => info!("Campaign progress saved"),
+        Err(err) => error!("Failed to save campaign progress: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A profile file of its own for each test, in the system temp dir.
+    fn profile_file(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("bevy-game-{}", std::process::id()))
+            .join(format!("{name}.ron"))
+    }
+
+    #[test]
+    fn a_saved_profile_loads_back_unchanged() {
+        let path = profile_file("round_trip");
+        let class = UnitClass::Archer;
+        let progress = CampaignProgress {
+            completed_scenarios: vec!["skirmish".to_string()],
+            roster: vec![RosterEntry {
+                id: UnitId(4),
+                class,
+                stats: class.base_stats(),
+            }],
+            gold: 375,
+        };
+
+        write_campaign(&progress, &path).unwrap();
+        let loaded = read_campaign(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, progress);
+    }
+
+    #[test]
+    fn a_profile_from_before_gold_starts_with_the_usual_gold() {
+        let path = profile_file("before_gold");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "(completed_scenarios: [\"skirmish\"], roster: [])").unwrap();
+
+        let loaded = read_campaign(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.completed_scenarios, vec!["skirmish".to_string()]);
+        assert_eq!(loaded.gold, STARTING_GOLD);
+    }
+
+    #[test]
+    fn a_missing_profile_starts_a_fresh_campaign() {
+        let loaded = read_campaign(&profile_file("missing"));
+
+        assert_eq!(loaded, CampaignProgress::default());
+    }
+}
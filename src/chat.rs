@@ -0,0 +1,175 @@
+//! Text chat between the people in a hotseat or online match.
+//!
+//! The chat key opens a line at the bottom of the chat box; Enter sends it
+//! and Escape throws it away. While a line is open the keyboard types into
+//! it rather than playing. Each line goes into the [`ChatLog`] under the
+//! side on screen, colored like that side's units, and online a
+//! [`ChatSent`] passes it on over the same connection as the commands.
+
+#[cfg(feature = "render")]
+use bevy::input::keyboard::KeyboardInput;
+#[cfg(feature = "render")]
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "render")]
+use crate::bindings::{ActionInput, InputAction};
+#[cfg(feature = "render")]
+use crate::constants::{CHAT_HISTORY, CHAT_MAX_LEN};
+use crate::seats::Seats;
+#[cfg(feature = "render")]
+use crate::seats::Viewer;
+#[cfg(feature = "render")]
+use crate::state::AppState;
+use crate::unit::Faction;
+#[cfg(feature = "render")]
+use crate::unit::FactionPalette;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatLine {
+    pub from: Faction,
+    pub text: String,
+}
+
+/// Every line said this battle, oldest first.
+#[derive(Resource, Debug, Default)]
+pub struct ChatLog {
+    pub lines: Vec<ChatLine>,
+}
+
+/// A line typed at this device, for passing on to the other player.
+#[derive(Message, Clone, Debug)]
+pub struct ChatSent(pub ChatLine);
+
+/// Chat is open to matches with more than one person in them.
+pub fn chat_enabled(seats: Res<Seats>) -> bool {
+    seats.hotseat() || seats.online()
+}
+
+pub fn reset_chat(mut chat: ResMut<ChatLog>) {
+    chat.lines.clear();
+}
+
+/// The line being typed, while one is open.
+#[cfg(feature = "render")]
+#[derive(Resource, Debug, Default)]
+pub struct ChatDraft(pub Option<String>);
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct ChatBox;
+
+#[cfg(feature = "render")]
+pub fn setup_chat_ui(mut commands: Commands, mut draft: ResMut<ChatDraft>) {
+    draft.0 = None;
+    commands.spawn((
+        ChatBox,
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(40.0),
+            left: Val::Px(10.0),
+            width: Val::Px(360.0),
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        DespawnOnExit(AppState::GamePlay),
+    ));
+}
+
+/// Opens a line on the chat key, then types into it until it is sent or
+/// thrown away. The keys it takes are cleared so that nothing after it in
+/// the frame acts on them: Enter doesn't end the turn, and WASD don't pan.
+#[cfg(feature = "render")]
+pub fn chat_input_system(
+    input: ActionInput,
+    mut typed: MessageReader<KeyboardInput>,
+    viewer: Res<Viewer>,
+    mut draft: ResMut<ChatDraft>,
+    mut chat: ResMut<ChatLog>,
+    mut sent: MessageWriter<ChatSent>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+) {
+    let Some(line) = &mut draft.0 else {
+        typed.clear();
+        if input.just_pressed(InputAction::Chat) {
+            draft.0 = Some(String::new());
+            keys.reset_all();
+        }
+        return;
+    };
+
+    let mut done = false;
+    for key in typed.read() {
+        if key.state != ButtonState::Pressed || done {
+            continue;
+        }
+        match key.key_code {
+            KeyCode::Enter | KeyCode::NumpadEnter => {
+                let text = line.trim();
+                if !text.is_empty() {
+                    let said = ChatLine {
+                        from: viewer.0,
+                        text: text.to_string(),
+                    };
+                    chat.lines.push(said.clone());
+                    sent.write(ChatSent(said));
+                }
+                done = true;
+            }
+            KeyCode::Escape => done = true,
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            _ => {
+                let text = key.text.as_deref().unwrap_or_default();
+                for typed in text.chars().filter(|typed| !typed.is_control()) {
+                    if line.chars().count() < CHAT_MAX_LEN {
+                        line.push(typed);
+                    }
+                }
+            }
+        }
+    }
+    if done {
+        draft.0 = None;
+    }
+    keys.reset_all();
+}
+
+/// Rewrites the chat box: the last few lines, each colored by the side that
+/// said it, and the line being typed.
+#[cfg(feature = "render")]
+pub fn update_chat_ui_system(
+    mut commands: Commands,
+    chat: Res<ChatLog>,
+    draft: Res<ChatDraft>,
+    palette: Res<FactionPalette>,
+    chat_box: Single<Entity, With<ChatBox>>,
+) {
+    let font = TextFont {
+        font_size: 16.0,
+        ..default()
+    };
+    let shown = chat.lines.len().saturating_sub(CHAT_HISTORY);
+    commands
+        .entity(*chat_box)
+        .despawn_related::<Children>()
+        .with_children(|lines| {
+            for line in &chat.lines[shown..] {
+                lines.spawn((
+                    Text::new(format!("{:?}: {}", line.from, line.text)),
+                    font.clone(),
+                    TextColor(palette.color(line.from).mix(&Color::WHITE, 0.5)),
+                ));
+            }
+            if let Some(draft) = &draft.0 {
+                lines.spawn((
+                    Text::new(format!("> {draft}_")),
+                    font.clone(),
+                    TextColor(Color::WHITE),
+                    BackgroundColor(Color::BLACK.with_alpha(0.6)),
+                ));
+            }
+        });
+}
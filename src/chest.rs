@@ -0,0 +1,164 @@
+//! Treasure chests holding an item or a purse of gold.
+//!
+//! Chest placement and contents come from `assets/data/chests.ron`. A unit
+//! standing on a closed chest can spend its action opening it; the loot
+//! pops up over the chest for a moment.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "render")]
+use crate::constants::*;
+use crate::data::{load_data_file, Layered};
+use crate::grid::GridPosition;
+use crate::items::{ItemCatalog, ItemId};
+use crate::state::AppState;
+
+/// Seconds the loot notification stays up after a chest is opened.
+#[cfg(feature = "render")]
+const POPUP_SECONDS: f32 = 1.5;
+/// World units the notification floats up over its lifetime.
+#[cfg(feature = "render")]
+const POPUP_RISE: f32 = TILE_SIZE * 0.6;
+
+#[cfg(feature = "render")]
+const CHEST_COLOR: Color = Color::srgb(0.80, 0.60, 0.15);
+#[cfg(feature = "render")]
+const OPENED_COLOR: Color = Color::srgb(0.40, 0.30, 0.15);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub enum Loot {
+    Item(ItemId),
+    Gold(u32),
+}
+
+impl Loot {
+    pub fn label(&self, catalog: &ItemCatalog) -> String {
+        match self {
+            Loot::Item(id) => catalog.name(id).to_string(),
+            Loot::Gold(amount) => format!("{amount} gold"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChestDef {
+    pub at: GridPosition,
+    pub loot: Loot,
+}
+
+/// Every chest on the default battlefield.
+#[derive(Resource, Clone, Debug, Default, Deserialize)]
+pub struct ChestLayout {
+    pub chests: Vec<ChestDef>,
+}
+
+impl ChestLayout {
+    pub fn sites(&self) -> impl Iterator<Item = GridPosition> + '_ {
+        self.chests.iter().map(|chest| chest.at)
+    }
+}
+
+/// Chests are told apart by where they stand.
+impl Layered for ChestLayout {
+    fn ids(&self) -> Vec<String> {
+        self.sites()
+            .map(|at| format!("chest at ({}, {})", at.x, at.y))
+            .collect()
+    }
+
+    fn overlay(&mut self, layer: Self) {
+        for chest in layer.chests {
+            match self.chests.iter_mut().find(|known| known.at == chest.at) {
+                Some(known) => *known = chest,
+                None => self.chests.push(chest),
+            }
+        }
+    }
+}
+
+pub fn load_chest_layout() -> ChestLayout {
+    load_data_file("chests.ron")
+}
+
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Reflect)]
+#[reflect(Component)]
+pub struct Chest {
+    pub loot: Loot,
+    pub opened: bool,
+}
+
+/// Floating text announcing what came out of a chest.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct LootPopup(Timer);
+
+pub fn spawn_chests(mut commands: Commands, layout: Res<ChestLayout>, state: Res<State<AppState>>) {
+    for chest in &layout.chests {
+        commands.spawn((
+            Chest {
+                loot: chest.loot.clone(),
+                opened: false,
+            },
+            chest.at,
+            DespawnOnExit(*state.get()),
+        ));
+    }
+}
+
+/// Gives newly placed chests their sprite.
+#[cfg(feature = "render")]
+pub fn add_chest_sprites_system(
+    mut commands: Commands,
+    chests: Query<(Entity, &GridPosition), Added<Chest>>,
+) {
+    for (entity, pos) in &chests {
+        commands.entity(entity).insert((
+            Sprite::from_color(CHEST_COLOR, Vec2::new(TILE_SIZE * 0.5, TILE_SIZE * 0.35)),
+            Transform::from_translation(pos.to_world(STRUCTURE_Z + 0.1)),
+        ));
+    }
+}
+
+/// Darkens opened chests and pops up a note of what they held.
+#[cfg(feature = "render")]
+pub fn update_chest_visuals_system(
+    mut commands: Commands,
+    catalog: Res<ItemCatalog>,
+    mut chests: Query<(Entity, &Chest, &mut Sprite), Changed<Chest>>,
+) {
+    for (entity, chest, mut sprite) in &mut chests {
+        if !chest.opened {
+            continue;
+        }
+        sprite.color = OPENED_COLOR;
+        commands.entity(entity).with_child((
+            LootPopup(Timer::from_seconds(POPUP_SECONDS, TimerMode::Once)),
+            Text2d::new(chest.loot.label(&catalog)),
+            TextFont {
+                font_size: TILE_SIZE * 0.3,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            Transform::from_xyz(0.0, TILE_SIZE * 0.3, UNIT_Z),
+        ));
+    }
+}
+
+/// Floats loot notifications upward, fading them out before removing them.
+#[cfg(feature = "render")]
+pub fn animate_loot_popups_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut popups: Query<(Entity, &mut LootPopup, &mut Transform, &mut TextColor)>,
+) {
+    for (entity, mut popup, mut transform, mut color) in &mut popups {
+        popup.0.tick(time.delta());
+        let progress = popup.0.fraction();
+        transform.translation.y = TILE_SIZE * 0.3 + POPUP_RISE * progress;
+        color.0 = color.0.with_alpha(1.0 - progress);
+        if popup.0.is_finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
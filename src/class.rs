@@ -0,0 +1,311 @@
+//! Unit classes, experience, and promotion to advanced classes.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::unit::{AttackRange, MoveRange, Stats};
+use crate::vision::Vision;
+
+/// Experience needed to gain one level.
+pub const XP_PER_LEVEL: u32 = 100;
+/// Experience for landing an attack, and the bonus for finishing the target.
+pub const XP_PER_ATTACK: u32 = 30;
+pub const XP_PER_KILL: u32 = 40;
+/// Experience for healing an ally.
+pub const XP_PER_HEAL: u32 = 25;
+/// HP restored by a heal on top of the healer's attack stat.
+pub const HEAL_BASE: u32 = 4;
+/// Level at which a base class may promote.
+pub const PROMOTION_LEVEL: u32 = 3;
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
+pub enum UnitClass {
+    /// Heavily armored melee fighter.
+    Knight,
+    /// Fragile but mobile, strikes from two tiles away.
+    Archer,
+    /// Hits hardest of all at range, but can barely take a hit.
+    Mage,
+    /// Weak in combat; exists to keep the rest of the army standing.
+    Healer,
+    // Advanced classes, reached only through promotion.
+    Paladin,
+    General,
+    Sniper,
+    Ranger,
+    Sage,
+    Bishop,
+}
+
+/// Passive combat abilities granted by advanced classes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClassAbility {
+    /// +2 attack when the unit moved before attacking this turn.
+    Charge,
+    /// Incoming damage is reduced by 1.
+    Bulwark,
+    /// Damage rolls always land at their maximum.
+    Deadeye,
+    /// Attacks ignore half of the defender's defense.
+    Arcane,
+}
+
+impl ClassAbility {
+    pub fn description(self) -> &'static str {
+        match self {
+            ClassAbility::Charge => "Charge: +2 attack after moving",
+            ClassAbility::Bulwark => "Bulwark: -1 damage taken",
+            ClassAbility::Deadeye => "Deadeye: always rolls max damage",
+            ClassAbility::Arcane => "Arcane: ignores half of defense",
+        }
+    }
+}
+
+impl UnitClass {
+    pub const ALL: [UnitClass; 10] = [
+        UnitClass::Knight,
+        UnitClass::Archer,
+        UnitClass::Mage,
+        UnitClass::Healer,
+        UnitClass::Paladin,
+        UnitClass::General,
+        UnitClass::Sniper,
+        UnitClass::Ranger,
+        UnitClass::Sage,
+        UnitClass::Bishop,
+    ];
+
+    pub fn base_stats(self) -> Stats {
+        let (hp, attack, defense) = match self {
+            UnitClass::Knight => (14, 6, 4),
+            UnitClass::Archer => (10, 5, 2),
+            UnitClass::Mage => (8, 7, 1),
+            UnitClass::Healer => (8, 2, 1),
+            UnitClass::Paladin => (16, 8, 5),
+            UnitClass::General => (20, 7, 7),
+            UnitClass::Sniper => (12, 8, 3),
+            UnitClass::Ranger => (13, 7, 3),
+            UnitClass::Sage => (11, 9, 2),
+            UnitClass::Bishop => (11, 4, 3),
+        };
+        Stats {
+            hp,
+            max_hp: hp,
+            attack,
+            defense,
+        }
+    }
+
+    pub fn move_range(self) -> MoveRange {
+        match self {
+            UnitClass::Knight | UnitClass::Mage | UnitClass::General | UnitClass::Sage => {
+                MoveRange(3)
+            }
+            UnitClass::Archer
+            | UnitClass::Healer
+            | UnitClass::Paladin
+            | UnitClass::Sniper
+            | UnitClass::Bishop => MoveRange(4),
+            UnitClass::Ranger => MoveRange(5),
+        }
+    }
+
+    /// Scouting classes see further than the rest.
+    pub fn vision(self) -> Vision {
+        let range = match self {
+            UnitClass::Archer | UnitClass::Sniper | UnitClass::Ranger => 5,
+            _ => 4,
+        };
+        Vision { range }
+    }
+
+    pub fn attack_range(self) -> AttackRange {
+        match self {
+            UnitClass::Knight
+            | UnitClass::Healer
+            | UnitClass::Paladin
+            | UnitClass::General
+            | UnitClass::Bishop => AttackRange(1),
+            UnitClass::Archer | UnitClass::Mage | UnitClass::Ranger | UnitClass::Sage => {
+                AttackRange(2)
+            }
+            UnitClass::Sniper => AttackRange(3),
+        }
+    }
+
+    /// Whether the class can only attack with a weapon equipped. Melee
+    /// classes can still fight bare-handed.
+    pub fn needs_weapon(self) -> bool {
+        matches!(
+            self,
+            UnitClass::Archer
+                | UnitClass::Mage
+                | UnitClass::Sniper
+                | UnitClass::Ranger
+                | UnitClass::Sage
+        )
+    }
+
+    /// Gold needed to recruit a unit of this class.
+    pub fn cost(self) -> u32 {
+        match self {
+            UnitClass::Healer => 200,
+            UnitClass::Archer => 250,
+            UnitClass::Knight | UnitClass::Mage => 300,
+            _ => 500,
+        }
+    }
+
+    /// How far away this class can heal an ally, for classes that can heal.
+    pub fn heal_range(self) -> Option<u32> {
+        match self {
+            UnitClass::Healer => Some(1),
+            UnitClass::Bishop => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Whether units of this class spot hidden traps near where they stop.
+    pub fn spots_traps(self) -> bool {
+        matches!(
+            self,
+            UnitClass::Archer | UnitClass::Sniper | UnitClass::Ranger
+        )
+    }
+
+    /// Whether units of this class start battles hidden and can hide again.
+    pub fn stealthy(self) -> bool {
+        matches!(
+            self,
+            UnitClass::Archer | UnitClass::Sniper | UnitClass::Ranger
+        )
+    }
+
+    /// How close a hidden enemy must be for units of this class to notice
+    /// it. Mages and sages have true sight and pick out stealth from afar.
+    pub fn detection_range(self) -> u32 {
+        match self {
+            UnitClass::Mage | UnitClass::Sage => 3,
+            _ => 1,
+        }
+    }
+
+    /// Extra attack the class fights with at night.
+    pub fn night_attack_bonus(self) -> u32 {
+        match self {
+            UnitClass::Mage | UnitClass::Sage | UnitClass::Ranger => 1,
+            _ => 0,
+        }
+    }
+
+    pub fn ability(self) -> Option<ClassAbility> {
+        match self {
+            UnitClass::Paladin | UnitClass::Ranger => Some(ClassAbility::Charge),
+            UnitClass::General | UnitClass::Bishop => Some(ClassAbility::Bulwark),
+            UnitClass::Sniper => Some(ClassAbility::Deadeye),
+            UnitClass::Sage => Some(ClassAbility::Arcane),
+            _ => None,
+        }
+    }
+
+    /// The advanced classes this class can promote into.
+    pub fn promotions(self) -> &'static [UnitClass] {
+        match self {
+            UnitClass::Knight => &[UnitClass::Paladin, UnitClass::General],
+            UnitClass::Archer => &[UnitClass::Sniper, UnitClass::Ranger],
+            UnitClass::Mage => &[UnitClass::Sage],
+            UnitClass::Healer => &[UnitClass::Bishop],
+            _ => &[],
+        }
+    }
+
+    /// Whether a unit of this class at the given experience may become `into`.
+    pub fn can_promote(self, experience: &Experience, into: UnitClass) -> bool {
+        experience.level >= PROMOTION_LEVEL && self.promotions().contains(&into)
+    }
+
+    /// Short label drawn on top of the unit's sprite.
+    pub fn glyph(self) -> &'static str {
+        match self {
+            UnitClass::Knight => "K",
+            UnitClass::Archer => "A",
+            UnitClass::Mage => "M",
+            UnitClass::Healer => "H",
+            UnitClass::Paladin => "P",
+            UnitClass::General => "G",
+            UnitClass::Sniper => "S",
+            UnitClass::Ranger => "R",
+            UnitClass::Sage => "Sa",
+            UnitClass::Bishop => "B",
+        }
+    }
+
+    /// Relative sprite size, so silhouettes differ as well as labels.
+    pub fn sprite_scale(self) -> f32 {
+        match self {
+            UnitClass::Knight | UnitClass::Paladin | UnitClass::General => 1.0,
+            UnitClass::Archer
+            | UnitClass::Mage
+            | UnitClass::Sniper
+            | UnitClass::Ranger
+            | UnitClass::Sage => 0.85,
+            UnitClass::Healer | UnitClass::Bishop => 0.75,
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
+pub struct Experience {
+    pub level: u32,
+    pub xp: u32,
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Self { level: 1, xp: 0 }
+    }
+}
+
+impl Experience {
+    /// Adds experience, returning how many levels were gained.
+    pub fn gain(&mut self, amount: u32) -> u32 {
+        self.xp += amount;
+        let levels = self.xp / XP_PER_LEVEL;
+        self.xp %= XP_PER_LEVEL;
+        self.level += levels;
+        levels
+    }
+}
+
+/// HP restored when a unit with these stats heals an ally.
+pub fn heal_amount(stats: &Stats) -> u32 {
+    HEAL_BASE + stats.attack
+}
+
+/// Stat growth applied on every level up.
+pub fn level_up(stats: &mut Stats) {
+    stats.hp += 2;
+    stats.max_hp += 2;
+    stats.attack += 1;
+}
+
+/// Switches a unit to its advanced class, keeping any growth from levels
+/// by adding the difference between the two classes' base stats.
+pub fn promote(
+    from: UnitClass,
+    into: UnitClass,
+    stats: &mut Stats,
+    move_range: &mut MoveRange,
+    attack_range: &mut AttackRange,
+) {
+    let old = from.base_stats();
+    let new = into.base_stats();
+    stats.hp = (stats.hp + new.hp).saturating_sub(old.hp).max(1);
+    stats.max_hp = (stats.max_hp + new.hp).saturating_sub(old.hp).max(1);
+    stats.attack = (stats.attack + new.attack).saturating_sub(old.attack);
+    stats.defense = (stats.defense + new.defense).saturating_sub(old.defense);
+    *move_range = into.move_range();
+    *attack_range = into.attack_range();
+}
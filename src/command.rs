@@ -0,0 +1,620 @@
+//! Serializable game commands and the executor that applies them.
+//!
+//! Every change to the battle goes through a [`GameCommand`]: player input,
+//! the AI, and replay playback all write commands, and [`execute_commands`]
+//! is the only system that applies them. Given the same seed, the same
+//! command sequence always produces the same battle.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::ecs::query::QueryData;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ability::{AbilityCatalog, AbilityError, Cooldowns};
+#[cfg(feature = "render")]
+use crate::animation::Dying;
+use crate::battle_log::{BattleEvent, BattleLog};
+use crate::board::{Board, BoardUnit, Catalogs};
+use crate::chest::Chest;
+use crate::class::{Experience, UnitClass};
+use crate::economy::{NextUnitId, Treasury};
+use crate::facing::Facing;
+use crate::fire::Fires;
+use crate::grid::{BattleMap, GridMap, GridPosition};
+use crate::items::{EquipSlot, Equipment, Inventory, ItemCatalog};
+use crate::leader::{AuraBonus, Leader};
+use crate::rescue::Rescue;
+use crate::rules::{next_turn, AttackError, GameRng, MoveError, OutOfTurn};
+use crate::state::AppState;
+use crate::status::StatusEffects;
+use crate::stealth::Stealth;
+use crate::structure::Structure;
+use crate::tiles::TileRegistry;
+use crate::trap::Trap;
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::{spawn_unit, AttackRange, Faction, MoveRange, Stats, TurnStatus, Unit, UnitId};
+use crate::vision::Flares;
+
+#[derive(Message, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameCommand {
+    Move {
+        unit: UnitId,
+        to: GridPosition,
+    },
+    Attack {
+        attacker: UnitId,
+        target: UnitId,
+    },
+    /// Restore HP to a wounded ally within the healer's heal range.
+    Heal {
+        healer: UnitId,
+        target: UnitId,
+    },
+    /// Pick up an adjacent ally and carry it. Ends the unit's turn.
+    PickUp {
+        unit: UnitId,
+        target: UnitId,
+    },
+    /// Set the carried ally down on a free tile next to the carrier. Ends the
+    /// unit's turn.
+    DropOff {
+        unit: UnitId,
+        to: GridPosition,
+    },
+    /// Open or close the door at `at`, or pull the lever there to raise or
+    /// lower its bridge. The tile must be next to the unit or under it.
+    /// Ends the unit's turn.
+    Operate {
+        unit: UnitId,
+        at: GridPosition,
+    },
+    /// Buy a new unit on a recruit site `faction` owns. The recruit can't
+    /// act until its side's next turn.
+    Recruit {
+        faction: Faction,
+        class: UnitClass,
+        at: GridPosition,
+    },
+    /// Spend the unit's action opening the chest it stands on.
+    OpenChest {
+        unit: UnitId,
+    },
+    /// Spend the unit's action capturing the structure it stands on.
+    Capture {
+        unit: UnitId,
+    },
+    /// Spend the unit's action hiding from the other side. Only stealthy
+    /// classes can hide, and not while an enemy would notice them.
+    Hide {
+        unit: UnitId,
+    },
+    /// End a unit's turn without attacking.
+    Wait {
+        unit: UnitId,
+    },
+    /// Throw away the item in an inventory slot. Does not use up the turn.
+    DiscardItem {
+        unit: UnitId,
+        slot: usize,
+    },
+    /// Use up the consumable in an inventory slot. Ends the unit's turn.
+    UseItem {
+        unit: UnitId,
+        slot: usize,
+    },
+    /// Equip the weapon or armor in an inventory slot, swapping out whatever
+    /// was equipped before. Does not use up the turn.
+    Equip {
+        unit: UnitId,
+        slot: usize,
+    },
+    /// Move an equipped item back into the inventory.
+    Unequip {
+        unit: UnitId,
+        slot: EquipSlot,
+    },
+    /// Use the ability in `slot` of the unit's class's list, aimed at `at`.
+    /// Ends the unit's turn.
+    UseAbility {
+        unit: UnitId,
+        slot: usize,
+        at: GridPosition,
+    },
+    /// Advance a unit that reached the promotion level into `class`.
+    Promote {
+        unit: UnitId,
+        class: UnitClass,
+    },
+    EndTurn,
+}
+
+impl GameCommand {
+    /// The unit carrying the command out, for commands given to one unit.
+    pub fn unit(&self) -> Option<UnitId> {
+        match *self {
+            GameCommand::Attack { attacker, .. } => Some(attacker),
+            GameCommand::Heal { healer, .. } => Some(healer),
+            GameCommand::Move { unit, .. }
+            | GameCommand::PickUp { unit, .. }
+            | GameCommand::DropOff { unit, .. }
+            | GameCommand::Operate { unit, .. }
+            | GameCommand::OpenChest { unit }
+            | GameCommand::Capture { unit }
+            | GameCommand::Hide { unit }
+            | GameCommand::Wait { unit }
+            | GameCommand::DiscardItem { unit, .. }
+            | GameCommand::UseItem { unit, .. }
+            | GameCommand::Equip { unit, .. }
+            | GameCommand::Unequip { unit, .. }
+            | GameCommand::UseAbility { unit, .. }
+            | GameCommand::Promote { unit, .. } => Some(unit),
+            GameCommand::Recruit { .. } | GameCommand::EndTurn => None,
+        }
+    }
+}
+
+/// The seed, the map, and every command applied so far in the current
+/// battle.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandLog {
+    pub seed: u64,
+    /// Logs saved before there was a choice of map were all fought on the
+    /// default one.
+    #[serde(default)]
+    pub map: BattleMap,
+    /// Gold each side started with. Logs saved before campaign gold carried
+    /// over started both sides on [`STARTING_GOLD`](crate::economy::STARTING_GOLD).
+    #[serde(default)]
+    pub treasury: Treasury,
+    pub commands: Vec<GameCommand>,
+}
+
+impl CommandLog {
+    /// The side whose turn comes after the logged commands: the player's
+    /// side moves first, and every [`GameCommand::EndTurn`] hands the turn
+    /// over.
+    pub fn side_to_move(&self) -> Faction {
+        self.commands
+            .iter()
+            .filter(|&&command| command == GameCommand::EndTurn)
+            .fold(CurrentTurn::default(), |turn, _| next_turn(turn))
+            .faction
+    }
+}
+
+/// Why the executor turned a command down. Rejected commands change nothing,
+/// even those turned down partway through, and are left out of the
+/// [`CommandLog`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// One side has already won.
+    BattleOver,
+    /// No unit on the board has the id the command gives for the unit
+    /// carrying it out.
+    UnknownUnit,
+    /// No unit on the board has the id the command gives for its target.
+    UnknownTarget,
+    /// The unit belongs to the side not taking its turn.
+    OutOfTurn,
+    Move(MoveError),
+    Attack(AttackError),
+    Ability(AbilityError),
+    AlreadyActed,
+    /// The command names the same unit twice.
+    InvalidPair,
+    CarriedUnitMissing,
+    CannotHeal,
+    HealEnemy,
+    FullHealth,
+    OutOfRange,
+    CarryEnemy,
+    AlreadyCarrying,
+    NotAdjacent,
+    NotCarrying,
+    CannotDrop,
+    TooFar,
+    DoorwayBlocked,
+    LeverNotConnected,
+    BridgeBlocked,
+    NothingToOperate,
+    NotRecruitable,
+    NotRecruitSite,
+    SiteOccupied,
+    NotEnoughGold,
+    NoChest,
+    InventoryFull,
+    NoStructure,
+    AlreadyOwned,
+    CannotHide,
+    AlreadyHidden,
+    /// An enemy is close enough to see the unit slip away.
+    Watched,
+    EmptySlot,
+    QuestItem,
+    NotUsable,
+    NothingToCure,
+    NotEquippable,
+    NothingEquipped,
+    NoSuchAbility,
+    CannotPromote,
+}
+
+impl Rejection {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Rejection::BattleOver => "the battle is already over",
+            Rejection::UnknownUnit => "unknown unit",
+            Rejection::UnknownTarget => "unknown target",
+            Rejection::OutOfTurn => OutOfTurn.reason(),
+            Rejection::Move(error) => error.reason(),
+            Rejection::Attack(error) => error.reason(),
+            Rejection::Ability(error) => error.reason(),
+            Rejection::AlreadyActed => "unit has already acted",
+            Rejection::InvalidPair => "a unit cannot do that to itself",
+            Rejection::CarriedUnitMissing => "carried unit is missing",
+            Rejection::CannotHeal => "unit cannot heal",
+            Rejection::HealEnemy => "cannot heal an enemy",
+            Rejection::FullHealth => "already at full health",
+            Rejection::OutOfRange => "target is out of range",
+            Rejection::CarryEnemy => "cannot carry an enemy",
+            Rejection::AlreadyCarrying => "already carrying a unit",
+            Rejection::NotAdjacent => "target is not adjacent",
+            Rejection::NotCarrying => "not carrying a unit",
+            Rejection::CannotDrop => "cannot drop a unit there",
+            Rejection::TooFar => "too far away to operate",
+            Rejection::DoorwayBlocked => "the doorway is blocked",
+            Rejection::LeverNotConnected => "lever is not connected",
+            Rejection::BridgeBlocked => "the bridge is blocked",
+            Rejection::NothingToOperate => "nothing to operate there",
+            Rejection::NotRecruitable => "class cannot be recruited",
+            Rejection::NotRecruitSite => "not an owned recruit site",
+            Rejection::SiteOccupied => "recruit site is occupied",
+            Rejection::NotEnoughGold => "not enough gold",
+            Rejection::NoChest => "no closed chest on this tile",
+            Rejection::InventoryFull => "inventory is full",
+            Rejection::NoStructure => "no structure on this tile",
+            Rejection::AlreadyOwned => "structure is already owned",
+            Rejection::CannotHide => "unit cannot hide",
+            Rejection::AlreadyHidden => "unit is already hidden",
+            Rejection::Watched => "an enemy is watching",
+            Rejection::EmptySlot => "empty inventory slot",
+            Rejection::QuestItem => "quest items cannot be discarded",
+            Rejection::NotUsable => "item cannot be used",
+            Rejection::NothingToCure => "nothing to cure",
+            Rejection::NotEquippable => "item cannot be equipped",
+            Rejection::NothingEquipped => "nothing equipped in that slot",
+            Rejection::NoSuchAbility => "unit has no such ability",
+            Rejection::CannotPromote => "unit cannot promote into that class",
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.reason())
+    }
+}
+
+impl From<OutOfTurn> for Rejection {
+    fn from(_: OutOfTurn) -> Self {
+        Rejection::OutOfTurn
+    }
+}
+
+impl From<MoveError> for Rejection {
+    fn from(error: MoveError) -> Self {
+        Rejection::Move(error)
+    }
+}
+
+impl From<AttackError> for Rejection {
+    fn from(error: AttackError) -> Self {
+        Rejection::Attack(error)
+    }
+}
+
+impl From<AbilityError> for Rejection {
+    fn from(error: AbilityError) -> Self {
+        Rejection::Ability(error)
+    }
+}
+
+/// A command the executor turned down, and why.
+#[derive(Message, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommandRejected {
+    pub command: GameCommand,
+    pub reason: Rejection,
+}
+
+/// A seed for a new battle when none was requested.
+pub fn fresh_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+/// Puts the shared battle resources back to their starting values, with
+/// each side holding `gold`.
+pub fn reset_battle(
+    seed: u64,
+    gold: Treasury,
+    rng: &mut GameRng,
+    turn: &mut CurrentTurn,
+    outcome: &mut BattleOutcome,
+    history: &mut BattleLog,
+    treasury: &mut Treasury,
+    fires: &mut Fires,
+    flares: &mut Flares,
+) {
+    *rng = GameRng::new(seed);
+    *treasury = gold;
+    *fires = Fires::default();
+    *flares = Flares::default();
+    *turn = CurrentTurn::default();
+    *outcome = BattleOutcome::default();
+    *history = BattleLog::new(seed);
+}
+
+/// Everything the executor reads or changes on a unit.
+#[derive(QueryData)]
+#[query_data(mutable)]
+pub struct UnitState {
+    entity: Entity,
+    id: &'static UnitId,
+    faction: &'static Faction,
+    class: &'static mut UnitClass,
+    pos: &'static mut GridPosition,
+    stats: &'static mut Stats,
+    status: &'static mut TurnStatus,
+    move_range: &'static mut MoveRange,
+    attack_range: &'static mut AttackRange,
+    experience: &'static mut Experience,
+    inventory: &'static mut Inventory,
+    equipment: &'static mut Equipment,
+    effects: &'static mut StatusEffects,
+    leader: Option<&'static Leader>,
+    aura: &'static mut AuraBonus,
+    rescue: &'static mut Rescue,
+    stealth: &'static mut Stealth,
+    facing: &'static mut Facing,
+    cooldowns: &'static mut Cooldowns,
+}
+
+type UnitQuery<'w, 's> = Query<'w, 's, UnitState, With<Unit>>;
+type StructureQuery<'w, 's> =
+    Query<'w, 's, (&'static GridPosition, &'static mut Structure), Without<Unit>>;
+type ChestQuery<'w, 's> = Query<'w, 's, (&'static GridPosition, &'static mut Chest), Without<Unit>>;
+type TrapQuery<'w, 's> = Query<'w, 's, (&'static GridPosition, &'static mut Trap), Without<Unit>>;
+
+/// Copies the battle out of the world.
+fn board_from_world(
+    map: &GridMap,
+    units: &UnitQuery,
+    structures: &StructureQuery,
+    traps: &TrapQuery,
+    chests: &ChestQuery,
+) -> Board {
+    Board {
+        units: units
+            .iter()
+            .map(|unit| BoardUnit {
+                id: *unit.id,
+                faction: *unit.faction,
+                class: *unit.class,
+                pos: *unit.pos,
+                stats: *unit.stats,
+                status: *unit.status,
+                move_range: *unit.move_range,
+                attack_range: *unit.attack_range,
+                experience: *unit.experience,
+                inventory: unit.inventory.clone(),
+                equipment: unit.equipment.clone(),
+                effects: unit.effects.clone(),
+                leader: unit.leader.copied(),
+                aura: *unit.aura,
+                rescue: *unit.rescue,
+                stealth: *unit.stealth,
+                facing: *unit.facing,
+                cooldowns: unit.cooldowns.clone(),
+            })
+            .collect(),
+        structures: structures
+            .iter()
+            .map(|(pos, structure)| (*pos, structure.clone()))
+            .collect(),
+        traps: traps
+            .iter()
+            .map(|(pos, trap)| (*pos, trap.clone()))
+            .collect(),
+        chests: chests
+            .iter()
+            .map(|(pos, chest)| (*pos, chest.clone()))
+            .collect(),
+        map: map.clone(),
+        ..default()
+    }
+}
+
+/// Copies `board` back into the world, in the order it was copied out,
+/// leaving whatever didn't change untouched so change detection only sees
+/// what a command did.
+fn board_to_world(
+    board: Board,
+    units: &mut UnitQuery,
+    structures: &mut StructureQuery,
+    traps: &mut TrapQuery,
+    chests: &mut ChestQuery,
+) {
+    for (mut unit, new) in units.iter_mut().zip(board.units) {
+        unit.class.set_if_neq(new.class);
+        unit.pos.set_if_neq(new.pos);
+        unit.stats.set_if_neq(new.stats);
+        unit.status.set_if_neq(new.status);
+        unit.move_range.set_if_neq(new.move_range);
+        unit.attack_range.set_if_neq(new.attack_range);
+        unit.experience.set_if_neq(new.experience);
+        unit.inventory.set_if_neq(new.inventory);
+        unit.equipment.set_if_neq(new.equipment);
+        unit.effects.set_if_neq(new.effects);
+        unit.aura.set_if_neq(new.aura);
+        unit.rescue.set_if_neq(new.rescue);
+        unit.stealth.set_if_neq(new.stealth);
+        unit.facing.set_if_neq(new.facing);
+        unit.cooldowns.set_if_neq(new.cooldowns);
+    }
+    for ((_, mut structure), (_, new)) in structures.iter_mut().zip(board.structures) {
+        structure.set_if_neq(new);
+    }
+    for ((_, mut trap), (_, new)) in traps.iter_mut().zip(board.traps) {
+        trap.set_if_neq(new);
+    }
+    for ((_, mut chest), (_, new)) in chests.iter_mut().zip(board.chests) {
+        chest.set_if_neq(new);
+    }
+}
+
+/// Takes a unit that just fell off the board.
+fn remove_fallen(commands: &mut Commands, entity: Entity) {
+    let mut fallen = commands.entity(entity);
+    fallen.remove::<Unit>();
+    // Without a sprite to fade out, the fallen leave the board at once.
+    #[cfg(feature = "render")]
+    fallen.insert(Dying::default());
+    #[cfg(not(feature = "render"))]
+    fallen.despawn();
+}
+
+/// Applies incoming commands in order on a [`Board`] copied out of the
+/// world, then copies it back, and reports each command it turns down as a
+/// [`CommandRejected`].
+///
+/// A recruited unit only exists once this system's [`Commands`] are applied,
+/// so after a recruit, or an ability bringing on units, the rest of the
+/// batch waits for the next frame. So does the rest of the batch after a
+/// turn starts or a unit falls, in case a scenario script brings on units
+/// then. This matters when a suspended battle is resumed and the whole log
+/// arrives at once.
+pub fn execute_commands(
+    mut incoming: MessageReader<GameCommand>,
+    mut queue: Local<VecDeque<GameCommand>>,
+    mut log: ResMut<CommandLog>,
+    state: Res<State<AppState>>,
+    (mut turn, mut rng, mut history, outcome): (
+        ResMut<CurrentTurn>,
+        ResMut<GameRng>,
+        ResMut<BattleLog>,
+        Res<BattleOutcome>,
+    ),
+    mut map: ResMut<GridMap>,
+    (items, abilities, tiles): (Res<ItemCatalog>, Res<AbilityCatalog>, Res<TileRegistry>),
+    mut units: UnitQuery,
+    mut structures: StructureQuery,
+    (mut traps, mut chests, mut fires, mut flares): (
+        TrapQuery,
+        ChestQuery,
+        ResMut<Fires>,
+        ResMut<Flares>,
+    ),
+    (mut treasury, mut next_id, mut rejected): (
+        ResMut<Treasury>,
+        ResMut<NextUnitId>,
+        MessageWriter<CommandRejected>,
+    ),
+    mut commands: Commands,
+) {
+    if state.is_changed() {
+        queue.clear();
+    }
+    queue.extend(incoming.read().copied());
+    if queue.is_empty() {
+        return;
+    }
+
+    let mut board = Board {
+        fires: fires.clone(),
+        flares: flares.clone(),
+        turn: *turn,
+        treasury: *treasury,
+        rng: rng.clone(),
+        history: std::mem::take(&mut *history),
+        next_id: *next_id,
+        ..board_from_world(&map, &units, &structures, &traps, &chests)
+    };
+    let data = Catalogs {
+        items: &items,
+        abilities: &abilities,
+        tiles: &tiles,
+    };
+    let recorded = board.history.entries.len();
+    while let Some(command) = queue.pop_front() {
+        let before = board.history.entries.len();
+        let result = if outcome.0.is_some() {
+            Err(Rejection::BattleOver)
+        } else {
+            board.apply(command, data)
+        };
+
+        match result {
+            Ok(()) => {
+                if *state.get() == AppState::GamePlay {
+                    log.commands.push(command);
+                }
+                let spawns = board.history.entries[before..].iter().any(|(_, event)| {
+                    matches!(
+                        event,
+                        BattleEvent::TurnStarted { .. }
+                            | BattleEvent::Died { .. }
+                            | BattleEvent::Arrived { .. }
+                    )
+                });
+                if matches!(command, GameCommand::Recruit { .. }) || spawns {
+                    break;
+                }
+            }
+            Err(reason) => {
+                warn!("Rejected {command:?}: {reason}");
+                rejected.write(CommandRejected { command, reason });
+            }
+        }
+    }
+    board.refresh_auras();
+
+    let fallen: Vec<UnitId> = board.history.entries[recorded..]
+        .iter()
+        .filter_map(|(_, event)| match event {
+            BattleEvent::Died { unit } => Some(*unit),
+            _ => None,
+        })
+        .collect();
+    for unit in units.iter().filter(|unit| fallen.contains(unit.id)) {
+        remove_fallen(&mut commands, unit.entity);
+    }
+    for arrival in board.arrivals.drain(..) {
+        let entity = spawn_unit(
+            &mut commands,
+            &items,
+            *state.get(),
+            arrival.unit,
+            arrival.faction,
+            arrival.class,
+            arrival.at,
+        );
+        commands.entity(entity).insert(TurnStatus {
+            has_moved: true,
+            has_acted: true,
+        });
+    }
+
+    map.set_if_neq(std::mem::take(&mut board.map));
+    fires.set_if_neq(std::mem::take(&mut board.fires));
+    flares.set_if_neq(std::mem::take(&mut board.flares));
+    turn.set_if_neq(board.turn);
+    treasury.set_if_neq(board.treasury);
+    next_id.set_if_neq(board.next_id);
+    *rng = std::mem::take(&mut board.rng);
+    *history = std::mem::take(&mut board.history);
+    board_to_world(board, &mut units, &mut structures, &mut traps, &mut chests);
+}
@@ -0,0 +1,143 @@
+//! Tunable constants shared across the game's systems.
+
+use bevy::prelude::*;
+
+/// Width of the default battlefield, in tiles.
+pub const GRID_WIDTH: i32 = 12;
+/// Height of the default battlefield, in tiles.
+pub const GRID_HEIGHT: i32 = 8;
+/// Seed for the default battlefield's water and mountains. Every map's seed
+/// is fixed, so a battle and its replay are fought on the same ground.
+pub const MAP_SEED: u64 = 1620;
+/// Columns on each edge of the map kept clear for deploying units.
+pub const DEPLOY_COLUMNS: i32 = 3;
+/// Side length of a single tile, in world units.
+pub const TILE_SIZE: f32 = 48.0;
+/// Tiles along each side of the chunks the board is drawn in. Each chunk
+/// is a single mesh, so even a large map takes only a few hundred.
+pub const CHUNK_SIZE: i32 = 16;
+/// Side length of a unit sprite, in world units.
+pub const UNIT_SIZE: f32 = TILE_SIZE * 0.7;
+
+/// Draw order for the different layers of the board.
+pub const TILE_Z: f32 = 0.0;
+pub const STRUCTURE_Z: f32 = 0.5;
+pub const NIGHT_Z: f32 = 0.8;
+pub const FOG_Z: f32 = 0.85;
+pub const HIGHLIGHT_Z: f32 = 1.0;
+pub const HOVER_Z: f32 = 1.1;
+pub const UNIT_Z: f32 = 2.0;
+pub const SELECTION_Z: f32 = 3.0;
+
+pub const TILE_LIGHT_COLOR: Color = Color::srgb(0.36, 0.55, 0.30);
+pub const TILE_DARK_COLOR: Color = Color::srgb(0.31, 0.49, 0.26);
+pub const TILE_FOREST_COLOR: Color = Color::srgb(0.16, 0.34, 0.17);
+pub const TILE_WATER_COLOR: Color = Color::srgb(0.20, 0.40, 0.70);
+pub const TILE_MOUNTAIN_COLOR: Color = Color::srgb(0.45, 0.40, 0.35);
+pub const TILE_DOOR_COLOR: Color = Color::srgb(0.35, 0.22, 0.12);
+pub const TILE_PLANKS_COLOR: Color = Color::srgb(0.60, 0.45, 0.28);
+pub const TILE_LEVER_COLOR: Color = Color::srgb(0.55, 0.55, 0.60);
+pub const PLAYER_COLOR: Color = Color::srgb(0.25, 0.45, 0.90);
+pub const ENEMY_COLOR: Color = Color::srgb(0.85, 0.25, 0.25);
+/// Blue and orange, which stay apart when red and green run together.
+pub const DEUTERANOPIA_PLAYER_COLOR: Color = Color::srgb(0.0, 0.45, 0.70);
+pub const DEUTERANOPIA_ENEMY_COLOR: Color = Color::srgb(0.90, 0.60, 0.0);
+/// Blue and yellow: without red cones orange looks dim, and a bright yellow
+/// stands out better.
+pub const PROTANOPIA_PLAYER_COLOR: Color = Color::srgb(0.0, 0.45, 0.70);
+pub const PROTANOPIA_ENEMY_COLOR: Color = Color::srgb(0.95, 0.85, 0.25);
+/// Teal and crimson, which stay apart when blue and yellow run together.
+pub const TRITANOPIA_PLAYER_COLOR: Color = Color::srgb(0.0, 0.60, 0.65);
+pub const TRITANOPIA_ENEMY_COLOR: Color = Color::srgb(0.90, 0.15, 0.35);
+/// The colorblind palettes mark heal targets by brightness rather than
+/// green.
+pub const COLORBLIND_HEAL_HIGHLIGHT_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.4);
+/// Side length of the shape in the corner of each unit telling the sides
+/// apart without color: a square for the player's, a diamond for the
+/// enemy's.
+pub const FACTION_MARKER_SIZE: f32 = UNIT_SIZE * 0.22;
+pub const SELECTION_COLOR: Color = Color::srgba(1.0, 0.9, 0.2, 0.9);
+/// Length and thickness of each arm of the selection indicator's corners.
+pub const SELECTION_CORNER_LENGTH: f32 = TILE_SIZE * 0.25;
+pub const SELECTION_CORNER_WIDTH: f32 = TILE_SIZE * 0.06;
+/// Seconds per pulse of the selection indicator, and how far it grows.
+pub const SELECTION_PULSE_SECONDS: f32 = 1.0;
+pub const SELECTION_PULSE_SCALE: f32 = 0.08;
+/// Radians per second the selection indicator turns; 0 keeps it still.
+pub const SELECTION_SPIN_SPEED: f32 = 0.0;
+/// Tint over the tile under the cursor.
+pub const HOVER_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.18);
+pub const MOVE_HIGHLIGHT_COLOR: Color = Color::srgba(0.3, 0.6, 1.0, 0.35);
+pub const ATTACK_HIGHLIGHT_COLOR: Color = Color::srgba(1.0, 0.3, 0.3, 0.35);
+pub const AURA_HIGHLIGHT_COLOR: Color = Color::srgba(1.0, 0.85, 0.3, 0.15);
+pub const HEAL_HIGHLIGHT_COLOR: Color = Color::srgba(0.3, 1.0, 0.45, 0.35);
+pub const ENEMY_MOVE_HIGHLIGHT_COLOR: Color = Color::srgba(0.75, 0.35, 1.0, 0.3);
+pub const ENEMY_ATTACK_HIGHLIGHT_COLOR: Color = Color::srgba(1.0, 0.45, 0.1, 0.3);
+/// Where an ability being aimed can be used, and what it would cover.
+pub const ABILITY_RANGE_COLOR: Color = Color::srgba(0.3, 0.9, 0.9, 0.3);
+pub const ABILITY_AREA_COLOR: Color = Color::srgba(1.0, 0.35, 0.8, 0.45);
+/// How far high contrast stretches colors away from mid grey.
+pub const HIGH_CONTRAST_STRETCH: f32 = 1.5;
+/// The least opacity range highlights are drawn with in high contrast.
+pub const HIGH_CONTRAST_OVERLAY_ALPHA: f32 = 0.5;
+/// Thickness of the border around overlays in high contrast.
+pub const HIGH_CONTRAST_BORDER_WIDTH: f32 = TILE_SIZE * 0.06;
+/// How much thicker the selection indicator is drawn in high contrast.
+pub const HIGH_CONTRAST_THICKEN: f32 = 2.0;
+
+/// Camera pan speed, in world units per second.
+pub const CAMERA_PAN_SPEED: f32 = 400.0;
+/// Distance in pixels from the window edge at which resting the cursor pans
+/// the camera, and how fast it pans, in world units per second.
+pub const EDGE_SCROLL_ZONE: f32 = 16.0;
+pub const EDGE_SCROLL_SPEED: f32 = 400.0;
+/// Seconds the camera takes to glide to an enemy unit as it acts.
+pub const CAMERA_FOLLOW_SECONDS: f32 = 0.4;
+/// How far past the edge of the map the camera may show.
+pub const CAMERA_EDGE_MARGIN: f32 = TILE_SIZE * 2.0;
+/// How far the camera can zoom in and out, as a projection scale: below 1
+/// is zoomed in.
+pub const CAMERA_MIN_ZOOM: f32 = 0.5;
+pub const CAMERA_MAX_ZOOM: f32 = 2.0;
+/// Zoom change per notch of the mouse wheel.
+pub const CAMERA_ZOOM_STEP: f32 = 0.1;
+/// Length in pixels of the minimap's longer side.
+pub const MINIMAP_SIZE: f32 = 160.0;
+/// Seconds between individual AI actions during the enemy turn.
+pub const ENEMY_ACTION_DELAY: f32 = 0.4;
+/// Seconds the AI waits at the start of its turn before its first action.
+pub const ENEMY_TURN_DELAY: f32 = 0.8;
+/// Seconds the phase banner stays up when a turn begins.
+pub const PHASE_BANNER_SECONDS: f32 = 1.2;
+/// Seconds between commands while a replay is playing.
+pub const REPLAY_STEP_DELAY: f32 = 0.5;
+/// Where the most recent battle's command log is saved and loaded from.
+pub const REPLAY_FILE: &str = "replays/latest.ron";
+/// Where both sides' boards and the command log are written when an online
+/// battle desyncs.
+pub const DESYNC_DIR: &str = "desync";
+/// Longest line, in bytes, the other end of an online battle may send; a
+/// longer line drops the link.
+pub const NET_MAX_LINE: usize = 1 << 20;
+/// Commands sent a line at a time to a player catching up on a battle, well
+/// within [`NET_MAX_LINE`] whatever the commands are.
+pub const NET_RESYNC_BATCH: usize = 1024;
+/// Chat lines shown at once, and the longest line that can be typed.
+pub const CHAT_HISTORY: usize = 8;
+pub const CHAT_MAX_LEN: usize = 200;
+/// Announcements shown at once, and how long the cursor has to rest on a
+/// tile before what is there is announced.
+pub const ANNOUNCEMENT_HISTORY: usize = 6;
+pub const ANNOUNCE_TILE_SECONDS: f32 = 0.6;
+/// Sound indicators shown at once, how long each stays up, and how long
+/// and how far toward white it flashes when it appears.
+pub const CUE_HISTORY: usize = 4;
+pub const CUE_SHOW_SECONDS: f32 = 2.5;
+pub const CUE_FLASH_SECONDS: f32 = 0.3;
+pub const CUE_FLASH_STRENGTH: f32 = 0.6;
+/// Seconds between checks of the data files for changes, in `dev-tools`
+/// builds.
+pub const DATA_POLL_SECONDS: f32 = 0.5;
+/// Operations a scenario script's hook may take before it is stopped, so a
+/// runaway loop can't hang the battle.
+pub const SCRIPT_MAX_OPERATIONS: u64 = 100_000;
@@ -0,0 +1,136 @@
+//! High contrast mode, for bright rooms and low vision.
+//!
+//! With high contrast on (G on the main menu), flat-colored tiles and the
+//! sides' colors are pushed away from mid grey, the range highlights are
+//! drawn stronger, the selection indicator's corners are drawn thicker, and
+//! every overlay on the board gets a solid border in its own color, so a
+//! highlighted tile stands out even where its tint barely shows.
+//!
+//! Overlays opt in to a border with [`Bordered`]; the border is spawned the
+//! first time it is needed and follows the overlay's color from then on.
+
+use bevy::prelude::*;
+
+use crate::constants::{
+    HIGH_CONTRAST_BORDER_WIDTH, HIGH_CONTRAST_OVERLAY_ALPHA, HIGH_CONTRAST_STRETCH,
+    HIGH_CONTRAST_THICKEN, TILE_SIZE,
+};
+
+/// Whether the board is drawn in high contrast, as set in the settings.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HighContrast(pub bool);
+
+impl HighContrast {
+    /// `color` with each channel pushed away from mid grey, when high
+    /// contrast is on.
+    pub fn apply(self, color: Color) -> Color {
+        if !self.0 {
+            return color;
+        }
+        let srgba = color.to_srgba();
+        let stretch =
+            |channel: f32| ((channel - 0.5) * HIGH_CONTRAST_STRETCH + 0.5).clamp(0.0, 1.0);
+        Color::srgba(
+            stretch(srgba.red),
+            stretch(srgba.green),
+            stretch(srgba.blue),
+            srgba.alpha,
+        )
+    }
+
+    /// An overlay's `color`, made more opaque when high contrast is on.
+    pub fn overlay(self, color: Color) -> Color {
+        if !self.0 {
+            return color;
+        }
+        let color = self.apply(color);
+        color.with_alpha(color.alpha().max(HIGH_CONTRAST_OVERLAY_ALPHA))
+    }
+}
+
+/// An overlay sprite outlined in its own color in high contrast.
+#[derive(Component)]
+pub struct Bordered;
+
+/// One side of a [`Bordered`] overlay's border.
+#[derive(Component)]
+pub struct BorderEdge;
+
+/// A thin bar drawn thicker in high contrast, with its usual size.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Thickened(pub Vec2);
+
+/// The four sides of the border around an overlay `size` across, as
+/// centers and sizes.
+fn border_edges(size: Vec2) -> [(Vec2, Vec2); 4] {
+    let width = HIGH_CONTRAST_BORDER_WIDTH;
+    let (half_x, half_y) = (size.x / 2.0 - width / 2.0, size.y / 2.0 - width / 2.0);
+    [
+        (Vec2::new(0.0, half_y), Vec2::new(size.x, width)),
+        (Vec2::new(0.0, -half_y), Vec2::new(size.x, width)),
+        (Vec2::new(-half_x, 0.0), Vec2::new(width, size.y)),
+        (Vec2::new(half_x, 0.0), Vec2::new(width, size.y)),
+    ]
+}
+
+/// Shows the borders of overlays in high contrast and hides them
+/// otherwise, in their overlay's color at full strength.
+pub fn update_overlay_borders_system(
+    mut commands: Commands,
+    contrast: Res<HighContrast>,
+    overlays: Query<(Entity, Ref<Sprite>, Option<&Children>), With<Bordered>>,
+    mut edges: Query<(&mut Sprite, &mut Visibility), (With<BorderEdge>, Without<Bordered>)>,
+) {
+    let visibility = if contrast.0 {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for (entity, sprite, children) in &overlays {
+        if !contrast.is_changed() && !sprite.is_changed() {
+            continue;
+        }
+        let color = sprite.color.with_alpha(1.0);
+        let Some(children) = children else {
+            // Borders are only spawned once they are first shown.
+            if contrast.0 {
+                let size = sprite.custom_size.unwrap_or(Vec2::splat(TILE_SIZE));
+                commands.entity(entity).with_children(|overlay| {
+                    for (center, size) in border_edges(size) {
+                        overlay.spawn((
+                            BorderEdge,
+                            Sprite::from_color(color, size),
+                            Transform::from_translation(center.extend(0.01)),
+                        ));
+                    }
+                });
+            }
+            continue;
+        };
+        for child in children {
+            if let Ok((mut edge, mut edge_visibility)) = edges.get_mut(*child) {
+                edge.color = color;
+                edge_visibility.set_if_neq(visibility);
+            }
+        }
+    }
+}
+
+/// Draws [`Thickened`] bars thicker across their narrow side in high
+/// contrast.
+pub fn thicken_bars_system(
+    contrast: Res<HighContrast>,
+    mut bars: Query<(&mut Sprite, Ref<Thickened>)>,
+) {
+    for (mut sprite, bar) in &mut bars {
+        if !contrast.is_changed() && !bar.is_added() {
+            continue;
+        }
+        let Thickened(size) = *bar;
+        sprite.custom_size = Some(match (contrast.0, size.x < size.y) {
+            (false, _) => size,
+            (true, true) => Vec2::new(size.x * HIGH_CONTRAST_THICKEN, size.y),
+            (true, false) => Vec2::new(size.x, size.y * HIGH_CONTRAST_THICKEN),
+        });
+    }
+}
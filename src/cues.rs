@@ -0,0 +1,223 @@
+//! Cues: the moments in battle a player should notice even while looking
+//! elsewhere, like an attack landing, a unit falling, or a new turn.
+//!
+//! Every cue is a [`Cue`] message, written as the battle log records what
+//! happened, so anything that reacts to them (a sound, an indicator on
+//! screen) reacts to the same moments. Like announcements, nothing done by
+//! a unit the side on screen can't see gives a cue.
+//!
+//! With sound indicators on (I on the main menu), each cue also flashes up
+//! as a labelled badge under the minimap, for players who can't hear the
+//! game.
+
+use bevy::prelude::*;
+
+use crate::battle_log::{BattleEvent, BattleLog};
+use crate::constants::{CUE_FLASH_SECONDS, CUE_FLASH_STRENGTH, CUE_HISTORY, CUE_SHOW_SECONDS};
+use crate::locale::Locale;
+use crate::seats::Viewer;
+use crate::settings::Settings;
+use crate::unit::{Faction, UnitId};
+use crate::vision::FactionVision;
+
+/// A moment in battle worth a sound and a flash.
+#[derive(Message, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Cue {
+    /// A unit swinging at another. Its hit or miss follows at once.
+    Attack,
+    Hit,
+    Miss,
+    Death,
+    Turn,
+    Trap,
+    Heal,
+    LevelUp,
+    Capture,
+}
+
+impl Cue {
+    /// The cues `event` gives, in order.
+    pub fn of(event: &BattleEvent) -> &'static [Self] {
+        match event {
+            BattleEvent::Attacked { .. } => &[Cue::Attack, Cue::Hit],
+            BattleEvent::Missed { .. } => &[Cue::Attack, Cue::Miss],
+            BattleEvent::Struck { .. }
+            | BattleEvent::Burned { .. }
+            | BattleEvent::Poisoned { .. } => &[Cue::Hit],
+            BattleEvent::Died { .. } => &[Cue::Death],
+            BattleEvent::TurnStarted { .. } => &[Cue::Turn],
+            BattleEvent::TrapSprung { .. } => &[Cue::Trap],
+            BattleEvent::Mended { .. } | BattleEvent::Healed { .. } => &[Cue::Heal],
+            BattleEvent::LeveledUp { .. } | BattleEvent::Promoted { .. } => &[Cue::LevelUp],
+            BattleEvent::Captured { .. } => &[Cue::Capture],
+            _ => &[],
+        }
+    }
+
+    /// The word on the cue's badge, if it gets one. A swing has none, as
+    /// the hit or miss after it says more.
+    pub fn label(self) -> Option<&'static str> {
+        Some(match self {
+            Cue::Attack => return None,
+            Cue::Hit => "Hit",
+            Cue::Miss => "Miss",
+            Cue::Death => "Unit down",
+            Cue::Turn => "New turn",
+            Cue::Trap => "Trap",
+            Cue::Heal => "Heal",
+            Cue::LevelUp => "Level up",
+            Cue::Capture => "Captured",
+        })
+    }
+
+    /// The badge's color, told apart by brightness as well as hue.
+    pub fn color(self) -> Color {
+        match self {
+            Cue::Attack => Color::srgb(0.85, 0.85, 0.85),
+            Cue::Hit => Color::srgb(0.85, 0.3, 0.25),
+            Cue::Miss => Color::srgb(0.45, 0.45, 0.5),
+            Cue::Death => Color::srgb(0.35, 0.05, 0.1),
+            Cue::Turn => Color::srgb(0.25, 0.45, 0.85),
+            Cue::Trap => Color::srgb(0.9, 0.55, 0.1),
+            Cue::Heal => Color::srgb(0.3, 0.75, 0.4),
+            Cue::LevelUp => Color::srgb(0.95, 0.85, 0.3),
+            Cue::Capture => Color::srgb(0.6, 0.35, 0.8),
+        }
+    }
+}
+
+/// Where the sound indicators are shown, newest first.
+#[derive(Component)]
+pub struct CueStrip;
+
+/// A sound indicator's badge, fading out as its time runs down.
+#[derive(Component)]
+pub struct CueBadge {
+    color: Color,
+    timer: Timer,
+}
+
+/// Gives a cue for each event the battle log records, unless it is the
+/// doing of a unit the side on screen can't see.
+pub fn cue_battle_events_system(
+    mut seen: Local<usize>,
+    history: Res<BattleLog>,
+    vision: Res<FactionVision>,
+    viewer: Res<Viewer>,
+    units: Query<(Entity, &UnitId, &Faction)>,
+    mut cues: MessageWriter<Cue>,
+) {
+    for (_, event) in history.read_new(&mut seen) {
+        let cues_of = Cue::of(event);
+        if cues_of.is_empty() {
+            continue;
+        }
+        let hidden = event.actor().is_some_and(|actor| {
+            units.iter().any(|(entity, id, faction)| {
+                *id == actor && !vision.can_see(viewer.0, entity, *faction)
+            })
+        });
+        if !hidden {
+            cues.write_batch(cues_of.iter().copied());
+        }
+    }
+}
+
+pub fn setup_cue_strip(mut commands: Commands) {
+    commands.spawn((
+        CueStrip,
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(180.0),
+            right: Val::Px(10.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::End,
+            row_gap: Val::Px(4.0),
+            ..default()
+        },
+    ));
+}
+
+/// Flashes up a badge for each cue while sound indicators are on, keeping
+/// the latest few.
+pub fn show_cues_system(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    locale: Res<Locale>,
+    mut cues: MessageReader<Cue>,
+    strip: Single<(Entity, Option<&Children>), With<CueStrip>>,
+) {
+    let (strip, badges) = *strip;
+    if !settings.sound_indicators {
+        cues.clear();
+        if let Some(badges) = badges {
+            for badge in badges {
+                commands.entity(*badge).try_despawn();
+            }
+        }
+        return;
+    }
+    let new: Vec<(Cue, &str)> = cues
+        .read()
+        .filter_map(|&cue| Some((cue, cue.label()?)))
+        .collect();
+    let new = &new[new.len().saturating_sub(CUE_HISTORY)..];
+    for &(cue, label) in new {
+        let badge = commands
+            .spawn((
+                CueBadge {
+                    color: cue.color(),
+                    timer: Timer::from_seconds(CUE_SHOW_SECONDS, TimerMode::Once),
+                },
+                Node {
+                    padding: UiRect::axes(Val::Px(8.0), Val::Px(3.0)),
+                    ..default()
+                },
+                BackgroundColor(Color::WHITE),
+            ))
+            .with_child((
+                Text::new(locale.tr(label)),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ))
+            .id();
+        commands.entity(strip).insert_children(0, &[badge]);
+    }
+    // The oldest are last, and make way for those just added.
+    if let Some(badges) = badges {
+        for badge in badges.iter().skip(CUE_HISTORY.saturating_sub(new.len())) {
+            commands.entity(badge).try_despawn();
+        }
+    }
+}
+
+/// Flashes each new badge white, then fades it out and takes it down once
+/// its time is up.
+pub fn fade_cues_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut badges: Query<(Entity, &mut CueBadge, &mut BackgroundColor, &Children)>,
+    mut labels: Query<&mut TextColor>,
+) {
+    for (entity, mut badge, mut background, children) in &mut badges {
+        if badge.timer.tick(time.delta()).is_finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let elapsed = badge.timer.elapsed_secs();
+        let flash = (1.0 - elapsed / CUE_FLASH_SECONDS).max(0.0);
+        let fade = badge.timer.fraction_remaining().min(0.5) * 2.0;
+        background.0 = badge
+            .color
+            .mix(&Color::WHITE, flash * CUE_FLASH_STRENGTH)
+            .with_alpha(fade);
+        for child in children {
+            if let Ok(mut label) = labels.get_mut(*child) {
+                label.0 = Color::WHITE.with_alpha(fade);
+            }
+        }
+    }
+}
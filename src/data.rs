@@ -0,0 +1,173 @@
+//! Loading of data-driven definitions from `assets/data/`, and from the
+//! [mods](crate::mods) that change them.
+//!
+//! Data files are read in layers: the built-in copy first, then each
+//! enabled mod's copy from the lowest priority up, every entry in a layer
+//! replacing the entry with the same id in the ones below. A mod only needs
+//! to list what it changes. Two mods changing the same entry is logged as a
+//! conflict, which the higher priority wins.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+
+use crate::mods::{enabled_mods, mod_providing, InstalledMod};
+
+/// The directory `assets/` is in, found the same way Bevy's asset server
+/// finds it, so headless builds without the asset server agree with it.
+pub fn base_path() -> PathBuf {
+    env::var_os("BEVY_ASSET_ROOT")
+        .or_else(|| env::var_os("CARGO_MANIFEST_DIR"))
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(PathBuf::from))
+        })
+        .unwrap_or_default()
+}
+
+/// Full path of the file at `relative` under `assets/`, taken from the
+/// enabled mod with the highest priority that has it.
+pub fn asset_file(relative: &Path) -> PathBuf {
+    match mod_providing(relative) {
+        Some(installed) => installed.dir.join(relative),
+        None => base_path().join("assets").join(relative),
+    }
+}
+
+/// Full path of a file in the `assets/data/` directory.
+pub fn data_path(name: &str) -> PathBuf {
+    asset_file(&Path::new("data").join(name))
+}
+
+/// Full path of a file in the `assets/scripts/` directory.
+pub fn script_path(name: &str) -> PathBuf {
+    asset_file(&Path::new("scripts").join(name))
+}
+
+/// A data file made of entries with ids, which mods change one entry at a
+/// time.
+pub trait Layered: DeserializeOwned {
+    /// The id of every entry, as shown in logs and the mods panel.
+    fn ids(&self) -> Vec<String>;
+
+    /// Lays `layer` over this, each of its entries replacing the one with
+    /// the same id or adding to the rest.
+    fn overlay(&mut self, layer: Self);
+}
+
+/// Where a data entry or file comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    BuiltIn,
+    Mod(&'static InstalledMod),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::BuiltIn => write!(f, "the built-in data"),
+            Source::Mod(installed) => write!(f, "{}", installed.manifest.name),
+        }
+    }
+}
+
+/// Every source that defines an entry, lowest priority first, by entry id.
+pub type EntrySources = BTreeMap<String, Vec<Source>>;
+
+/// Every copy of the data file `name` in the game at `base` and in `mods`,
+/// which are given highest priority first, lowest priority first. Without
+/// any, the built-in path, so reading it fails with the usual error.
+fn data_layers(base: &Path, mods: &[&'static InstalledMod], name: &str) -> Vec<(Source, PathBuf)> {
+    let relative = Path::new("data").join(name);
+    let builtin = base.join("assets").join(&relative);
+    let mut layers: Vec<(Source, PathBuf)> = builtin
+        .is_file()
+        .then(|| (Source::BuiltIn, builtin.clone()))
+        .into_iter()
+        .chain(
+            mods.iter()
+                .rev()
+                .map(|&installed| (Source::Mod(installed), installed.dir.join(&relative)))
+                .filter(|(_, path)| path.is_file()),
+        )
+        .collect();
+    if layers.is_empty() {
+        layers.push((Source::BuiltIn, builtin));
+    }
+    layers
+}
+
+/// Reads and parses a single RON file.
+fn parse_file<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {err}", path.display()))?;
+    ron::from_str(&contents).map_err(|err| format!("Failed to parse {}: {err}", path.display()))
+}
+
+/// Reads every layer of the data file `name` and lays them over each other,
+/// along with where each entry was defined.
+pub fn read_layers<T: Layered>(name: &str) -> Result<(T, EntrySources), String> {
+    let mods: Vec<&'static InstalledMod> = enabled_mods().collect();
+    read_layers_in(&base_path(), &mods, name)
+}
+
+/// [`read_layers`] for the game at `base`, with `mods` enabled, highest
+/// priority first.
+pub fn read_layers_in<T: Layered>(
+    base: &Path,
+    mods: &[&'static InstalledMod],
+    name: &str,
+) -> Result<(T, EntrySources), String> {
+    let mut merged: Option<T> = None;
+    let mut sources = EntrySources::new();
+    for (source, path) in data_layers(base, mods, name) {
+        let layer: T = parse_file(&path)?;
+        for id in layer.ids() {
+            sources.entry(id).or_default().push(source);
+        }
+        match &mut merged {
+            Some(merged) => merged.overlay(layer),
+            None => merged = Some(layer),
+        }
+    }
+    let merged = merged.expect("there is always a layer");
+    Ok((merged, sources))
+}
+
+/// Reads and parses a RON data file, with the mods' changes laid over it.
+/// Entries more than one mod changes are logged.
+pub fn read_data_file<T: Layered>(name: &str) -> Result<T, String> {
+    let (data, sources) = read_layers(name)?;
+    for (id, defined) in &sources {
+        let mods: Vec<String> = defined
+            .iter()
+            .filter(|source| **source != Source::BuiltIn)
+            .map(Source::to_string)
+            .collect();
+        if let [losers @ .., winner] = &mods[..] {
+            if !losers.is_empty() {
+                warn!(
+                    "{name}: {id} is changed by {} and {winner}; {winner} wins",
+                    losers.join(", ")
+                );
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// Reads and parses a RON data file, falling back to an empty default (and
+/// logging why) so a broken data file never takes the game down.
+pub fn load_data_file<T: Layered + Default>(name: &str) -> T {
+    read_data_file(name).unwrap_or_else(|err| {
+        error!("{err}");
+        T::default()
+    })
+}
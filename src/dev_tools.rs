@@ -0,0 +1,31 @@
+//! Live inspectors for development builds, behind the `dev-tools` feature.
+//!
+//! F12 opens a world inspector for editing any entity's reflected components
+//! and any resource, plus quick panels for the current turn and the app
+//! state. Everything here needs the types to be registered for reflection.
+
+use bevy::input::common_conditions::input_toggle_active;
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiPlugin;
+use bevy_inspector_egui::quick::{
+    ResourceInspectorPlugin, StateInspectorPlugin, WorldInspectorPlugin,
+};
+
+use crate::state::AppState;
+use crate::turn::CurrentTurn;
+
+const TOGGLE_KEY: KeyCode = KeyCode::F12;
+
+pub struct DevToolsPlugin;
+
+impl Plugin for DevToolsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<AppState>().add_plugins((
+            EguiPlugin::default(),
+            WorldInspectorPlugin::new().run_if(input_toggle_active(false, TOGGLE_KEY)),
+            ResourceInspectorPlugin::<CurrentTurn>::new()
+                .run_if(input_toggle_active(false, TOGGLE_KEY)),
+            StateInspectorPlugin::<AppState>::new().run_if(input_toggle_active(false, TOGGLE_KEY)),
+        ));
+    }
+}
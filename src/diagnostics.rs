@@ -0,0 +1,136 @@
+//! A performance overlay, toggled with F3.
+//!
+//! It shows Bevy's frame time and entity count diagnostics alongside the
+//! game's own counters, the units on the board and the highlight tiles on
+//! show, and the state the app and the battle are in. While it is hidden
+//! nothing here runs but the counters.
+
+use bevy::diagnostic::{
+    Diagnostic, DiagnosticPath, Diagnostics, DiagnosticsStore, EntityCountDiagnosticsPlugin,
+    FrameTimeDiagnosticsPlugin, RegisterDiagnostic,
+};
+use bevy::prelude::*;
+
+use crate::bindings::{ActionInput, InputAction};
+use crate::leader::AuraHighlight;
+use crate::selection::{EnemyRangeHighlight, MovementHighlight};
+use crate::state::AppState;
+use crate::turn::CurrentTurn;
+use crate::unit::Unit;
+
+pub const UNIT_COUNT: DiagnosticPath = DiagnosticPath::const_new("game/units");
+pub const HIGHLIGHT_COUNT: DiagnosticPath = DiagnosticPath::const_new("game/highlights");
+
+#[derive(Component)]
+pub struct DiagnosticsOverlay;
+
+/// Bevy's frame time and entity count diagnostics, the game's counters, and
+/// the overlay that shows them.
+pub struct DiagnosticsOverlayPlugin;
+
+impl Plugin for DiagnosticsOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            FrameTimeDiagnosticsPlugin::default(),
+            EntityCountDiagnosticsPlugin::default(),
+        ))
+        .register_diagnostic(Diagnostic::new(UNIT_COUNT))
+        .register_diagnostic(Diagnostic::new(HIGHLIGHT_COUNT))
+        .add_systems(Startup, setup_diagnostics_overlay)
+        .add_systems(
+            Update,
+            (
+                measure_game_diagnostics_system,
+                toggle_diagnostics_overlay_system,
+                update_diagnostics_overlay_system.run_if(overlay_shown),
+            )
+                .chain(),
+        );
+    }
+}
+
+pub fn setup_diagnostics_overlay(mut commands: Commands) {
+    commands.spawn((
+        DiagnosticsOverlay,
+        Text::new(""),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(10.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        },
+        Visibility::Hidden,
+    ));
+}
+
+/// Counts the units on the board and the highlight tiles on show.
+pub fn measure_game_diagnostics_system(
+    mut diagnostics: Diagnostics,
+    units: Query<(), With<Unit>>,
+    highlights: Query<
+        &Visibility,
+        Or<(
+            With<MovementHighlight>,
+            With<EnemyRangeHighlight>,
+            With<AuraHighlight>,
+        )>,
+    >,
+) {
+    diagnostics.add_measurement(&UNIT_COUNT, || units.iter().len() as f64);
+    diagnostics.add_measurement(&HIGHLIGHT_COUNT, || {
+        highlights
+            .iter()
+            .filter(|visibility| **visibility != Visibility::Hidden)
+            .count() as f64
+    });
+}
+
+pub fn toggle_diagnostics_overlay_system(
+    input: ActionInput,
+    mut overlay: Single<&mut Visibility, With<DiagnosticsOverlay>>,
+) {
+    if input.just_pressed(InputAction::Diagnostics) {
+        **overlay = match **overlay {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+pub fn overlay_shown(overlay: Single<&Visibility, With<DiagnosticsOverlay>>) -> bool {
+    **overlay != Visibility::Hidden
+}
+
+pub fn update_diagnostics_overlay_system(
+    store: Res<DiagnosticsStore>,
+    state: Res<State<AppState>>,
+    turn: Res<CurrentTurn>,
+    mut text: Single<&mut Text, With<DiagnosticsOverlay>>,
+) {
+    let smoothed = |path: &DiagnosticPath| {
+        store
+            .get(path)
+            .and_then(Diagnostic::smoothed)
+            .unwrap_or_default()
+    };
+    text.0 = format!(
+        "FPS {:.0}  Frame {:.2} ms\n\
+         Entities {:.0}  Units {:.0}  Highlights {:.0}\n\
+         {:?}  Turn {} ({:?})",
+        smoothed(&FrameTimeDiagnosticsPlugin::FPS),
+        smoothed(&FrameTimeDiagnosticsPlugin::FRAME_TIME),
+        smoothed(&EntityCountDiagnosticsPlugin::ENTITY_COUNT),
+        smoothed(&UNIT_COUNT),
+        smoothed(&HIGHLIGHT_COUNT),
+        state.get(),
+        turn.number,
+        turn.faction,
+    );
+}
@@ -0,0 +1,246 @@
+//! Per-side gold, structure income, and recruiting new units at owned forts.
+//!
+//! Gold only changes inside the command executor: income is collected when
+//! a side's turn starts, and [`GameCommand::Recruit`] pays for new units.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "render")]
+use crate::bindings::{ActionInput, InputAction, InputMap};
+use crate::class::UnitClass;
+#[cfg(feature = "render")]
+use crate::command::GameCommand;
+#[cfg(feature = "render")]
+use crate::grid::GridPosition;
+#[cfg(feature = "render")]
+use crate::input::{ButtonActivated, GameAction};
+#[cfg(feature = "render")]
+use crate::locale::Locale;
+#[cfg(feature = "render")]
+use crate::seats::Viewer;
+#[cfg(feature = "render")]
+use crate::selection::SelectedUnit;
+#[cfg(feature = "render")]
+use crate::state::AppState;
+#[cfg(feature = "render")]
+use crate::structure::Structure;
+#[cfg(feature = "render")]
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::Faction;
+#[cfg(feature = "render")]
+use crate::unit::{Unit, UnitIndex};
+
+/// Gold each side starts a battle with.
+pub const STARTING_GOLD: u32 = 200;
+
+/// Classes that can be recruited, cheapest first.
+pub const RECRUITABLE: [UnitClass; 4] = [
+    UnitClass::Healer,
+    UnitClass::Archer,
+    UnitClass::Knight,
+    UnitClass::Mage,
+];
+
+#[cfg(feature = "render")]
+const PANEL_COLOR: Color = Color::srgba(0.08, 0.08, 0.12, 0.9);
+#[cfg(feature = "render")]
+const CHOICE_COLOR: Color = Color::srgb(0.22, 0.22, 0.30);
+/// Gold held by each side in the current battle.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+#[reflect(Resource)]
+pub struct Treasury {
+    pub player: u32,
+    pub enemy: u32,
+}
+
+impl Default for Treasury {
+    fn default() -> Self {
+        Self {
+            player: STARTING_GOLD,
+            enemy: STARTING_GOLD,
+        }
+    }
+}
+
+impl Treasury {
+    pub fn get(&self, faction: Faction) -> u32 {
+        match faction {
+            Faction::Player => self.player,
+            Faction::Enemy => self.enemy,
+        }
+    }
+
+    pub fn get_mut(&mut self, faction: Faction) -> &mut u32 {
+        match faction {
+            Faction::Player => &mut self.player,
+            Faction::Enemy => &mut self.enemy,
+        }
+    }
+}
+
+/// Id to hand out to the next unit that joins the battle. Recruits get ids
+/// in the order they are recruited, so replays assign the same ones.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct NextUnitId(pub u32);
+
+/// The owned, empty fort the player clicked on to recruit from.
+#[cfg(feature = "render")]
+#[derive(Resource, Default)]
+pub struct RecruitSite(pub Option<GridPosition>);
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct RecruitPanel;
+
+#[cfg(feature = "render")]
+#[derive(Component, Clone, Copy)]
+pub struct RecruitChoice(pub UnitClass);
+
+/// Clicking an empty recruit site the side on screen owns opens the recruit panel
+/// there; clicking anywhere else closes it.
+#[cfg(feature = "render")]
+pub fn recruit_site_system(
+    mut actions: MessageReader<GameAction>,
+    turn: Res<CurrentTurn>,
+    outcome: Res<BattleOutcome>,
+    viewer: Res<Viewer>,
+    mut site: ResMut<RecruitSite>,
+    selected: Res<SelectedUnit>,
+    index: Res<UnitIndex>,
+    structures: Query<(&GridPosition, &Structure), Without<Unit>>,
+) {
+    let mut clicked = None;
+    let mut cancelled = false;
+    for action in actions.read() {
+        match *action {
+            GameAction::SelectAt(tile) => clicked = Some(tile),
+            GameAction::Cancel => cancelled = true,
+            _ => {}
+        }
+    }
+    let can_recruit = turn.faction == viewer.0 && outcome.0.is_none();
+    if !can_recruit || selected.0.is_some() || cancelled {
+        if site.0.is_some() {
+            site.0 = None;
+        }
+        return;
+    }
+    let Some(clicked) = clicked else {
+        return;
+    };
+
+    let valid = Some(clicked).filter(|&clicked| {
+        !index.is_occupied(clicked)
+            && structures.iter().any(|(pos, structure)| {
+                *pos == clicked && structure.kind.can_recruit() && structure.owner == Some(viewer.0)
+            })
+    });
+    if site.0 != valid {
+        site.0 = valid;
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn update_recruit_panel_system(
+    mut commands: Commands,
+    site: Res<RecruitSite>,
+    treasury: Res<Treasury>,
+    viewer: Res<Viewer>,
+    bindings: Res<InputMap>,
+    locale: Res<Locale>,
+    panels: Query<Entity, With<RecruitPanel>>,
+) {
+    if !site.is_changed() && !treasury.is_changed() && !locale.is_changed() {
+        return;
+    }
+    for panel in &panels {
+        commands.entity(panel).despawn();
+    }
+    if site.0.is_none() {
+        return;
+    }
+
+    commands
+        .spawn((
+            RecruitPanel,
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            BackgroundColor(PANEL_COLOR),
+            DespawnOnExit(AppState::GamePlay),
+        ))
+        .with_children(|panel| {
+            panel.spawn(Text::new(
+                locale.fill("Recruit ({} gold)", &[&treasury.get(viewer.0)]),
+            ));
+            for (index, class) in RECRUITABLE.into_iter().enumerate() {
+                panel
+                    .spawn((
+                        RecruitChoice(class),
+                        Button,
+                        Node {
+                            padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                            ..default()
+                        },
+                        BackgroundColor(CHOICE_COLOR),
+                    ))
+                    .with_child((
+                        Text::new(format!(
+                            "[{}] {} - {}g",
+                            bindings.hint(InputAction::CHOICES[index]),
+                            locale.tr(&format!("{class:?}")),
+                            class.cost()
+                        )),
+                        TextFont {
+                            font_size: 16.0,
+                            ..default()
+                        },
+                    ));
+            }
+        });
+}
+
+#[cfg(feature = "render")]
+pub fn recruit_input_system(
+    input: ActionInput,
+    mut activated: MessageReader<ButtonActivated>,
+    viewer: Res<Viewer>,
+    mut site: ResMut<RecruitSite>,
+    choices: Query<(&RecruitChoice, &Interaction), Changed<Interaction>>,
+    entries: Query<&RecruitChoice>,
+    mut orders: MessageWriter<GameCommand>,
+) {
+    let activated = activated
+        .read()
+        .find_map(|ButtonActivated(entity)| entries.get(*entity).ok())
+        .map(|choice| choice.0);
+    let Some(at) = site.0 else {
+        return;
+    };
+
+    let clicked = choices
+        .iter()
+        .find(|(_, interaction)| **interaction == Interaction::Pressed)
+        .map(|(choice, _)| choice.0);
+    let pressed = InputAction::CHOICES
+        .into_iter()
+        .zip(RECRUITABLE)
+        .find(|(choice, _)| input.just_pressed(*choice))
+        .map(|(_, class)| class);
+
+    if let Some(class) = clicked.or(activated).or(pressed) {
+        orders.write(GameCommand::Recruit {
+            faction: viewer.0,
+            class,
+            at,
+        });
+        site.0 = None;
+    }
+}
@@ -0,0 +1,144 @@
+//! Combat effects: attacker lunges, hit flashes, and damage numbers.
+//!
+//! Effects are spawned from the attacks the battle log records, so they
+//! never touch how an attack is resolved and replays get them for free.
+
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::animation::MoveTween;
+use crate::battle_log::{BattleEvent, BattleLog};
+use crate::constants::*;
+use crate::grid::GridPosition;
+use crate::unit::UnitId;
+
+/// Seconds a lunge takes, out and back.
+const LUNGE_SECONDS: f32 = 0.25;
+/// How far toward the target an attacker lunges.
+const LUNGE_DISTANCE: f32 = TILE_SIZE * 0.25;
+const FLASH_SECONDS: f32 = 0.2;
+const FLASH_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.8);
+const POPUP_SECONDS: f32 = 0.8;
+const POPUP_RISE: f32 = TILE_SIZE * 0.5;
+const DAMAGE_COLOR: Color = Color::srgb(1.0, 0.35, 0.3);
+const MISS_COLOR: Color = Color::srgb(0.85, 0.85, 0.85);
+
+/// An attacker jabbing toward its target and back.
+#[derive(Component)]
+pub struct Lunge {
+    direction: Vec3,
+    timer: Timer,
+}
+
+/// A white flash over a unit that was just hit.
+#[derive(Component)]
+pub struct HitFlash(Timer);
+
+/// A damage number or "Miss" floating up from the defender.
+#[derive(Component)]
+pub struct DamagePopup {
+    origin: Vec3,
+    timer: Timer,
+}
+
+/// Starts a lunge and hit effects for each attack recorded since the last
+/// frame.
+pub fn spawn_combat_effects_system(
+    mut commands: Commands,
+    mut seen: Local<usize>,
+    history: Res<BattleLog>,
+    // No `With<Unit>`: a defender the attack killed is already dying, and
+    // still gets its flash.
+    units: Query<(Entity, &UnitId, &GridPosition)>,
+) {
+    let find = |id: UnitId| units.iter().find(|(_, unit, _)| **unit == id);
+    for (_, event) in history.read_new(&mut seen) {
+        let (attacker, target, damage) = match *event {
+            BattleEvent::Attacked {
+                attacker,
+                target,
+                damage,
+                ..
+            } => (attacker, target, Some(damage)),
+            BattleEvent::Missed { attacker, target } => (attacker, target, None),
+            _ => continue,
+        };
+        let (Some((attacker, _, &from)), Some((_, _, &at))) = (find(attacker), find(target)) else {
+            continue;
+        };
+
+        commands.entity(attacker).insert(Lunge {
+            direction: (at.to_world(0.0) - from.to_world(0.0)).normalize_or_zero(),
+            timer: Timer::from_seconds(LUNGE_SECONDS, TimerMode::Once),
+        });
+        if damage.is_some() {
+            commands.spawn((
+                HitFlash(Timer::from_seconds(FLASH_SECONDS, TimerMode::Once)),
+                Sprite::from_color(FLASH_COLOR, Vec2::splat(UNIT_SIZE)),
+                Transform::from_translation(at.to_world(UNIT_Z + 0.5)),
+            ));
+        }
+        let (label, color) = match damage {
+            Some(damage) => (format!("-{damage}"), DAMAGE_COLOR),
+            None => ("Miss".to_string(), MISS_COLOR),
+        };
+        let origin = at.to_world(SELECTION_Z) + Vec3::Y * TILE_SIZE * 0.3;
+        commands.spawn((
+            DamagePopup {
+                origin,
+                timer: Timer::from_seconds(POPUP_SECONDS, TimerMode::Once),
+            },
+            Text2d::new(label),
+            TextFont {
+                font_size: TILE_SIZE * 0.35,
+                ..default()
+            },
+            TextColor(color),
+            Transform::from_translation(origin),
+        ));
+    }
+}
+
+/// Pushes lunging attackers toward their target and back to their tile.
+/// A unit still walking finishes its walk first.
+pub fn animate_lunges_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut units: Query<(Entity, &GridPosition, &mut Lunge, &mut Transform), Without<MoveTween>>,
+) {
+    for (entity, pos, mut lunge, mut transform) in &mut units {
+        lunge.timer.tick(time.delta());
+        let reach = (lunge.timer.fraction() * PI).sin() * LUNGE_DISTANCE;
+        transform.translation = pos.to_world(UNIT_Z) + lunge.direction * reach;
+        if lunge.timer.is_finished() {
+            commands.entity(entity).remove::<Lunge>();
+        }
+    }
+}
+
+/// Fades out hit flashes and floats damage numbers up, removing both once
+/// they are done.
+pub fn animate_hit_effects_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut flashes: Query<(Entity, &mut HitFlash, &mut Sprite)>,
+    mut popups: Query<(Entity, &mut DamagePopup, &mut Transform, &mut TextColor)>,
+) {
+    for (entity, mut flash, mut sprite) in &mut flashes {
+        flash.0.tick(time.delta());
+        sprite.color = FLASH_COLOR.with_alpha(FLASH_COLOR.alpha() * (1.0 - flash.0.fraction()));
+        if flash.0.is_finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+    for (entity, mut popup, mut transform, mut color) in &mut popups {
+        popup.timer.tick(time.delta());
+        let progress = popup.timer.fraction();
+        transform.translation = popup.origin + Vec3::Y * POPUP_RISE * progress;
+        color.0 = color.0.with_alpha(1.0 - progress);
+        if popup.timer.is_finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
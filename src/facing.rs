@@ -0,0 +1,85 @@
+//! Which way each unit is facing.
+//!
+//! Facing is battle state like any other: the executor turns a unit toward
+//! the tile it walks to and the unit it attacks, so replays agree on it and
+//! flanking rules can build on [`Facing::flank`]. Defenders don't turn to
+//! meet an attack. Sprites are mirrored while their unit faces left.
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+use crate::grid::GridPosition;
+use crate::unit::Faction;
+#[cfg(feature = "render")]
+use crate::unit::Unit;
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Serialize, Reflect)]
+#[reflect(Component)]
+pub enum Facing {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Which side of a unit an attack comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flank {
+    Front,
+    Side,
+    Rear,
+}
+
+impl Facing {
+    /// Each side starts the battle facing the other's deployment edge.
+    pub fn for_faction(faction: Faction) -> Self {
+        match faction {
+            Faction::Player => Facing::Right,
+            Faction::Enemy => Facing::Left,
+        }
+    }
+
+    /// The way to face to look from `from` toward `to`, along whichever axis
+    /// is further; ties go horizontal. `None` when they are the same tile.
+    pub fn toward(from: GridPosition, to: GridPosition) -> Option<Self> {
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        match (dx, dy) {
+            (0, 0) => None,
+            _ if dx.abs() >= dy.abs() && dx > 0 => Some(Facing::Right),
+            _ if dx.abs() >= dy.abs() => Some(Facing::Left),
+            _ if dy > 0 => Some(Facing::Up),
+            _ => Some(Facing::Down),
+        }
+    }
+
+    /// One tile's step in this direction.
+    pub fn offset(self) -> (i32, i32) {
+        match self {
+            Facing::Up => (0, 1),
+            Facing::Down => (0, -1),
+            Facing::Left => (-1, 0),
+            Facing::Right => (1, 0),
+        }
+    }
+
+    /// The side of a unit at `pos`, facing this way, that an attack from
+    /// `from` lands on.
+    pub fn flank(self, pos: GridPosition, from: GridPosition) -> Flank {
+        let (x, y) = self.offset();
+        match ((from.x - pos.x) * x + (from.y - pos.y) * y).signum() {
+            1 => Flank::Front,
+            0 => Flank::Side,
+            _ => Flank::Rear,
+        }
+    }
+}
+
+/// Mirrors unit sprites to match their facing.
+#[cfg(feature = "render")]
+pub fn update_facing_visuals_system(
+    mut units: Query<(&Facing, &mut Sprite), (With<Unit>, Changed<Facing>)>,
+) {
+    for (facing, mut sprite) in &mut units {
+        sprite.flip_x = *facing == Facing::Left;
+    }
+}
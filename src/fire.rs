@@ -0,0 +1,122 @@
+//! Fire spreading through the forests.
+//!
+//! Fire tomes set forest tiles alight. Every round a fire may jump to a
+//! neighbouring forest, then burns down a little; water snuffs out any fire
+//! next to it. Units starting their turn in the flames get burned. Like the
+//! rest of the battle, fire only changes inside the command executor.
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+#[cfg(feature = "render")]
+use crate::constants::*;
+use crate::grid::{GridMap, GridPosition, TileType};
+use crate::rules::GameRng;
+#[cfg(feature = "render")]
+use crate::state::AppState;
+
+/// Rounds a tile keeps burning before it burns out.
+pub const FIRE_TURNS: u32 = 3;
+/// Percent chance each round that a fire spreads to each neighbouring forest.
+pub const FIRE_SPREAD_CHANCE: u32 = 35;
+/// Damage dealt to a unit that starts its turn on a burning tile. Like
+/// poison, fire never drops a unit below 1 HP.
+pub const FIRE_DAMAGE: u32 = 3;
+
+#[cfg(feature = "render")]
+const FIRE_COLOR: Color = Color::srgba(1.0, 0.45, 0.1, 0.55);
+
+/// Burning tiles with the rounds they have left, in the order they caught
+/// fire, and tiles that have already burned out and can't catch again.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize, Reflect)]
+#[reflect(Resource)]
+pub struct Fires {
+    pub burning: Vec<(GridPosition, u32)>,
+    pub burnt: Vec<GridPosition>,
+}
+
+impl Fires {
+    pub fn is_burning(&self, pos: GridPosition) -> bool {
+        self.burning.iter().any(|(burning, _)| *burning == pos)
+    }
+
+    fn can_catch(&self, map: &GridMap, pos: GridPosition) -> bool {
+        map.tile(pos) == Some(TileType::Forest)
+            && !next_to_water(map, pos)
+            && !self.is_burning(pos)
+            && !self.burnt.contains(&pos)
+    }
+
+    /// Sets `pos` alight if it is a forest that hasn't burned yet. Returns
+    /// whether it caught fire.
+    pub fn ignite(&mut self, map: &GridMap, pos: GridPosition) -> bool {
+        let catches = self.can_catch(map, pos);
+        if catches {
+            self.burning.push((pos, FIRE_TURNS));
+        }
+        catches
+    }
+
+    /// Runs once per round: spreads every fire, then burns each one down.
+    /// Returns the tiles that caught fire.
+    pub fn advance(&mut self, map: &GridMap, rng: &mut GameRng) -> Vec<GridPosition> {
+        let mut spread = Vec::new();
+        for (pos, _) in self.burning.clone() {
+            for next in pos.neighbors() {
+                if self.can_catch(map, next)
+                    && !spread.contains(&next)
+                    && rng.range(1, 100) <= FIRE_SPREAD_CHANCE
+                {
+                    spread.push(next);
+                }
+            }
+        }
+
+        for (_, turns) in &mut self.burning {
+            *turns -= 1;
+        }
+        let (out, still): (Vec<_>, Vec<_>) = self
+            .burning
+            .drain(..)
+            .partition(|(pos, turns)| *turns == 0 || next_to_water(map, *pos));
+        self.burning = still;
+        self.burnt.extend(out.into_iter().map(|(pos, _)| pos));
+        self.burning
+            .extend(spread.iter().map(|&pos| (pos, FIRE_TURNS)));
+        spread
+    }
+}
+
+fn next_to_water(map: &GridMap, pos: GridPosition) -> bool {
+    pos.neighbors()
+        .into_iter()
+        .any(|next| map.tile(next) == Some(TileType::Water))
+}
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct FireMarker;
+
+/// Redraws the flames whenever the set of burning tiles changes.
+#[cfg(feature = "render")]
+pub fn update_fire_visuals_system(
+    mut commands: Commands,
+    fires: Res<Fires>,
+    state: Res<State<AppState>>,
+    markers: Query<Entity, With<FireMarker>>,
+) {
+    if !fires.is_changed() {
+        return;
+    }
+    for marker in &markers {
+        commands.entity(marker).despawn();
+    }
+    for (pos, _) in &fires.burning {
+        commands.spawn((
+            FireMarker,
+            Sprite::from_color(FIRE_COLOR, Vec2::splat(TILE_SIZE)),
+            Transform::from_translation(pos.to_world(HIGHLIGHT_Z - 0.1)),
+            DespawnOnExit(*state.get()),
+        ));
+    }
+}
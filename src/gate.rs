@@ -0,0 +1,44 @@
+//! Doors and drawbridges that change which tiles can be walked on mid-battle.
+//!
+//! A unit next to a door can open or close it, and a drawbridge is raised
+//! and lowered from its lever. Both are tiles of the [`GridMap`], so
+//! movement, highlights, and the AI see the change as soon as the executor
+//! applies it.
+
+use crate::grid::{GridMap, GridPosition, TileType};
+
+/// Doors on the default battlefield. Every battle starts with them closed.
+const DOORS: [GridPosition; 1] = [GridPosition::new(4, 2)];
+
+/// Each lever on the default battlefield and the drawbridge it works. Every
+/// battle starts with the bridges raised.
+const LEVERS: [(GridPosition, GridPosition); 1] =
+    [(GridPosition::new(2, 6), GridPosition::new(4, 6))];
+
+/// Tiles taken up by doors, levers, and bridges.
+pub fn gate_sites() -> impl Iterator<Item = GridPosition> {
+    DOORS.into_iter().chain(
+        LEVERS
+            .into_iter()
+            .flat_map(|(lever, bridge)| [lever, bridge]),
+    )
+}
+
+/// The drawbridge worked by the lever at `lever`, if there is one.
+pub fn lever_bridge(lever: GridPosition) -> Option<GridPosition> {
+    LEVERS
+        .into_iter()
+        .find(|(pos, _)| *pos == lever)
+        .map(|(_, bridge)| bridge)
+}
+
+/// Closes every door and raises every bridge.
+pub fn place_gates(map: &mut GridMap) {
+    for door in DOORS {
+        map.set_tile(door, TileType::Door { open: false });
+    }
+    for (lever, bridge) in LEVERS {
+        map.set_tile(lever, TileType::Lever);
+        map.set_tile(bridge, TileType::Bridge { lowered: false });
+    }
+}
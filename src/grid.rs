@@ -0,0 +1,793 @@
+//! The battlefield grid: tile layout, coordinate conversion, reachability,
+//! and the chunks the terrain is drawn in.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::sprite_render::{AlphaMode2d, TileData, TilemapChunk, TilemapChunkTileData};
+use serde::{Deserialize, Serialize};
+
+use crate::chest::ChestLayout;
+use crate::command::CommandLog;
+use crate::constants::*;
+#[cfg(feature = "render")]
+use crate::contrast::HighContrast;
+#[cfg(feature = "render")]
+use crate::fire::Fires;
+use crate::gate::{gate_sites, place_gates};
+#[cfg(feature = "render")]
+use crate::input::GridCursor;
+#[cfg(feature = "render")]
+use crate::locale::Locale;
+use crate::rules::GameRng;
+#[cfg(feature = "render")]
+use crate::sprites::{tile_animation, tile_frame, TileSet};
+use crate::structure::structure_sites;
+#[cfg(feature = "render")]
+use crate::structure::{terrain_at, Structure};
+use crate::tiles::{CustomTile, TileRegistry};
+use crate::trap::trap_sites;
+use crate::unit::{UnitId, UnitIndex};
+
+/// A tile coordinate on the battlefield. `(0, 0)` is the bottom-left tile.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
+pub struct GridPosition {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl GridPosition {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// Manhattan distance, which is how both movement and attack ranges are measured.
+    pub fn distance(self, other: GridPosition) -> u32 {
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
+    }
+
+    /// The tiles strictly between `self` and `other` along a four-connected
+    /// line, so there are `distance - 1` of them.
+    pub fn line_between(self, other: GridPosition) -> Vec<GridPosition> {
+        let (dx, dy) = (self.x.abs_diff(other.x), self.y.abs_diff(other.y));
+        let (step_x, step_y) = ((other.x - self.x).signum(), (other.y - self.y).signum());
+        let (mut taken_x, mut taken_y) = (0, 0);
+        let mut tile = self;
+        let mut line = Vec::new();
+        while taken_x + taken_y + 1 < dx + dy {
+            // Step along whichever axis keeps the line closest to the
+            // straight line between the tile centres.
+            if (1 + 2 * taken_x) * dy < (1 + 2 * taken_y) * dx {
+                tile.x += step_x;
+                taken_x += 1;
+            } else {
+                tile.y += step_y;
+                taken_y += 1;
+            }
+            line.push(tile);
+        }
+        line
+    }
+
+    /// Every tile within `range` steps of this one, itself included, on the
+    /// map or not.
+    pub fn within(self, range: u32) -> impl Iterator<Item = GridPosition> {
+        let range = range as i32;
+        (-range..=range).flat_map(move |dy| {
+            let span = range - dy.abs();
+            (-span..=span).map(move |dx| GridPosition::new(self.x + dx, self.y + dy))
+        })
+    }
+
+    pub fn neighbors(self) -> [GridPosition; 4] {
+        [
+            GridPosition::new(self.x + 1, self.y),
+            GridPosition::new(self.x - 1, self.y),
+            GridPosition::new(self.x, self.y + 1),
+            GridPosition::new(self.x, self.y - 1),
+        ]
+    }
+
+    /// World-space center of this tile at the given draw depth.
+    pub fn to_world(self, z: f32) -> Vec3 {
+        Vec3::new(self.x as f32 * TILE_SIZE, self.y as f32 * TILE_SIZE, z)
+    }
+
+    /// The tile containing the given world-space point.
+    pub fn from_world(world: Vec2) -> Self {
+        let tile = (world / TILE_SIZE).round();
+        GridPosition::new(tile.x as i32, tile.y as i32)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Reflect)]
+pub enum TileType {
+    Grass,
+    /// Cover that makes units standing in it harder to hit.
+    Forest,
+    Water,
+    Mountain,
+    /// Blocks movement until a unit next to it opens it.
+    Door {
+        open: bool,
+    },
+    /// Crossing over water, but only while lowered from its lever.
+    Bridge {
+        lowered: bool,
+    },
+    Lever,
+    /// A kind from the [`TileRegistry`], with its rules.
+    Custom(CustomTile),
+}
+
+impl TileType {
+    pub fn is_walkable(self) -> bool {
+        match self {
+            TileType::Grass | TileType::Forest | TileType::Lever => true,
+            TileType::Water | TileType::Mountain => false,
+            TileType::Door { open } => open,
+            TileType::Bridge { lowered } => lowered,
+            TileType::Custom(custom) => custom.walkable,
+        }
+    }
+
+    /// Movement used up stepping onto this tile. Every built-in tile costs
+    /// one step.
+    pub fn move_cost(self) -> u32 {
+        match self {
+            TileType::Custom(custom) => custom.move_cost,
+            _ => 1,
+        }
+    }
+
+    /// Whether units can see past this tile. Mountains and closed doors cut
+    /// off line of sight; the tile itself can still be seen.
+    pub fn blocks_vision(self) -> bool {
+        match self {
+            TileType::Mountain | TileType::Door { open: false } => true,
+            TileType::Grass
+            | TileType::Forest
+            | TileType::Water
+            | TileType::Door { open: true }
+            | TileType::Bridge { .. }
+            | TileType::Lever => false,
+            TileType::Custom(custom) => custom.blocks_vision,
+        }
+    }
+
+    /// Tiles of sight range used up looking through this tile. Forests
+    /// thin out vision without cutting it off.
+    pub fn sight_cost(self) -> u32 {
+        match self {
+            TileType::Forest => 2,
+            TileType::Custom(custom) => custom.sight_cost,
+            _ => 1,
+        }
+    }
+
+    /// The name shown for a built-in tile. Custom tiles are named in the
+    /// [`TileRegistry`].
+    pub fn label(self) -> &'static str {
+        match self {
+            TileType::Grass => "Grass",
+            TileType::Forest => "Forest",
+            TileType::Water => "Water",
+            TileType::Mountain => "Mountain",
+            TileType::Door { open: false } => "Door (closed)",
+            TileType::Door { open: true } => "Door (open)",
+            TileType::Bridge { lowered: false } => "Bridge (raised)",
+            TileType::Bridge { lowered: true } => "Bridge (lowered)",
+            TileType::Lever => "Lever",
+            TileType::Custom(_) => "Terrain",
+        }
+    }
+
+    /// Combat modifiers for a unit standing on this tile.
+    pub fn bonus(self) -> TerrainBonus {
+        match self {
+            TileType::Forest => TerrainBonus {
+                defense: 0,
+                avoid: 20,
+                ranged_cover: 2,
+            },
+            TileType::Custom(custom) => custom.bonus,
+            _ => TerrainBonus::default(),
+        }
+    }
+}
+
+/// Combat modifiers a defender gets from where it stands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Reflect)]
+pub struct TerrainBonus {
+    /// Added to the defender's defense.
+    pub defense: u32,
+    /// Percent chance that an attack misses outright.
+    pub avoid: u32,
+    /// Damage taken off attacks from more than one tile away.
+    pub ranged_cover: u32,
+}
+
+/// Forest tiles on the default battlefield.
+const FORESTS: [GridPosition; 7] = [
+    GridPosition::new(4, 3),
+    GridPosition::new(4, 4),
+    GridPosition::new(5, 4),
+    GridPosition::new(3, 1),
+    GridPosition::new(7, 2),
+    GridPosition::new(7, 3),
+    GridPosition::new(8, 6),
+];
+
+/// A square block of [`CHUNK_SIZE`] tiles a side, drawn as a single mesh.
+/// Chunks along the top and right edges may run past the map.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component)]
+pub struct Chunk {
+    /// Its bottom-left tile.
+    pub origin: GridPosition,
+}
+
+impl Chunk {
+    /// The chunks covering `map`, bottom row first.
+    pub fn covering(map: &GridMap) -> impl Iterator<Item = Chunk> + use<> {
+        let (width, height) = (map.width, map.height);
+        (0..height).step_by(CHUNK_SIZE as usize).flat_map(move |y| {
+            (0..width).step_by(CHUNK_SIZE as usize).map(move |x| Chunk {
+                origin: GridPosition::new(x, y),
+            })
+        })
+    }
+
+    /// Its tiles, row by row from the bottom, the order its tile data is in.
+    pub fn tiles(self) -> impl Iterator<Item = GridPosition> {
+        (0..CHUNK_SIZE).flat_map(move |y| {
+            (0..CHUNK_SIZE).map(move |x| GridPosition::new(self.origin.x + x, self.origin.y + y))
+        })
+    }
+
+    /// World-space center of the chunk at the given draw depth.
+    pub fn to_world(self, z: f32) -> Vec3 {
+        let offset = (CHUNK_SIZE - 1) as f32 * TILE_SIZE / 2.0;
+        self.origin.to_world(z) + Vec3::new(offset, offset, 0.0)
+    }
+
+    /// Draws `tiles`, listed in [`Chunk::tiles`] order, from `tileset` at
+    /// depth `z`.
+    #[cfg(feature = "render")]
+    pub fn bundle(
+        self,
+        tileset: Handle<Image>,
+        alpha_mode: AlphaMode2d,
+        z: f32,
+        tiles: Vec<Option<TileData>>,
+    ) -> impl Bundle {
+        (
+            self,
+            self.tilemap(tileset, alpha_mode),
+            TilemapChunkTileData(tiles),
+            Transform::from_translation(self.to_world(z)),
+        )
+    }
+
+    /// The chunk's mesh, drawn from `tileset`. Reinserting it is how a chunk
+    /// switches to another tileset.
+    #[cfg(feature = "render")]
+    pub fn tilemap(self, tileset: Handle<Image>, alpha_mode: AlphaMode2d) -> TilemapChunk {
+        TilemapChunk {
+            chunk_size: UVec2::splat(CHUNK_SIZE as u32),
+            tile_display_size: UVec2::splat(TILE_SIZE as u32),
+            tileset,
+            alpha_mode,
+        }
+    }
+}
+
+/// Marker for the chunks that draw the terrain.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct TerrainChunk {
+    /// Whether any of its tiles animate, and so need redrawing every frame.
+    pub animated: bool,
+}
+
+/// The logical layout of the battlefield.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Reflect)]
+#[reflect(Resource)]
+pub struct GridMap {
+    pub width: i32,
+    pub height: i32,
+    tiles: Vec<TileType>,
+}
+
+impl GridMap {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            tiles: vec![TileType::Grass; (width * height) as usize],
+        }
+    }
+
+    pub fn in_bounds(&self, pos: GridPosition) -> bool {
+        pos.x >= 0 && pos.y >= 0 && pos.x < self.width && pos.y < self.height
+    }
+
+    pub fn tile(&self, pos: GridPosition) -> Option<TileType> {
+        self.in_bounds(pos)
+            .then(|| self.tiles[(pos.y * self.width + pos.x) as usize])
+    }
+
+    pub fn set_tile(&mut self, pos: GridPosition, tile: TileType) {
+        if self.in_bounds(pos) {
+            self.tiles[(pos.y * self.width + pos.x) as usize] = tile;
+        }
+    }
+
+    pub fn is_walkable(&self, pos: GridPosition) -> bool {
+        self.tile(pos).is_some_and(TileType::is_walkable)
+    }
+
+    /// Scatters small water patches and short mountain ridges over the
+    /// middle of the map, leaving the deployment columns and `reserved`
+    /// tiles alone. A feature that would wall off part of the map is skipped.
+    pub fn scatter_terrain(&mut self, rng: &mut GameRng, reserved: &HashSet<GridPosition>) {
+        let min_x = DEPLOY_COLUMNS as u32;
+        let max_x = (self.width - DEPLOY_COLUMNS - 1) as u32;
+        let max_y = (self.height - 1) as u32;
+
+        for _ in 0..3 {
+            let center =
+                GridPosition::new(rng.range(min_x, max_x) as i32, rng.range(0, max_y) as i32);
+            let mut patch = vec![center];
+            for _ in 0..rng.range(1, 2) {
+                patch.push(center.neighbors()[rng.range(0, 3) as usize]);
+            }
+            self.place_feature(&patch, TileType::Water, reserved);
+        }
+        for _ in 0..2 {
+            let start =
+                GridPosition::new(rng.range(min_x, max_x) as i32, rng.range(0, max_y) as i32);
+            let (dx, dy) = if rng.range(0, 1) == 0 { (1, 0) } else { (0, 1) };
+            let ridge: Vec<GridPosition> = (0..rng.range(2, 3) as i32)
+                .map(|step| GridPosition::new(start.x + dx * step, start.y + dy * step))
+                .collect();
+            self.place_feature(&ridge, TileType::Mountain, reserved);
+        }
+    }
+
+    /// Scatters `count` single `tile`s over the middle of the map, as
+    /// [`GridMap::scatter_terrain`] does its water and mountains.
+    pub fn scatter_tiles(
+        &mut self,
+        rng: &mut GameRng,
+        tile: TileType,
+        count: u32,
+        reserved: &HashSet<GridPosition>,
+    ) {
+        let min_x = DEPLOY_COLUMNS as u32;
+        let max_x = (self.width - DEPLOY_COLUMNS - 1) as u32;
+        let max_y = (self.height - 1) as u32;
+        for _ in 0..count {
+            let pos = GridPosition::new(rng.range(min_x, max_x) as i32, rng.range(0, max_y) as i32);
+            self.place_feature(&[pos], tile, reserved);
+        }
+    }
+
+    fn place_feature(
+        &mut self,
+        tiles: &[GridPosition],
+        tile: TileType,
+        reserved: &HashSet<GridPosition>,
+    ) {
+        let open: Vec<GridPosition> = tiles
+            .iter()
+            .copied()
+            .filter(|pos| {
+                pos.x >= DEPLOY_COLUMNS
+                    && pos.x < self.width - DEPLOY_COLUMNS
+                    && self.tile(*pos) == Some(TileType::Grass)
+                    && !reserved.contains(pos)
+            })
+            .collect();
+        for pos in &open {
+            self.set_tile(*pos, tile);
+        }
+        if !self.is_connected() {
+            for pos in &open {
+                self.set_tile(*pos, TileType::Grass);
+            }
+        }
+    }
+
+    /// Whether every walkable tile can be reached from every other one.
+    fn is_connected(&self) -> bool {
+        let walkable: Vec<GridPosition> = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| GridPosition::new(x, y)))
+            .filter(|pos| self.is_walkable(*pos))
+            .collect();
+        walkable.first().is_none_or(|&start| {
+            reachable_tiles(self, start, u32::MAX, &HashSet::new()).len() == walkable.len()
+        })
+    }
+
+    /// Width and height of the whole board, in world units.
+    pub fn world_size(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32) * TILE_SIZE
+    }
+
+    /// World-space center of the whole map, used to frame the camera.
+    pub fn center(&self) -> Vec2 {
+        Vec2::new(
+            (self.width - 1) as f32 * TILE_SIZE / 2.0,
+            (self.height - 1) as f32 * TILE_SIZE / 2.0,
+        )
+    }
+}
+
+impl Default for GridMap {
+    fn default() -> Self {
+        let mut map = Self::new(GRID_WIDTH, GRID_HEIGHT);
+        for pos in FORESTS {
+            map.set_tile(pos, TileType::Forest);
+        }
+        map
+    }
+}
+
+/// Every tile reachable from `start` within `range` steps, walking around
+/// `blocked` tiles. Stepping onto a tile uses up its
+/// [move cost](TileType::move_cost). The starting tile is always included.
+pub fn reachable_tiles(
+    map: &GridMap,
+    start: GridPosition,
+    range: u32,
+    blocked: &HashSet<GridPosition>,
+) -> HashSet<GridPosition> {
+    let mut cheapest = HashMap::from([(start, 0)]);
+    let mut frontier = VecDeque::from([(start, 0u32)]);
+
+    while let Some((pos, spent)) = frontier.pop_front() {
+        // A cheaper way here was found after this one was queued.
+        if cheapest[&pos] < spent {
+            continue;
+        }
+        for next in pos.neighbors() {
+            let Some(tile) = map.tile(next).filter(|tile| tile.is_walkable()) else {
+                continue;
+            };
+            let cost = spent.saturating_add(tile.move_cost());
+            if cost <= range
+                && !blocked.contains(&next)
+                && cheapest.get(&next).is_none_or(|&known| cost < known)
+            {
+                cheapest.insert(next, cost);
+                frontier.push_back((next, cost));
+            }
+        }
+    }
+
+    cheapest.into_keys().collect()
+}
+
+/// Reachable tiles already worked out, keyed by the unit, the tile it
+/// starts from, and how far it walks. Every query walks around the tiles
+/// in [`UnitIndex`], so an entry holds until a unit moves, joins or leaves
+/// the board, the terrain changes, or a new turn begins.
+#[derive(Resource, Default, Debug)]
+pub struct PathCache {
+    reachable: HashMap<(UnitId, GridPosition, u32), HashSet<GridPosition>>,
+}
+
+impl PathCache {
+    /// The tiles `unit` can reach from `start` within `range` steps, worked
+    /// out on first use.
+    pub fn reachable(
+        &mut self,
+        map: &GridMap,
+        index: &UnitIndex,
+        unit: UnitId,
+        start: GridPosition,
+        range: u32,
+    ) -> &HashSet<GridPosition> {
+        // The unit's own tile counts as occupied, which doesn't change what
+        // it can reach.
+        self.reachable
+            .entry((unit, start, range))
+            .or_insert_with(|| reachable_tiles(map, start, range, &index.occupied()))
+    }
+
+    pub fn get(
+        &self,
+        unit: UnitId,
+        start: GridPosition,
+        range: u32,
+    ) -> Option<&HashSet<GridPosition>> {
+        self.reachable.get(&(unit, start, range))
+    }
+
+    pub fn insert(
+        &mut self,
+        unit: UnitId,
+        start: GridPosition,
+        range: u32,
+        tiles: HashSet<GridPosition>,
+    ) {
+        self.reachable.insert((unit, start, range), tiles);
+    }
+}
+
+/// Forgets every cached path. Runs once the board they were worked out on
+/// has changed, right after the unit index catches up with the executor.
+pub fn invalidate_path_cache_system(mut paths: ResMut<PathCache>) {
+    paths.reachable.clear();
+}
+
+#[cfg(feature = "render")]
+pub fn tile_color(map: &GridMap, pos: GridPosition) -> Color {
+    match map.tile(pos) {
+        Some(TileType::Forest) => TILE_FOREST_COLOR,
+        Some(TileType::Water | TileType::Bridge { lowered: false }) => TILE_WATER_COLOR,
+        Some(TileType::Mountain) => TILE_MOUNTAIN_COLOR,
+        Some(TileType::Door { open: false }) => TILE_DOOR_COLOR,
+        Some(TileType::Door { open: true } | TileType::Bridge { lowered: true }) => {
+            TILE_PLANKS_COLOR
+        }
+        Some(TileType::Lever) => TILE_LEVER_COLOR,
+        Some(TileType::Custom(custom)) => {
+            let [red, green, blue] = custom.color;
+            Color::srgb_u8(red, green, blue)
+        }
+        _ if (pos.x + pos.y) % 2 == 0 => TILE_LIGHT_COLOR,
+        _ => TILE_DARK_COLOR,
+    }
+}
+
+/// The battlefields to choose from. They share the structures, traps, and
+/// gates, and each scatters its water and mountains from its own seed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BattleMap {
+    #[default]
+    Crossroads,
+    Lowlands,
+    Ridgeback,
+}
+
+impl BattleMap {
+    pub const ALL: [BattleMap; 3] = [
+        BattleMap::Crossroads,
+        BattleMap::Lowlands,
+        BattleMap::Ridgeback,
+    ];
+
+    fn terrain_seed(self) -> u64 {
+        MAP_SEED + self as u64
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    /// The file in `assets/scripts/` holding the map's scenario script:
+    /// `crossroads.rhai` for the Crossroads, and so on.
+    pub fn script_name(self) -> String {
+        format!("{self:?}.rhai").to_lowercase()
+    }
+}
+
+/// Clears the board and lays out `battle_map` on it, with the doors closed
+/// and the bridges raised.
+pub fn lay_out(
+    map: &mut GridMap,
+    chests: &ChestLayout,
+    tiles: &TileRegistry,
+    battle_map: BattleMap,
+) {
+    *map = GridMap::new(map.width, map.height);
+    let reserved: HashSet<GridPosition> = structure_sites()
+        .chain(trap_sites())
+        .chain(gate_sites())
+        .chain(chests.sites())
+        .collect();
+    // Bigger boards get as much terrain for their size as the default one.
+    let rounds = (map.width * map.height / (GRID_WIDTH * GRID_HEIGHT)).max(1);
+    let mut rng = GameRng::new(battle_map.terrain_seed());
+    for _ in 0..rounds {
+        map.scatter_terrain(&mut rng, &reserved);
+        for (tile, count) in tiles.scattered() {
+            map.scatter_tiles(&mut rng, tile, count, &reserved);
+        }
+    }
+    place_gates(map);
+}
+
+pub fn setup_grid(mut map: ResMut<GridMap>, chests: Res<ChestLayout>, tiles: Res<TileRegistry>) {
+    lay_out(&mut map, &chests, &tiles, BattleMap::default());
+}
+
+/// Lays out the map the battle in the [`CommandLog`] is fought on, which
+/// also undoes whatever the last battle did to the doors and bridges.
+pub fn lay_out_battle_map(
+    log: Res<CommandLog>,
+    mut map: ResMut<GridMap>,
+    chests: Res<ChestLayout>,
+    tiles: Res<TileRegistry>,
+) {
+    lay_out(&mut map, &chests, &tiles, log.map);
+}
+
+/// How a tile is drawn: a frame of the tile sheet when it has loaded, and
+/// a flat color, stretched in high contrast, otherwise. Tiles off the map
+/// are left empty.
+#[cfg(feature = "render")]
+fn terrain_tile(
+    map: &GridMap,
+    tileset: &TileSet,
+    contrast: HighContrast,
+    pos: GridPosition,
+    elapsed: f32,
+) -> Option<TileData> {
+    let tile = map.tile(pos)?;
+    let animation = tile_animation(tile);
+    Some(match (&tileset.tiles, animation, tile_frame(tile)) {
+        (Some(_), Some(animation), _) => {
+            TileData::from_tileset_index(animation.frame(pos, elapsed) as u16)
+        }
+        (Some(_), None, Some(frame)) => TileData::from_tileset_index(frame as u16),
+        (None, Some(animation), _) => TileData {
+            color: contrast.apply(animation.tint(tile_color(map, pos), pos, elapsed)),
+            ..default()
+        },
+        (_, None, _) => TileData {
+            color: contrast.apply(tile_color(map, pos)),
+            ..default()
+        },
+    })
+}
+
+/// Whether two chunks' worth of tile data draw the same.
+#[cfg(feature = "render")]
+pub fn same_tiles(a: &[Option<TileData>], b: &[Option<TileData>]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| match (a, b) {
+            (Some(a), Some(b)) => {
+                a.tileset_index == b.tileset_index && a.color == b.color && a.visible == b.visible
+            }
+            (None, None) => true,
+            _ => false,
+        })
+}
+
+/// Spawns the chunks that draw the map [`setup_grid`] laid out.
+#[cfg(feature = "render")]
+pub fn spawn_tiles(
+    mut commands: Commands,
+    map: Res<GridMap>,
+    tileset: Res<TileSet>,
+    contrast: Res<HighContrast>,
+) {
+    for chunk in Chunk::covering(&map) {
+        let tiles = chunk
+            .tiles()
+            .map(|pos| terrain_tile(&map, &tileset, *contrast, pos, 0.0))
+            .collect();
+        commands.spawn((
+            TerrainChunk::default(),
+            chunk.bundle(tileset.white.clone(), AlphaMode2d::Opaque, TILE_Z, tiles),
+        ));
+    }
+}
+
+/// Redraws the terrain when doors open or bridges move, once the tile sheet
+/// has loaded, and when high contrast is switched, and steps the chunks
+/// holding animated tiles.
+#[cfg(feature = "render")]
+pub fn update_tile_visuals_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    map: Res<GridMap>,
+    tileset: Res<TileSet>,
+    contrast: Res<HighContrast>,
+    mut chunks: Query<(Entity, &Chunk, &mut TerrainChunk, &mut TilemapChunkTileData)>,
+) {
+    let elapsed = time.elapsed_secs();
+    let redraw = map.is_changed() || tileset.is_changed() || contrast.is_changed();
+    for (entity, &chunk, mut terrain, mut data) in &mut chunks {
+        if tileset.is_changed() {
+            let image = tileset
+                .tiles
+                .clone()
+                .unwrap_or_else(|| tileset.white.clone());
+            commands
+                .entity(entity)
+                .insert(chunk.tilemap(image, AlphaMode2d::Opaque));
+        }
+        if redraw {
+            terrain.animated = chunk
+                .tiles()
+                .any(|pos| map.tile(pos).and_then(tile_animation).is_some());
+        } else if !terrain.animated {
+            continue;
+        }
+        let tiles: Vec<_> = chunk
+            .tiles()
+            .map(|pos| terrain_tile(&map, &tileset, *contrast, pos, elapsed))
+            .collect();
+        if !same_tiles(&data, &tiles) {
+            data.0 = tiles;
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct TileInfoText;
+
+#[cfg(feature = "render")]
+pub fn setup_tile_info(mut commands: Commands) {
+    commands.spawn((
+        TileInfoText,
+        Text::new(""),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.0),
+            bottom: Val::Px(10.0),
+            ..default()
+        },
+    ));
+}
+
+/// What stands at `pos` and the combat bonus it grants, e.g.
+/// `"Forest  DEF +1  AVO 20%"`, in the player's language.
+#[cfg(feature = "render")]
+pub fn tile_info(
+    map: &GridMap,
+    tiles: &TileRegistry,
+    fires: &Fires,
+    structures: &Query<(&GridPosition, &Structure)>,
+    locale: &Locale,
+    pos: GridPosition,
+) -> Option<String> {
+    let tile = map.tile(pos)?;
+    let structure = structures
+        .iter()
+        .find(|(structure_pos, _)| **structure_pos == pos)
+        .map(|(_, structure)| structure.kind);
+    let bonus = terrain_at(map, structure, pos);
+    let name = structure.map_or(tiles.label(tile).to_string(), |kind| format!("{kind:?}"));
+    let mut info = locale.fill(
+        "{}  DEF +{}  AVO {}%",
+        &[&locale.tr(&name), &bonus.defense, &bonus.avoid],
+    );
+    if bonus.ranged_cover > 0 {
+        info += &locale.fill("  Ranged -{}", &[&bonus.ranged_cover]);
+    }
+    if fires.is_burning(pos) {
+        info += locale.tr("  Burning");
+    }
+    Some(info)
+}
+
+/// Describes the tile under the cursor and the combat bonus it grants.
+#[cfg(feature = "render")]
+pub fn update_tile_info_system(
+    cursor: Res<GridCursor>,
+    map: Res<GridMap>,
+    tiles: Res<TileRegistry>,
+    fires: Res<Fires>,
+    structures: Query<(&GridPosition, &Structure)>,
+    locale: Res<Locale>,
+    mut text: Single<&mut Text, With<TileInfoText>>,
+) {
+    let info = cursor
+        .0
+        .and_then(|pos| tile_info(&map, &tiles, &fires, &structures, &locale, pos))
+        .unwrap_or_default();
+    text.set_if_neq(Text::new(info));
+}
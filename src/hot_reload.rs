@@ -0,0 +1,174 @@
+//! Reloading data files while the game runs, behind the `dev-tools`
+//! feature.
+//!
+//! The files in `assets/data/`, and in the data of enabled mods, are checked
+//! for changes a couple of times a second. A changed file is parsed again
+//! and, if it parses, replaces the definitions loaded from it; one that
+//! doesn't is logged and the old definitions stay. Anything read from a
+//! catalog when it is used, like an item's bonuses, changes straight away.
+//! What was copied onto the board when the battle started is brought in
+//! line where that's safe: unopened chests take their new loot, and no item
+//! keeps more uses than its definition now allows. Everything else applies
+//! from the next battle.
+//!
+//! Reloading is for trying out changes, not for playing: a battle reloaded
+//! mid-way no longer matches its replay or the other side of an online
+//! match.
+
+use std::collections::HashMap;
+use std::fs;
+use std::iter;
+use std::time::SystemTime;
+
+use bevy::prelude::*;
+
+use crate::ability::AbilityCatalog;
+use crate::chest::{Chest, ChestLayout};
+use crate::constants::DATA_POLL_SECONDS;
+use crate::data::{base_path, read_data_file, Layered};
+use crate::grid::GridPosition;
+use crate::items::{Equipment, Inventory, Item, ItemCatalog};
+use crate::mods::enabled_mods;
+
+/// The name of a data file that changed on disk, e.g. `"items.ron"`.
+#[derive(Message, Clone, Debug, PartialEq, Eq)]
+pub struct DataChanged(pub String);
+
+/// When each data file was last changed, as of the last check.
+#[derive(Resource)]
+pub struct DataWatcher {
+    modified: HashMap<String, SystemTime>,
+    timer: Timer,
+}
+
+impl Default for DataWatcher {
+    fn default() -> Self {
+        Self {
+            modified: scan_data_files(),
+            timer: Timer::from_seconds(DATA_POLL_SECONDS, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Every data file, built in or from a mod, with when any of its copies
+/// was last changed.
+fn scan_data_files() -> HashMap<String, SystemTime> {
+    let dirs = iter::once(base_path().join("assets"))
+        .chain(enabled_mods().map(|installed| installed.dir.clone()))
+        .map(|dir| dir.join("data"));
+    let mut modified: HashMap<String, SystemTime> = HashMap::new();
+    for entry in dirs
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+    {
+        let Ok(stamp) = entry.metadata().and_then(|meta| meta.modified()) else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let latest = modified.entry(name).or_insert(stamp);
+        *latest = (*latest).max(stamp);
+    }
+    modified
+}
+
+pub fn watch_data_files_system(
+    time: Res<Time>,
+    mut watcher: ResMut<DataWatcher>,
+    mut changed: MessageWriter<DataChanged>,
+) {
+    if !watcher.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let modified = scan_data_files();
+    for (name, stamp) in &modified {
+        if watcher.modified.get(name) != Some(stamp) {
+            info!("{name} changed on disk");
+            changed.write(DataChanged(name.clone()));
+        }
+    }
+    watcher.modified = modified;
+}
+
+/// A system that reloads the resource `T` from the data file `name` when it
+/// changes.
+pub fn reload_data_file<T: Resource + Layered>(
+    name: &'static str,
+) -> impl FnMut(MessageReader<DataChanged>, Commands) {
+    move |mut changed: MessageReader<DataChanged>, mut commands: Commands| {
+        if !changed.read().any(|file| file.0 == name) {
+            return;
+        }
+        match read_data_file::<T>(name) {
+            Ok(data) => {
+                info!("Reloaded {name}");
+                commands.insert_resource(data);
+            }
+            Err(err) => error!("{err}; keeping the old definitions"),
+        }
+    }
+}
+
+/// Cuts down the uses left on every carried item to what its definition now
+/// allows, and takes them away from items that no longer wear out.
+pub fn refresh_item_uses_system(
+    catalog: Res<ItemCatalog>,
+    mut units: Query<(&mut Inventory, &mut Equipment)>,
+) {
+    let refresh = |item: &mut Item| {
+        let total = catalog.get(&item.id).and_then(|def| def.uses);
+        item.uses = match (item.uses, total) {
+            (Some(left), Some(total)) => Some(left.min(total)),
+            (None, Some(total)) => Some(total),
+            (_, None) => None,
+        };
+    };
+    for (mut inventory, mut equipment) in &mut units {
+        inventory.items.iter_mut().for_each(refresh);
+        equipment.weapon.iter_mut().for_each(refresh);
+        equipment.armor.iter_mut().for_each(refresh);
+    }
+}
+
+/// Gives every unopened chest the loot its site now has.
+pub fn refresh_chests_system(
+    layout: Res<ChestLayout>,
+    mut chests: Query<(&mut Chest, &GridPosition)>,
+) {
+    for (mut chest, pos) in &mut chests {
+        let Some(def) = layout.chests.iter().find(|def| def.at == *pos) else {
+            continue;
+        };
+        if !chest.opened && chest.loot != def.loot {
+            chest.loot = def.loot.clone();
+        }
+    }
+}
+
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<DataChanged>()
+            .init_resource::<DataWatcher>()
+            .add_systems(
+                First,
+                (
+                    watch_data_files_system,
+                    (
+                        reload_data_file::<ItemCatalog>("items.ron"),
+                        reload_data_file::<ChestLayout>("chests.ron"),
+                        reload_data_file::<AbilityCatalog>("abilities.ron"),
+                    ),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                PreUpdate,
+                (
+                    refresh_item_uses_system.run_if(resource_changed::<ItemCatalog>),
+                    refresh_chests_system.run_if(resource_changed::<ChestLayout>),
+                ),
+            );
+    }
+}
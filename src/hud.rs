@@ -0,0 +1,91 @@
+//! Live HUD labels.
+//!
+//! A label is a text entity with a [`HudLabel`]: the [`HudEvent`]s it
+//! follows and a function that writes it from [`HudSources`]. Labels are
+//! only rewritten when one of their events arrives, and in place, so a
+//! frame where nothing happened costs nothing and a rewrite reuses the
+//! label's buffer. The events are sent as the resources behind them change,
+//! and any system can send one to refresh the labels that follow it.
+//!
+//! Adding a label takes a spawn with a [`HudLabel`]; data no label has
+//! shown before goes in [`HudSources`], along with an event for it.
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::announce::AnnouncementLog;
+use crate::bindings::InputMap;
+use crate::economy::Treasury;
+use crate::locale::Locale;
+use crate::seats::Seats;
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::FactionPalette;
+use crate::victory::Objectives;
+
+/// Something HUD labels show has changed.
+#[derive(Message, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HudEvent {
+    Turn,
+    Gold,
+    Outcome,
+    Palette,
+    Objectives,
+    Announcements,
+    Bindings,
+    Language,
+}
+
+impl HudEvent {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Everything a HUD label can show.
+#[derive(SystemParam)]
+pub struct HudSources<'w> {
+    pub turn: Res<'w, CurrentTurn>,
+    pub outcome: Res<'w, BattleOutcome>,
+    pub treasury: Res<'w, Treasury>,
+    pub palette: Res<'w, FactionPalette>,
+    pub seats: Res<'w, Seats>,
+    pub objectives: Res<'w, Objectives>,
+    pub announcements: Res<'w, AnnouncementLog>,
+    pub bindings: Res<'w, InputMap>,
+    pub locale: Res<'w, Locale>,
+}
+
+/// Writes a label's text, which starts out empty, and may set its color.
+pub type WriteLabel = fn(&HudSources, &mut String, &mut Color);
+
+#[derive(Component)]
+pub struct HudLabel {
+    /// The events that call for a rewrite.
+    pub events: &'static [HudEvent],
+    pub write: WriteLabel,
+}
+
+/// Sends `event` each time it runs, for running when the data behind the
+/// event changes.
+pub fn send_hud_event(event: HudEvent) -> impl FnMut(MessageWriter<HudEvent>) {
+    move |mut events: MessageWriter<HudEvent>| {
+        events.write(event);
+    }
+}
+
+/// Rewrites the labels that follow any event sent since the last run, and
+/// writes new labels for the first time.
+pub fn update_hud_labels_system(
+    mut events: MessageReader<HudEvent>,
+    sources: HudSources,
+    mut labels: Query<(Ref<HudLabel>, &mut Text, &mut TextColor)>,
+) {
+    let fired = events.read().fold(0, |fired, event| fired | event.bit());
+    for (label, mut text, mut color) in &mut labels {
+        let follows = label.events.iter().any(|event| fired & event.bit() != 0);
+        if follows || label.is_added() {
+            text.0.clear();
+            (label.write)(&sources, &mut text.0, &mut color.0);
+        }
+    }
+}
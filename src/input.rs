@@ -0,0 +1,293 @@
+//! Turning mouse, keyboard, and gamepad input into board actions.
+//!
+//! The board is played through a [`GridCursor`] and [`GameAction`]s. The
+//! mouse moves the cursor by hovering and clicks to act on the tile under
+//! it; the keyboard steers the cursor with IJKL and acts on its tile with
+//! F; a gamepad steers it with the d-pad or left stick and acts with its
+//! buttons. Menus are reached without a pointer through [`UiFocus`]: the
+//! menu focus key or button (M, or West on a gamepad, by default) jumps
+//! into the open menus, Page Up and Page Down or the d-pad move between
+//! their buttons, confirm or select presses the focused one and cancel
+//! backs out. On the main menu focus never leaves the buttons. Which keys
+//! and buttons do what comes from the player's [`InputMap`].
+//!
+//! Only the systems here read the mouse, keyboard, and gamepads for the
+//! board. Selection, movement, and turn flow read [`GameAction`]s alone,
+//! so writing those messages drives the board exactly as a player would.
+//!
+//! [`InputMap`]: crate::bindings::InputMap
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::bindings::{ActionInput, InputAction};
+use crate::grid::{GridMap, GridPosition};
+use crate::selection::{cursor_grid_position, pointer_over_ui};
+use crate::state::AppState;
+
+/// Seconds a direction is held before the cursor starts repeating, and
+/// between repeats after that.
+const CURSOR_REPEAT_DELAY: f32 = 0.3;
+const CURSOR_REPEAT_SECONDS: f32 = 0.1;
+/// How far a stick has to lean to count as a direction.
+const STICK_THRESHOLD: f32 = 0.5;
+const FOCUS_OUTLINE_COLOR: Color = Color::srgb(1.0, 0.9, 0.2);
+
+/// Something the player asked the board to do, whatever they pressed.
+#[derive(Message, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameAction {
+    /// Act on a tile: select the unit there, or send the selected unit to
+    /// move to or attack it.
+    SelectAt(GridPosition),
+    /// Accept what the selected unit is being offered: in its action menu,
+    /// end its action with Wait.
+    Confirm,
+    /// Drop the selection or close the open panel.
+    Cancel,
+    EndTurn,
+    /// Select the next or previous player unit that can still act.
+    CycleUnit {
+        forward: bool,
+    },
+}
+
+/// The tile the player is pointing at, with the mouse or a gamepad.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GridCursor(pub Option<GridPosition>);
+
+/// Whether keyboard and gamepad input goes to the on-screen buttons rather
+/// than the board, and which button it is on.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct UiFocus {
+    pub active: bool,
+    pub button: Option<Entity>,
+}
+
+/// A button pressed from the keyboard or a gamepad through [`UiFocus`];
+/// menus treat it like a click.
+#[derive(Message, Clone, Copy, Debug)]
+pub struct ButtonActivated(pub Entity);
+
+/// Whether a cursor held in `direction` steps this frame: once straight
+/// away, then repeatedly once it has been held a moment.
+fn repeat_step(held: &mut (IVec2, f32), direction: IVec2, delta: f32) -> bool {
+    let (last, since) = held;
+    let step = if direction == IVec2::ZERO {
+        false
+    } else if direction != *last {
+        *since = -CURSOR_REPEAT_DELAY;
+        true
+    } else {
+        *since += delta;
+        if *since >= CURSOR_REPEAT_SECONDS {
+            *since = 0.0;
+            true
+        } else {
+            false
+        }
+    };
+    *last = direction;
+    step
+}
+
+/// Moves the cursor one step in `direction`, starting from the middle of
+/// the map when there is no cursor yet.
+fn step_cursor(cursor: &mut GridCursor, map: &GridMap, direction: IVec2) {
+    let from = cursor
+        .0
+        .unwrap_or_else(|| GridPosition::from_world(map.center()));
+    let to = GridPosition::new(from.x + direction.x, from.y + direction.y);
+    if map.in_bounds(to) {
+        cursor.0 = Some(to);
+    }
+}
+
+/// Points and acts with the mouse, and steers the cursor and acts on the
+/// board from the keyboard. Select acts on the tile under the mouse when
+/// clicked, and on the cursor's tile when pressed on the keyboard.
+pub fn mouse_keyboard_input_system(
+    time: Res<Time>,
+    input: ActionInput,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform)>,
+    (map, focus): (Res<GridMap>, Res<UiFocus>),
+    ui: Query<&Interaction>,
+    (mut last_pointer, mut held): (Local<Option<Vec2>>, Local<(IVec2, f32)>),
+    mut cursor: ResMut<GridCursor>,
+    mut actions: MessageWriter<GameAction>,
+) {
+    let (camera, camera_transform) = *camera;
+    let hovered = cursor_grid_position(&window, camera, camera_transform);
+    // Only a moving mouse takes the cursor, so a resting one doesn't snatch
+    // it back from a gamepad.
+    let pointer = window.cursor_position();
+    if pointer != *last_pointer {
+        *last_pointer = pointer;
+        cursor.set_if_neq(GridCursor(hovered));
+    }
+    if focus.active {
+        *held = (IVec2::ZERO, 0.0);
+        return;
+    }
+
+    let axis = |negative, positive| input.pressed(positive) as i32 - input.pressed(negative) as i32;
+    let direction = IVec2::new(
+        axis(InputAction::CursorLeft, InputAction::CursorRight),
+        axis(InputAction::CursorDown, InputAction::CursorUp),
+    );
+    if repeat_step(&mut held, direction, time.delta_secs()) {
+        step_cursor(&mut cursor, &map, direction);
+    }
+
+    if input.just_pressed_on_mouse_or_keyboard(InputAction::Confirm) {
+        actions.write(GameAction::Confirm);
+    }
+    if input.just_pressed_on_mouse_or_keyboard(InputAction::Cancel) {
+        actions.write(GameAction::Cancel);
+    }
+    if input.just_pressed_on_mouse_or_keyboard(InputAction::EndTurn) {
+        actions.write(GameAction::EndTurn);
+    }
+    if input.just_pressed_on_mouse_or_keyboard(InputAction::NextUnit) {
+        actions.write(GameAction::CycleUnit { forward: true });
+    }
+    if input.just_pressed_on_mouse_or_keyboard(InputAction::PreviousUnit) {
+        actions.write(GameAction::CycleUnit { forward: false });
+    }
+    if input.just_pressed_on_keyboard(InputAction::Select) {
+        if let Some(tile) = cursor.0 {
+            actions.write(GameAction::SelectAt(tile));
+        }
+    } else if input.just_pressed_on_mouse_or_keyboard(InputAction::Select) {
+        if let Some(tile) = hovered.filter(|_| !pointer_over_ui(&ui)) {
+            cursor.set_if_neq(GridCursor(Some(tile)));
+            actions.write(GameAction::SelectAt(tile));
+        }
+    }
+}
+
+/// The direction a gamepad's d-pad or left stick points, as a grid step.
+fn gamepad_direction(gamepad: &Gamepad) -> IVec2 {
+    let lean = gamepad.dpad() + gamepad.left_stick();
+    let axis = |value: f32| {
+        if value > STICK_THRESHOLD {
+            1
+        } else if value < -STICK_THRESHOLD {
+            -1
+        } else {
+            0
+        }
+    };
+    IVec2::new(axis(lean.x), axis(lean.y))
+}
+
+/// Steers the cursor and acts on the board from a gamepad: select acts on
+/// the cursor's tile, and cancel, end turn, and unit cycling work as on the
+/// keyboard.
+pub fn gamepad_input_system(
+    time: Res<Time>,
+    input: ActionInput,
+    gamepads: Query<&Gamepad>,
+    map: Res<GridMap>,
+    focus: Res<UiFocus>,
+    mut held: Local<(IVec2, f32)>,
+    mut cursor: ResMut<GridCursor>,
+    mut actions: MessageWriter<GameAction>,
+) {
+    if focus.active {
+        *held = (IVec2::ZERO, 0.0);
+        return;
+    }
+    for gamepad in &gamepads {
+        let direction = gamepad_direction(gamepad);
+        if repeat_step(&mut held, direction, time.delta_secs()) {
+            step_cursor(&mut cursor, &map, direction);
+        }
+    }
+
+    let select = input.just_pressed_on_gamepad(InputAction::Select);
+    if let Some(tile) = cursor.0.filter(|_| select) {
+        actions.write(GameAction::SelectAt(tile));
+    }
+    if input.just_pressed_on_gamepad(InputAction::Confirm) {
+        actions.write(GameAction::Confirm);
+    }
+    if input.just_pressed_on_gamepad(InputAction::Cancel) {
+        actions.write(GameAction::Cancel);
+    }
+    if input.just_pressed_on_gamepad(InputAction::EndTurn) {
+        actions.write(GameAction::EndTurn);
+    }
+    if input.just_pressed_on_gamepad(InputAction::NextUnit) {
+        actions.write(GameAction::CycleUnit { forward: true });
+    }
+    if input.just_pressed_on_gamepad(InputAction::PreviousUnit) {
+        actions.write(GameAction::CycleUnit { forward: false });
+    }
+}
+
+/// Moves focus between the buttons on screen, top to bottom, and presses
+/// the focused one on confirm from the keyboard or select from a gamepad.
+/// The focused button is outlined.
+pub fn ui_focus_system(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    input: ActionInput,
+    buttons: Query<(Entity, &UiGlobalTransform, &InheritedVisibility), With<Button>>,
+    mut outlined: Local<Option<Entity>>,
+    mut focus: ResMut<UiFocus>,
+    mut activated: MessageWriter<ButtonActivated>,
+) {
+    let mut visible: Vec<(Entity, Vec2)> = buttons
+        .iter()
+        .filter(|(_, _, visibility)| visibility.get())
+        .map(|(entity, transform, _)| (entity, transform.translation))
+        .collect();
+    // UI coordinates grow downward, so this is reading order.
+    visible.sort_by(|(_, a), (_, b)| a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x)));
+    let in_menu = matches!(state.get(), AppState::MainMenu | AppState::Lobby);
+
+    if !in_menu && input.just_pressed(InputAction::MenuFocus) {
+        focus.active = !focus.active;
+    } else if focus.active && !in_menu && input.just_pressed(InputAction::Cancel) {
+        focus.active = false;
+    }
+    focus.active = (focus.active || in_menu) && !visible.is_empty();
+    let index = focus
+        .button
+        .and_then(|button| visible.iter().position(|(entity, _)| *entity == button));
+
+    if focus.active {
+        let mut index = index.unwrap_or(0);
+        if input.just_pressed(InputAction::FocusNext) {
+            index = (index + 1) % visible.len();
+        }
+        if input.just_pressed(InputAction::FocusPrevious) {
+            index = (index + visible.len() - 1) % visible.len();
+        }
+        if input.just_pressed_on_gamepad(InputAction::Select)
+            || input.just_pressed_on_keyboard(InputAction::Confirm)
+        {
+            activated.write(ButtonActivated(visible[index].0));
+        }
+        focus.button = Some(visible[index].0);
+    } else {
+        focus.button = None;
+    }
+
+    if *outlined != focus.button {
+        if let Some(entity) = outlined.take() {
+            if let Ok(mut entity) = commands.get_entity(entity) {
+                entity.remove::<Outline>();
+            }
+        }
+        if let Some(entity) = focus.button {
+            commands.entity(entity).insert(Outline::new(
+                Val::Px(2.0),
+                Val::ZERO,
+                FOCUS_OUTLINE_COLOR,
+            ));
+        }
+        *outlined = focus.button;
+    }
+}
@@ -0,0 +1,258 @@
+//! Items, the data-defined item catalog, and unit inventories.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::class::UnitClass;
+use crate::data::{load_data_file, Layered};
+use crate::leader::AuraBonus;
+use crate::status::{StatusEffect, StatusEffects};
+use crate::unit::{AttackRange, Stats};
+
+/// How many items a single unit can carry.
+pub const INVENTORY_SLOTS: usize = 4;
+
+/// Key of an item in the [`ItemCatalog`], e.g. `"iron_sword"`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+#[serde(transparent)]
+pub struct ItemId(pub String);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum ItemKind {
+    Weapon,
+    Armor,
+    Consumable,
+    Quest,
+    #[default]
+    Misc,
+}
+
+impl ItemKind {
+    /// The equipment slot items of this kind go into, if they can be equipped.
+    pub fn equip_slot(self) -> Option<EquipSlot> {
+        match self {
+            ItemKind::Weapon => Some(EquipSlot::Weapon),
+            ItemKind::Armor => Some(EquipSlot::Armor),
+            _ => None,
+        }
+    }
+}
+
+/// Stat changes granted by an item while it is equipped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+#[serde(default)]
+pub struct StatBonus {
+    pub attack: i32,
+    pub defense: i32,
+    pub range: i32,
+}
+
+/// What a consumable does when a unit uses it.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum ConsumableEffect {
+    /// Restores up to this much HP.
+    Heal(u32),
+    /// Adds a stat bonus until the start of the user's `turns`-th next turn.
+    Buff { bonus: StatBonus, turns: u32 },
+    /// Removes poison and stun.
+    Cure,
+    /// Lights up every tile within `radius` of the user for its side until
+    /// the start of the user's `turns`-th next turn.
+    Reveal { radius: u32, turns: u32 },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ItemDef {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub kind: ItemKind,
+    #[serde(default)]
+    pub bonus: StatBonus,
+    /// Set for consumables; using the item applies this and uses it up.
+    #[serde(default)]
+    pub effect: Option<ConsumableEffect>,
+    /// Set for weapons that apply a status effect to whatever they hit.
+    #[serde(default)]
+    pub inflicts: Option<StatusEffect>,
+    /// Set for weapons that set the target's tile on fire if it is a forest.
+    #[serde(default)]
+    pub ignites: bool,
+    /// How many attacks a weapon lasts before it breaks. Unset means it
+    /// never breaks.
+    #[serde(default)]
+    pub uses: Option<u32>,
+}
+
+/// One carried copy of an item. Weapons track their remaining uses per copy.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub struct Item {
+    pub id: ItemId,
+    pub uses: Option<u32>,
+}
+
+/// Every item definition, loaded from `assets/data/items.ron`.
+#[derive(Resource, Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ItemCatalog {
+    pub items: HashMap<ItemId, ItemDef>,
+    /// Items each class starts a battle with.
+    pub loadouts: HashMap<UnitClass, Vec<ItemId>>,
+}
+
+impl ItemCatalog {
+    pub fn get(&self, id: &ItemId) -> Option<&ItemDef> {
+        self.items.get(id)
+    }
+
+    /// Display name for an item, falling back to its id for unknown items.
+    pub fn name<'a>(&'a self, id: &'a ItemId) -> &'a str {
+        self.get(id).map_or(&id.0, |item| &item.name)
+    }
+
+    /// A new copy of an item with all of its uses left.
+    pub fn instance(&self, id: ItemId) -> Item {
+        let uses = self.get(&id).and_then(|item| item.uses);
+        Item { id, uses }
+    }
+
+    /// Display name plus remaining uses, e.g. "Iron Sword (28/30)".
+    pub fn label(&self, item: &Item) -> String {
+        let name = self.name(&item.id);
+        match (item.uses, self.get(&item.id).and_then(|def| def.uses)) {
+            (Some(left), Some(total)) => format!("{name} ({left}/{total})"),
+            _ => name.to_string(),
+        }
+    }
+
+    /// The starting inventory for a class, skipping ids missing from the
+    /// catalog, with the first weapon and armor in it already equipped.
+    pub fn loadout(&self, class: UnitClass) -> (Inventory, Equipment) {
+        let mut inventory = Inventory::default();
+        let mut equipment = Equipment::default();
+        let items = self
+            .loadouts
+            .get(&class)
+            .into_iter()
+            .flatten()
+            .filter(|id| {
+                let known = self.items.contains_key(id);
+                if !known {
+                    warn!("Loadout for {class:?} references unknown item {:?}", id.0);
+                }
+                known
+            })
+            .map(|id| self.instance(id.clone()));
+        for item in items {
+            let slot = self.get(&item.id).and_then(|def| def.kind.equip_slot());
+            match slot {
+                Some(slot) if equipment.get(slot).is_none() => {
+                    *equipment.get_mut(slot) = Some(item);
+                }
+                _ if inventory.items.len() < INVENTORY_SLOTS => inventory.items.push(item),
+                _ => {}
+            }
+        }
+        (inventory, equipment)
+    }
+}
+
+impl Layered for ItemCatalog {
+    fn ids(&self) -> Vec<String> {
+        let items = self.items.keys().map(|id| id.0.clone());
+        let loadouts = self
+            .loadouts
+            .keys()
+            .map(|class| format!("{class:?} loadout"));
+        items.chain(loadouts).collect()
+    }
+
+    fn overlay(&mut self, layer: Self) {
+        self.items.extend(layer.items);
+        self.loadouts.extend(layer.loadouts);
+    }
+}
+
+pub fn load_item_catalog() -> ItemCatalog {
+    load_data_file("items.ron")
+}
+
+#[derive(Component, Clone, Debug, Default, PartialEq, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
+pub struct Inventory {
+    pub items: Vec<Item>,
+}
+
+impl Inventory {
+    /// Takes the item out of `slot`, if there is one.
+    pub fn remove(&mut self, slot: usize) -> Option<Item> {
+        (slot < self.items.len()).then(|| self.items.remove(slot))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EquipSlot {
+    Weapon,
+    Armor,
+}
+
+/// Items a unit has equipped. Equipped items leave the inventory and free
+/// up their slot until they are unequipped again.
+#[derive(Component, Clone, Debug, Default, PartialEq, Serialize, Reflect)]
+#[reflect(Component)]
+pub struct Equipment {
+    pub weapon: Option<Item>,
+    pub armor: Option<Item>,
+}
+
+impl Equipment {
+    pub fn get(&self, slot: EquipSlot) -> Option<&Item> {
+        match slot {
+            EquipSlot::Weapon => self.weapon.as_ref(),
+            EquipSlot::Armor => self.armor.as_ref(),
+        }
+    }
+
+    pub fn get_mut(&mut self, slot: EquipSlot) -> &mut Option<Item> {
+        match slot {
+            EquipSlot::Weapon => &mut self.weapon,
+            EquipSlot::Armor => &mut self.armor,
+        }
+    }
+}
+
+/// Whether a unit can attack with what it has equipped. Ranged classes need
+/// a weapon; everyone else falls back to fighting bare-handed.
+pub fn can_attack(class: UnitClass, equipment: &Equipment) -> bool {
+    !class.needs_weapon() || equipment.weapon.is_some()
+}
+
+/// A unit's base stats and attack range plus the bonuses of everything it has
+/// equipped, any stat-boosting status effects, and leader auras. Anything
+/// that depends on how strong a unit is in combat reads its stats through
+/// here.
+pub fn effective_stats(
+    stats: &Stats,
+    attack_range: AttackRange,
+    equipment: &Equipment,
+    effects: &StatusEffects,
+    aura: &AuraBonus,
+    catalog: &ItemCatalog,
+) -> (Stats, AttackRange) {
+    let equipped = [&equipment.weapon, &equipment.armor]
+        .into_iter()
+        .flatten()
+        .filter_map(|item| catalog.get(&item.id))
+        .map(|item| item.bonus);
+
+    let mut stats = *stats;
+    let mut range = attack_range.0;
+    for bonus in equipped.chain(effects.bonuses()).chain([aura.0]) {
+        stats.attack = stats.attack.saturating_add_signed(bonus.attack);
+        stats.defense = stats.defense.saturating_add_signed(bonus.defense);
+        range = range.saturating_add_signed(bonus.range);
+    }
+    (stats, AttackRange(range.max(1)))
+}
@@ -0,0 +1,164 @@
+//! Command-line options for starting straight into a battle.
+//!
+//! `--seed 42` fights the battle with a fixed seed, so a reported bug can be
+//! replayed roll for roll. `--map` picks the battlefield and `--difficulty`
+//! how well the AI plays. `--autoplay` hands the player's side to the AI
+//! too, and `--headless` runs without a window, quitting once the battle is
+//! decided; together they simulate whole battles for balancing. `--stress`
+//! crowds a large board for profiling. `--host` and `--join` start an online
+//! battle, in builds with the `net` feature, and `--mail` plays a turn of a
+//! battle [by mail](crate::mail). Any of them skips the main menu.
+//! `--validate` [checks](crate::validate) the maps and data instead of
+//! playing.
+
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::ai::AiLevel;
+use crate::grid::BattleMap;
+use crate::turn::BattleOutcome;
+
+pub const USAGE: &str = "\
+Usage: bevy-game [OPTIONS]
+
+Options:
+  --seed <SEED>  Fight the battle with this seed instead of a random one
+  --map <NAME>   Fight on this map: crossroads, lowlands, or ridgeback
+  --difficulty <LEVEL>
+                 How well the AI plays: easy, normal, or hard
+  --autoplay     Let the AI play the player's side as well
+  --headless     Run without a window and quit once the battle is decided
+  --stress <N>   Fight with N units a side on a board big enough for them
+  --host <PORT>  Wait for an opponent to join on PORT and play the player's side
+  --join <ADDR>  Join the game hosted at ADDR (host:port) as the enemy
+  --mail <FILE>  Play the next turn of the battle in FILE, or start one there,
+                 and write the turn back to it to send to the other player
+  --validate     Check the maps, data files, and scenario scripts, then quit
+  -h, --help     Print this help";
+
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LaunchOptions {
+    pub seed: Option<u64>,
+    pub map: Option<BattleMap>,
+    /// The level of every side the AI plays.
+    pub difficulty: Option<AiLevel>,
+    pub autoplay: bool,
+    pub headless: bool,
+    /// Units a side for a stress test, in place of the starting roster.
+    pub stress: Option<u32>,
+    /// The port to wait on for an opponent.
+    pub host: Option<u16>,
+    /// The opponent's address, resolved.
+    pub join: Option<SocketAddr>,
+    /// The file a battle is played by mail through.
+    pub mail: Option<PathBuf>,
+}
+
+/// What the command line asked for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Launch {
+    Run(LaunchOptions),
+    /// Check the game's content and report what's wrong with it.
+    Validate,
+    Help,
+}
+
+impl LaunchOptions {
+    /// Reads options from the arguments after the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Launch, String> {
+        let mut options = LaunchOptions::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let seed = args.next().ok_or("--seed needs a value")?;
+                    let seed = seed.parse().map_err(|_| format!("invalid seed: {seed}"))?;
+                    options.seed = Some(seed);
+                }
+                "--map" => {
+                    let name = args.next().ok_or("--map needs a name")?;
+                    let map = named(&BattleMap::ALL, &name)
+                        .ok_or_else(|| format!("unknown map: {name}"))?;
+                    options.map = Some(map);
+                }
+                "--difficulty" => {
+                    let name = args.next().ok_or("--difficulty needs a level")?;
+                    let level = named(&AiLevel::ALL, &name)
+                        .ok_or_else(|| format!("unknown difficulty: {name}"))?;
+                    options.difficulty = Some(level);
+                }
+                "--autoplay" => options.autoplay = true,
+                "--headless" => options.headless = true,
+                "--stress" => {
+                    let units = args.next().ok_or("--stress needs a value")?;
+                    let units = units
+                        .parse()
+                        .ok()
+                        .filter(|&units| units > 0)
+                        .ok_or_else(|| format!("invalid unit count: {units}"))?;
+                    options.stress = Some(units);
+                }
+                "--host" => {
+                    let port = args.next().ok_or("--host needs a port")?;
+                    let port = port.parse().map_err(|_| format!("invalid port: {port}"))?;
+                    options.host = Some(port);
+                }
+                "--join" => {
+                    let addr = args.next().ok_or("--join needs an address")?;
+                    let resolved = addr
+                        .to_socket_addrs()
+                        .ok()
+                        .and_then(|mut addrs| addrs.next())
+                        .ok_or_else(|| format!("invalid address: {addr}"))?;
+                    options.join = Some(resolved);
+                }
+                "--mail" => {
+                    let path = args.next().ok_or("--mail needs a file")?;
+                    options.mail = Some(PathBuf::from(path));
+                }
+                "--validate" => return Ok(Launch::Validate),
+                "-h" | "--help" => return Ok(Launch::Help),
+                _ => return Err(format!("unknown argument: {arg}")),
+            }
+        }
+        if options.host.is_some() && options.join.is_some() {
+            return Err("--host and --join can't be used together".to_string());
+        }
+        if options.mail.is_some() && options.online() {
+            return Err("--mail can't be used with --host or --join".to_string());
+        }
+        Ok(Launch::Run(options))
+    }
+
+    /// Whether the other side is played from another copy of the game.
+    pub fn online(&self) -> bool {
+        self.host.is_some() || self.join.is_some()
+    }
+
+    /// Whether to go straight into a battle rather than the main menu.
+    pub fn skips_menu(&self) -> bool {
+        *self != LaunchOptions::default()
+    }
+}
+
+/// The choice in `choices` whose name is `name`, ignoring case.
+fn named<T: Copy + std::fmt::Debug>(choices: &[T], name: &str) -> Option<T> {
+    choices
+        .iter()
+        .copied()
+        .find(|choice| format!("{choice:?}").eq_ignore_ascii_case(name))
+}
+
+/// Ends a headless run once the battle is decided, reporting the winner.
+pub fn exit_when_decided_system(
+    options: Res<LaunchOptions>,
+    outcome: Res<BattleOutcome>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    if let Some(winner) = outcome.0.filter(|_| options.headless) {
+        info!("{winner:?} won the battle");
+        exit.write(AppExit::Success);
+    }
+}
@@ -0,0 +1,111 @@
+//! Leader units and the stat auras they project onto nearby allies.
+
+use bevy::prelude::*;
+
+#[cfg(feature = "render")]
+use crate::constants::*;
+#[cfg(feature = "render")]
+use crate::contrast::Bordered;
+#[cfg(feature = "render")]
+use crate::grid::GridMap;
+use crate::grid::GridPosition;
+use crate::items::StatBonus;
+#[cfg(feature = "render")]
+use crate::selection::SelectedUnit;
+#[cfg(feature = "render")]
+use crate::state::AppState;
+#[cfg(feature = "render")]
+use crate::unit::Unit;
+use crate::unit::{Faction, UnitId};
+
+/// Units of the starting roster that lead their side.
+pub const STARTING_LEADERS: [UnitId; 2] = [UnitId(0), UnitId(5)];
+
+/// Grants `bonus` to every ally within `radius` tiles (but not to itself).
+#[derive(Component, Clone, Copy, Debug, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct Leader {
+    pub radius: u32,
+    pub bonus: StatBonus,
+}
+
+impl Default for Leader {
+    fn default() -> Self {
+        Self {
+            radius: 2,
+            bonus: StatBonus {
+                attack: 1,
+                defense: 1,
+                range: 0,
+            },
+        }
+    }
+}
+
+/// The aura bonus a unit currently receives from nearby leaders. Kept up to
+/// date by the command executor, which recomputes it before every command.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct AuraBonus(pub StatBonus);
+
+/// The aura a unit at `pos` receives. Auras from several leaders don't
+/// stack; each stat takes the best bonus on offer.
+pub fn aura_at(
+    unit: UnitId,
+    pos: GridPosition,
+    faction: Faction,
+    leaders: &[(UnitId, GridPosition, Faction, Leader)],
+) -> AuraBonus {
+    let mut aura = StatBonus::default();
+    for (id, leader_pos, leader_faction, leader) in leaders {
+        if *id == unit || *leader_faction != faction || leader_pos.distance(pos) > leader.radius {
+            continue;
+        }
+        aura.attack = aura.attack.max(leader.bonus.attack);
+        aura.defense = aura.defense.max(leader.bonus.defense);
+        aura.range = aura.range.max(leader.bonus.range);
+    }
+    AuraBonus(aura)
+}
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct AuraHighlight;
+
+/// Shades the area of a selected leader's aura.
+#[cfg(feature = "render")]
+pub fn highlight_aura_system(
+    mut commands: Commands,
+    selected: Res<SelectedUnit>,
+    map: Res<GridMap>,
+    leaders: Query<(&GridPosition, &Leader), With<Unit>>,
+    moved: Query<(), (With<Leader>, Changed<GridPosition>)>,
+    highlights: Query<Entity, With<AuraHighlight>>,
+) {
+    if !selected.is_changed() && moved.is_empty() {
+        return;
+    }
+    for highlight in &highlights {
+        commands.entity(highlight).despawn();
+    }
+
+    let Some((&center, leader)) = selected.0.and_then(|entity| leaders.get(entity).ok()) else {
+        return;
+    };
+    let radius = leader.radius as i32;
+    for dx in -radius..=radius {
+        for dy in -radius..=radius {
+            let pos = GridPosition::new(center.x + dx, center.y + dy);
+            if pos == center || center.distance(pos) > leader.radius || !map.in_bounds(pos) {
+                continue;
+            }
+            commands.spawn((
+                AuraHighlight,
+                Bordered,
+                Sprite::from_color(AURA_HIGHLIGHT_COLOR, Vec2::splat(TILE_SIZE)),
+                Transform::from_translation(pos.to_world(HIGHLIGHT_Z)),
+                DespawnOnExit(AppState::GamePlay),
+            ));
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! A small turn-based tactics game built on Bevy.
+//!
+//! [`GamePlugin`] adds the whole game to an app. Tools and tests that only
+//! need part of it can add the plugins in [`plugins`] on their own.
+//!
+//! Drawing, input, and menus sit behind the `render` feature, on by
+//! default. Without it the battle rules build against Bevy's ECS and states
+//! alone, for headless simulations and tests.
+
+// Bevy systems routinely take many parameters and nested query types.
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+pub mod ability;
+#[cfg(feature = "render")]
+pub mod action_menu;
+pub mod ai;
+#[cfg(feature = "render")]
+pub mod animation;
+#[cfg(feature = "render")]
+pub mod announce;
+#[cfg(feature = "render")]
+pub mod audio;
+pub mod battle_log;
+#[cfg(feature = "render")]
+pub mod bindings;
+pub mod board;
+#[cfg(feature = "render")]
+pub mod camera;
+pub mod campaign;
+pub mod chat;
+pub mod chest;
+pub mod class;
+pub mod command;
+pub mod constants;
+#[cfg(feature = "render")]
+pub mod contrast;
+#[cfg(feature = "render")]
+pub mod cues;
+pub mod data;
+#[cfg(feature = "dev-tools")]
+pub mod dev_tools;
+#[cfg(feature = "render")]
+pub mod diagnostics;
+pub mod economy;
+#[cfg(feature = "render")]
+pub mod effects;
+pub mod facing;
+pub mod fire;
+pub mod gate;
+pub mod grid;
+#[cfg(feature = "dev-tools")]
+pub mod hot_reload;
+#[cfg(feature = "render")]
+pub mod hud;
+#[cfg(feature = "render")]
+pub mod input;
+pub mod items;
+pub mod launch;
+pub mod leader;
+pub mod loading;
+pub mod lobby;
+#[cfg(feature = "render")]
+pub mod locale;
+pub mod mail;
+#[cfg(feature = "render")]
+pub mod menu;
+#[cfg(feature = "render")]
+pub mod minimap;
+pub mod mods;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod plugins;
+#[cfg(feature = "render")]
+pub mod promotion;
+pub mod replay;
+pub mod rescue;
+pub mod rules;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod seats;
+#[cfg(feature = "render")]
+pub mod selection;
+#[cfg(feature = "net")]
+pub mod server;
+#[cfg(feature = "render")]
+pub mod settings;
+pub mod snapshot;
+#[cfg(feature = "render")]
+pub mod sprites;
+pub mod state;
+pub mod status;
+pub mod stealth;
+pub mod stress;
+pub mod structure;
+pub mod suspend;
+pub mod test_utils;
+pub mod tiles;
+pub mod trap;
+pub mod turn;
+pub mod unit;
+pub mod validate;
+pub mod victory;
+pub mod vision;
+
+pub use plugins::{BattlePlugin, GamePlugin, GameSet};
@@ -0,0 +1,134 @@
+//! The loading screen the game opens on.
+//!
+//! Startup queues every asset the game draws or plays in [`Preload`], and
+//! [`AppState::Loading`] shows a progress bar until each one has loaded or
+//! failed, so nothing pops in half-drawn or loads in the middle of a battle.
+//! A sheet that fails to load is dropped with a warning and its sprites keep
+//! their flat colors. The game then goes on to the main menu, the lobby of
+//! an online match, or straight into a battle when the command line asked
+//! for one. Headless builds have nothing to load and move on at once.
+
+use bevy::prelude::*;
+
+use crate::launch::LaunchOptions;
+#[cfg(feature = "render")]
+use crate::locale::Localized;
+#[cfg(feature = "render")]
+use crate::sprites::{SpriteAtlases, TileSet};
+use crate::state::AppState;
+use crate::suspend::PendingResume;
+
+/// Assets that must be ready before the loading screen closes.
+#[cfg(feature = "render")]
+#[derive(Resource, Default)]
+pub struct Preload(pub Vec<UntypedHandle>);
+
+/// The filled part of the progress bar.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct LoadingBar;
+
+/// Where the game goes once everything has loaded. Online matches are set
+/// up in the lobby first, where there is a screen to show it, unless one is
+/// being rejoined.
+fn after_loading(launch: &LaunchOptions, resuming: bool) -> AppState {
+    if launch.online() && !resuming && cfg!(feature = "render") {
+        AppState::Lobby
+    } else if launch.skips_menu() {
+        AppState::GamePlay
+    } else {
+        AppState::MainMenu
+    }
+}
+
+#[cfg(not(feature = "render"))]
+pub fn finish_loading(
+    launch: Res<LaunchOptions>,
+    resume: Option<Res<PendingResume>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    next_state.set(after_loading(&launch, resume.is_some()));
+}
+
+#[cfg(feature = "render")]
+pub fn setup_loading_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            DespawnOnExit(AppState::Loading),
+        ))
+        .with_children(|screen| {
+            screen.spawn((
+                Localized("Loading..."),
+                Text::new("Loading..."),
+                TextFont {
+                    font_size: 22.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+            screen
+                .spawn((
+                    Node {
+                        width: Val::Px(300.0),
+                        height: Val::Px(16.0),
+                        border: UiRect::all(Val::Px(2.0)),
+                        ..default()
+                    },
+                    BorderColor::all(Color::WHITE),
+                ))
+                .with_child((
+                    LoadingBar,
+                    Node {
+                        width: Val::Percent(0.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::WHITE),
+                ));
+        });
+}
+
+/// Fills the progress bar as assets finish loading, and closes the loading
+/// screen once all of them have and the tile sheet has been cut into the
+/// board's tile set.
+#[cfg(feature = "render")]
+pub fn update_loading_system(
+    asset_server: Res<AssetServer>,
+    preload: Res<Preload>,
+    atlases: Res<SpriteAtlases>,
+    tileset: Res<TileSet>,
+    launch: Res<LaunchOptions>,
+    resume: Option<Res<PendingResume>>,
+    mut bar: Single<&mut Node, With<LoadingBar>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let finished = preload
+        .0
+        .iter()
+        .filter(|handle| {
+            let state = asset_server.load_state(handle.id());
+            state.is_loaded() || state.is_failed()
+        })
+        .count();
+    let total = preload.0.len();
+    let progress = if total == 0 {
+        1.0
+    } else {
+        finished as f32 / total as f32
+    };
+    bar.width = Val::Percent(progress * 100.0);
+
+    let stacked = atlases.tiles.is_none() || tileset.tiles.is_some();
+    if finished == total && stacked {
+        next_state.set(after_loading(&launch, resume.is_some()));
+    }
+}
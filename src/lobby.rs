@@ -0,0 +1,440 @@
+//! Setting up a match before it starts.
+//!
+//! Local matches open the lobby from the main menu, and online ones once
+//! the other player has connected. There the map and fog of war are chosen,
+//! along with who plays each side: a person at this device or the AI at
+//! some level locally, and which side the host plays online. Online, only
+//! the host changes the setup. The match starts once every side a person
+//! plays is ready, and changing anything takes them back out of ready. The
+//! choices land in [`MatchSetup`], which the battle is then set up from.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "render")]
+use crate::ai::AiLevel;
+#[cfg(feature = "render")]
+use crate::bindings::{ActionInput, InputAction};
+use crate::grid::BattleMap;
+#[cfg(feature = "render")]
+use crate::input::ButtonActivated;
+use crate::launch::LaunchOptions;
+#[cfg(feature = "render")]
+use crate::locale::{Locale, Localized};
+use crate::seats::{Controller, Seats};
+#[cfg(feature = "render")]
+use crate::settings::Settings;
+#[cfg(feature = "render")]
+use crate::state::AppState;
+use crate::unit::Faction;
+
+#[cfg(feature = "render")]
+const BUTTON_COLOR: Color = Color::srgb(0.18, 0.18, 0.22);
+#[cfg(feature = "render")]
+const BUTTON_HOVER_COLOR: Color = Color::srgb(0.28, 0.28, 0.34);
+
+/// What the next battle is fought with.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchSetup {
+    pub map: BattleMap,
+    /// The side the host plays online; the other player takes the other.
+    pub host_side: Faction,
+    /// Who plays each side in a match at this device.
+    pub sides: Seats,
+    pub fog_of_war: bool,
+}
+
+impl Default for MatchSetup {
+    fn default() -> Self {
+        Self {
+            map: BattleMap::default(),
+            host_side: Faction::Player,
+            sides: Seats {
+                player: Controller::Local,
+                enemy: Controller::Local,
+            },
+            fog_of_war: true,
+        }
+    }
+}
+
+impl MatchSetup {
+    /// The default setup, on the map the command line asked for and with
+    /// the player's usual fog of war.
+    #[cfg(feature = "render")]
+    pub fn for_launch(launch: &LaunchOptions, settings: &Settings) -> Self {
+        Self {
+            map: launch.map.unwrap_or_default(),
+            fog_of_war: settings.fog_of_war,
+            ..default()
+        }
+    }
+
+    /// Who plays each side: as chosen for a match at this device, or this
+    /// device and the other player online.
+    pub fn seats(&self, launch: &LaunchOptions) -> Seats {
+        if !launch.online() {
+            return self.sides;
+        }
+        let own = if launch.host.is_some() {
+            self.host_side
+        } else {
+            self.host_side.opponent()
+        };
+        let seat = |side| {
+            if side == own {
+                Controller::Local
+            } else {
+                Controller::Remote
+            }
+        };
+        Seats {
+            player: seat(Faction::Player),
+            enemy: seat(Faction::Enemy),
+        }
+    }
+}
+
+/// Whether each side is ready, and whether the match has begun.
+#[derive(Resource, Debug, Default)]
+pub struct Lobby {
+    player_ready: bool,
+    enemy_ready: bool,
+    /// Set on the host once it has told the other player to begin, and on
+    /// the other player once told.
+    pub begun: bool,
+}
+
+impl Lobby {
+    pub fn is_ready(&self, side: Faction) -> bool {
+        match side {
+            Faction::Player => self.player_ready,
+            Faction::Enemy => self.enemy_ready,
+        }
+    }
+
+    pub fn set_ready(&mut self, side: Faction, ready: bool) {
+        match side {
+            Faction::Player => self.player_ready = ready,
+            Faction::Enemy => self.enemy_ready = ready,
+        }
+    }
+
+    /// Whether every side is ready. Sides the AI plays are readied as they
+    /// are seated.
+    pub fn all_ready(&self) -> bool {
+        self.player_ready && self.enemy_ready
+    }
+
+    pub fn unready(&mut self) {
+        self.player_ready = false;
+        self.enemy_ready = false;
+    }
+}
+
+/// Single-player battles are fought on the default setup, with the usual
+/// seats, whatever the last match was, save for a map or difficulty given
+/// on the command line.
+#[cfg(feature = "render")]
+pub fn reset_match_setup(
+    launch: Res<LaunchOptions>,
+    settings: Res<Settings>,
+    mut setup: ResMut<MatchSetup>,
+    mut seats: ResMut<Seats>,
+) {
+    *setup = MatchSetup::for_launch(&launch, &settings);
+    *seats = Seats::for_launch(&launch);
+}
+
+/// Whether this device may change the setup: always in hotseat, and only
+/// on the host online.
+#[cfg(feature = "render")]
+fn configures(launch: &LaunchOptions) -> bool {
+    !launch.online() || launch.host.is_some()
+}
+
+/// The next choice for who plays a side at this device.
+#[cfg(feature = "render")]
+fn next_controller(controller: Controller) -> Controller {
+    match controller {
+        Controller::Local => Controller::Ai(AiLevel::ALL[0]),
+        Controller::Ai(level) => AiLevel::ALL
+            .into_iter()
+            .skip_while(|&other| other != level)
+            .nth(1)
+            .map_or(Controller::Local, Controller::Ai),
+        Controller::Remote => Controller::Local,
+    }
+}
+
+#[cfg(feature = "render")]
+fn controller_label(controller: Controller, locale: &Locale) -> String {
+    match controller {
+        Controller::Local => locale.tr("Human").to_string(),
+        Controller::Ai(level) => locale.fill("AI ({})", &[&locale.tr(&format!("{level:?}"))]),
+        Controller::Remote => locale.tr("Online").to_string(),
+    }
+}
+
+#[cfg(feature = "render")]
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum LobbyButton {
+    Map,
+    Side,
+    /// Who plays this side at this device.
+    Seat(Faction),
+    Fog,
+    Ready(Faction),
+    Back,
+}
+
+#[cfg(feature = "render")]
+impl LobbyButton {
+    fn label(self, setup: &MatchSetup, lobby: &Lobby, seats: &Seats, locale: &Locale) -> String {
+        let name = |value: &dyn std::fmt::Debug| locale.tr(&format!("{value:?}")).to_string();
+        match self {
+            LobbyButton::Map => format!("[M] {}: {}", locale.tr("Map"), name(&setup.map)),
+            LobbyButton::Side => format!(
+                "[S] {}: {}",
+                locale.tr("Host plays"),
+                name(&setup.host_side)
+            ),
+            LobbyButton::Seat(side) => format!(
+                "[{}] {}: {}",
+                match side {
+                    Faction::Player => 'P',
+                    Faction::Enemy => 'E',
+                },
+                name(&side),
+                controller_label(setup.sides.controller(side), locale)
+            ),
+            LobbyButton::Fog => format!(
+                "[F] {}: {}",
+                locale.tr("Fog of war"),
+                locale.tr(if setup.fog_of_war { "On" } else { "Off" })
+            ),
+            LobbyButton::Ready(side) => format!(
+                "[{}] {}: {}",
+                match side {
+                    Faction::Player => 1,
+                    Faction::Enemy => 2,
+                },
+                name(&side),
+                locale.tr(if let Controller::Ai(_) = seats.controller(side) {
+                    "AI"
+                } else if lobby.is_ready(side) {
+                    "Ready"
+                } else {
+                    "Not ready"
+                })
+            ),
+            LobbyButton::Back => format!("[Esc] {}", locale.tr("Back")),
+        }
+    }
+
+    fn hotkey(self) -> KeyCode {
+        match self {
+            LobbyButton::Map => KeyCode::KeyM,
+            LobbyButton::Side => KeyCode::KeyS,
+            LobbyButton::Seat(Faction::Player) => KeyCode::KeyP,
+            LobbyButton::Seat(Faction::Enemy) => KeyCode::KeyE,
+            LobbyButton::Fog => KeyCode::KeyF,
+            LobbyButton::Ready(Faction::Player) => KeyCode::Digit1,
+            LobbyButton::Ready(Faction::Enemy) => KeyCode::Digit2,
+            LobbyButton::Back => KeyCode::Escape,
+        }
+    }
+}
+
+/// The text on a lobby button.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct LobbyLabel(LobbyButton);
+
+#[cfg(feature = "render")]
+pub fn setup_lobby(
+    mut commands: Commands,
+    launch: Res<LaunchOptions>,
+    settings: Res<Settings>,
+    seats: Res<Seats>,
+    locale: Res<Locale>,
+    mut setup: ResMut<MatchSetup>,
+    mut lobby: ResMut<Lobby>,
+) {
+    *lobby = Lobby::default();
+    if configures(&launch) {
+        setup.fog_of_war = settings.fog_of_war;
+    }
+
+    let mut buttons = vec![LobbyButton::Map];
+    if launch.online() {
+        buttons.push(LobbyButton::Side);
+    } else {
+        buttons.extend([
+            LobbyButton::Seat(Faction::Player),
+            LobbyButton::Seat(Faction::Enemy),
+        ]);
+    }
+    buttons.extend([
+        LobbyButton::Fog,
+        LobbyButton::Ready(Faction::Player),
+        LobbyButton::Ready(Faction::Enemy),
+    ]);
+    if !launch.online() {
+        buttons.push(LobbyButton::Back);
+    }
+    let title = if launch.online() {
+        "Online match"
+    } else {
+        "Local match"
+    };
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+            DespawnOnExit(AppState::Lobby),
+        ))
+        .with_children(|menu| {
+            menu.spawn((
+                Localized(title),
+                Text::new(title),
+                TextFont {
+                    font_size: 28.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+            for button in buttons {
+                menu.spawn((
+                    button,
+                    Button,
+                    Node {
+                        width: Val::Px(280.0),
+                        padding: UiRect::all(Val::Px(10.0)),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    BackgroundColor(BUTTON_COLOR),
+                ))
+                .with_child((
+                    LobbyLabel(button),
+                    Text::new(button.label(&setup, &lobby, &seats, &locale)),
+                    TextFont {
+                        font_size: 22.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                ));
+            }
+        });
+}
+
+/// Changes the setup and readiness from the lobby's buttons and hotkeys.
+/// Each device only readies the sides seated at it.
+#[cfg(feature = "render")]
+pub fn lobby_input_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    input: ActionInput,
+    launch: Res<LaunchOptions>,
+    seats: Res<Seats>,
+    mut activated: MessageReader<ButtonActivated>,
+    mut buttons: Query<(Entity, &LobbyButton, &Interaction, &mut BackgroundColor)>,
+    mut setup: ResMut<MatchSetup>,
+    mut lobby: ResMut<Lobby>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let activated: Vec<Entity> = activated
+        .read()
+        .map(|ButtonActivated(entity)| *entity)
+        .collect();
+    let mut chosen = None;
+    for (entity, &button, interaction, mut background) in &mut buttons {
+        background.0 = match interaction {
+            Interaction::Hovered => BUTTON_HOVER_COLOR,
+            _ => BUTTON_COLOR,
+        };
+        if *interaction == Interaction::Pressed
+            || activated.contains(&entity)
+            || keys.just_pressed(button.hotkey())
+        {
+            chosen = Some(button);
+        }
+    }
+    if chosen.is_none() && !launch.online() && input.just_pressed(InputAction::Cancel) {
+        chosen = Some(LobbyButton::Back);
+    }
+
+    let configures = configures(&launch);
+    let before = *setup;
+    match chosen {
+        Some(LobbyButton::Map) if configures => setup.map = setup.map.next(),
+        Some(LobbyButton::Side) if configures => setup.host_side = setup.host_side.opponent(),
+        Some(LobbyButton::Seat(Faction::Player)) if configures => {
+            setup.sides.player = next_controller(setup.sides.player);
+        }
+        Some(LobbyButton::Seat(Faction::Enemy)) if configures => {
+            setup.sides.enemy = next_controller(setup.sides.enemy);
+        }
+        Some(LobbyButton::Fog) if configures => setup.fog_of_war = !setup.fog_of_war,
+        Some(LobbyButton::Ready(side)) if seats.is_local(side) => {
+            let ready = !lobby.is_ready(side);
+            lobby.set_ready(side, ready);
+        }
+        Some(LobbyButton::Back) => next_state.set(AppState::MainMenu),
+        _ => {}
+    }
+    if *setup != before {
+        lobby.unready();
+    }
+}
+
+/// Seats the sides as the setup says, readying those the AI plays.
+#[cfg(feature = "render")]
+pub fn seat_lobby_system(
+    launch: Res<LaunchOptions>,
+    setup: Res<MatchSetup>,
+    mut seats: ResMut<Seats>,
+    mut lobby: ResMut<Lobby>,
+) {
+    seats.set_if_neq(setup.seats(&launch));
+    for side in [Faction::Player, Faction::Enemy] {
+        if matches!(seats.controller(side), Controller::Ai(_)) && !lobby.is_ready(side) {
+            lobby.set_ready(side, true);
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn update_lobby_labels_system(
+    setup: Res<MatchSetup>,
+    lobby: Res<Lobby>,
+    seats: Res<Seats>,
+    locale: Res<Locale>,
+    mut labels: Query<(&LobbyLabel, &mut Text)>,
+) {
+    for (label, mut text) in &mut labels {
+        text.0 = label.0.label(&setup, &lobby, &seats, &locale);
+    }
+}
+
+/// Starts the battle once every side is ready, or online once the host has
+/// said to begin.
+#[cfg(feature = "render")]
+pub fn start_match_system(
+    launch: Res<LaunchOptions>,
+    lobby: Res<Lobby>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if lobby.begun || (!launch.online() && lobby.all_ready()) {
+        next_state.set(AppState::GamePlay);
+    }
+}
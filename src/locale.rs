@@ -0,0 +1,130 @@
+//! Translations of the game's menus, panels, and HUD, switched from the
+//! main menu while the game runs.
+//!
+//! The English text is its own key: UI code looks every fixed piece of text
+//! up with [`Locale::tr`], and fills in what changes with [`Locale::fill`],
+//! so anything a language leaves out is shown in English. Languages are
+//! defined in `assets/data/languages.ron`, by the name they call themselves,
+//! and mods can add languages or lines to them.
+//!
+//! Labels written once, like panel headings, carry a [`Localized`] and are
+//! rewritten when the language changes. Labels built from the board (the
+//! menus, the turn banner, the action menu) are rebuilt instead.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Write};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::data::{load_data_file, Layered};
+use crate::settings::Settings;
+
+/// The language the game is written in, which needs no translation.
+pub const ENGLISH: &str = "English";
+
+/// Every language's lines, by the English they translate.
+#[derive(Resource, Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Translations {
+    pub languages: BTreeMap<String, HashMap<String, String>>,
+}
+
+impl Translations {
+    /// English, then every defined language in order of its name.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(ENGLISH).chain(self.languages.keys().map(String::as_str))
+    }
+}
+
+impl Layered for Translations {
+    fn ids(&self) -> Vec<String> {
+        self.languages.keys().cloned().collect()
+    }
+
+    fn overlay(&mut self, layer: Self) {
+        for (language, lines) in layer.languages {
+            self.languages.entry(language).or_default().extend(lines);
+        }
+    }
+}
+
+pub fn load_translations() -> Translations {
+    load_data_file("languages.ron")
+}
+
+/// The language UI text is shown in, with its lines.
+#[derive(Resource, Clone, Debug, PartialEq, Eq)]
+pub struct Locale {
+    pub language: String,
+    lines: HashMap<String, String>,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            language: ENGLISH.to_string(),
+            lines: HashMap::new(),
+        }
+    }
+}
+
+impl Locale {
+    /// `language` from `translations`, or English if it isn't defined.
+    pub fn new(translations: &Translations, language: &str) -> Self {
+        match translations.languages.get(language) {
+            Some(lines) => Self {
+                language: language.to_string(),
+                lines: lines.clone(),
+            },
+            None => Self::default(),
+        }
+    }
+
+    /// `text` in this language, or as it is when there is no translation.
+    pub fn tr<'a>(&'a self, text: &'a str) -> &'a str {
+        self.lines.get(text).map_or(text, String::as_str)
+    }
+
+    /// `template` in this language, with each `{}` replaced by the next of
+    /// `args`.
+    pub fn fill(&self, template: &str, args: &[&dyn Display]) -> String {
+        let mut args = args.iter();
+        let mut text = String::new();
+        for (index, piece) in self.tr(template).split("{}").enumerate() {
+            if let Some(arg) = (index > 0).then(|| args.next()).flatten() {
+                let _ = write!(text, "{arg}");
+            }
+            text += piece;
+        }
+        text
+    }
+}
+
+/// A text entity showing a fixed piece of text, kept in the player's
+/// language.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Localized(pub &'static str);
+
+/// Switches the [`Locale`] when the language setting or the translations
+/// change.
+pub fn update_locale_system(
+    settings: Res<Settings>,
+    translations: Res<Translations>,
+    mut locale: ResMut<Locale>,
+) {
+    if !settings.is_changed() && !translations.is_changed() {
+        return;
+    }
+    locale.set_if_neq(Locale::new(&translations, &settings.language));
+}
+
+/// Writes new [`Localized`] labels, and rewrites them all when the
+/// language changes.
+pub fn localize_labels_system(locale: Res<Locale>, mut labels: Query<(Ref<Localized>, &mut Text)>) {
+    for (label, mut text) in &mut labels {
+        if locale.is_changed() || label.is_added() {
+            text.0 = locale.tr(label.0).to_string();
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Play by mail: a battle fought one turn at a time over email, or any
+//! other way of passing a file around.
+//!
+//! `--mail battle.ron` opens the battle in the file for whoever's turn it
+//! is, or starts a new one when there is no file yet. The other side waits
+//! for the next file. Once the turn ends, or the battle is decided, the file
+//! is written back with the turn in it, ready to send to the other player.
+//!
+//! The file is a [`MailTurn`]: the battle's seed and map, which rebuild the
+//! board it started on, and every command since, as in a replay. Opening it
+//! re-executes the commands, so both players always arrive at the same
+//! board without a server or any state of their own.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::command::{CommandLog, GameCommand};
+#[cfg(feature = "render")]
+use crate::locale::Locale;
+use crate::seats::{Controller, Seats};
+#[cfg(feature = "render")]
+use crate::state::AppState;
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::Faction;
+
+/// The contents of a mail file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MailTurn {
+    pub log: CommandLog,
+}
+
+pub fn read_mail_turn(path: &Path) -> Result<MailTurn, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    ron::from_str(&contents).map_err(|err| err.to_string())
+}
+
+/// Writes the turn without any pretty-printing, to keep the file small.
+pub fn write_mail_turn(turn: &MailTurn, path: &Path) -> Result<(), String> {
+    let contents = ron::to_string(turn).map_err(|err| err.to_string())?;
+    fs::write(path, contents).map_err(|err| err.to_string())
+}
+
+/// The battle being played by mail, and the side taking its turn here.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct MailGame {
+    pub path: PathBuf,
+    pub side: Faction,
+    /// How many commands the battle had when it was opened; set to the full
+    /// log once the turn has been written.
+    pub received: usize,
+    pub sent: bool,
+}
+
+impl MailGame {
+    /// Opens the battle in the file at `path`, along with its log to resume,
+    /// or a new battle for the player's side when there is no file yet.
+    pub fn open(path: &Path) -> Result<(Self, Option<CommandLog>), String> {
+        let turn = match read_mail_turn(path) {
+            Ok(turn) => Some(turn),
+            Err(_) if !path.exists() => None,
+            Err(err) => return Err(err),
+        };
+        let side = turn
+            .as_ref()
+            .map_or(Faction::Player, |turn| turn.log.side_to_move());
+        let game = Self {
+            path: path.to_path_buf(),
+            side,
+            received: turn.as_ref().map_or(0, |turn| turn.log.commands.len()),
+            sent: false,
+        };
+        Ok((game, turn.map(|turn| turn.log)))
+    }
+
+    /// This side is played here; the other is played from the file.
+    pub fn seats(&self) -> Seats {
+        let seat = |side| {
+            if side == self.side {
+                Controller::Local
+            } else {
+                Controller::Remote
+            }
+        };
+        Seats {
+            player: seat(Faction::Player),
+            enemy: seat(Faction::Enemy),
+        }
+    }
+}
+
+/// Writes the battle back to the mail file once this side's turn has ended
+/// or the battle has been decided.
+pub fn send_mail_turn_system(
+    log: Res<CommandLog>,
+    turn: Res<CurrentTurn>,
+    outcome: Res<BattleOutcome>,
+    mut mail: ResMut<MailGame>,
+) {
+    // Commands still being re-executed from the file don't count.
+    if mail.sent || log.commands.len() <= mail.received {
+        return;
+    }
+    let ended = log.commands.last() == Some(&GameCommand::EndTurn) && turn.faction != mail.side;
+    if !ended && outcome.0.is_none() {
+        return;
+    }
+
+    let file = MailTurn { log: log.clone() };
+    match write_mail_turn(&file, &mail.path) {
+        Ok(()) => info!(
+            "Wrote the {:?} turn to {}; send it to the other player",
+            mail.side,
+            mail.path.display()
+        ),
+        Err(err) => error!("Failed to write {}: {err}", mail.path.display()),
+    }
+    mail.received = log.commands.len();
+    mail.sent = true;
+}
+
+/// The line in the corner saying whose turn is played here, and where it
+/// goes once done.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct MailText;
+
+#[cfg(feature = "render")]
+pub fn setup_mail_ui(mut commands: Commands) {
+    commands.spawn((
+        MailText,
+        Text::new(""),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            right: Val::Px(10.0),
+            ..default()
+        },
+        DespawnOnExit(AppState::GamePlay),
+    ));
+}
+
+#[cfg(feature = "render")]
+pub fn update_mail_ui_system(
+    mail: Res<MailGame>,
+    locale: Res<Locale>,
+    mut text: Single<&mut Text, With<MailText>>,
+) {
+    text.0 = if mail.sent {
+        locale.fill(
+            "Turn written to {}: send it to the other player",
+            &[&mail.path.display()],
+        )
+    } else {
+        locale.fill(
+            "Playing {} by mail; the turn is written to {} when it ends",
+            &[
+                &locale.tr(&format!("{:?}", mail.side)),
+                &mail.path.display(),
+            ],
+        )
+    };
+}
+
+/// The file only plays one turn; back at the menu, battles are played here
+/// again.
+pub fn end_mail_game(mut commands: Commands) {
+    commands.remove_resource::<MailGame>();
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::grid::GridPosition;
+    use crate::unit::UnitId;
+
+    /// A mail file of its own for each test, in the system temp dir.
+    fn mail_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bevy-game-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{name}.ron"));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn a_missing_file_starts_a_battle_for_the_player_side() {
+        let path = mail_file("missing");
+
+        let (game, log) = MailGame::open(&path).unwrap();
+
+        assert_eq!(game.side, Faction::Player);
+        assert_eq!(game.received, 0);
+        assert_eq!(log, None);
+    }
+
+    #[test]
+    fn an_existing_file_seats_the_side_to_move() {
+        let path = mail_file("existing");
+        let log = CommandLog {
+            seed: 5,
+            commands: vec![GameCommand::EndTurn],
+            ..default()
+        };
+        write_mail_turn(&MailTurn { log: log.clone() }, &path).unwrap();
+
+        let (game, resumed) = MailGame::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(game.side, Faction::Enemy);
+        assert_eq!(game.received, 1);
+        assert_eq!(game.seats().enemy, Controller::Local);
+        assert_eq!(resumed, Some(log));
+    }
+
+    #[test]
+    fn the_file_is_written_once_this_sides_turn_ends() {
+        let path = mail_file("send");
+        let mut world = World::new();
+        world.init_resource::<CommandLog>();
+        world.init_resource::<CurrentTurn>();
+        world.init_resource::<BattleOutcome>();
+        world.insert_resource(MailGame {
+            path: path.clone(),
+            side: Faction::Player,
+            received: 0,
+            sent: false,
+        });
+        let send = |world: &mut World| world.run_system_once(send_mail_turn_system).unwrap();
+
+        world
+            .resource_mut::<CommandLog>()
+            .commands
+            .push(GameCommand::Move {
+                unit: UnitId(0),
+                to: GridPosition::new(1, 1),
+            });
+        send(&mut world);
+        assert!(!path.exists());
+
+        world
+            .resource_mut::<CommandLog>()
+            .commands
+            .push(GameCommand::EndTurn);
+        world.resource_mut::<CurrentTurn>().faction = Faction::Enemy;
+        send(&mut world);
+
+        let written = read_mail_turn(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&written.log, world.resource::<CommandLog>());
+        assert!(world.resource::<MailGame>().sent);
+    }
+}
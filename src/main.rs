@@ -1,14 +1,125 @@
-//! A minimal example that outputs "hello world"
+//! A small turn-based tactics game built on Bevy.
 
+use std::env;
+use std::process;
+use std::time::Duration;
+
+use bevy::app::ScheduleRunnerPlugin;
 use bevy::prelude::*;
+use bevy::render::settings::WgpuSettings;
+use bevy::render::RenderPlugin;
+use bevy::window::ExitCondition;
+use bevy::winit::WinitPlugin;
+
+use bevy_game::ai::AiPacing;
+use bevy_game::launch::{Launch, LaunchOptions, USAGE};
+use bevy_game::lobby::MatchSetup;
+use bevy_game::mail::MailGame;
+use bevy_game::mods::register_mod_assets;
+use bevy_game::seats::Seats;
+use bevy_game::settings::Settings;
+use bevy_game::stress::stress_map;
+use bevy_game::suspend::PendingResume;
+use bevy_game::validate::validate;
+use bevy_game::GamePlugin;
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_systems(Update, hello_world_system)
-        .run();
-}
+    #[cfg_attr(not(feature = "net"), allow(unused_mut))]
+    let mut options = match LaunchOptions::parse(env::args().skip(1)) {
+        Ok(Launch::Run(options)) => options,
+        Ok(Launch::Validate) => {
+            let diagnostics = validate();
+            for diagnostic in &diagnostics {
+                println!("{diagnostic}");
+            }
+            let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+            println!("{errors} errors, {} warnings", diagnostics.len() - errors);
+            process::exit(if errors > 0 { 1 } else { 0 });
+        }
+        Ok(Launch::Help) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    #[cfg(feature = "net")]
+    let connection = match bevy_game::net::connect(&mut options) {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("Couldn't connect to the other player: {err}");
+            process::exit(1);
+        }
+    };
+    #[cfg(not(feature = "net"))]
+    if options.host.is_some() || options.join.is_some() {
+        eprintln!("Online play needs a build with the `net` feature");
+        process::exit(2);
+    }
+
+    let mail = match options.mail.as_deref().map(MailGame::open).transpose() {
+        Ok(mail) => mail,
+        Err(err) => {
+            eprintln!("Couldn't read the battle to play by mail: {err}");
+            process::exit(1);
+        }
+    };
 
-fn hello_world_system() {
-    println!("hello world");
+    let mut app = App::new();
+    register_mod_assets(&mut app);
+    if options.headless {
+        // No window, no GPU, and no waiting between the AI's actions.
+        app.add_plugins((
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }
+                    .into(),
+                    ..default()
+                })
+                .disable::<WinitPlugin>(),
+            ScheduleRunnerPlugin::run_loop(Duration::ZERO),
+            GamePlugin,
+        ))
+        .insert_resource(AiPacing::INSTANT);
+    } else {
+        app.add_plugins((DefaultPlugins, GamePlugin));
+    }
+    let setup = MatchSetup::for_launch(&options, app.world().resource::<Settings>());
+    app.insert_resource(Seats::for_launch(&options))
+        .insert_resource(setup);
+    #[cfg(feature = "net")]
+    if let Some(connection) = connection {
+        app.insert_resource(connection.link);
+        if let Some(listener) = connection.listener {
+            app.insert_resource(listener);
+        }
+        // Rejoining goes straight back into the battle, caught up on it.
+        if let Some((setup, log)) = connection.resync {
+            app.insert_resource(setup)
+                .insert_resource(setup.seats(&options))
+                .insert_resource(PendingResume(log));
+        }
+    }
+    if let Some(units) = options.stress {
+        app.insert_resource(stress_map(units));
+    }
+    if let Some((mail, log)) = mail {
+        app.insert_resource(mail.seats());
+        if let Some(log) = log {
+            app.insert_resource(PendingResume(log));
+        }
+        app.insert_resource(mail);
+    }
+    app.insert_resource(options).run();
 }
@@ -0,0 +1,655 @@
+//! The main menu, and the controls and mods panels opened from it.
+
+use bevy::prelude::*;
+
+use crate::ability::AbilityCatalog;
+use crate::bindings::{ActionInput, Binding, InputAction, InputMap};
+use crate::chest::ChestLayout;
+use crate::data::{read_layers, EntrySources, Layered, Source};
+use crate::input::ButtonActivated;
+use crate::items::ItemCatalog;
+use crate::lobby::MatchSetup;
+use crate::locale::{Locale, Translations};
+use crate::mods::{installed_mods, is_layered, mod_files, mod_providing, mods_dir, InstalledMod};
+use crate::seats::Seats;
+use crate::settings::Settings;
+use crate::state::AppState;
+use crate::suspend::{has_suspend_save, take_suspend_save, PendingResume};
+use crate::tiles::TileRegistry;
+
+const BUTTON_COLOR: Color = Color::srgb(0.18, 0.18, 0.22);
+const BUTTON_HOVER_COLOR: Color = Color::srgb(0.28, 0.28, 0.34);
+
+/// Heading of the mods panel when there are mods to list.
+const MODS_HEADING: &str =
+    "Mods, highest priority first. Set `enabled` in a mod's mod.ron to switch it.";
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum MenuButton {
+    Continue,
+    NewBattle,
+    LocalMatch,
+    Fog,
+    Vision,
+    Palette,
+    HighContrast,
+    TextSize,
+    EdgeScroll,
+    CameraFollow,
+    Pace,
+    Announcements,
+    SoundIndicators,
+    Language,
+    Controls,
+    Mods,
+    Quit,
+}
+
+impl MenuButton {
+    fn label(self, settings: &Settings, locale: &Locale) -> String {
+        let on_off = |on: bool| locale.tr(if on { "On" } else { "Off" });
+        let percent = |scale: f32| format!("{:.0}%", scale * 100.0);
+        let (text, value) = match self {
+            MenuButton::Continue => ("Continue", None),
+            MenuButton::NewBattle => ("New Battle", None),
+            MenuButton::LocalMatch => ("Local Match", None),
+            MenuButton::Fog => ("Fog of war", Some(on_off(settings.fog_of_war).to_string())),
+            MenuButton::Vision => ("Vision range", Some(percent(settings.vision_scale))),
+            MenuButton::Palette => (
+                "Colors",
+                Some(locale.tr(&format!("{:?}", settings.palette)).to_string()),
+            ),
+            MenuButton::HighContrast => (
+                "High contrast",
+                Some(on_off(settings.high_contrast).to_string()),
+            ),
+            MenuButton::TextSize => ("Text size", Some(percent(settings.text_scale))),
+            MenuButton::EdgeScroll => (
+                "Edge scrolling",
+                Some(on_off(settings.edge_scroll).to_string()),
+            ),
+            MenuButton::CameraFollow => (
+                "Follow enemy moves",
+                Some(on_off(settings.camera_follow).to_string()),
+            ),
+            MenuButton::Pace => ("Pace", Some(locale.tr(settings.pacing.name()).to_string())),
+            MenuButton::Announcements => (
+                "Announcements",
+                Some(
+                    locale
+                        .tr(&format!("{:?}", settings.announcements))
+                        .to_string(),
+                ),
+            ),
+            MenuButton::SoundIndicators => (
+                "Sound indicators",
+                Some(on_off(settings.sound_indicators).to_string()),
+            ),
+            MenuButton::Language => ("Language", Some(locale.language.clone())),
+            MenuButton::Controls => ("Controls", None),
+            MenuButton::Mods => ("Mods", None),
+            MenuButton::Quit => ("Quit", None),
+        };
+        let key = format!("{:?}", self.hotkey());
+        let key = key.strip_prefix("Key").unwrap_or(&key);
+        match value {
+            Some(value) => format!("[{key}] {}: {value}", locale.tr(text)),
+            None => format!("[{key}] {}", locale.tr(text)),
+        }
+    }
+
+    fn hotkey(self) -> KeyCode {
+        match self {
+            MenuButton::Continue => KeyCode::KeyC,
+            MenuButton::NewBattle => KeyCode::KeyN,
+            MenuButton::LocalMatch => KeyCode::KeyH,
+            MenuButton::Fog => KeyCode::KeyF,
+            MenuButton::Vision => KeyCode::KeyV,
+            MenuButton::Palette => KeyCode::KeyP,
+            MenuButton::HighContrast => KeyCode::KeyG,
+            MenuButton::TextSize => KeyCode::KeyT,
+            MenuButton::EdgeScroll => KeyCode::KeyE,
+            MenuButton::CameraFollow => KeyCode::KeyM,
+            MenuButton::Pace => KeyCode::KeyS,
+            MenuButton::Announcements => KeyCode::KeyA,
+            MenuButton::SoundIndicators => KeyCode::KeyI,
+            MenuButton::Language => KeyCode::KeyL,
+            MenuButton::Controls => KeyCode::KeyK,
+            MenuButton::Mods => KeyCode::KeyO,
+            MenuButton::Quit => KeyCode::KeyQ,
+        }
+    }
+}
+
+/// The text on a main menu button, rewritten when the settings it shows
+/// change.
+#[derive(Component)]
+pub struct MenuLabel(MenuButton);
+
+/// The main menu's column of buttons, hidden while a panel is open.
+#[derive(Component)]
+pub struct MainMenuRoot;
+
+/// A panel opened from the main menu, over it.
+#[derive(Component)]
+pub struct MenuPanel;
+
+/// The controls panel: one row per action, listing what it is bound to.
+#[derive(Component)]
+pub struct ControlsPanel;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum ControlsButton {
+    /// Waits for the next key or button and binds it to this action.
+    Rebind(InputAction),
+    Reset,
+    Back,
+}
+
+/// The text on a controls panel row.
+#[derive(Component)]
+pub struct ControlsLabel(InputAction);
+
+fn controls_label(action: InputAction, map: &InputMap, locale: &Locale, waiting: bool) -> String {
+    let name = locale.tr(action.label());
+    if waiting {
+        return format!("{name}: {}", locale.tr("press a key or button..."));
+    }
+    let bindings: Vec<String> = map
+        .bindings(action)
+        .into_iter()
+        .map(Binding::label)
+        .collect();
+    format!("{name}: {}", bindings.join(", "))
+}
+
+fn spawn_controls_panel(commands: &mut Commands, map: &InputMap, locale: &Locale) {
+    let button_node = Node {
+        padding: UiRect::axes(Val::Px(10.0), Val::Px(4.0)),
+        ..default()
+    };
+    let font = TextFont {
+        font_size: 16.0,
+        ..default()
+    };
+    commands
+        .spawn((
+            ControlsPanel,
+            MenuPanel,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            DespawnOnExit(AppState::MainMenu),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new(locale.fill(
+                    "Click an action, or reach it with {}/{} and press {}, then press its new \
+                     key or button. Escape cancels.",
+                    &[
+                        &map.hint(InputAction::FocusPrevious),
+                        &map.hint(InputAction::FocusNext),
+                        &map.hint(InputAction::Confirm),
+                    ],
+                )),
+                font.clone(),
+                TextColor(Color::WHITE),
+            ));
+            panel
+                .spawn(Node {
+                    display: Display::Grid,
+                    grid_template_columns: RepeatedGridTrack::px(2, 360.0),
+                    row_gap: Val::Px(4.0),
+                    column_gap: Val::Px(8.0),
+                    ..default()
+                })
+                .with_children(|grid| {
+                    for action in InputAction::ALL {
+                        grid.spawn((
+                            ControlsButton::Rebind(action),
+                            Button,
+                            button_node.clone(),
+                            BackgroundColor(BUTTON_COLOR),
+                        ))
+                        .with_child((
+                            ControlsLabel(action),
+                            Text::new(controls_label(action, map, locale, false)),
+                            font.clone(),
+                            TextColor(Color::WHITE),
+                        ));
+                    }
+                });
+            panel
+                .spawn(Node {
+                    column_gap: Val::Px(12.0),
+                    ..default()
+                })
+                .with_children(|row| {
+                    let back = format!("[{}] {}", map.hint(InputAction::Cancel), locale.tr("Back"));
+                    for (button, label) in [
+                        (
+                            ControlsButton::Reset,
+                            locale.tr("Reset to defaults").to_string(),
+                        ),
+                        (ControlsButton::Back, back),
+                    ] {
+                        row.spawn((
+                            button,
+                            Button,
+                            button_node.clone(),
+                            BackgroundColor(BUTTON_COLOR),
+                        ))
+                        .with_child((
+                            Text::new(label),
+                            font.clone(),
+                            TextColor(Color::WHITE),
+                        ));
+                    }
+                });
+        });
+}
+
+/// Where each entry of the data file `name` comes from, or nothing when it
+/// can't be read.
+fn entry_sources<T: Layered>(name: &'static str) -> (&'static str, EntrySources) {
+    let sources = read_layers::<T>(name)
+        .map(|(_, sources)| sources)
+        .unwrap_or_default();
+    (name, sources)
+}
+
+/// What `installed` changes: each data entry and file it brings, noting the
+/// ones a higher priority mod's copy takes the place of.
+fn mod_changes(installed: &InstalledMod, data: &[(&str, EntrySources)]) -> Vec<String> {
+    let ours = |source: &Source| matches!(source, Source::Mod(other) if other.id == installed.id);
+    let mut changes = Vec::new();
+    for (name, sources) in data {
+        let entries: Vec<String> = sources
+            .iter()
+            .filter(|(_, defined)| defined.iter().any(ours))
+            .map(|(id, defined)| match defined.last() {
+                Some(winner) if !ours(winner) => format!("{id} (overridden by {winner})"),
+                _ => id.clone(),
+            })
+            .collect();
+        if !entries.is_empty() {
+            changes.push(format!("{name}: {}", entries.join(", ")));
+        }
+    }
+    for file in mod_files(installed) {
+        if is_layered(&file) {
+            continue;
+        }
+        changes.push(match mod_providing(&file) {
+            Some(winner) if winner.id != installed.id => format!(
+                "{} (overridden by {})",
+                file.display(),
+                winner.manifest.name
+            ),
+            _ => file.display().to_string(),
+        });
+    }
+    changes
+}
+
+/// The mods panel: every mod found at startup, highest priority first,
+/// with what each enabled one changes.
+#[derive(Component)]
+pub struct ModsPanel;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub struct ModsBack;
+
+fn spawn_mods_panel(commands: &mut Commands, map: &InputMap, locale: &Locale) {
+    let font = TextFont {
+        font_size: 16.0,
+        ..default()
+    };
+    let mods = installed_mods();
+    let data = [
+        entry_sources::<ItemCatalog>("items.ron"),
+        entry_sources::<ChestLayout>("chests.ron"),
+        entry_sources::<TileRegistry>("tiles.ron"),
+        entry_sources::<AbilityCatalog>("abilities.ron"),
+        entry_sources::<Translations>("languages.ron"),
+    ];
+    commands
+        .spawn((
+            ModsPanel,
+            MenuPanel,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+            DespawnOnExit(AppState::MainMenu),
+        ))
+        .with_children(|panel| {
+            let heading = if mods.is_empty() {
+                locale.fill("No mods installed. Mods go in {}", &[&mods_dir().display()])
+            } else {
+                locale.tr(MODS_HEADING).to_string()
+            };
+            panel.spawn((Text::new(heading), font.clone(), TextColor(Color::WHITE)));
+            for installed in mods {
+                let manifest = &installed.manifest;
+                let version = if manifest.version.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {}", manifest.version)
+                };
+                let mut line = locale.fill(
+                    "{}{} (priority {})",
+                    &[&manifest.name, &version, &manifest.priority],
+                );
+                if !manifest.enabled {
+                    line.push_str(locale.tr(", disabled"));
+                }
+                if !manifest.description.is_empty() {
+                    line = format!("{line}: {}", manifest.description);
+                }
+                let color = if manifest.enabled {
+                    Color::WHITE
+                } else {
+                    Color::srgb(0.5, 0.5, 0.5)
+                };
+                panel.spawn((Text::new(line), font.clone(), TextColor(color)));
+                if !manifest.enabled {
+                    continue;
+                }
+                let changes = mod_changes(installed, &data);
+                let changes = if changes.is_empty() {
+                    locale.tr("Changes nothing").to_string()
+                } else {
+                    locale.fill("Changes {}", &[&changes.join("; ")])
+                };
+                panel.spawn((
+                    Text::new(changes),
+                    TextFont {
+                        font_size: 13.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.7, 0.7, 0.7)),
+                    Node {
+                        max_width: Val::Percent(80.0),
+                        ..default()
+                    },
+                ));
+            }
+            panel
+                .spawn((
+                    ModsBack,
+                    Button,
+                    Node {
+                        padding: UiRect::axes(Val::Px(10.0), Val::Px(4.0)),
+                        ..default()
+                    },
+                    BackgroundColor(BUTTON_COLOR),
+                ))
+                .with_child((
+                    Text::new(format!(
+                        "[{}] {}",
+                        map.hint(InputAction::Cancel),
+                        locale.tr("Back")
+                    )),
+                    font.clone(),
+                    TextColor(Color::WHITE),
+                ));
+        });
+}
+
+pub fn setup_main_menu(mut commands: Commands, settings: Res<Settings>, locale: Res<Locale>) {
+    let mut buttons = vec![
+        MenuButton::NewBattle,
+        MenuButton::LocalMatch,
+        MenuButton::Fog,
+        MenuButton::Vision,
+        MenuButton::Palette,
+        MenuButton::HighContrast,
+        MenuButton::TextSize,
+        MenuButton::EdgeScroll,
+        MenuButton::CameraFollow,
+        MenuButton::Pace,
+        MenuButton::Announcements,
+        MenuButton::SoundIndicators,
+        MenuButton::Language,
+        MenuButton::Controls,
+        MenuButton::Mods,
+        MenuButton::Quit,
+    ];
+    if has_suspend_save() {
+        buttons.insert(0, MenuButton::Continue);
+    }
+
+    commands
+        .spawn((
+            MainMenuRoot,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+            DespawnOnExit(AppState::MainMenu),
+        ))
+        .with_children(|menu| {
+            for button in buttons {
+                menu.spawn((
+                    button,
+                    Button,
+                    Node {
+                        width: Val::Px(240.0),
+                        padding: UiRect::all(Val::Px(10.0)),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    BackgroundColor(BUTTON_COLOR),
+                ))
+                .with_child((
+                    MenuLabel(button),
+                    Text::new(button.label(&settings, &locale)),
+                    TextFont {
+                        font_size: 24.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                ));
+            }
+        });
+}
+
+pub fn main_menu_system(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut activated: MessageReader<ButtonActivated>,
+    mut buttons: Query<(Entity, &MenuButton, &Interaction, &mut BackgroundColor)>,
+    mut labels: Query<(&MenuLabel, &mut Text)>,
+    panels: Query<(), With<MenuPanel>>,
+    mut settings: ResMut<Settings>,
+    (mut setup, mut seats): (ResMut<MatchSetup>, ResMut<Seats>),
+    (locale, translations): (Res<Locale>, Res<Translations>),
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    let activated: Vec<Entity> = activated
+        .read()
+        .map(|ButtonActivated(entity)| *entity)
+        .collect();
+    let mut chosen = None;
+    for (entity, &button, interaction, mut background) in &mut buttons {
+        background.0 = match interaction {
+            Interaction::Hovered => BUTTON_HOVER_COLOR,
+            _ => BUTTON_COLOR,
+        };
+        // Keys pressed on a panel are meant for it, not the menu.
+        if !panels.is_empty() {
+            continue;
+        }
+        if *interaction == Interaction::Pressed
+            || activated.contains(&entity)
+            || keys.just_pressed(button.hotkey())
+        {
+            chosen = Some(button);
+        }
+    }
+
+    match chosen {
+        Some(MenuButton::Continue) => {
+            if let Some(suspend) = take_suspend_save() {
+                *setup = suspend.setup;
+                *seats = suspend.seats;
+                commands.insert_resource(PendingResume(suspend.log));
+            }
+            next_state.set(AppState::GamePlay);
+        }
+        Some(MenuButton::NewBattle) => next_state.set(AppState::GamePlay),
+        Some(MenuButton::LocalMatch) => next_state.set(AppState::Lobby),
+        Some(MenuButton::Fog) => {
+            settings.fog_of_war = !settings.fog_of_war;
+            setup.fog_of_war = settings.fog_of_war;
+        }
+        Some(MenuButton::Vision) => settings.cycle_vision_scale(),
+        Some(MenuButton::Palette) => settings.palette = settings.palette.next(),
+        Some(MenuButton::HighContrast) => settings.high_contrast = !settings.high_contrast,
+        Some(MenuButton::TextSize) => settings.cycle_text_scale(),
+        Some(MenuButton::EdgeScroll) => settings.edge_scroll = !settings.edge_scroll,
+        Some(MenuButton::CameraFollow) => settings.camera_follow = !settings.camera_follow,
+        Some(MenuButton::Pace) => settings.cycle_pacing(),
+        Some(MenuButton::Announcements) => {
+            settings.announcements = settings.announcements.next();
+        }
+        Some(MenuButton::SoundIndicators) => settings.sound_indicators = !settings.sound_indicators,
+        Some(MenuButton::Language) => settings.cycle_language(&translations),
+        Some(MenuButton::Controls) => {
+            spawn_controls_panel(&mut commands, &settings.bindings, &locale);
+        }
+        Some(MenuButton::Mods) => spawn_mods_panel(&mut commands, &settings.bindings, &locale),
+        Some(MenuButton::Quit) => {
+            exit.write(AppExit::Success);
+        }
+        None => {}
+    }
+
+    if settings.is_changed() || locale.is_changed() {
+        for (label, mut text) in &mut labels {
+            text.0 = label.0.label(&settings, &locale);
+        }
+    }
+}
+
+/// Runs the controls panel: picking a row waits for the next key or button
+/// and binds it to that action, in place of its old binding on the same
+/// kind of device. Escape stops waiting; cancel closes the panel.
+pub fn controls_menu_system(
+    mut commands: Commands,
+    input: ActionInput,
+    mut activated: MessageReader<ButtonActivated>,
+    mut buttons: Query<(Entity, &ControlsButton, &Interaction, &mut BackgroundColor)>,
+    mut labels: Query<(&ControlsLabel, &mut Text)>,
+    locale: Res<Locale>,
+    panels: Query<Entity, With<ControlsPanel>>,
+    mut waiting: Local<Option<InputAction>>,
+    mut settings: ResMut<Settings>,
+) {
+    let panel = panels.single().ok();
+    let activated: Vec<Entity> = activated
+        .read()
+        .map(|ButtonActivated(entity)| *entity)
+        .collect();
+    let Some(panel) = panel else {
+        *waiting = None;
+        return;
+    };
+
+    let was_waiting = *waiting;
+    if let Some(action) = *waiting {
+        // Whatever was pressed is the new binding, not a click on a row.
+        if let Some(binding) = input.any_just_pressed() {
+            if binding != Binding::Key(KeyCode::Escape) {
+                settings.bindings.rebind(action, binding);
+            }
+            *waiting = None;
+        }
+    } else {
+        let mut chosen = None;
+        for (entity, &button, interaction, mut background) in &mut buttons {
+            background.0 = match interaction {
+                Interaction::Hovered => BUTTON_HOVER_COLOR,
+                _ => BUTTON_COLOR,
+            };
+            if *interaction == Interaction::Pressed || activated.contains(&entity) {
+                chosen = Some(button);
+            }
+        }
+        match chosen {
+            Some(ControlsButton::Rebind(action)) => *waiting = Some(action),
+            Some(ControlsButton::Reset) => settings.bindings = InputMap::default(),
+            Some(ControlsButton::Back) => commands.entity(panel).despawn(),
+            None if input.just_pressed(InputAction::Cancel) => commands.entity(panel).despawn(),
+            None => {}
+        }
+    }
+
+    if settings.is_changed() || *waiting != was_waiting {
+        for (label, mut text) in &mut labels {
+            let action = label.0;
+            text.0 = controls_label(
+                action,
+                &settings.bindings,
+                &locale,
+                *waiting == Some(action),
+            );
+        }
+    }
+}
+
+/// Closes the mods panel on its back button or on cancel.
+pub fn mods_menu_system(
+    mut commands: Commands,
+    input: ActionInput,
+    mut activated: MessageReader<ButtonActivated>,
+    mut back: Query<(Entity, &Interaction, &mut BackgroundColor), With<ModsBack>>,
+    panel: Single<Entity, With<ModsPanel>>,
+) {
+    let activated: Vec<Entity> = activated
+        .read()
+        .map(|ButtonActivated(entity)| *entity)
+        .collect();
+    let mut close = input.just_pressed(InputAction::Cancel);
+    for (entity, interaction, mut background) in &mut back {
+        background.0 = match interaction {
+            Interaction::Hovered => BUTTON_HOVER_COLOR,
+            _ => BUTTON_COLOR,
+        };
+        close |= *interaction == Interaction::Pressed || activated.contains(&entity);
+    }
+    if close {
+        commands.entity(*panel).despawn();
+    }
+}
+
+/// Hides the main menu's buttons while a panel is open over them.
+pub fn hide_main_menu_system(
+    panels: Query<(), With<MenuPanel>>,
+    mut root: Single<&mut Visibility, With<MainMenuRoot>>,
+) {
+    root.set_if_neq(if panels.is_empty() {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+}
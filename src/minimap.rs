@@ -0,0 +1,186 @@
+//! An overview of the whole board for maps too big to fit on screen.
+//!
+//! The minimap draws a pixel per tile: the terrain, the fog over it, and the
+//! units the player can see, with a frame around the part of the board the
+//! camera shows. Clicking it moves the camera there. It only appears while
+//! the board doesn't fit in the window.
+
+use bevy::color::Mix;
+use bevy::image::ImageSampler;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::ui::RelativeCursorPosition;
+use bevy::window::PrimaryWindow;
+
+use crate::camera::zoom;
+use crate::constants::*;
+use crate::grid::{tile_color, GridMap, GridPosition};
+use crate::seats::Viewer;
+use crate::state::AppState;
+use crate::unit::{Faction, FactionPalette, UnitIndex};
+use crate::vision::FactionVision;
+
+/// How far explored tiles out of sight are darkened, as on the board.
+const FOG_DIM: f32 = 0.45;
+
+#[derive(Component)]
+pub struct Minimap;
+
+/// The frame around the part of the board on screen.
+#[derive(Component)]
+pub struct MinimapView;
+
+pub fn setup_minimap(mut commands: Commands, map: Res<GridMap>, mut images: ResMut<Assets<Image>>) {
+    let size = Extent3d {
+        width: map.width as u32,
+        height: map.height as u32,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        default(),
+    );
+    image.sampler = ImageSampler::nearest();
+    let scale = MINIMAP_SIZE / map.width.max(map.height) as f32;
+    commands
+        .spawn((
+            Minimap,
+            ImageNode::new(images.add(image)),
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                top: Val::Px(10.0),
+                width: Val::Px(map.width as f32 * scale),
+                height: Val::Px(map.height as f32 * scale),
+                ..default()
+            },
+            Interaction::default(),
+            RelativeCursorPosition::default(),
+            Visibility::Hidden,
+        ))
+        .with_child((
+            MinimapView,
+            Node {
+                position_type: PositionType::Absolute,
+                border: UiRect::all(Val::Px(1.0)),
+                ..default()
+            },
+            BorderColor::all(Color::WHITE),
+        ));
+}
+
+/// Redraws the minimap when the terrain, the fog, or any unit's tile
+/// changes.
+pub fn update_minimap_system(
+    state: Res<State<AppState>>,
+    map: Res<GridMap>,
+    vision: Res<FactionVision>,
+    viewer: Res<Viewer>,
+    palette: Res<FactionPalette>,
+    index: Res<UnitIndex>,
+    units: Query<&GridPosition>,
+    minimap: Single<&ImageNode, With<Minimap>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !map.is_changed()
+        && !vision.is_changed()
+        && !viewer.is_changed()
+        && !palette.is_changed()
+        && !index.is_changed()
+        && !state.is_changed()
+    {
+        return;
+    }
+    let Some(image) = images.get_mut(&minimap.image) else {
+        return;
+    };
+    let fogged = *state.get() == AppState::GamePlay;
+    let sight = vision.sight(viewer.0);
+    // Image rows run top to bottom, the board's bottom to top.
+    let mut paint = |pos: GridPosition, color: Color| {
+        let _ = image.set_color_at(pos.x as u32, (map.height - 1 - pos.y) as u32, color);
+    };
+    for y in 0..map.height {
+        for x in 0..map.width {
+            let pos = GridPosition::new(x, y);
+            let color = if !fogged || sight.tiles.contains(&pos) {
+                tile_color(&map, pos)
+            } else if sight.explored.contains(&pos) {
+                tile_color(&map, pos).mix(&Color::BLACK, FOG_DIM)
+            } else {
+                Color::BLACK
+            };
+            paint(pos, color);
+        }
+    }
+    for faction in [Faction::Player, Faction::Enemy] {
+        let seen = index
+            .faction(faction)
+            .filter(|&entity| !fogged || vision.can_see(viewer.0, entity, faction));
+        for &pos in units.iter_many(seen) {
+            paint(pos, palette.color(faction));
+        }
+    }
+}
+
+/// Moves the camera to the spot on the minimap the player clicks.
+pub fn minimap_click_system(
+    map: Res<GridMap>,
+    minimap: Single<(&Interaction, &RelativeCursorPosition), With<Minimap>>,
+    mut camera: Single<&mut Transform, With<Camera2d>>,
+) {
+    let (interaction, cursor) = *minimap;
+    let Some(cursor) = cursor
+        .normalized
+        .filter(|_| *interaction == Interaction::Pressed)
+    else {
+        return;
+    };
+    // The cursor is measured from the minimap's center, with y downward.
+    let offset = cursor * Vec2::new(1.0, -1.0) * map.world_size();
+    let at = map.center() + offset;
+    camera.translation = at.extend(camera.translation.z);
+}
+
+/// Shows the minimap only while the board doesn't fit in the window, and
+/// frames the part of it on screen.
+pub fn update_minimap_view_system(
+    map: Res<GridMap>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Transform, &Projection), With<Camera2d>>,
+    mut minimap: Single<&mut Visibility, With<Minimap>>,
+    mut view: Single<&mut Node, With<MinimapView>>,
+) {
+    let (transform, projection) = *camera;
+    let view_size = window.size() * zoom(projection);
+    let board = map.world_size();
+    let fits = view_size.cmpge(board).all();
+    minimap.set_if_neq(if fits {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    });
+    if fits {
+        return;
+    }
+
+    // The on-screen part of the board, as fractions of it from the
+    // bottom-left corner.
+    let corner = map.center() - board / 2.0;
+    let center = transform.translation.truncate();
+    let min = ((center - view_size / 2.0 - corner) / board).clamp(Vec2::ZERO, Vec2::ONE);
+    let max = ((center + view_size / 2.0 - corner) / board).clamp(Vec2::ZERO, Vec2::ONE);
+    let frame = Node {
+        left: Val::Percent(min.x * 100.0),
+        top: Val::Percent((1.0 - max.y) * 100.0),
+        width: Val::Percent((max.x - min.x) * 100.0),
+        height: Val::Percent((max.y - min.y) * 100.0),
+        ..view.clone()
+    };
+    if **view != frame {
+        **view = frame;
+    }
+}
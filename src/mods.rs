@@ -0,0 +1,341 @@
+//! Mods: packages of data, scripts, and sprites in the `mods/` directory
+//! next to `assets/`, found when the game starts.
+//!
+//! Each mod is a directory holding a `mod.ron` manifest and files laid out
+//! as they are under `assets/`: `data/items.ron`, `scripts/lowlands.rhai`,
+//! `textures/units.png`, and so on. Wherever the game reads one of those
+//! files, it takes the copy from the enabled mod with the highest priority
+//! that has one, and the built-in copy otherwise. Data files are the
+//! exception: they are [layered](crate::data) entry by entry, so a mod's
+//! `items.ron` only needs the items it adds or changes. A mod is switched
+//! off by setting `enabled: false` in its manifest.
+//!
+//! Two enabled mods with the same file, or changing the same data entry,
+//! are logged as a conflict; the mods panel shows which mod each file and
+//! entry ends up coming from.
+//!
+//! Mods change the battle itself, so both players of an online or mail
+//! battle need the same ones, as does the server.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[cfg(feature = "render")]
+use bevy::asset::io::AssetSourceBuilder;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::data::base_path;
+
+const MODS_DIR: &str = "mods";
+const MANIFEST: &str = "mod.ron";
+/// The asset source files in mods are loaded through, as
+/// `mods://<mod>/<path>`.
+#[cfg(feature = "render")]
+const MODS_SOURCE: &str = "mods";
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// A mod's `mod.ron`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ModManifest {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// When two mods have the same file, the one with the higher priority
+    /// wins.
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstalledMod {
+    /// The name of the mod's directory.
+    pub id: String,
+    pub dir: PathBuf,
+    pub manifest: ModManifest,
+}
+
+/// The `mods/` directory, next to `assets/`.
+pub fn mods_dir() -> PathBuf {
+    base_path().join(MODS_DIR)
+}
+
+/// Every mod in `dir` with a readable manifest, highest priority first and
+/// then by id. Anything else in there is skipped with a warning.
+pub fn discover_mods(dir: &Path) -> Vec<InstalledMod> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut mods: Vec<InstalledMod> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let dir = entry.path();
+            let manifest = dir.join(MANIFEST);
+            let parsed = fs::read_to_string(&manifest)
+                .map_err(|err| err.to_string())
+                .and_then(|contents| ron::from_str(&contents).map_err(|err| err.to_string()));
+            match parsed {
+                Ok(manifest) => Some(InstalledMod {
+                    id: entry.file_name().to_string_lossy().into_owned(),
+                    dir,
+                    manifest,
+                }),
+                Err(err) => {
+                    warn!("Skipping mod {}: {err}", manifest.display());
+                    None
+                }
+            }
+        })
+        .collect();
+    mods.sort_by(|a, b| {
+        b.manifest
+            .priority
+            .cmp(&a.manifest.priority)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    mods
+}
+
+/// Whether the file at `relative` under `assets/` is a data file, which
+/// mods change entry by entry rather than replace.
+pub fn is_layered(relative: &Path) -> bool {
+    relative.starts_with("data") && relative.extension().is_some_and(|ext| ext == "ron")
+}
+
+/// Every file in `dir` and below, relative to `root`.
+fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(root, &path, files);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_path_buf());
+        }
+    }
+}
+
+/// Every file a mod brings, relative to its directory, its manifest aside.
+pub fn mod_files(installed: &InstalledMod) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    walk(&installed.dir, &installed.dir, &mut files);
+    files.retain(|file| file != Path::new(MANIFEST));
+    files.sort();
+    files
+}
+
+/// Logs every file more than one of `mods` has, other than data files,
+/// whose conflicts are logged entry by entry as they are read.
+fn log_conflicts(mods: &[InstalledMod]) {
+    let enabled: Vec<&InstalledMod> = mods
+        .iter()
+        .filter(|installed| installed.manifest.enabled)
+        .collect();
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    for (rank, installed) in enabled.iter().enumerate() {
+        for file in mod_files(installed) {
+            if is_layered(&file) || !seen.insert(file.clone()) {
+                continue;
+            }
+            let losers: Vec<&str> = enabled[rank + 1..]
+                .iter()
+                .filter(|other| other.dir.join(&file).is_file())
+                .map(|other| other.manifest.name.as_str())
+                .collect();
+            if !losers.is_empty() {
+                warn!(
+                    "{} is in {} and {}; {} wins",
+                    file.display(),
+                    installed.manifest.name,
+                    losers.join(", "),
+                    installed.manifest.name
+                );
+            }
+        }
+    }
+}
+
+/// The mods found at startup, highest priority first.
+pub fn installed_mods() -> &'static [InstalledMod] {
+    static MODS: OnceLock<Vec<InstalledMod>> = OnceLock::new();
+    MODS.get_or_init(|| {
+        let mods = discover_mods(&mods_dir());
+        for installed in &mods {
+            info!(
+                "Found mod {} ({}, priority {}){}",
+                installed.manifest.name,
+                installed.id,
+                installed.manifest.priority,
+                if installed.manifest.enabled {
+                    ""
+                } else {
+                    ", disabled"
+                }
+            );
+        }
+        log_conflicts(&mods);
+        mods
+    })
+}
+
+pub fn enabled_mods() -> impl Iterator<Item = &'static InstalledMod> {
+    installed_mods()
+        .iter()
+        .filter(|installed| installed.manifest.enabled)
+}
+
+/// The enabled mod that the file at `relative` under `assets/` is read
+/// from, if any has it.
+pub fn mod_providing(relative: &Path) -> Option<&'static InstalledMod> {
+    enabled_mods().find(|installed| installed.dir.join(relative).is_file())
+}
+
+/// Lets the asset server load files out of mods, as `mods://<mod>/<path>`.
+/// Has to be called before `DefaultPlugins` are added.
+#[cfg(feature = "render")]
+pub fn register_mod_assets(app: &mut App) {
+    app.register_asset_source(
+        MODS_SOURCE,
+        AssetSourceBuilder::platform_default(MODS_DIR, None),
+    );
+}
+
+/// The asset path to load `relative` under `assets/` from, and the file it
+/// reads: the copy in a mod if there is one.
+#[cfg(feature = "render")]
+pub fn resolve_asset(relative: &str) -> (String, PathBuf) {
+    match mod_providing(Path::new(relative)) {
+        Some(installed) => (
+            format!("{MODS_SOURCE}://{}/{relative}", installed.id),
+            installed.dir.join(relative),
+        ),
+        None => (
+            relative.to_string(),
+            base_path().join("assets").join(relative),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::data::{read_layers_in, EntrySources, Layered, Source};
+    use crate::validate::{checked, Problem, Severity};
+
+    const NAME: &str = "entries.ron";
+
+    /// A data file of numbers by id.
+    #[derive(Debug, Default, PartialEq, Deserialize)]
+    struct Entries {
+        entries: BTreeMap<String, u32>,
+    }
+
+    impl Layered for Entries {
+        fn ids(&self) -> Vec<String> {
+            self.entries.keys().cloned().collect()
+        }
+
+        fn overlay(&mut self, layer: Self) {
+            self.entries.extend(layer.entries);
+        }
+    }
+
+    fn entries(pairs: &[(&str, u32)]) -> Entries {
+        Entries {
+            entries: pairs
+                .iter()
+                .map(|&(id, value)| (id.to_string(), value))
+                .collect(),
+        }
+    }
+
+    /// A game directory of its own for each test, in the system temp dir,
+    /// with `entries` as its built-in data.
+    fn game(test: &str, entries: &str) -> PathBuf {
+        let base = std::env::temp_dir()
+            .join(format!("bevy-game-{}", std::process::id()))
+            .join(test);
+        let _ = fs::remove_dir_all(&base);
+        write(&base.join("assets").join("data").join(NAME), entries);
+        base
+    }
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Installs a mod with `priority` whose copy of the data is `entries`.
+    fn install(base: &Path, id: &str, priority: i32, entries: &str) {
+        let dir = base.join(MODS_DIR).join(id);
+        write(
+            &dir.join(MANIFEST),
+            &format!("(name: {id:?}, priority: {priority})"),
+        );
+        write(&dir.join("data").join(NAME), entries);
+    }
+
+    /// Reads the data with every mod installed under `base`.
+    fn read(base: &Path) -> Result<(Entries, EntrySources), String> {
+        let mods: &'static [InstalledMod] = discover_mods(&base.join(MODS_DIR)).leak();
+        let mods: Vec<&'static InstalledMod> = mods.iter().collect();
+        read_layers_in(base, &mods, NAME)
+    }
+
+    #[test]
+    fn without_mods_the_built_in_data_applies() {
+        let base = game("no_mods", r#"(entries: {"a": 1, "b": 2})"#);
+
+        let (data, sources) = read(&base).unwrap();
+
+        assert_eq!(data, entries(&[("a", 1), ("b", 2)]));
+        assert_eq!(sources["a"], vec![Source::BuiltIn]);
+    }
+
+    #[test]
+    fn a_higher_priority_mod_overrides_a_lower_one() {
+        let base = game("override", r#"(entries: {"a": 1, "b": 2})"#);
+        install(&base, "high", 5, r#"(entries: {"b": 4, "c": 5})"#);
+        install(&base, "low", 0, r#"(entries: {"b": 3})"#);
+
+        let (data, sources) = read(&base).unwrap();
+
+        assert_eq!(data, entries(&[("a", 1), ("b", 4), ("c", 5)]));
+        let names: Vec<String> = sources["b"].iter().map(Source::to_string).collect();
+        assert_eq!(names, ["the built-in data", "low", "high"]);
+    }
+
+    #[test]
+    fn an_invalid_mod_file_is_reported() {
+        let base = game("invalid", r#"(entries: {"a": 1})"#);
+        install(&base, "broken", 0, r#"(entries: {"a": )"#);
+
+        let mut diagnostics = Vec::new();
+        let data: Entries = checked(NAME, read(&base).map(|(data, _)| data), &mut diagnostics);
+
+        assert_eq!(data, Entries::default());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        let Problem::Unreadable(err) = &diagnostics[0].problem else {
+            panic!(
+                "expected the file to be unreadable, got {:?}",
+                diagnostics[0].problem
+            );
+        };
+        assert!(err.contains("broken"), "{err}");
+    }
+}
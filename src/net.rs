@@ -0,0 +1,952 @@
+//! Online play between two copies of the game, one side each.
+//!
+//! The battle is deterministic given its seed and commands, so little more
+//! than those crosses the wire: the host picks the seed and sends it when
+//! the other player joins, the two settle the [`MatchSetup`] in the lobby,
+//! and from then on each side sends every command the executor accepted on
+//! its own turns. The other copy feeds them through its own executor and
+//! arrives at the same board. Input stays blocked on the remote side's
+//! turn, so the two logs can never interleave.
+//!
+//! To make sure the boards really are the same, both sides send a digest of
+//! theirs at the end of every turn. If they ever differ, the two swap their
+//! boards, write both and the command log to [`DESYNC_DIR`] for debugging,
+//! and stop playing together, rather than carry on with different battles.
+//!
+//! Both copies can also join a dedicated [server](crate::server) instead,
+//! which hosts each of them and keeps the battle's authoritative copy.
+//!
+//! The transport is a plain TCP stream carrying one RON message per line,
+//! each at most [`NET_MAX_LINE`] bytes long.
+//! If it drops in the lobby the game goes back to the main menu, and if it
+//! drops mid-battle the AI takes over the remote side and the battle goes
+//! on. The host keeps listening, though: a player who joins again is sent
+//! the setup and the whole command log, catches up by replaying it, and
+//! takes their side back from the AI. Only the player who left can do so:
+//! the host hands out a session token with the seed, the joining game keeps
+//! it in [`session_path`], and a player rejoining has to show it first. The
+//! log goes out a piece at a time as the socket takes it, so the host's
+//! game carries on while a long one is sent.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::hash::{BuildHasher, RandomState};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ai::AiLevel;
+use crate::chat::{ChatLine, ChatLog, ChatSent};
+use crate::command::{fresh_seed, CommandLog, GameCommand};
+use crate::constants::{DESYNC_DIR, NET_MAX_LINE, NET_RESYNC_BATCH};
+use crate::launch::LaunchOptions;
+use crate::lobby::{Lobby, MatchSetup};
+#[cfg(feature = "render")]
+use crate::locale::Locale;
+use crate::plugins::GameSet;
+use crate::replay::{save_replay, start_live_battle};
+use crate::seats::{Controller, Seats};
+use crate::snapshot::{SyncSnapshot, SyncSources};
+use crate::state::AppState;
+use crate::suspend::PendingResume;
+use crate::turn::CurrentTurn;
+use crate::unit::Faction;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NetMessage {
+    /// A joining player's first word: the session token it was given the
+    /// last time it joined, which lets it rejoin that battle.
+    Join {
+        session: Option<u64>,
+    },
+    /// The host's greeting: the seed the battle is fought with, and the
+    /// token to show when rejoining it.
+    Start {
+        seed: u64,
+        session: u64,
+    },
+    /// The host's greeting in place of [`Start`](NetMessage::Start) to a
+    /// player rejoining a battle in progress: its setup and its log, whose
+    /// `commands` commands so far follow in [`Catchup`](NetMessage::Catchup)
+    /// batches, as a long battle's log is more than one line can hold.
+    Resync {
+        setup: MatchSetup,
+        log: CommandLog,
+        commands: usize,
+    },
+    /// The next of the commands a [`Resync`](NetMessage::Resync) is
+    /// followed by.
+    Catchup(Vec<GameCommand>),
+    /// The host's setup for the match, whenever it changes.
+    Setup(MatchSetup),
+    /// Whether the sender's side is ready.
+    Ready(bool),
+    /// The host's word that both sides are ready and the battle starts.
+    Begin,
+    Command(GameCommand),
+    /// The digest of the sender's board once the first `at` commands in the
+    /// log have been applied.
+    Checksum {
+        at: usize,
+        digest: u64,
+    },
+    /// The sender's whole board at `at`, once the digests there differed.
+    Board {
+        at: usize,
+        board: String,
+    },
+    Chat(ChatLine),
+}
+
+/// The connection to the other player.
+#[derive(Resource)]
+pub struct NetLink {
+    stream: TcpStream,
+    /// Received bytes not yet making up a whole line.
+    inbox: Vec<u8>,
+    /// Bytes the socket wasn't ready to take yet.
+    outbox: Vec<u8>,
+}
+
+impl NetLink {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            inbox: Vec::new(),
+            outbox: Vec::new(),
+        })
+    }
+
+    /// Greets the player at the other end of `stream` with `greeting`.
+    fn greet(stream: TcpStream, greeting: NetMessage) -> io::Result<Self> {
+        let mut link = Self::new(stream)?;
+        link.send(greeting)?;
+        link.stream.set_nonblocking(true)?;
+        Ok(link)
+    }
+
+    /// Waits for someone to join on `port`, then tells them the seed. The
+    /// listener is kept for them to rejoin through.
+    pub fn host(port: u16, seed: u64) -> io::Result<(Self, NetListener)> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        info!("Waiting for an opponent on port {port}...");
+        let session = session_token();
+        let link = Self::accept(&listener, seed, session)?;
+        listener.set_nonblocking(true)?;
+        Ok((
+            link,
+            NetListener {
+                listener,
+                session,
+                rejoining: Rejoining::default(),
+            },
+        ))
+    }
+
+    /// Waits for the next player to join through `listener`, then tells them
+    /// the seed and the session token to rejoin with.
+    pub fn accept(listener: &TcpListener, seed: u64, session: u64) -> io::Result<Self> {
+        let (stream, peer) = listener.accept()?;
+        info!("{peer} joined");
+        Self::greet(stream, NetMessage::Start { seed, session })
+    }
+
+    /// Joins the game hosted at `addr`, showing `session` in case this is a
+    /// battle being rejoined, and returns the link and the host's greeting:
+    /// [`NetMessage::Start`], or [`NetMessage::Resync`] with the whole log
+    /// when rejoining.
+    pub fn join(addr: SocketAddr, session: Option<u64>) -> io::Result<(Self, NetMessage)> {
+        let mut link = Self::new(TcpStream::connect(addr)?)?;
+        link.send(NetMessage::Join { session })?;
+        // Anything sent after the greeting stays in the inbox.
+        let greeting = match link.wait_for_message()? {
+            greeting @ NetMessage::Start { .. } => greeting,
+            NetMessage::Resync {
+                setup,
+                mut log,
+                commands,
+            } => {
+                while log.commands.len() < commands {
+                    let NetMessage::Catchup(batch) = link.wait_for_message()? else {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            "expected the rest of the log",
+                        ));
+                    };
+                    log.commands.extend(batch);
+                }
+                NetMessage::Resync {
+                    setup,
+                    log,
+                    commands,
+                }
+            }
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "expected a greeting",
+                ));
+            }
+        };
+        link.stream.set_nonblocking(true)?;
+        Ok((link, greeting))
+    }
+
+    /// Blocks until the next whole message has arrived, and takes it.
+    fn wait_for_message(&mut self) -> io::Result<NetMessage> {
+        loop {
+            if let Some(message) = self.next_message()? {
+                return Ok(message);
+            }
+            self.read_some()?;
+        }
+    }
+
+    /// Queues `message` and sends as much of the queue as the socket takes.
+    pub fn send(&mut self, message: NetMessage) -> io::Result<()> {
+        let line = ron::to_string(&message).map_err(io::Error::other)?;
+        self.outbox.extend_from_slice(line.as_bytes());
+        self.outbox.push(b'\n');
+        self.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.outbox.is_empty() {
+            match self.stream.write(&self.outbox) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(sent) => {
+                    self.outbox.drain(..sent);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// The messages that have arrived in full since the last call.
+    pub fn receive(&mut self) -> io::Result<Vec<NetMessage>> {
+        self.flush()?;
+        self.read_available()?;
+        let mut messages = Vec::new();
+        while let Some(message) = self.next_message()? {
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    fn read_available(&mut self) -> io::Result<()> {
+        loop {
+            match self.read_some() {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Adds what the socket has to the inbox. Fails once the line still
+    /// arriving is longer than [`NET_MAX_LINE`], so the other end can't
+    /// fill up memory by never ending one.
+    fn read_some(&mut self) -> io::Result<()> {
+        let mut buffer = [0; 4096];
+        let read = self.stream.read(&mut buffer)?;
+        if read == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        self.inbox.extend_from_slice(&buffer[..read]);
+        let line_start = self
+            .inbox
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |end| end + 1);
+        if self.inbox.len() - line_start > NET_MAX_LINE {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "the other end sent an overlong line",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Takes the first whole message out of the inbox.
+    fn next_message(&mut self) -> io::Result<Option<NetMessage>> {
+        let Some(end) = self.inbox.iter().position(|&byte| byte == b'\n') else {
+            return Ok(None);
+        };
+        let line: Vec<u8> = self.inbox.drain(..=end).collect();
+        std::str::from_utf8(&line)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+            .and_then(|line| {
+                ron::from_str(line).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+            })
+            .map(Some)
+    }
+}
+
+/// A player connecting to take a side back, who has yet to show the
+/// session token for it.
+#[derive(Default)]
+pub struct Rejoining(Option<NetLink>);
+
+impl Rejoining {
+    /// Takes the next player connecting through `listener`, and once they
+    /// show `session`, starts sending them `setup` and `log` and hands their
+    /// link back. None of it waits on the network: the log goes out with
+    /// the link's later sends and receives.
+    pub fn poll(
+        &mut self,
+        listener: &TcpListener,
+        session: u64,
+        setup: MatchSetup,
+        log: &CommandLog,
+    ) -> io::Result<Option<NetLink>> {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                info!("{peer} is rejoining");
+                let link = NetLink::new(stream)?;
+                link.stream.set_nonblocking(true)?;
+                // A newer attempt replaces one that never showed its token.
+                self.0 = Some(link);
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err),
+        }
+        // Dropped, rather than put back, if anything goes wrong.
+        let Some(mut link) = self.0.take() else {
+            return Ok(None);
+        };
+        link.read_available()?;
+        match link.next_message()? {
+            None => {
+                self.0 = Some(link);
+                Ok(None)
+            }
+            Some(NetMessage::Join {
+                session: Some(shown),
+            }) if shown == session => {
+                let resync = NetMessage::Resync {
+                    setup,
+                    log: CommandLog {
+                        commands: Vec::new(),
+                        ..log.clone()
+                    },
+                    commands: log.commands.len(),
+                };
+                link.send(resync)?;
+                for batch in log.commands.chunks(NET_RESYNC_BATCH) {
+                    link.send(NetMessage::Catchup(batch.to_vec()))?;
+                }
+                Ok(Some(link))
+            }
+            Some(_) => Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                "they didn't show this battle's session token",
+            )),
+        }
+    }
+}
+
+/// Where the host waits for the other player to rejoin, and the session
+/// token they have to show.
+#[derive(Resource)]
+pub struct NetListener {
+    listener: TcpListener,
+    session: u64,
+    rejoining: Rejoining,
+}
+
+/// A token for a new session, which nobody but the player it is given to
+/// can guess.
+pub fn session_token() -> u64 {
+    RandomState::new().hash_one(fresh_seed())
+}
+
+/// `<data dir>/bevy-game/session.ron`: the session token from the last game
+/// joined, for rejoining it.
+pub fn session_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("bevy-game").join("session.ron"))
+}
+
+fn load_session() -> Option<u64> {
+    let contents = fs::read_to_string(session_path()?).ok()?;
+    ron::from_str(&contents).ok()
+}
+
+fn save_session(session: u64) -> Result<(), String> {
+    let path = session_path().ok_or("no data directory on this platform")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+    let contents = ron::to_string(&session).map_err(|err| err.to_string())?;
+    fs::write(&path, contents).map_err(|err| err.to_string())
+}
+
+/// A connection made from the command line.
+pub struct Connection {
+    pub link: NetLink,
+    /// The host's listener, for the other player to rejoin through.
+    pub listener: Option<NetListener>,
+    /// The setup and log of the battle in progress, when rejoining one.
+    pub resync: Option<(MatchSetup, CommandLog)>,
+}
+
+/// Connects to the other player if the command line asked to host or join,
+/// settling the seed between the two.
+pub fn connect(options: &mut LaunchOptions) -> io::Result<Option<Connection>> {
+    if let Some(port) = options.host {
+        let seed = *options.seed.get_or_insert_with(fresh_seed);
+        let (link, listener) = NetLink::host(port, seed)?;
+        return Ok(Some(Connection {
+            link,
+            listener: Some(listener),
+            resync: None,
+        }));
+    }
+    if let Some(addr) = options.join {
+        let (link, greeting) = NetLink::join(addr, load_session())?;
+        let resync = match greeting {
+            NetMessage::Resync { setup, log, .. } => {
+                options.seed = Some(log.seed);
+                Some((setup, log))
+            }
+            NetMessage::Start { seed, session } => {
+                options.seed = Some(seed);
+                if let Err(err) = save_session(session) {
+                    warn!("Couldn't keep the session token; rejoining won't work: {err}");
+                }
+                None
+            }
+            _ => unreachable!("join only returns greetings"),
+        };
+        return Ok(Some(Connection {
+            link,
+            listener: None,
+            resync,
+        }));
+    }
+    Ok(None)
+}
+
+/// How far into the [`CommandLog`] the other player has been sent, and
+/// whose turn the next command in it was issued on.
+#[derive(Resource, Debug)]
+pub struct NetSync {
+    shared: usize,
+    side: Faction,
+}
+
+impl Default for NetSync {
+    fn default() -> Self {
+        Self {
+            shared: 0,
+            side: Faction::Player,
+        }
+    }
+}
+
+/// This side's boards at the end of each turn and the other side's digests,
+/// each kept until it has been compared with the other.
+#[derive(Resource, Debug, Default)]
+pub struct DesyncCheck {
+    local: VecDeque<(usize, SyncSnapshot)>,
+    remote: VecDeque<(usize, u64)>,
+    /// Where the boards were found to differ, and this side's board there.
+    mismatch: Option<(usize, SyncSnapshot)>,
+    /// The other side's board where they differ, once it has arrived.
+    remote_board: Option<String>,
+    /// How long the log was when the battle was resynced. Boards from before
+    /// then aren't compared, since only one side has them.
+    from: usize,
+}
+
+/// The banner telling the player about a desync.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct DesyncBanner;
+
+/// The two copies of the battle have gone their separate ways.
+#[derive(Resource, Debug)]
+pub struct Desync {
+    /// How many commands in the log both had applied when their boards first
+    /// differed.
+    pub at: usize,
+}
+
+/// Sending and receiving commands while connected.
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        let connected = resource_exists::<NetLink>.and(in_state(AppState::GamePlay));
+        app.init_resource::<NetSync>()
+            .init_resource::<DesyncCheck>()
+            .add_systems(
+                OnEnter(AppState::GamePlay),
+                reset_net_sync.before(start_live_battle),
+            )
+            .add_systems(
+                Update,
+                lobby_sync_system
+                    .run_if(resource_exists::<NetLink>.and(in_state(AppState::Lobby)))
+                    .in_set(GameSet::Input),
+            )
+            .add_systems(
+                Update,
+                (receive_commands_system, send_chat_system)
+                    .run_if(connected.clone())
+                    .in_set(GameSet::Input),
+            )
+            .add_systems(
+                Update,
+                accept_rejoin_system
+                    .run_if(
+                        resource_exists::<NetListener>
+                            .and(not(resource_exists::<NetLink>))
+                            .and(in_state(AppState::GamePlay)),
+                    )
+                    .in_set(GameSet::Input),
+            )
+            .add_systems(
+                Update,
+                (send_commands_system, check_sync_system)
+                    .chain()
+                    .run_if(connected)
+                    .after(crate::command::execute_commands)
+                    .in_set(GameSet::Logic),
+            );
+        #[cfg(feature = "render")]
+        app.add_systems(
+            Update,
+            show_desync_system
+                .run_if(
+                    resource_exists::<Desync>
+                        .and(resource_added::<Desync>.or(resource_changed::<Locale>)),
+                )
+                .in_set(GameSet::Visuals),
+        );
+    }
+}
+
+/// Starts counting from the top of the log, or when rejoining, from the end
+/// of the log being caught up on, which the host already has.
+pub fn reset_net_sync(
+    resume: Option<Res<PendingResume>>,
+    mut sync: ResMut<NetSync>,
+    mut check: ResMut<DesyncCheck>,
+) {
+    *sync = NetSync::default();
+    *check = DesyncCheck::default();
+    if let Some(resume) = resume {
+        sync.shared = resume.0.commands.len();
+        sync.side = resume.0.side_to_move();
+        check.from = sync.shared;
+    }
+}
+
+/// Keeps the lobby the same at both ends: the host sends the setup as it
+/// changes, each side says whether it is ready, and the host says when to
+/// begin.
+pub fn lobby_sync_system(
+    mut commands: Commands,
+    mut link: ResMut<NetLink>,
+    mut launch: ResMut<LaunchOptions>,
+    mut setup: ResMut<MatchSetup>,
+    mut lobby: ResMut<Lobby>,
+    mut sent_ready: Local<bool>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let hosting = launch.host.is_some();
+    let own_side = |setup: &MatchSetup| {
+        if hosting {
+            setup.host_side
+        } else {
+            setup.host_side.opponent()
+        }
+    };
+    let mut outgoing = Vec::new();
+    let received = link.receive().map(|messages| {
+        for message in messages {
+            match message {
+                NetMessage::Setup(received) if !hosting => {
+                    *setup = received;
+                    lobby.unready();
+                }
+                NetMessage::Ready(ready) => lobby.set_ready(own_side(&setup).opponent(), ready),
+                NetMessage::Begin if !hosting => lobby.begun = true,
+                _ => {}
+            }
+        }
+        if hosting && setup.is_changed() {
+            outgoing.push(NetMessage::Setup(*setup));
+        }
+        let ready = lobby.is_ready(own_side(&setup));
+        if ready != *sent_ready {
+            *sent_ready = ready;
+            outgoing.push(NetMessage::Ready(ready));
+        }
+        if hosting && lobby.all_ready() && !lobby.begun {
+            lobby.begun = true;
+            outgoing.push(NetMessage::Begin);
+        }
+    });
+    let sent = received.and_then(|()| {
+        outgoing
+            .into_iter()
+            .try_for_each(|message| link.send(message))
+    });
+    if let Err(err) = sent {
+        // There is no match left to set up.
+        warn!("Lost the connection to the other player ({err})");
+        commands.remove_resource::<NetLink>();
+        commands.remove_resource::<NetListener>();
+        launch.host = None;
+        launch.join = None;
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+/// Seats a player rejoining mid-battle back at their side, once they have
+/// shown their session token and everything played so far is on its way.
+pub fn accept_rejoin_system(
+    mut commands: Commands,
+    mut listener: ResMut<NetListener>,
+    setup: Res<MatchSetup>,
+    log: Res<CommandLog>,
+    turn: Res<CurrentTurn>,
+    mut seats: ResMut<Seats>,
+    mut sync: ResMut<NetSync>,
+    mut check: ResMut<DesyncCheck>,
+) {
+    let listener = &mut *listener;
+    let polled = listener
+        .rejoining
+        .poll(&listener.listener, listener.session, *setup, &log);
+    let link = match polled {
+        Ok(Some(link)) => link,
+        Ok(None) => return,
+        Err(err) => return warn!("A player failed to rejoin: {err}"),
+    };
+    info!("The other player has rejoined and takes their side back");
+    seats.set(setup.host_side.opponent(), Controller::Remote);
+    *sync = NetSync {
+        shared: log.commands.len(),
+        side: turn.faction,
+    };
+    *check = DesyncCheck {
+        from: log.commands.len(),
+        ..default()
+    };
+    commands.remove_resource::<Desync>();
+    commands.insert_resource(link);
+}
+
+/// Hands the remote side to the AI once the link is gone.
+fn disconnect(commands: &mut Commands, seats: &mut Seats, err: io::Error) {
+    warn!("Lost the connection to the other player ({err}); the AI takes over their side");
+    commands.remove_resource::<NetLink>();
+    for seat in [&mut seats.player, &mut seats.enemy] {
+        if *seat == Controller::Remote {
+            *seat = Controller::Ai(AiLevel::default());
+        }
+    }
+}
+
+/// Feeds the other player's commands to the executor, their digests and
+/// boards to the desync check, and what they say to the chat.
+pub fn receive_commands_system(
+    mut commands: Commands,
+    mut link: ResMut<NetLink>,
+    mut seats: ResMut<Seats>,
+    mut check: ResMut<DesyncCheck>,
+    mut chat: ResMut<ChatLog>,
+    mut orders: MessageWriter<GameCommand>,
+) {
+    let messages = match link.receive() {
+        Ok(messages) => messages,
+        Err(err) => return disconnect(&mut commands, &mut seats, err),
+    };
+    for message in messages {
+        match message {
+            NetMessage::Command(command) => {
+                orders.write(command);
+            }
+            NetMessage::Checksum { at, digest } if at > check.from => {
+                check.remote.push_back((at, digest));
+            }
+            NetMessage::Board { board, .. } => check.remote_board = Some(board),
+            NetMessage::Chat(line) => chat.lines.push(line),
+            _ => {}
+        }
+    }
+}
+
+/// Sends the other player the commands accepted on this side's turns.
+pub fn send_commands_system(
+    mut commands: Commands,
+    mut link: ResMut<NetLink>,
+    mut seats: ResMut<Seats>,
+    log: Res<CommandLog>,
+    mut sync: ResMut<NetSync>,
+) {
+    let sync = &mut *sync;
+    for &command in &log.commands[sync.shared.min(log.commands.len())..] {
+        if seats.is_local(sync.side) {
+            if let Err(err) = link.send(NetMessage::Command(command)) {
+                disconnect(&mut commands, &mut seats, err);
+                return;
+            }
+        }
+        if command == GameCommand::EndTurn {
+            sync.side = sync.side.opponent();
+        }
+        sync.shared += 1;
+    }
+}
+
+/// Passes on what is said at this end.
+pub fn send_chat_system(
+    mut commands: Commands,
+    mut link: ResMut<NetLink>,
+    mut seats: ResMut<Seats>,
+    mut said: MessageReader<ChatSent>,
+) {
+    for ChatSent(line) in said.read() {
+        if let Err(err) = link.send(NetMessage::Chat(line.clone())) {
+            return disconnect(&mut commands, &mut seats, err);
+        }
+    }
+}
+
+/// Sends the digest of the board each time a turn ends, compares it with
+/// the other side's, and reports a desync once both boards are in hand.
+pub fn check_sync_system(
+    mut commands: Commands,
+    mut link: ResMut<NetLink>,
+    mut seats: ResMut<Seats>,
+    log: Res<CommandLog>,
+    sources: SyncSources,
+    mut check: ResMut<DesyncCheck>,
+    mut checked: Local<usize>,
+) {
+    // Only the board after a whole turn is compared: the commands of the
+    // turn that follows arrive, and are applied, frames apart from it.
+    let at = log.commands.len();
+    if log.commands.last() == Some(&GameCommand::EndTurn) && *checked != at && at > check.from {
+        *checked = at;
+        let board = sources.snapshot();
+        let digest = board.digest();
+        if let Err(err) = link.send(NetMessage::Checksum { at, digest }) {
+            return disconnect(&mut commands, &mut seats, err);
+        }
+        check.local.push_back((at, board));
+    }
+
+    let check = &mut *check;
+    while check.mismatch.is_none() {
+        let (Some((local_at, board)), Some(&(remote_at, digest))) =
+            (check.local.front(), check.remote.front())
+        else {
+            break;
+        };
+        if *local_at != remote_at || board.digest() != digest {
+            let board = board.clone();
+            if let Err(err) = link.send(NetMessage::Board {
+                at: remote_at,
+                board: board.to_ron(),
+            }) {
+                return disconnect(&mut commands, &mut seats, err);
+            }
+            check.mismatch = Some((remote_at, board));
+            break;
+        }
+        check.local.pop_front();
+        check.remote.pop_front();
+    }
+
+    let (Some((at, board)), Some(remote_board)) = (&check.mismatch, &check.remote_board) else {
+        return;
+    };
+    let dir = Path::new(DESYNC_DIR);
+    let written = fs::create_dir_all(dir)
+        .and_then(|()| fs::write(dir.join("local.ron"), board.to_ron()))
+        .and_then(|()| fs::write(dir.join("remote.ron"), remote_board))
+        .map_err(|err| err.to_string())
+        .and_then(|()| save_replay(&log, &dir.join("replay.ron")));
+    match written {
+        Ok(()) => error!(
+            "Desync after {at} commands; both boards and the log are in {}",
+            dir.display()
+        ),
+        Err(err) => error!("Desync after {at} commands; couldn't write the boards: {err}"),
+    }
+    commands.insert_resource(Desync { at: *at });
+    commands.remove_resource::<NetLink>();
+}
+
+/// Puts the desync in front of the player, who otherwise would only see the
+/// other side stop moving, and rewrites it when the language changes.
+#[cfg(feature = "render")]
+pub fn show_desync_system(
+    mut commands: Commands,
+    desync: Res<Desync>,
+    locale: Res<Locale>,
+    mut banner: Query<&mut Text, With<DesyncBanner>>,
+) {
+    let text = locale.fill(
+        "Desync after {} commands: the online battle has stopped. Both boards were written to {}/",
+        &[&desync.at, &DESYNC_DIR],
+    );
+    if let Ok(mut shown) = banner.single_mut() {
+        shown.0 = text;
+        return;
+    }
+    commands.spawn((
+        DesyncBanner,
+        Text::new(text),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        TextColor(Color::srgb(1.0, 0.4, 0.4)),
+        TextLayout::new_with_justify(Justify::Center),
+        BackgroundColor(Color::BLACK.with_alpha(0.8)),
+        // A strip across the board, below where the phase banner shows and
+        // clear of the HUD along the edges.
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            top: Val::Percent(60.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        },
+        DespawnOnExit(AppState::GamePlay),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Both ends of a connection over loopback, the first as a link.
+    fn connected() -> (NetLink, TcpStream) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let other = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let link = NetLink::new(stream).unwrap();
+        link.stream.set_nonblocking(true).unwrap();
+        (link, other)
+    }
+
+    #[test]
+    fn a_line_that_never_ends_drops_the_link() {
+        let (mut link, mut other) = connected();
+        let line = vec![b'('; NET_MAX_LINE + 1];
+        // More than the socket buffers hold, so it is written from another
+        // thread while the link reads.
+        let writer = std::thread::spawn(move || other.write_all(&line));
+
+        let failed = loop {
+            if let Err(err) = link.receive() {
+                break err;
+            }
+        };
+        assert_eq!(failed.kind(), ErrorKind::InvalidData);
+        drop(link);
+        let _ = writer.join();
+    }
+
+    #[test]
+    fn messages_split_across_reads_arrive_whole() {
+        let (mut link, mut other) = connected();
+        let line = ron::to_string(&NetMessage::Ready(true)).unwrap();
+        let (first, rest) = line.as_bytes().split_at(4);
+
+        other.write_all(first).unwrap();
+        assert_eq!(link.receive().unwrap(), Vec::new());
+        other.write_all(rest).unwrap();
+        other.write_all(b"\n").unwrap();
+        let received = loop {
+            let messages = link.receive().unwrap();
+            if !messages.is_empty() {
+                break messages;
+            }
+        };
+        assert_eq!(received, vec![NetMessage::Ready(true)]);
+    }
+
+    /// A player showing `session` rejoins a host expecting session 7 in a
+    /// battle `commands` long, and each end reports how it went.
+    fn rejoin(
+        session: Option<u64>,
+        commands: usize,
+    ) -> (io::Result<NetLink>, io::Result<NetMessage>) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let log = CommandLog {
+            seed: 3,
+            commands: vec![GameCommand::EndTurn; commands],
+            ..default()
+        };
+        let joiner = std::thread::spawn(move || NetLink::join(addr, session));
+
+        let mut rejoining = Rejoining::default();
+        let polled = loop {
+            match rejoining.poll(&listener, 7, MatchSetup::default(), &log) {
+                Ok(None) => continue,
+                Ok(Some(link)) => break Ok(link),
+                Err(err) => break Err(err),
+            }
+        };
+        // The log goes out as the link is used.
+        let polled = polled.map(|mut link| {
+            while !joiner.is_finished() {
+                let _ = link.receive();
+            }
+            link
+        });
+        let greeting = joiner.join().unwrap().map(|(_, greeting)| greeting);
+        (polled, greeting)
+    }
+
+    #[test]
+    fn a_rejoining_player_has_to_show_the_session_token() {
+        for session in [None, Some(8)] {
+            let (polled, greeting) = rejoin(session, 20_000);
+            assert_eq!(
+                polled.err().map(|err| err.kind()),
+                Some(ErrorKind::PermissionDenied)
+            );
+            assert!(greeting.is_err());
+        }
+    }
+
+    #[test]
+    fn a_rejoining_player_with_the_token_is_sent_the_whole_log() {
+        let (polled, greeting) = rejoin(Some(7), 20_000);
+        assert!(polled.is_ok());
+        let Ok(NetMessage::Resync { log, .. }) = greeting else {
+            panic!("expected a resync, got {greeting:?}");
+        };
+        assert_eq!((log.seed, log.commands.len()), (3, 20_000));
+    }
+
+    #[test]
+    fn a_log_longer_than_a_line_still_reaches_a_rejoining_player() {
+        let commands = NET_MAX_LINE;
+        let whole = ron::to_string(&vec![GameCommand::EndTurn; commands]).unwrap();
+        assert!(whole.len() > NET_MAX_LINE);
+
+        let (polled, greeting) = rejoin(Some(7), commands);
+        assert!(polled.is_ok());
+        let Ok(NetMessage::Resync { log, .. }) = greeting else {
+            panic!("expected a resync, got {greeting:?}");
+        };
+        assert_eq!(log.commands.len(), commands);
+    }
+}
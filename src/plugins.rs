@@ -0,0 +1,723 @@
+//! The game's Bevy plugins and the order their systems run in.
+//!
+//! Each plugin owns the resources and systems of one part of the game and
+//! places its systems in a [`GameSet`]. The sets run one after another;
+//! inside a set, systems are only chained where one needs another's
+//! results in the same frame, so the rest can run in parallel.
+//! [`GamePlugin`] adds them all; [`BattlePlugin`] adds only the battle
+//! itself, for tools and tests that play both sides.
+//!
+//! [`BattlePlugin`] never draws anything: commands, turns, and the board's
+//! state work the same with or without the `render` feature, and headless
+//! apps add `MinimalPlugins` and `StatesPlugin` alongside it. Sprites,
+//! input, and UI come from the plugins [`GamePlugin`] adds on top.
+
+use bevy::prelude::*;
+
+use crate::ability::{load_ability_catalog, Cooldowns};
+#[cfg(feature = "render")]
+use crate::action_menu::*;
+use crate::ai::*;
+#[cfg(feature = "render")]
+use crate::animation::*;
+#[cfg(feature = "render")]
+use crate::announce::*;
+#[cfg(feature = "render")]
+use crate::audio::*;
+use crate::battle_log::*;
+#[cfg(feature = "render")]
+use crate::bindings::*;
+#[cfg(feature = "render")]
+use crate::camera::*;
+use crate::campaign::*;
+use crate::chat::*;
+use crate::chest::*;
+use crate::class::*;
+use crate::command::*;
+#[cfg(feature = "render")]
+use crate::contrast::*;
+#[cfg(feature = "render")]
+use crate::cues::*;
+use crate::economy::*;
+#[cfg(feature = "render")]
+use crate::effects::*;
+use crate::facing::*;
+use crate::fire::*;
+use crate::grid::*;
+#[cfg(feature = "render")]
+use crate::hud::*;
+#[cfg(feature = "render")]
+use crate::input::*;
+use crate::items::*;
+use crate::launch::*;
+use crate::leader::*;
+use crate::loading::*;
+use crate::lobby::*;
+#[cfg(feature = "render")]
+use crate::locale::*;
+use crate::mail::*;
+#[cfg(feature = "render")]
+use crate::menu::*;
+#[cfg(feature = "render")]
+use crate::minimap::*;
+#[cfg(feature = "render")]
+use crate::promotion::*;
+use crate::replay::*;
+use crate::rescue::*;
+use crate::rules::GameRng;
+use crate::seats::*;
+#[cfg(feature = "render")]
+use crate::selection::*;
+#[cfg(feature = "render")]
+use crate::settings::*;
+#[cfg(feature = "render")]
+use crate::sprites::*;
+use crate::state::AppState;
+use crate::status::*;
+use crate::stealth::*;
+use crate::structure::*;
+#[cfg(feature = "render")]
+use crate::suspend::*;
+use crate::tiles::load_tile_registry;
+use crate::trap::*;
+use crate::turn::*;
+use crate::unit::*;
+use crate::validate::report_map_problems;
+use crate::victory::*;
+use crate::vision::*;
+
+/// The stages of a frame, run in this order.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameSet {
+    /// Turning the player's input and replays into [`GameCommand`]s.
+    Input,
+    /// The enemy's [`GameCommand`]s.
+    Ai,
+    /// Applying this frame's commands.
+    Logic,
+    /// Settling the battle's outcome and recording it.
+    TurnFlow,
+    /// Walks, attacks, deaths, and combat effects. Dying units are
+    /// despawned here, before anything in [`GameSet::Visuals`] touches them.
+    Animation,
+    /// Keeping sprites, tiles, fog, highlights, and panels in step with
+    /// battle state.
+    Visuals,
+    Camera,
+}
+
+/// The whole game.
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((BattlePlugin, AiPlugin, CampaignPlugin));
+        #[cfg(feature = "render")]
+        app.add_plugins((
+            BattleVisualsPlugin,
+            UiPlugin,
+            SoundPlugin,
+            crate::diagnostics::DiagnosticsOverlayPlugin,
+        ));
+        #[cfg(feature = "net")]
+        app.add_plugins(crate::net::NetPlugin);
+        #[cfg(feature = "dev-tools")]
+        app.add_plugins((
+            crate::dev_tools::DevToolsPlugin,
+            crate::hot_reload::HotReloadPlugin,
+        ));
+    }
+}
+
+/// The app state, the [`GameSet`]s, and the battle: the board, units,
+/// commands, and turns. Neither side is played and nothing is saved.
+pub struct BattlePlugin;
+
+impl Plugin for BattlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<AppState>()
+            .configure_sets(
+                Update,
+                (
+                    GameSet::Input,
+                    GameSet::Ai,
+                    GameSet::Logic,
+                    GameSet::TurnFlow,
+                    GameSet::Animation,
+                    GameSet::Visuals,
+                    GameSet::Camera,
+                )
+                    .chain(),
+            )
+            .add_plugins((GridPlugin, UnitPlugin, TurnPlugin));
+        #[cfg(feature = "scripting")]
+        app.add_plugins(crate::script::ScriptPlugin);
+        #[cfg(not(feature = "render"))]
+        app.add_systems(Update, finish_loading.run_if(in_state(AppState::Loading)));
+    }
+}
+
+/// The battlefield: terrain, structures, traps, chests, fire, and fog.
+pub struct GridPlugin;
+
+impl Plugin for GridPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<GridPosition>()
+            .register_type::<Chunk>()
+            .register_type::<TerrainChunk>()
+            .register_type::<GridMap>()
+            .register_type::<TileType>()
+            .register_type::<Structure>()
+            .register_type::<Trap>()
+            .register_type::<Chest>()
+            .register_type::<Fires>()
+            .insert_resource(load_chest_layout())
+            .init_resource::<GridMap>()
+            .init_resource::<Fires>()
+            .init_resource::<Flares>()
+            .init_resource::<PathCache>()
+            .add_systems(Startup, setup_grid);
+        // Kinds registered by plugins added earlier stay.
+        app.world_mut()
+            .get_resource_or_insert_with(load_tile_registry);
+    }
+}
+
+/// Units, their classes and items, and how they are drawn.
+pub struct UnitPlugin;
+
+impl Plugin for UnitPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Unit>()
+            .register_type::<UnitId>()
+            .register_type::<Faction>()
+            .register_type::<Stats>()
+            .register_type::<MoveRange>()
+            .register_type::<AttackRange>()
+            .register_type::<TurnStatus>()
+            .register_type::<UnitClass>()
+            .register_type::<Experience>()
+            .register_type::<StatusEffects>()
+            .register_type::<Stealth>()
+            .register_type::<Rescue>()
+            .register_type::<Leader>()
+            .register_type::<AuraBonus>()
+            .register_type::<Inventory>()
+            .register_type::<Equipment>()
+            .register_type::<Facing>()
+            .register_type::<Vision>()
+            .register_type::<Cooldowns>()
+            .insert_resource(load_item_catalog())
+            .insert_resource(load_ability_catalog())
+            .init_resource::<NextUnitId>()
+            .init_resource::<UnitIndex>();
+    }
+}
+
+/// Commands and their log, turn order, victory, and replays. Starting a
+/// battle lives here too, since it resets all of the above before the field
+/// is spawned.
+pub struct TurnPlugin;
+
+impl Plugin for TurnPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<CurrentTurn>()
+            .register_type::<BattleOutcome>()
+            .register_type::<Treasury>()
+            .add_message::<GameCommand>()
+            .add_message::<CommandRejected>()
+            .init_resource::<CommandLog>()
+            .init_resource::<BattleLog>()
+            .init_resource::<GameRng>()
+            .init_resource::<CurrentTurn>()
+            .init_resource::<BattleOutcome>()
+            .init_resource::<Objectives>()
+            .add_victory_condition(SeizeThrone)
+            .add_victory_condition(Rout)
+            .init_resource::<Treasury>()
+            .init_resource::<LaunchOptions>()
+            .init_resource::<Seats>()
+            .init_resource::<MatchSetup>()
+            .init_resource::<Lobby>()
+            .init_resource::<ChatLog>()
+            .add_message::<ChatSent>()
+            .add_systems(OnEnter(AppState::GamePlay), reset_chat)
+            .add_systems(
+                OnEnter(AppState::GamePlay),
+                (
+                    start_live_battle,
+                    lay_out_battle_map,
+                    report_map_problems,
+                    spawn_structures,
+                    spawn_traps,
+                    spawn_chests,
+                    spawn_units,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                OnEnter(AppState::Replay),
+                (
+                    begin_replay,
+                    lay_out_battle_map,
+                    spawn_structures,
+                    spawn_traps,
+                    spawn_chests,
+                    spawn_units,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                replay_playback_system
+                    .run_if(in_state(AppState::Replay))
+                    .in_set(GameSet::Input),
+            )
+            .add_systems(
+                Update,
+                (
+                    execute_commands,
+                    update_unit_index_system,
+                    invalidate_path_cache_system.run_if(
+                        resource_changed::<UnitIndex>
+                            .or(resource_changed::<GridMap>)
+                            .or(resource_changed::<CurrentTurn>),
+                    ),
+                )
+                    .chain()
+                    .in_set(GameSet::Logic),
+            )
+            .add_systems(
+                Update,
+                (
+                    check_victory_system.run_if(
+                        resource_changed::<UnitIndex>
+                            .or(any_match_filter::<(With<Unit>, Changed<Stats>)>)
+                            .or(any_match_filter::<Changed<Structure>>)
+                            .or(resource_changed::<CurrentTurn>),
+                    ),
+                    exit_when_decided_system.run_if(resource_changed::<BattleOutcome>),
+                    send_mail_turn_system.run_if(resource_exists::<MailGame>),
+                )
+                    .chain()
+                    .in_set(GameSet::TurnFlow),
+            )
+            .add_systems(
+                OnExit(AppState::GamePlay),
+                end_mail_game.run_if(resource_exists::<MailGame>),
+            );
+    }
+}
+
+/// Campaign progress, saved to the player's profile after each victory.
+pub struct CampaignPlugin;
+
+impl Plugin for CampaignPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_campaign())
+            .init_resource::<CurrentScenario>()
+            .add_systems(
+                Update,
+                record_campaign_progress_system
+                    .run_if(in_state(AppState::GamePlay))
+                    .after(check_victory_system)
+                    .in_set(GameSet::TurnFlow),
+            );
+    }
+}
+
+/// The enemy's turn.
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AiTimer>()
+            .init_resource::<AiPacing>()
+            .init_resource::<AiPlans>()
+            .init_resource::<AiOrders>()
+            .add_systems(OnEnter(AppState::GamePlay), reset_ai_orders)
+            .add_systems(
+                Update,
+                ai_movement_system
+                    .run_if(in_state(AppState::GamePlay))
+                    .in_set(GameSet::Ai),
+            );
+    }
+}
+
+/// How the battle is drawn: tiles, fog, sprites, and their animations, and
+/// the loading screen that waits for their assets. Without it the battle
+/// still plays out, so tests can run [`BattlePlugin`] alone in any build.
+#[cfg(feature = "render")]
+pub struct BattleVisualsPlugin;
+
+#[cfg(feature = "render")]
+impl Plugin for BattleVisualsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Preload>()
+            .add_systems(OnEnter(AppState::Loading), setup_loading_screen)
+            .add_systems(
+                Update,
+                (drop_failed_atlases_system, update_loading_system)
+                    .chain()
+                    .run_if(in_state(AppState::Loading)),
+            );
+        app.init_resource::<FactionVision>()
+            .init_resource::<HighContrast>()
+            .init_resource::<SpriteAtlases>()
+            .init_resource::<TileSet>()
+            .add_systems(
+                Startup,
+                (
+                    load_sprite_atlases,
+                    spawn_tiles.after(setup_grid),
+                    setup_night_overlay,
+                    setup_fog,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        (stack_tile_sheet_system, update_tile_visuals_system).chain(),
+                        update_night_overlay_system,
+                        (
+                            add_structure_sprites_system,
+                            update_structure_visuals_system,
+                        )
+                            .chain(),
+                        (add_trap_sprites_system, update_trap_visuals_system).chain(),
+                        update_fire_visuals_system,
+                        (add_chest_sprites_system, update_chest_visuals_system).chain(),
+                        animate_loot_popups_system,
+                    ),
+                    (
+                        update_vision_system.run_if(sight_changed),
+                        update_unit_visibility_system.run_if(
+                            resource_changed::<FactionVision>
+                                .or(resource_changed::<Viewer>)
+                                .or(state_changed::<AppState>)
+                                .or(any_match_filter::<(
+                                    With<Unit>,
+                                    Or<(Added<Unit>, Changed<Rescue>)>,
+                                )>),
+                        ),
+                        update_fog_system,
+                    )
+                        .chain(),
+                )
+                    .in_set(GameSet::Visuals),
+            );
+        app.init_resource::<FactionPalette>()
+            .add_systems(
+                Update,
+                (
+                    sync_unit_transforms,
+                    tween_units_system,
+                    start_attack_animations_system,
+                    animate_units_system,
+                    animate_deaths_system,
+                    spawn_combat_effects_system,
+                    animate_lunges_system,
+                    animate_hit_effects_system,
+                )
+                    .chain()
+                    .in_set(GameSet::Animation),
+            )
+            .add_systems(
+                Update,
+                (
+                    update_class_visuals_system,
+                    update_status_icons_system,
+                    update_stealth_visuals_system,
+                    update_facing_visuals_system,
+                )
+                    .in_set(GameSet::Visuals),
+            );
+        app.init_resource::<Viewer>().add_systems(
+            OnEnter(AppState::GamePlay),
+            (reset_vision, reset_viewer).after(start_live_battle),
+        );
+    }
+}
+
+/// Everything the player touches: input, selection, menus and panels,
+/// settings, and the camera.
+#[cfg(feature = "render")]
+pub struct UiPlugin;
+
+#[cfg(feature = "render")]
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<GameAction>()
+            .add_message::<HudEvent>()
+            .add_message::<ButtonActivated>()
+            .add_message::<Announcement>()
+            .add_message::<Cue>()
+            .insert_resource(load_settings())
+            .insert_resource(load_translations())
+            .init_resource::<Locale>()
+            .init_resource::<InputMap>()
+            .init_resource::<SelectedUnit>()
+            .init_resource::<GridCursor>()
+            .init_resource::<UiFocus>()
+            .init_resource::<ActionMenuMode>()
+            .init_resource::<RecruitSite>()
+            .init_resource::<ChatDraft>()
+            .init_resource::<AnnouncementLog>()
+            .add_systems(
+                Startup,
+                (
+                    setup_camera,
+                    setup_selection_ring,
+                    setup_hover_highlight,
+                    setup_turn_ui,
+                    setup_objectives_ui,
+                    setup_announcement_ui,
+                    setup_cue_strip,
+                    setup_tile_info,
+                    setup_minimap,
+                ),
+            )
+            .add_systems(
+                OnEnter(AppState::GamePlay),
+                frame_player_units.after(spawn_units).after(reset_viewer),
+            )
+            .add_systems(
+                OnEnter(AppState::Replay),
+                frame_player_units.after(spawn_units),
+            )
+            .add_systems(
+                OnEnter(AppState::MainMenu),
+                (setup_main_menu, reset_match_setup),
+            )
+            .add_systems(OnEnter(AppState::Lobby), setup_lobby)
+            .add_systems(
+                OnEnter(AppState::GamePlay),
+                setup_chat_ui.run_if(chat_enabled),
+            )
+            .add_systems(
+                OnExit(AppState::GamePlay),
+                (clear_selection, clear_announcements),
+            )
+            .add_systems(
+                Update,
+                (
+                    // Focus changes after the board has seen this frame's
+                    // input, so the press that leaves it isn't also played.
+                    (
+                        (mouse_keyboard_input_system, gamepad_input_system),
+                        ui_focus_system,
+                    )
+                        .chain(),
+                    // Everything else acts on the selection as it stands
+                    // after this frame's clicks.
+                    (
+                        selection_system,
+                        (
+                            movement_system,
+                            aim_ability_system,
+                            promotion_input_system,
+                            action_menu_input_system,
+                            (recruit_site_system, recruit_input_system).chain(),
+                            end_turn_input_system,
+                        ),
+                    )
+                        .chain()
+                        .run_if(in_state(AppState::GamePlay)),
+                )
+                    .chain()
+                    .in_set(GameSet::Input),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        highlight_movement_system,
+                        highlight_enemy_range_system.run_if(in_state(AppState::GamePlay)),
+                        highlight_ability_system.run_if(
+                            resource_changed::<ActionMenuMode>
+                                .or(resource_changed::<GridCursor>)
+                                .or(resource_changed::<SelectedUnit>),
+                        ),
+                    ),
+                    highlight_aura_system,
+                    (
+                        update_selection_ring
+                            .run_if(has_selection.or(resource_changed::<SelectedUnit>)),
+                        update_hover_highlight.run_if(
+                            resource_changed::<GridCursor>
+                                .or(resource_changed::<GridMap>)
+                                .or(any_match_filter::<Changed<Interaction>>),
+                        ),
+                    ),
+                    update_promotion_panel_system,
+                    update_action_menu_system,
+                    update_recruit_panel_system,
+                    (
+                        (
+                            (
+                                announce_battle_events_system.run_if(resource_changed::<BattleLog>),
+                                announce_selection_system.run_if(resource_changed::<SelectedUnit>),
+                                announce_tile_system,
+                            ),
+                            collect_announcements_system.before(update_hud_labels_system),
+                        )
+                            .chain(),
+                        (
+                            cue_battle_events_system.run_if(resource_changed::<BattleLog>),
+                            show_cues_system,
+                            fade_cues_system,
+                        )
+                            .chain(),
+                        (
+                            send_hud_event(HudEvent::Turn).run_if(resource_changed::<CurrentTurn>),
+                            send_hud_event(HudEvent::Gold).run_if(resource_changed::<Treasury>),
+                            send_hud_event(HudEvent::Outcome)
+                                .run_if(resource_changed::<BattleOutcome>),
+                            send_hud_event(HudEvent::Palette)
+                                .run_if(resource_changed::<FactionPalette>),
+                            send_hud_event(HudEvent::Objectives)
+                                .run_if(resource_changed::<Objectives>),
+                            send_hud_event(HudEvent::Announcements)
+                                .run_if(resource_changed::<AnnouncementLog>),
+                            send_hud_event(HudEvent::Bindings).run_if(resource_changed::<InputMap>),
+                            send_hud_event(HudEvent::Language).run_if(resource_changed::<Locale>),
+                            update_hud_labels_system.run_if(
+                                on_message::<HudEvent>.or(any_match_filter::<Added<HudLabel>>),
+                            ),
+                        )
+                            .chain(),
+                        update_tile_info_system.run_if(
+                            resource_changed::<GridCursor>
+                                .or(resource_changed::<GridMap>)
+                                .or(resource_changed::<Fires>)
+                                .or(resource_changed::<Locale>)
+                                .or(any_match_filter::<Changed<Structure>>),
+                        ),
+                    ),
+                    update_minimap_system.after(update_vision_system),
+                    (update_overlay_borders_system, thicken_bars_system),
+                    update_replay_ui_system.run_if(in_state(AppState::Replay)),
+                    phase_banner_system.run_if(in_state(AppState::GamePlay)),
+                    update_chat_ui_system.run_if(
+                        in_state(AppState::GamePlay)
+                            .and(chat_enabled)
+                            .and(resource_changed::<ChatLog>.or(resource_changed::<ChatDraft>)),
+                    ),
+                )
+                    .in_set(GameSet::Visuals),
+            )
+            .add_systems(
+                Update,
+                chat_input_system
+                    .run_if(in_state(AppState::GamePlay).and(chat_enabled))
+                    .before(GameSet::Input)
+                    .before(settings_hotkeys_system),
+            )
+            .add_systems(
+                Update,
+                (
+                    camera_zoom_system,
+                    camera_pan_system,
+                    camera_drag_system,
+                    camera_follow_system,
+                    minimap_click_system,
+                    (clamp_camera_system, update_minimap_view_system)
+                        .chain()
+                        .run_if(view_changed),
+                )
+                    .chain()
+                    .in_set(GameSet::Camera),
+            )
+            .add_systems(
+                Update,
+                (
+                    main_menu_system,
+                    controls_menu_system,
+                    mods_menu_system,
+                    hide_main_menu_system,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::MainMenu))
+                    .after(ui_focus_system),
+            )
+            .add_systems(
+                Update,
+                (
+                    lobby_input_system,
+                    seat_lobby_system.run_if(resource_changed::<MatchSetup>),
+                    update_lobby_labels_system
+                        .run_if(resource_changed::<MatchSetup>.or(resource_changed::<Lobby>)),
+                    start_match_system,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Lobby))
+                    .after(ui_focus_system),
+            )
+            .add_systems(
+                Update,
+                (
+                    settings_hotkeys_system,
+                    apply_settings_system,
+                    update_locale_system,
+                    localize_labels_system,
+                    save_settings_system,
+                    scale_text_system,
+                )
+                    .chain(),
+            );
+        app.add_systems(OnEnter(AppState::Replay), setup_replay_ui)
+            .add_systems(
+                OnEnter(AppState::GamePlay),
+                setup_mail_ui.run_if(resource_exists::<MailGame>),
+            )
+            .add_systems(
+                Update,
+                (
+                    (replay_hotkeys_system, suspend_on_quit_system)
+                        .run_if(in_state(AppState::GamePlay)),
+                    replay_controls_system
+                        .run_if(in_state(AppState::Replay))
+                        .before(replay_playback_system),
+                )
+                    .in_set(GameSet::Input),
+            )
+            .add_systems(
+                Update,
+                (
+                    export_battle_log_system.after(check_victory_system),
+                    handover_system
+                        .after(check_victory_system)
+                        .run_if(in_state(AppState::GamePlay)),
+                    update_mail_ui_system.after(send_mail_turn_system).run_if(
+                        in_state(AppState::GamePlay)
+                            .and(resource_exists::<MailGame>)
+                            .and(resource_changed::<MailGame>.or(resource_changed::<Locale>)),
+                    ),
+                )
+                    .in_set(GameSet::TurnFlow),
+            );
+    }
+}
+
+/// Sound effects for selection, movement, and the battle's cues.
+#[cfg(feature = "render")]
+pub struct SoundPlugin;
+
+#[cfg(feature = "render")]
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SoundEffects>()
+            .add_systems(Startup, load_sound_effects)
+            .add_systems(
+                Update,
+                (
+                    play_cue_sounds_system.after(cue_battle_events_system),
+                    play_selection_sound_system.run_if(resource_changed::<SelectedUnit>),
+                    play_step_sounds_system,
+                )
+                    .in_set(GameSet::Visuals),
+            );
+    }
+}
@@ -0,0 +1,149 @@
+//! Promotion choice panel for player units that reached the promotion level.
+
+use bevy::prelude::*;
+
+use crate::bindings::{ActionInput, InputAction, InputMap};
+use crate::class::{Experience, UnitClass, PROMOTION_LEVEL};
+use crate::command::GameCommand;
+use crate::constants::UNIT_SIZE;
+use crate::input::ButtonActivated;
+use crate::locale::Locale;
+use crate::selection::SelectedUnit;
+use crate::state::AppState;
+use crate::unit::{Unit, UnitId};
+
+const PANEL_COLOR: Color = Color::srgba(0.08, 0.08, 0.12, 0.9);
+const CHOICE_COLOR: Color = Color::srgb(0.22, 0.22, 0.30);
+
+#[derive(Component)]
+pub struct PromotionPanel;
+
+/// A button that promotes the selected unit into the given class.
+#[derive(Component, Clone, Copy)]
+pub struct PromotionChoice(pub UnitClass);
+
+/// Rebuilds the panel whenever the selection or a unit's class or level changes.
+pub fn update_promotion_panel_system(
+    mut commands: Commands,
+    selected: Res<SelectedUnit>,
+    bindings: Res<InputMap>,
+    locale: Res<Locale>,
+    units: Query<(&UnitClass, &Experience), With<Unit>>,
+    changed: Query<(), (With<Unit>, Or<(Changed<UnitClass>, Changed<Experience>)>)>,
+    panels: Query<Entity, With<PromotionPanel>>,
+) {
+    if !selected.is_changed() && changed.is_empty() && !locale.is_changed() {
+        return;
+    }
+    for panel in &panels {
+        commands.entity(panel).despawn();
+    }
+
+    let Some((&class, experience)) = selected.0.and_then(|entity| units.get(entity).ok()) else {
+        return;
+    };
+    if experience.level < PROMOTION_LEVEL || class.promotions().is_empty() {
+        return;
+    }
+
+    commands
+        .spawn((
+            PromotionPanel,
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                top: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(6.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            BackgroundColor(PANEL_COLOR),
+            DespawnOnExit(AppState::GamePlay),
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                Text::new(locale.fill("{} can promote:", &[&locale.tr(&format!("{class:?}"))])),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+            ));
+            for (index, &into) in class.promotions().iter().enumerate() {
+                let ability = into
+                    .ability()
+                    .map_or("", |ability| locale.tr(ability.description()));
+                panel
+                    .spawn((
+                        PromotionChoice(into),
+                        Button,
+                        Node {
+                            padding: UiRect::all(Val::Px(6.0)),
+                            ..default()
+                        },
+                        BackgroundColor(CHOICE_COLOR),
+                    ))
+                    .with_child((
+                        Text::new(format!(
+                            "[{}] {} - {ability}",
+                            bindings.hint(InputAction::CHOICES[index]),
+                            locale.tr(&format!("{into:?}"))
+                        )),
+                        TextFont {
+                            font_size: 16.0,
+                            ..default()
+                        },
+                    ));
+            }
+        });
+}
+
+pub fn promotion_input_system(
+    input: ActionInput,
+    mut activated: MessageReader<ButtonActivated>,
+    selected: Res<SelectedUnit>,
+    units: Query<(&UnitId, &UnitClass, &Experience), With<Unit>>,
+    choices: Query<(&PromotionChoice, &Interaction), Changed<Interaction>>,
+    entries: Query<&PromotionChoice>,
+    mut orders: MessageWriter<GameCommand>,
+) {
+    let activated = activated
+        .read()
+        .find_map(|ButtonActivated(entity)| entries.get(*entity).ok())
+        .map(|choice| choice.0);
+    let Some((&unit, class, experience)) = selected.0.and_then(|entity| units.get(entity).ok())
+    else {
+        return;
+    };
+
+    let clicked = choices
+        .iter()
+        .find(|(_, interaction)| **interaction == Interaction::Pressed)
+        .map(|(choice, _)| choice.0);
+    let pressed = InputAction::CHOICES
+        .into_iter()
+        .zip(class.promotions())
+        .find(|(choice, _)| input.just_pressed(*choice))
+        .map(|(_, &into)| into);
+
+    if let Some(into) = clicked.or(activated).or(pressed) {
+        if class.can_promote(experience, into) {
+            orders.write(GameCommand::Promote { unit, class: into });
+        }
+    }
+}
+
+/// Keeps unit sprites and labels in step with their current class.
+pub fn update_class_visuals_system(
+    mut units: Query<(&UnitClass, &mut Sprite, &Children), (With<Unit>, Changed<UnitClass>)>,
+    mut labels: Query<&mut Text2d>,
+) {
+    for (class, mut sprite, children) in &mut units {
+        sprite.custom_size = Some(Vec2::splat(UNIT_SIZE * class.sprite_scale()));
+        for child in children {
+            if let Ok(mut label) = labels.get_mut(*child) {
+                label.0 = class.glyph().to_string();
+            }
+        }
+    }
+}
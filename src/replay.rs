@@ -0,0 +1,289 @@
+//! Recording battles and playing them back.
+//!
+//! A replay is just a [`CommandLog`]: the battle seed plus every command the
+//! executor accepted. Playback resets the board from the seed and feeds the
+//! commands back through [`execute_commands`](crate::command::execute_commands),
+//! so a replay can never drift from what actually happened.
+
+use std::fs;
+use std::path::Path;
+
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+
+use crate::battle_log::BattleLog;
+#[cfg(feature = "render")]
+use crate::bindings::{ActionInput, InputAction, InputMap};
+use crate::campaign::{is_campaign_battle, CampaignProgress};
+use crate::command::{fresh_seed, reset_battle, CommandLog, GameCommand};
+#[cfg(feature = "render")]
+use crate::constants::REPLAY_FILE;
+use crate::constants::REPLAY_STEP_DELAY;
+use crate::economy::Treasury;
+use crate::fire::Fires;
+use crate::launch::LaunchOptions;
+use crate::lobby::MatchSetup;
+#[cfg(feature = "render")]
+use crate::locale::Locale;
+use crate::rules::GameRng;
+use crate::seats::Seats;
+#[cfg(feature = "render")]
+use crate::state::AppState;
+use crate::suspend::PendingResume;
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::vision::Flares;
+
+/// Playback position within a loaded replay.
+#[derive(Resource)]
+pub struct ReplayPlayer {
+    pub log: CommandLog,
+    pub cursor: usize,
+    pub playing: bool,
+    pub timer: Timer,
+}
+
+impl ReplayPlayer {
+    pub fn new(log: CommandLog) -> Self {
+        Self {
+            log,
+            cursor: 0,
+            playing: false,
+            timer: Timer::from_seconds(REPLAY_STEP_DELAY, TimerMode::Repeating),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.cursor >= self.log.commands.len()
+    }
+
+    /// The next command to execute, advancing the cursor.
+    fn step(&mut self) -> Option<GameCommand> {
+        let command = self.log.commands.get(self.cursor).copied()?;
+        self.cursor += 1;
+        Some(command)
+    }
+}
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct ReplayText;
+
+pub fn save_replay(log: &CommandLog, path: &Path) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+    let contents =
+        ron::ser::to_string_pretty(log, PrettyConfig::default()).map_err(|err| err.to_string())?;
+    fs::write(path, contents).map_err(|err| err.to_string())
+}
+
+pub fn load_replay(path: &Path) -> Result<CommandLog, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    ron::from_str(&contents).map_err(|err| err.to_string())
+}
+
+/// Starts a live battle with a new seed, or the one given on the command
+/// line, on the map set up for the match, and an empty log. A campaign
+/// battle starts the home side with the gold saved in its profile. When
+/// resuming a suspended battle it takes that battle's seed, map, and
+/// starting gold instead and queues all of its commands up for the
+/// executor, so the board ends up exactly where it was left.
+pub fn start_live_battle(
+    mut commands: Commands,
+    resume: Option<Res<PendingResume>>,
+    launch: Res<LaunchOptions>,
+    seats: Res<Seats>,
+    progress: Option<Res<CampaignProgress>>,
+    mut log: ResMut<CommandLog>,
+    mut rng: ResMut<GameRng>,
+    mut turn: ResMut<CurrentTurn>,
+    mut outcome: ResMut<BattleOutcome>,
+    mut history: ResMut<BattleLog>,
+    mut treasury: ResMut<Treasury>,
+    mut fires: ResMut<Fires>,
+    mut flares: ResMut<Flares>,
+    setup: Res<MatchSetup>,
+    mut orders: MessageWriter<GameCommand>,
+) {
+    let (seed, map, gold) = match &resume {
+        Some(resume) => (resume.0.seed, resume.0.map, resume.0.treasury),
+        None => {
+            let gold = match progress {
+                Some(progress) if is_campaign_battle(&seats) => {
+                    progress.starting_treasury(seats.home())
+                }
+                _ => Treasury::default(),
+            };
+            (launch.seed.unwrap_or_else(fresh_seed), setup.map, gold)
+        }
+    };
+    *log = CommandLog {
+        seed,
+        map,
+        treasury: gold,
+        commands: Vec::new(),
+    };
+    reset_battle(
+        seed,
+        gold,
+        &mut rng,
+        &mut turn,
+        &mut outcome,
+        &mut history,
+        &mut treasury,
+        &mut fires,
+        &mut flares,
+    );
+
+    if let Some(resume) = resume {
+        orders.write_batch(resume.0.commands.iter().copied());
+        commands.remove_resource::<PendingResume>();
+    }
+}
+
+/// F5 saves the current battle's log; F9 loads the saved log and replays it.
+#[cfg(feature = "render")]
+pub fn replay_hotkeys_system(
+    mut commands: Commands,
+    input: ActionInput,
+    log: Res<CommandLog>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let path = Path::new(REPLAY_FILE);
+
+    if input.just_pressed(InputAction::SaveReplay) {
+        match save_replay(&log, path) {
+            Ok(()) => info!("Saved replay to {}", path.display()),
+            Err(err) => error!("Failed to save replay to {}: {err}", path.display()),
+        }
+    }
+
+    if input.just_pressed(InputAction::PlayReplay) {
+        match load_replay(path) {
+            Ok(log) => {
+                commands.insert_resource(ReplayPlayer::new(log));
+                next_state.set(AppState::Replay);
+            }
+            Err(err) => error!("Failed to load replay from {}: {err}", path.display()),
+        }
+    }
+}
+
+/// Resets the battle to the replay's seed and map, with nothing applied yet.
+pub fn begin_replay(
+    player: Res<ReplayPlayer>,
+    mut log: ResMut<CommandLog>,
+    mut rng: ResMut<GameRng>,
+    mut turn: ResMut<CurrentTurn>,
+    mut outcome: ResMut<BattleOutcome>,
+    mut history: ResMut<BattleLog>,
+    mut treasury: ResMut<Treasury>,
+    mut fires: ResMut<Fires>,
+    mut flares: ResMut<Flares>,
+) {
+    *log = CommandLog {
+        commands: Vec::new(),
+        ..player.log.clone()
+    };
+    reset_battle(
+        player.log.seed,
+        player.log.treasury,
+        &mut rng,
+        &mut turn,
+        &mut outcome,
+        &mut history,
+        &mut treasury,
+        &mut fires,
+        &mut flares,
+    );
+}
+
+/// Shows the replay's progress in the bottom corner.
+#[cfg(feature = "render")]
+pub fn setup_replay_ui(mut commands: Commands) {
+    commands.spawn((
+        ReplayText,
+        Text::new(""),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        },
+        DespawnOnExit(AppState::Replay),
+    ));
+}
+
+/// Space toggles playback, `.` steps a single command, Escape returns to the menu.
+#[cfg(feature = "render")]
+pub fn replay_controls_system(
+    input: ActionInput,
+    mut player: ResMut<ReplayPlayer>,
+    mut orders: MessageWriter<GameCommand>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if input.just_pressed(InputAction::ReplayPlayPause) {
+        player.playing = !player.playing;
+    }
+    if input.just_pressed(InputAction::ReplayStep) {
+        player.playing = false;
+        if let Some(command) = player.step() {
+            orders.write(command);
+        }
+    }
+    if input.just_pressed(InputAction::ReplayExit) {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+pub fn replay_playback_system(
+    time: Res<Time>,
+    mut player: ResMut<ReplayPlayer>,
+    mut orders: MessageWriter<GameCommand>,
+) {
+    if !player.playing || !player.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    match player.step() {
+        Some(command) => {
+            orders.write(command);
+        }
+        None => player.playing = false,
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn update_replay_ui_system(
+    player: Res<ReplayPlayer>,
+    locale: Res<Locale>,
+    bindings: Res<InputMap>,
+    mut text: Single<&mut Text, With<ReplayText>>,
+) {
+    if !player.is_changed() && !locale.is_changed() && !bindings.is_changed() {
+        return;
+    }
+
+    let status = if player.is_finished() {
+        "Finished"
+    } else if player.playing {
+        "Playing"
+    } else {
+        "Paused"
+    };
+    text.0 = locale.fill(
+        "REPLAY {} - {}/{} commands  [{}] play/pause  [{}] step  [{}] exit",
+        &[
+            &locale.tr(status),
+            &player.cursor,
+            &player.log.commands.len(),
+            &bindings.hint(InputAction::ReplayPlayPause),
+            &bindings.hint(InputAction::ReplayStep),
+            &bindings.hint(InputAction::ReplayExit),
+        ],
+    );
+}
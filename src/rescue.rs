@@ -0,0 +1,35 @@
+//! Rescuing: a unit can pick up an adjacent ally and carry it off the board,
+//! moving more slowly until it drops the ally on a free tile next to it.
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+use crate::unit::{MoveRange, UnitId};
+
+/// Tiles of movement a unit loses while carrying an ally.
+pub const CARRY_MOVE_PENALTY: u32 = 2;
+
+/// Who a unit is carrying, or who is carrying it. A carried unit is off the
+/// board: it can't act, be targeted, or block movement, and its position
+/// follows its carrier's.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Serialize, Reflect)]
+#[reflect(Component)]
+pub struct Rescue {
+    pub carrying: Option<UnitId>,
+    pub carried_by: Option<UnitId>,
+}
+
+impl Rescue {
+    pub fn is_carried(&self) -> bool {
+        self.carried_by.is_some()
+    }
+
+    /// Movement left after the carry penalty, if carrying anyone.
+    pub fn move_range(&self, base: MoveRange) -> u32 {
+        if self.carrying.is_some() {
+            base.0.saturating_sub(CARRY_MOVE_PENALTY)
+        } else {
+            base.0
+        }
+    }
+}
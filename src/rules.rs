@@ -0,0 +1,456 @@
+//! The rules of the game, apart from the ECS: whether a unit may move to a
+//! tile or attack another unit, how an attack plays out, and how turns pass
+//! from side to side.
+//!
+//! Everything here works on plain values handed in by the caller and never
+//! touches the world, so the same rules serve player input, the AI, the
+//! command executor, and anything that wants to play a battle out without
+//! running the app. Player input asks here so it never offers an order the
+//! executor would turn down, and the AI so it never wastes one. Whether the
+//! battle is over is left to the executor.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use bevy::prelude::Resource;
+use serde::Serialize;
+
+use crate::class::{ClassAbility, UnitClass};
+use crate::grid::{reachable_tiles, GridMap, GridPosition, TerrainBonus};
+use crate::turn::{CurrentTurn, TimeOfDay, NIGHT_MISS_CHANCE};
+use crate::unit::{AttackRange, Faction, Stats, TurnStatus};
+
+/// Why a unit can't move to a tile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveError {
+    /// The tile is off the map.
+    OutOfBounds,
+    /// Another unit stands on the tile.
+    Occupied,
+    /// No path within the unit's move range leads to the tile.
+    Unreachable,
+    /// The unit has already moved this turn.
+    AlreadyMoved,
+}
+
+impl MoveError {
+    pub fn reason(self) -> &'static str {
+        match self {
+            MoveError::OutOfBounds => "destination is off the map",
+            MoveError::Occupied => "destination is occupied",
+            MoveError::Unreachable => "destination is not reachable",
+            MoveError::AlreadyMoved => "unit has already moved",
+        }
+    }
+}
+
+/// Why a unit can't attack another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttackError {
+    /// The attacker has already acted this turn.
+    AlreadyActed,
+    /// The target is on the attacker's own side.
+    Ally,
+    /// The target is in stealth, so the attacker can't pick it out.
+    Hidden,
+    /// The attacker's class needs a weapon and it has none equipped.
+    Unarmed,
+    /// The target is farther away than the attacker's range.
+    OutOfRange,
+}
+
+impl AttackError {
+    pub fn reason(self) -> &'static str {
+        match self {
+            AttackError::AlreadyActed => "unit has already acted",
+            AttackError::Ally => "cannot attack an ally",
+            AttackError::Hidden => "target is hidden",
+            AttackError::Unarmed => "unit has no weapon equipped",
+            AttackError::OutOfRange => "target is out of range",
+        }
+    }
+}
+
+/// Why a unit can't be given orders: it is the other side's turn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfTurn;
+
+impl OutOfTurn {
+    pub fn reason(self) -> &'static str {
+        "not this side's turn"
+    }
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.reason())
+    }
+}
+
+impl fmt::Display for AttackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.reason())
+    }
+}
+
+impl fmt::Display for OutOfTurn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.reason())
+    }
+}
+
+/// Checks a move from `from` to `to` for a unit that walks up to `range`
+/// tiles. `occupied` holds the tiles of every other unit on the board.
+pub fn validate_move(
+    map: &GridMap,
+    occupied: &HashSet<GridPosition>,
+    from: GridPosition,
+    range: u32,
+    status: &TurnStatus,
+    to: GridPosition,
+) -> Result<(), MoveError> {
+    if status.has_moved {
+        return Err(MoveError::AlreadyMoved);
+    }
+    if !map.in_bounds(to) {
+        return Err(MoveError::OutOfBounds);
+    }
+    if occupied.contains(&to) {
+        return Err(MoveError::Occupied);
+    }
+    if !reachable_tiles(map, from, range, occupied).contains(&to) {
+        return Err(MoveError::Unreachable);
+    }
+    Ok(())
+}
+
+/// An attack to check with [`validate_attack`].
+pub struct AttackAttempt<'a> {
+    /// The attacker's side.
+    pub faction: Faction,
+    pub status: &'a TurnStatus,
+    /// Whether the attacker can attack at all with what it has equipped.
+    pub armed: bool,
+    /// The attacker's range with its equipment and bonuses.
+    pub range: AttackRange,
+    /// Where the attacker stands.
+    pub from: GridPosition,
+    /// The target's side.
+    pub target: Faction,
+    /// Whether the target is in stealth.
+    pub hidden: bool,
+    /// Where the target stands.
+    pub at: GridPosition,
+}
+
+/// Checks an attack from `attempt.from` on a unit of `attempt.target`
+/// standing at `attempt.at`.
+pub fn validate_attack(attempt: AttackAttempt) -> Result<(), AttackError> {
+    if attempt.faction == attempt.target {
+        return Err(AttackError::Ally);
+    }
+    if attempt.hidden {
+        return Err(AttackError::Hidden);
+    }
+    if attempt.status.has_acted {
+        return Err(AttackError::AlreadyActed);
+    }
+    if !attempt.armed {
+        return Err(AttackError::Unarmed);
+    }
+    if attempt.from.distance(attempt.at) > attempt.range.0 {
+        return Err(AttackError::OutOfRange);
+    }
+    Ok(())
+}
+
+/// Checks that it is `faction`'s turn to give orders.
+pub fn check_turn(faction: Faction, turn: &CurrentTurn) -> Result<(), OutOfTurn> {
+    if faction != turn.faction {
+        return Err(OutOfTurn);
+    }
+    Ok(())
+}
+
+/// The turn after `turn`: the other side's, and a new round once it comes
+/// back to the player's side.
+pub fn next_turn(turn: CurrentTurn) -> CurrentTurn {
+    let faction = turn.faction.opponent();
+    CurrentTurn {
+        faction,
+        number: turn.number + u32::from(faction == Faction::Player),
+    }
+}
+
+/// Deterministic random source for anything that affects battle outcomes.
+/// Seeded from the [`CommandLog`](crate::command::CommandLog) so replays
+/// roll the same numbers.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct GameRng(u64);
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// SplitMix64: tiny, fast, and stable across platforms and crate versions.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed value in `low..=high`.
+    pub fn range(&mut self, low: u32, high: u32) -> u32 {
+        low + (self.next_u64() % u64::from(high - low + 1)) as u32
+    }
+}
+
+/// One side of an attack, as seen by [`resolve_attack`] and [`strike`].
+pub struct Combatant<'a> {
+    /// Its stats with equipment, auras, and status effects counted in.
+    pub stats: &'a Stats,
+    pub class: UnitClass,
+    pub pos: GridPosition,
+    /// Whether the unit moved earlier this turn.
+    pub moved: bool,
+}
+
+pub struct AttackRoll {
+    pub roll: u32,
+    pub damage: u32,
+}
+
+/// Damage dealt by one attack: attack plus a small roll, minus defense, never
+/// below 1, adjusted by either side's class ability.
+pub fn resolve_attack(attacker: Combatant, defender: Combatant, rng: &mut GameRng) -> AttackRoll {
+    let mut roll = rng.range(0, 2);
+    let mut attack = attacker.stats.attack;
+    let mut defense = defender.stats.defense;
+
+    match attacker.class.ability() {
+        Some(ClassAbility::Deadeye) => roll = 2,
+        Some(ClassAbility::Charge) if attacker.moved => attack += 2,
+        Some(ClassAbility::Arcane) => defense /= 2,
+        _ => {}
+    }
+    let mut damage = (attack + roll).saturating_sub(defense).max(1);
+    if defender.class.ability() == Some(ClassAbility::Bulwark) {
+        damage = damage.saturating_sub(1).max(1);
+    }
+    AttackRoll { roll, damage }
+}
+
+/// How an attack turned out.
+pub enum Strike {
+    Missed,
+    Hit(AttackRoll),
+}
+
+/// Plays out an attack on a defender standing on ground that gives it
+/// `terrain`, at `time` of day.
+///
+/// Cover and darkness give the attack a chance to miss, which is only
+/// rolled when there is one, so daytime attacks on open ground draw the
+/// same numbers as a bare [`resolve_attack`].
+pub fn strike(
+    attacker: Combatant,
+    defender: Combatant,
+    terrain: TerrainBonus,
+    time: TimeOfDay,
+    rng: &mut GameRng,
+) -> Strike {
+    let mut attacker_stats = *attacker.stats;
+    let mut defender_stats = *defender.stats;
+    defender_stats.defense += terrain.defense;
+    let mut avoid = terrain.avoid;
+    if time == TimeOfDay::Night {
+        attacker_stats.attack += attacker.class.night_attack_bonus();
+        avoid += NIGHT_MISS_CHANCE;
+    }
+    if avoid > 0 && rng.range(1, 100) <= avoid {
+        return Strike::Missed;
+    }
+
+    let ranged = attacker.pos.distance(defender.pos) > 1;
+    let mut hit = resolve_attack(
+        Combatant {
+            stats: &attacker_stats,
+            ..attacker
+        },
+        Combatant {
+            stats: &defender_stats,
+            ..defender
+        },
+        rng,
+    );
+    if ranged {
+        hit.damage = hit.damage.saturating_sub(terrain.ranged_cover).max(1);
+    }
+    Strike::Hit(hit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::TileType;
+
+    const FROM: GridPosition = GridPosition::new(0, 0);
+
+    fn check_move(
+        map: &GridMap,
+        occupied: &[GridPosition],
+        status: TurnStatus,
+        to: GridPosition,
+    ) -> Result<(), MoveError> {
+        let occupied: HashSet<GridPosition> = occupied.iter().copied().collect();
+        validate_move(map, &occupied, FROM, 3, &status, to)
+    }
+
+    #[test]
+    fn moves_within_range_are_allowed() {
+        let map = GridMap::new(5, 5);
+        assert_eq!(
+            check_move(&map, &[], TurnStatus::default(), GridPosition::new(2, 1)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn moves_off_the_map_are_out_of_bounds() {
+        let map = GridMap::new(5, 5);
+        let to = GridPosition::new(-1, 0);
+        assert_eq!(
+            check_move(&map, &[], TurnStatus::default(), to),
+            Err(MoveError::OutOfBounds)
+        );
+    }
+
+    #[test]
+    fn moves_onto_another_unit_are_occupied() {
+        let map = GridMap::new(5, 5);
+        let to = GridPosition::new(1, 0);
+        assert_eq!(
+            check_move(&map, &[to], TurnStatus::default(), to),
+            Err(MoveError::Occupied)
+        );
+    }
+
+    #[test]
+    fn moves_past_the_units_range_or_walled_off_are_unreachable() {
+        let mut map = GridMap::new(5, 5);
+        let far = GridPosition::new(4, 4);
+        assert_eq!(
+            check_move(&map, &[], TurnStatus::default(), far),
+            Err(MoveError::Unreachable)
+        );
+
+        map.set_tile(GridPosition::new(1, 0), TileType::Water);
+        map.set_tile(GridPosition::new(0, 1), TileType::Mountain);
+        let near = GridPosition::new(1, 1);
+        assert_eq!(
+            check_move(&map, &[], TurnStatus::default(), near),
+            Err(MoveError::Unreachable)
+        );
+    }
+
+    #[test]
+    fn a_unit_only_moves_once_a_turn() {
+        let map = GridMap::new(5, 5);
+        let status = TurnStatus {
+            has_moved: true,
+            has_acted: false,
+        };
+        let to = GridPosition::new(1, 0);
+        assert_eq!(
+            check_move(&map, &[], status, to),
+            Err(MoveError::AlreadyMoved)
+        );
+    }
+
+    fn check_attack(
+        target: Faction,
+        hidden: bool,
+        armed: bool,
+        status: TurnStatus,
+        at: GridPosition,
+    ) -> Result<(), AttackError> {
+        validate_attack(AttackAttempt {
+            faction: Faction::Player,
+            status: &status,
+            armed,
+            range: AttackRange(1),
+            from: FROM,
+            target,
+            hidden,
+            at,
+        })
+    }
+
+    #[test]
+    fn attacks_on_adjacent_enemies_are_allowed() {
+        let at = GridPosition::new(1, 0);
+        assert_eq!(
+            check_attack(Faction::Enemy, false, true, TurnStatus::default(), at),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn attacks_on_allies_are_refused() {
+        let at = GridPosition::new(1, 0);
+        assert_eq!(
+            check_attack(Faction::Player, false, true, TurnStatus::default(), at),
+            Err(AttackError::Ally)
+        );
+    }
+
+    #[test]
+    fn hidden_targets_cannot_be_attacked() {
+        let at = GridPosition::new(1, 0);
+        assert_eq!(
+            check_attack(Faction::Enemy, true, true, TurnStatus::default(), at),
+            Err(AttackError::Hidden)
+        );
+    }
+
+    #[test]
+    fn unarmed_units_cannot_attack() {
+        let at = GridPosition::new(1, 0);
+        assert_eq!(
+            check_attack(Faction::Enemy, false, false, TurnStatus::default(), at),
+            Err(AttackError::Unarmed)
+        );
+    }
+
+    #[test]
+    fn attacks_past_the_units_range_are_out_of_range() {
+        let at = GridPosition::new(2, 0);
+        assert_eq!(
+            check_attack(Faction::Enemy, false, true, TurnStatus::default(), at),
+            Err(AttackError::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn a_unit_only_acts_once_a_turn() {
+        let status = TurnStatus {
+            has_moved: false,
+            has_acted: true,
+        };
+        let at = GridPosition::new(1, 0);
+        assert_eq!(
+            check_attack(Faction::Enemy, false, true, status, at),
+            Err(AttackError::AlreadyActed)
+        );
+    }
+
+    #[test]
+    fn only_the_acting_side_gives_orders() {
+        let turn = CurrentTurn::default();
+        assert_eq!(check_turn(Faction::Player, &turn), Ok(()));
+        assert_eq!(check_turn(Faction::Enemy, &turn), Err(OutOfTurn));
+    }
+}
@@ -0,0 +1,627 @@
+//! Scenario scripts: the waves, dialogue, triggers, and rewards that turn a
+//! map into a scenario.
+//!
+//! A map's script is `assets/scripts/<map>.rhai`, run by an embedded
+//! [Rhai](https://rhai.rs) engine whenever a battle on that map starts. It
+//! may define any of these hooks, each called once the battle log records
+//! what it is about:
+//!
+//! - `on_start()` once the units are on the board
+//! - `on_turn(side, number)` as each side's turn begins
+//! - `on_defeated(unit)` as a unit falls, by its id
+//! - `on_end(winner)` once the battle is decided
+//!
+//! Hooks read the battle through `turn()`, `side()`, `units(side)`,
+//! `gold(side)`, and `unit_at(x, y)`, and change it only through
+//! `spawn_unit(side, class, x, y)`, `say(speaker, text)`,
+//! `give_gold(side, amount)`, and `win(side)`. Sides and classes are named
+//! as in the game, e.g. `"Enemy"` and `"Knight"`; units by their ids, which
+//! `unit_at` and `spawn_unit` return.
+//!
+//! Hooks can also give a unit the AI plays a standing [order](AiOrder) that
+//! overrides its usual choices: `hold(unit)` keeps it where it is,
+//! `guard(unit, x, y, radius)` keeps it within `radius` tiles of a spot,
+//! `advance(unit, x, y)` sends it somewhere rather than at the enemy,
+//! `idle(unit)` has it only wait, and `release(unit)` hands it back to the
+//! AI.
+//! Anything a script needs to remember between hooks it keeps on `this`,
+//! an empty map at the start of each battle.
+//!
+//! Hooks only answer the battle log, which the command executor fills the
+//! same way from the same commands, so replays, resumed battles, and both
+//! ends of an online match see the same waves on the same turns, as long as
+//! everyone has the same script.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bevy::prelude::*;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST, INT};
+
+use crate::ai::{AiOrder, AiOrders};
+use crate::battle_log::{BattleEvent, BattleLog};
+use crate::class::UnitClass;
+use crate::command::CommandLog;
+use crate::constants::SCRIPT_MAX_OPERATIONS;
+use crate::data::script_path;
+use crate::economy::{NextUnitId, Treasury};
+use crate::grid::{lay_out_battle_map, GridMap, GridPosition};
+use crate::items::ItemCatalog;
+use crate::plugins::GameSet;
+use crate::rescue::Rescue;
+use crate::state::AppState;
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::{spawn_unit, Faction, Stats, Unit, UnitId, UnitIndex};
+use crate::victory::check_victory_system;
+
+/// A change a hook asked for, made once the hook returns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ScriptAction {
+    Spawn {
+        unit: UnitId,
+        faction: Faction,
+        class: UnitClass,
+        at: GridPosition,
+    },
+    /// Gives `unit` a standing order, or takes it away.
+    Order {
+        unit: UnitId,
+        order: Option<AiOrder>,
+    },
+    Say {
+        speaker: String,
+        text: String,
+    },
+    GiveGold {
+        faction: Faction,
+        amount: u32,
+    },
+    Win(Faction),
+}
+
+/// The battle as the hooks see it, and what they have asked for.
+#[derive(Debug, Default)]
+struct ScriptView {
+    turn: CurrentTurn,
+    alive: HashMap<Faction, INT>,
+    treasury: Treasury,
+    /// Every unit standing on the board, by tile.
+    standing: HashMap<GridPosition, UnitId>,
+    /// The id the next unit spawned will have.
+    next_id: u32,
+    actions: Vec<ScriptAction>,
+}
+
+/// A hook to call, with its arguments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Hook {
+    Start,
+    Turn(Faction, u32),
+    Defeated(u32),
+    End(Faction),
+}
+
+/// The running script of the map being fought on.
+#[derive(Resource)]
+pub struct ScenarioScript {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    /// What the hooks keep between calls, as `this`.
+    state: Dynamic,
+    view: Arc<Mutex<ScriptView>>,
+    started: bool,
+    /// How far into the [`BattleLog`] the hooks have been called for.
+    seen: usize,
+}
+
+fn lock(view: &Mutex<ScriptView>) -> MutexGuard<'_, ScriptView> {
+    // A hook that panicked leaves nothing half-written worth refusing.
+    view.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn parse_faction(side: &str) -> Result<Faction, Box<EvalAltResult>> {
+    ron::from_str(side).map_err(|_| format!("unknown side: {side}").into())
+}
+
+fn parse_class(class: &str) -> Result<UnitClass, Box<EvalAltResult>> {
+    ron::from_str(class).map_err(|_| format!("unknown class: {class}").into())
+}
+
+fn parse_unit(unit: INT) -> Result<UnitId, Box<EvalAltResult>> {
+    u32::try_from(unit)
+        .map(UnitId)
+        .map_err(|_| format!("unknown unit: {unit}").into())
+}
+
+fn parse_tile(x: INT, y: INT) -> GridPosition {
+    GridPosition::new(x as i32, y as i32)
+}
+
+/// Queues giving `unit` the standing order `order`.
+fn give_order(
+    view: &Mutex<ScriptView>,
+    unit: INT,
+    order: Option<AiOrder>,
+) -> Result<(), Box<EvalAltResult>> {
+    let unit = parse_unit(unit)?;
+    lock(view).actions.push(ScriptAction::Order { unit, order });
+    Ok(())
+}
+
+/// The engine with the scenario API registered on it, reading from and
+/// writing to `view`.
+fn scenario_engine(view: &Arc<Mutex<ScriptView>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(SCRIPT_MAX_OPERATIONS);
+
+    let shared = view.clone();
+    engine.register_fn("turn", move || lock(&shared).turn.number as INT);
+    let shared = view.clone();
+    engine.register_fn("side", move || format!("{:?}", lock(&shared).turn.faction));
+    let shared = view.clone();
+    engine.register_fn("units", move |side: &str| {
+        let faction = parse_faction(side)?;
+        Ok::<_, Box<EvalAltResult>>(lock(&shared).alive.get(&faction).copied().unwrap_or(0))
+    });
+    let shared = view.clone();
+    engine.register_fn("gold", move |side: &str| {
+        let faction = parse_faction(side)?;
+        Ok::<_, Box<EvalAltResult>>(lock(&shared).treasury.get(faction) as INT)
+    });
+    let shared = view.clone();
+    engine.register_fn("unit_at", move |x: INT, y: INT| {
+        let view = lock(&shared);
+        view.standing
+            .get(&parse_tile(x, y))
+            .map_or(-1, |unit| unit.0 as INT)
+    });
+
+    let shared = view.clone();
+    engine.register_fn(
+        "spawn_unit",
+        move |side: &str, class: &str, x: INT, y: INT| {
+            let (faction, class) = (parse_faction(side)?, parse_class(class)?);
+            let mut view = lock(&shared);
+            let unit = UnitId(view.next_id);
+            view.next_id += 1;
+            view.actions.push(ScriptAction::Spawn {
+                unit,
+                faction,
+                class,
+                at: parse_tile(x, y),
+            });
+            Ok::<_, Box<EvalAltResult>>(unit.0 as INT)
+        },
+    );
+    let shared = view.clone();
+    engine.register_fn("say", move |speaker: &str, text: &str| {
+        lock(&shared).actions.push(ScriptAction::Say {
+            speaker: speaker.to_string(),
+            text: text.to_string(),
+        });
+    });
+    let shared = view.clone();
+    engine.register_fn("give_gold", move |side: &str, amount: INT| {
+        let faction = parse_faction(side)?;
+        let amount = u32::try_from(amount).map_err(|_| format!("invalid amount: {amount}"))?;
+        lock(&shared)
+            .actions
+            .push(ScriptAction::GiveGold { faction, amount });
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    let shared = view.clone();
+    engine.register_fn("win", move |side: &str| {
+        let faction = parse_faction(side)?;
+        lock(&shared).actions.push(ScriptAction::Win(faction));
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+
+    let shared = view.clone();
+    engine.register_fn("hold", move |unit: INT| {
+        give_order(&shared, unit, Some(AiOrder::Hold))
+    });
+    let shared = view.clone();
+    engine.register_fn("guard", move |unit: INT, x: INT, y: INT, radius: INT| {
+        let radius = u32::try_from(radius).map_err(|_| format!("invalid radius: {radius}"))?;
+        let at = parse_tile(x, y);
+        give_order(&shared, unit, Some(AiOrder::Guard { at, radius }))
+    });
+    let shared = view.clone();
+    engine.register_fn("advance", move |unit: INT, x: INT, y: INT| {
+        give_order(&shared, unit, Some(AiOrder::Advance(parse_tile(x, y))))
+    });
+    let shared = view.clone();
+    engine.register_fn("idle", move |unit: INT| {
+        give_order(&shared, unit, Some(AiOrder::Idle))
+    });
+    let shared = view.clone();
+    engine.register_fn("release", move |unit: INT| give_order(&shared, unit, None));
+    engine
+}
+
+impl ScenarioScript {
+    /// Compiles the script at `path` and runs its top level, which may set
+    /// up constants for the hooks.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let view = Arc::new(Mutex::new(ScriptView::default()));
+        let engine = scenario_engine(&view);
+        let ast = engine.compile(source).map_err(|err| err.to_string())?;
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|err| err.to_string())?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            engine,
+            ast,
+            scope,
+            state: Dynamic::from_map(Map::new()),
+            view,
+            started: false,
+            seen: 0,
+        })
+    }
+
+    /// Calls `hook` if the script defines it, returning what it asked for.
+    /// A hook that fails is logged and has no effect.
+    fn call(&mut self, hook: &str, args: impl FuncArgs) -> Vec<ScriptAction> {
+        if !self
+            .ast
+            .iter_functions()
+            .any(|function| function.name == hook)
+        {
+            return Vec::new();
+        }
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            hook,
+            args,
+        );
+        let actions = std::mem::take(&mut lock(&self.view).actions);
+        match result {
+            Ok(_) => actions,
+            Err(err) => {
+                error!("{hook} in {} failed: {err}", self.path.display());
+                Vec::new()
+            }
+        }
+    }
+
+    fn run(&mut self, hook: &Hook) -> Vec<ScriptAction> {
+        match *hook {
+            Hook::Start => self.call("on_start", ()),
+            Hook::Turn(faction, number) => {
+                self.call("on_turn", (format!("{faction:?}"), number as INT))
+            }
+            Hook::Defeated(unit) => self.call("on_defeated", (unit as INT,)),
+            Hook::End(winner) => self.call("on_end", (format!("{winner:?}"),)),
+        }
+    }
+}
+
+/// Loads the script of the map the battle in the [`CommandLog`] is fought
+/// on, if it has one.
+pub fn load_scenario_script(mut commands: Commands, log: Res<CommandLog>) {
+    commands.remove_resource::<ScenarioScript>();
+    let path = script_path(&log.map.script_name());
+    if !path.exists() {
+        return;
+    }
+    match ScenarioScript::load(&path) {
+        Ok(script) => {
+            info!("Running scenario script {}", path.display());
+            commands.insert_resource(script);
+        }
+        Err(err) => error!("Failed to load {}: {err}", path.display()),
+    }
+}
+
+pub fn end_scenario_script(mut commands: Commands) {
+    commands.remove_resource::<ScenarioScript>();
+}
+
+/// Calls the script's hooks for what the battle log recorded since the last
+/// frame, and makes the changes they ask for.
+pub fn run_scenario_script_system(
+    mut script: ResMut<ScenarioScript>,
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    turn: Res<CurrentTurn>,
+    mut history: ResMut<BattleLog>,
+    mut outcome: ResMut<BattleOutcome>,
+    mut treasury: ResMut<Treasury>,
+    mut next_id: ResMut<NextUnitId>,
+    mut orders: ResMut<AiOrders>,
+    map: Res<GridMap>,
+    index: Res<UnitIndex>,
+    catalog: Res<ItemCatalog>,
+    units: Query<(&UnitId, &Faction, &GridPosition, &Stats, &Rescue), With<Unit>>,
+) {
+    let script = &mut *script;
+    let mut hooks = Vec::new();
+    if !script.started {
+        script.started = true;
+        hooks.push(Hook::Start);
+    }
+    for (_, event) in history.read_new(&mut script.seen) {
+        match *event {
+            BattleEvent::TurnStarted { faction, number } => hooks.push(Hook::Turn(faction, number)),
+            BattleEvent::Died { unit } => hooks.push(Hook::Defeated(unit.0)),
+            BattleEvent::BattleEnded { winner } => hooks.push(Hook::End(winner)),
+            _ => {}
+        }
+    }
+    if hooks.is_empty() {
+        return;
+    }
+
+    let mut alive: HashMap<Faction, INT> = HashMap::new();
+    let mut standing: HashMap<GridPosition, UnitId> = HashMap::new();
+    for (&unit, &faction, &pos, stats, rescue) in &units {
+        if stats.is_alive() {
+            *alive.entry(faction).or_default() += 1;
+            if !rescue.is_carried() {
+                standing.insert(pos, unit);
+            }
+        }
+    }
+    // Units spawned this frame aren't in the index yet.
+    let mut spawned: Vec<GridPosition> = Vec::new();
+    for hook in &hooks {
+        {
+            let mut view = lock(&script.view);
+            view.turn = *turn;
+            view.alive = alive.clone();
+            view.treasury = *treasury;
+            view.standing = standing.clone();
+            view.next_id = next_id.0;
+        }
+        for action in script.run(hook) {
+            match action {
+                ScriptAction::Spawn {
+                    unit,
+                    faction,
+                    class,
+                    at,
+                } => {
+                    // Ids are handed out as the script asks, so one that
+                    // can't be spawned still uses its id up.
+                    next_id.0 = next_id.0.max(unit.0 + 1);
+                    if !map.is_walkable(at) || index.is_occupied(at) || spawned.contains(&at) {
+                        warn!(
+                            "{} can't spawn a {class:?} at ({}, {})",
+                            script.path.display(),
+                            at.x,
+                            at.y
+                        );
+                        continue;
+                    }
+                    spawn_unit(
+                        &mut commands,
+                        &catalog,
+                        *state.get(),
+                        unit,
+                        faction,
+                        class,
+                        at,
+                    );
+                    spawned.push(at);
+                    standing.insert(at, unit);
+                    *alive.entry(faction).or_default() += 1;
+                    history.record(
+                        turn.number,
+                        BattleEvent::Arrived {
+                            unit,
+                            faction,
+                            class,
+                        },
+                    );
+                }
+                ScriptAction::Order {
+                    unit,
+                    order: Some(order),
+                } => {
+                    orders.0.insert(unit, order);
+                }
+                ScriptAction::Order { unit, order: None } => {
+                    orders.0.remove(&unit);
+                }
+                ScriptAction::Say { speaker, text } => {
+                    history.record(turn.number, BattleEvent::Said { speaker, text });
+                }
+                ScriptAction::GiveGold { faction, amount } => {
+                    let gold = treasury.get_mut(faction);
+                    *gold = gold.saturating_add(amount);
+                    history.record(turn.number, BattleEvent::Rewarded { faction, amount });
+                }
+                ScriptAction::Win(winner) if outcome.0.is_none() => {
+                    history.record(turn.number, BattleEvent::BattleEnded { winner });
+                    outcome.0 = Some(winner);
+                }
+                ScriptAction::Win(_) => {}
+            }
+        }
+    }
+}
+
+/// The last thing a script said, shown until the next turn begins.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct DialogueText;
+
+#[cfg(feature = "render")]
+pub fn setup_dialogue_ui(mut commands: Commands, state: Res<State<AppState>>) {
+    commands.spawn((
+        DialogueText,
+        Text::new(""),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        BackgroundColor(Color::NONE),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(50.0),
+            left: Val::Percent(25.0),
+            width: Val::Percent(50.0),
+            padding: UiRect::all(Val::Px(8.0)),
+            ..default()
+        },
+        DespawnOnExit(*state.get()),
+    ));
+}
+
+#[cfg(feature = "render")]
+pub fn update_dialogue_ui_system(
+    history: Res<BattleLog>,
+    dialogue: Single<(&mut Text, &mut BackgroundColor), With<DialogueText>>,
+) {
+    let (mut text, mut background) = dialogue.into_inner();
+    let said = history
+        .entries
+        .iter()
+        .rev()
+        .find_map(|(_, event)| match event {
+            BattleEvent::Said { speaker, text } => Some(Some(format!("{speaker}: {text}"))),
+            BattleEvent::TurnStarted { .. } => Some(None),
+            _ => None,
+        })
+        .flatten();
+    background.0 = if said.is_some() {
+        Color::BLACK.with_alpha(0.7)
+    } else {
+        Color::NONE
+    };
+    text.0 = said.unwrap_or_default();
+}
+
+/// Running the scenario script of the map being fought on, in live battles
+/// and replays alike.
+pub struct ScriptPlugin;
+
+impl Plugin for ScriptPlugin {
+    fn build(&self, app: &mut App) {
+        // Hooks give orders whether or not the AI is plugged in.
+        app.init_resource::<AiOrders>();
+        for state in [AppState::GamePlay, AppState::Replay] {
+            app.add_systems(
+                OnEnter(state),
+                load_scenario_script.after(lay_out_battle_map),
+            )
+            .add_systems(
+                OnExit(state),
+                end_scenario_script.run_if(resource_exists::<ScenarioScript>),
+            );
+            #[cfg(feature = "render")]
+            app.add_systems(OnEnter(state), setup_dialogue_ui);
+        }
+        app.add_systems(
+            Update,
+            run_scenario_script_system
+                .run_if(resource_exists::<ScenarioScript>)
+                .after(check_victory_system)
+                .in_set(GameSet::TurnFlow),
+        );
+        #[cfg(feature = "render")]
+        app.add_systems(
+            Update,
+            update_dialogue_ui_system
+                .run_if(resource_changed::<BattleLog>)
+                .in_set(GameSet::Visuals),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestGame;
+
+    /// Says which hook ran with what, and spawns two enemies on its first
+    /// call: one on an open tile and one on top of the player's unit.
+    const SCRIPT: &str = r#"
+        fn on_start() {
+            let open = spawn_unit("Enemy", "Knight", 2, 1);
+            let blocked = spawn_unit("Enemy", "Knight", 1, 1);
+            say("on_start", `${open} ${blocked}`);
+        }
+        fn on_turn(side, number) { say("on_turn", `${side} ${number}`); }
+        fn on_defeated(unit) { say("on_defeated", `${unit}`); }
+        fn on_end(winner) { say("on_end", winner); }
+    "#;
+
+    /// Everything the script has said, as "hook: what".
+    fn said(game: &TestGame) -> Vec<String> {
+        game.app
+            .world()
+            .resource::<BattleLog>()
+            .entries
+            .iter()
+            .filter(|(_, event)| matches!(event, BattleEvent::Said { .. }))
+            .map(|(_, event)| event.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn hooks_answer_the_battle_log_and_spawn_where_they_can() {
+        let mut game = TestGame::new();
+        let player = game.spawn_player_unit(GridPosition::new(1, 1));
+        game.spawn_enemy_unit(GridPosition::new(3, 1));
+        game.update();
+        let path = std::env::temp_dir().join(format!("bevy-game-{}.rhai", std::process::id()));
+        fs::write(&path, SCRIPT).unwrap();
+        let script = ScenarioScript::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let first = game.app.world().resource::<NextUnitId>().0;
+
+        game.app.insert_resource(script);
+        game.update();
+        assert_eq!(said(&game), [format!("on_start: {first} {}", first + 1)]);
+        // The refused spawn still used its id up.
+        assert_eq!(game.app.world().resource::<NextUnitId>().0, first + 2);
+        let arrived: Vec<UnitId> = game
+            .app
+            .world()
+            .resource::<BattleLog>()
+            .entries
+            .iter()
+            .filter_map(|(_, event)| match event {
+                BattleEvent::Arrived { unit, .. } => Some(*unit),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(arrived, [UnitId(first)]);
+        game.assert_unit_at(UnitId(first), GridPosition::new(2, 1));
+        game.assert_unit_at(player, GridPosition::new(1, 1));
+
+        game.advance_turn();
+        assert_eq!(said(&game)[1..], ["on_turn: Enemy 1"]);
+        let mut history = game.app.world_mut().resource_mut::<BattleLog>();
+        history.record(1, BattleEvent::Died { unit: player });
+        history.record(
+            1,
+            BattleEvent::BattleEnded {
+                winner: Faction::Enemy,
+            },
+        );
+        game.update();
+        assert_eq!(
+            said(&game)[2..],
+            [
+                format!("on_defeated: {}", player.0),
+                "on_end: Enemy".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Who plays each side, and whose eyes the screen shows.
+//!
+//! A side is taken by someone at this machine, by the AI at some
+//! [level](AiLevel), or by someone playing over the network. Normally the
+//! player's side is local and the enemy is the AI, `--autoplay` hands
+//! both to the AI, and `--difficulty` sets the AI's level. Other matches
+//! are seated in the [lobby](crate::lobby): any mix of people and AIs at
+//! one device, or one person at each end of a `--host` / `--join`
+//! connection. Each turn goes to whoever is seated at the side whose turn
+//! it is.
+//!
+//! Input, fog, and the HUD follow the [`Viewer`], the local side the screen
+//! is showing. In hotseat it changes hands between turns, behind a screen
+//! asking for the device to be passed when fog of war would otherwise show
+//! the next player what the last one could see.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ai::AiLevel;
+#[cfg(feature = "render")]
+use crate::bindings::{ActionInput, InputAction, InputMap};
+use crate::launch::LaunchOptions;
+#[cfg(feature = "render")]
+use crate::lobby::MatchSetup;
+#[cfg(feature = "render")]
+use crate::locale::Locale;
+#[cfg(feature = "render")]
+use crate::state::AppState;
+#[cfg(feature = "render")]
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::Faction;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Controller {
+    Local,
+    Ai(AiLevel),
+    /// Commands arrive over the network.
+    Remote,
+}
+
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Seats {
+    pub player: Controller,
+    pub enemy: Controller,
+}
+
+impl Default for Seats {
+    fn default() -> Self {
+        Self {
+            player: Controller::Local,
+            enemy: Controller::Ai(AiLevel::default()),
+        }
+    }
+}
+
+impl Seats {
+    pub fn for_launch(launch: &LaunchOptions) -> Self {
+        if launch.host.is_some() {
+            return Self {
+                player: Controller::Local,
+                enemy: Controller::Remote,
+            };
+        }
+        if launch.join.is_some() {
+            return Self {
+                player: Controller::Remote,
+                enemy: Controller::Local,
+            };
+        }
+        let ai = Controller::Ai(launch.difficulty.unwrap_or_default());
+        Self {
+            player: if launch.autoplay {
+                ai
+            } else {
+                Controller::Local
+            },
+            enemy: ai,
+        }
+    }
+
+    pub fn controller(&self, faction: Faction) -> Controller {
+        match faction {
+            Faction::Player => self.player,
+            Faction::Enemy => self.enemy,
+        }
+    }
+
+    pub fn set(&mut self, faction: Faction, controller: Controller) {
+        match faction {
+            Faction::Player => self.player = controller,
+            Faction::Enemy => self.enemy = controller,
+        }
+    }
+
+    pub fn is_local(&self, faction: Faction) -> bool {
+        self.controller(faction) == Controller::Local
+    }
+
+    /// Whether two people share this device, one a side.
+    pub fn hotseat(&self) -> bool {
+        self.player == Controller::Local && self.enemy == Controller::Local
+    }
+
+    /// Whether the other side is played over the network.
+    pub fn online(&self) -> bool {
+        self.player == Controller::Remote || self.enemy == Controller::Remote
+    }
+
+    /// The side this device plays, or watches when the AI plays both: the
+    /// one the screen starts each battle on, and whose win is a victory.
+    pub fn home(&self) -> Faction {
+        if self.player != Controller::Local && self.enemy == Controller::Local {
+            Faction::Enemy
+        } else {
+            Faction::Player
+        }
+    }
+}
+
+/// The side the screen is showing: whose units can be ordered, and whose
+/// sight the fog follows.
+#[cfg(feature = "render")]
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewer(pub Faction);
+
+#[cfg(feature = "render")]
+impl Default for Viewer {
+    fn default() -> Self {
+        Self(Faction::Player)
+    }
+}
+
+/// The screen covering the board until the next player takes the device.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct HandoverScreen;
+
+#[cfg(feature = "render")]
+pub fn reset_viewer(seats: Res<Seats>, mut viewer: ResMut<Viewer>) {
+    *viewer = Viewer(seats.home());
+}
+
+/// Turns the screen over to each local side as its turn begins, behind a
+/// handover screen in hotseat with fog of war on.
+#[cfg(feature = "render")]
+pub fn handover_system(
+    mut commands: Commands,
+    input: ActionInput,
+    seats: Res<Seats>,
+    setup: Res<MatchSetup>,
+    turn: Res<CurrentTurn>,
+    outcome: Res<BattleOutcome>,
+    mut viewer: ResMut<Viewer>,
+    bindings: Res<InputMap>,
+    locale: Res<Locale>,
+    screens: Query<(Entity, &Interaction), With<HandoverScreen>>,
+) {
+    if let Ok((screen, interaction)) = screens.single() {
+        let taken = *interaction == Interaction::Pressed
+            || input.just_pressed(InputAction::Select)
+            || input.just_pressed(InputAction::Confirm);
+        if taken {
+            commands.entity(screen).despawn();
+            viewer.set_if_neq(Viewer(turn.faction));
+        }
+        return;
+    }
+
+    let side = turn.faction;
+    if side == viewer.0 || !seats.is_local(side) || outcome.0.is_some() {
+        return;
+    }
+    if !seats.hotseat() || !setup.fog_of_war {
+        viewer.0 = side;
+        return;
+    }
+    commands
+        .spawn((
+            HandoverScreen,
+            Button,
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(Color::BLACK),
+            GlobalZIndex(10),
+            DespawnOnExit(AppState::GamePlay),
+        ))
+        .with_child((
+            Text::new(locale.fill(
+                "{} player's turn\nPass the device, then press {} or click to continue",
+                &[
+                    &locale.tr(&format!("{side:?}")),
+                    &bindings.hint(InputAction::Confirm),
+                ],
+            )),
+            TextFont {
+                font_size: 28.0,
+                ..default()
+            },
+            TextLayout::new_with_justify(Justify::Center),
+            TextColor(Color::WHITE),
+        ));
+}
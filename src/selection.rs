@@ -0,0 +1,738 @@
+//! Player input during a live battle: selecting units and ordering them around.
+//!
+//! These systems act on [`GameAction`]s rather than raw input. None of them
+//! touch unit state directly; they only write [`GameCommand`]s for the
+//! executor to apply, checking moves and attacks against the same
+//! [`rules`](crate::rules) the executor does.
+
+use std::collections::HashSet;
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::ability::{area, check_target, AbilityCatalog};
+use crate::action_menu::ActionMenuMode;
+use crate::class::UnitClass;
+use crate::command::GameCommand;
+use crate::constants::*;
+use crate::contrast::{Bordered, Thickened};
+use crate::grid::{GridMap, GridPosition, PathCache};
+use crate::input::{GameAction, GridCursor};
+use crate::items::{can_attack, effective_stats, Equipment, ItemCatalog};
+use crate::leader::AuraBonus;
+use crate::rescue::Rescue;
+use crate::rules::{validate_attack, validate_move, AttackAttempt};
+use crate::seats::Viewer;
+use crate::state::AppState;
+use crate::status::StatusEffects;
+use crate::stealth::Stealth;
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::{
+    AttackRange, Faction, FactionPalette, MoveRange, Stats, TurnStatus, Unit, UnitId, UnitIndex,
+};
+use crate::vision::FactionVision;
+
+/// The player unit currently receiving orders, if any.
+#[derive(Resource, Default)]
+pub struct SelectedUnit(pub Option<Entity>);
+
+#[derive(Component)]
+pub struct SelectionRing;
+
+/// Tint over the tile under the cursor, showing where a click will land.
+#[derive(Component)]
+pub struct HoverHighlight;
+
+#[derive(Component, Default)]
+pub struct MovementHighlight;
+
+/// Overlay showing where the ability being aimed can be used.
+#[derive(Component, Default)]
+pub struct AbilityHighlight;
+
+/// Overlay previewing the range of the enemy under the cursor.
+#[derive(Component, Default)]
+pub struct EnemyRangeHighlight;
+
+type HighlightPool<'w, 's, M> = Query<
+    'w,
+    's,
+    (
+        &'static mut Sprite,
+        &'static mut Transform,
+        &'static mut Visibility,
+    ),
+    With<M>,
+>;
+
+/// Draws an overlay over `tiles` with the highlight entities marked `M`.
+/// Highlights are kept between redraws rather than despawned: each redraw
+/// moves and recolors the ones it needs, spawns more only once those run
+/// out, and hides the rest.
+fn draw_highlights<M: Component + Default>(
+    commands: &mut Commands,
+    pool: &mut HighlightPool<M>,
+    tiles: impl IntoIterator<Item = (GridPosition, Color)>,
+) {
+    let mut pool = pool.iter_mut();
+    for (pos, color) in tiles {
+        let translation = pos.to_world(HIGHLIGHT_Z);
+        match pool.next() {
+            Some((mut sprite, mut transform, mut visibility)) => {
+                sprite.color = color;
+                transform.translation = translation;
+                *visibility = Visibility::Inherited;
+            }
+            None => {
+                commands.spawn((
+                    M::default(),
+                    Bordered,
+                    Sprite::from_color(color, Vec2::splat(TILE_SIZE)),
+                    Transform::from_translation(translation),
+                    DespawnOnExit(AppState::GamePlay),
+                ));
+            }
+        }
+    }
+    for (_, _, mut visibility) in pool {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+/// The tile under the mouse cursor, if the cursor is inside the window.
+pub fn cursor_grid_position(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<GridPosition> {
+    let cursor = window.cursor_position()?;
+    let world = camera.viewport_to_world_2d(camera_transform, cursor).ok()?;
+    Some(GridPosition::from_world(world))
+}
+
+/// Whether the pointer is over a UI button, in which case board clicks are ignored.
+pub fn pointer_over_ui(interactions: &Query<&Interaction>) -> bool {
+    interactions
+        .iter()
+        .any(|interaction| *interaction != Interaction::None)
+}
+
+/// Whether the side on screen is the one taking its turn.
+fn player_can_act(turn: &CurrentTurn, outcome: &BattleOutcome, viewer: &Viewer) -> bool {
+    turn.faction == viewer.0 && outcome.0.is_none()
+}
+
+/// Whether a unit of the side on screen can still be picked to receive
+/// orders.
+fn ready(viewer: &Viewer, faction: Faction, status: &TurnStatus, rescue: &Rescue) -> bool {
+    faction == viewer.0 && !status.has_acted && !rescue.is_carried()
+}
+
+pub fn selection_system(
+    mut actions: MessageReader<GameAction>,
+    mode: Res<ActionMenuMode>,
+    turn: Res<CurrentTurn>,
+    outcome: Res<BattleOutcome>,
+    viewer: Res<Viewer>,
+    mut selected: ResMut<SelectedUnit>,
+    mut cursor: ResMut<GridCursor>,
+    index: Res<UnitIndex>,
+    units: Query<
+        (
+            Entity,
+            &UnitId,
+            &GridPosition,
+            &Faction,
+            &TurnStatus,
+            &Rescue,
+        ),
+        With<Unit>,
+    >,
+) {
+    // Drop the selection once the unit is gone or has finished its turn.
+    if let Some(entity) = selected.0 {
+        let finished = units
+            .get(entity)
+            .map_or(true, |(.., status, _)| status.has_acted);
+        if finished || !player_can_act(&turn, &outcome, &viewer) {
+            selected.0 = None;
+        }
+    }
+
+    for action in actions.read() {
+        match *action {
+            GameAction::Cancel => selected.0 = None,
+            // Picking a tile while aiming picks the ability's target instead.
+            GameAction::SelectAt(tile)
+                if player_can_act(&turn, &outcome, &viewer) && mode.aiming().is_none() =>
+            {
+                let clicked_unit = index
+                    .at(tile)
+                    .and_then(|entity| units.get(entity).ok())
+                    .filter(|(.., faction, status, rescue)| {
+                        ready(&viewer, **faction, status, rescue)
+                    });
+                if let Some((entity, ..)) = clicked_unit {
+                    selected.0 = Some(entity);
+                }
+            }
+            GameAction::CycleUnit { forward } if player_can_act(&turn, &outcome, &viewer) => {
+                let mut ready_units: Vec<_> = units
+                    .iter()
+                    .filter(|(.., faction, status, rescue)| {
+                        ready(&viewer, **faction, status, rescue)
+                    })
+                    .map(|(entity, id, pos, ..)| (*id, entity, *pos))
+                    .collect();
+                if ready_units.is_empty() {
+                    continue;
+                }
+                ready_units.sort_by_key(|(id, ..)| *id);
+                let count = ready_units.len();
+                let current = selected
+                    .0
+                    .and_then(|entity| ready_units.iter().position(|(_, e, _)| *e == entity));
+                let next = match (current, forward) {
+                    (Some(index), true) => (index + 1) % count,
+                    (Some(index), false) => (index + count - 1) % count,
+                    (None, true) => 0,
+                    (None, false) => count - 1,
+                };
+                let (_, entity, pos) = ready_units[next];
+                selected.0 = Some(entity);
+                cursor.0 = Some(pos);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Turns board actions into move and attack orders for the selected unit.
+/// Clicks the rules don't allow are ignored.
+pub fn movement_system(
+    mut actions: MessageReader<GameAction>,
+    mode: Res<ActionMenuMode>,
+    selected: Res<SelectedUnit>,
+    vision: Res<FactionVision>,
+    viewer: Res<Viewer>,
+    map: Res<GridMap>,
+    catalog: Res<ItemCatalog>,
+    index: Res<UnitIndex>,
+    units: Query<
+        (
+            Entity,
+            &UnitId,
+            &GridPosition,
+            &Faction,
+            &Stats,
+            &Stealth,
+            &Rescue,
+        ),
+        With<Unit>,
+    >,
+    movers: Query<(
+        &TurnStatus,
+        &MoveRange,
+        &AttackRange,
+        &UnitClass,
+        &Equipment,
+        &StatusEffects,
+        &AuraBonus,
+    )>,
+    mut orders: MessageWriter<GameCommand>,
+) {
+    for action in actions.read() {
+        let GameAction::SelectAt(clicked) = *action else {
+            continue;
+        };
+        let Some(selected_entity) = selected.0.filter(|_| mode.aiming().is_none()) else {
+            continue;
+        };
+        let Ok((_, &unit, &from, &faction, stats, _, rescue)) = units.get(selected_entity) else {
+            continue;
+        };
+        let Ok((status, move_range, &attack_range, class, equipment, effects, aura)) =
+            movers.get(selected_entity)
+        else {
+            continue;
+        };
+        if clicked == from {
+            continue;
+        }
+
+        // Enemies hidden in the fog are treated as empty ground.
+        let on_board = index
+            .at(clicked)
+            .and_then(|entity| units.get(entity).ok())
+            .filter(|(entity, _, _, faction, ..)| vision.can_see(viewer.0, *entity, **faction));
+        match on_board {
+            Some((_, &target, &at, &target_faction, _, stealth, _))
+                if target_faction != faction =>
+            {
+                let (_, range) =
+                    effective_stats(stats, attack_range, equipment, effects, aura, &catalog);
+                let allowed = validate_attack(AttackAttempt {
+                    faction,
+                    status,
+                    armed: can_attack(*class, equipment),
+                    range,
+                    from,
+                    target: target_faction,
+                    hidden: stealth.hidden,
+                    at,
+                });
+                if allowed.is_ok() {
+                    orders.write(GameCommand::Attack {
+                        attacker: unit,
+                        target,
+                    });
+                }
+            }
+            // Clicking a friendly unit is handled as a selection change.
+            Some(_) => {}
+            None => {
+                let occupied = index.occupied_except(from);
+                let range = rescue.move_range(*move_range);
+                if validate_move(&map, &occupied, from, range, status, clicked).is_ok() {
+                    orders.write(GameCommand::Move { unit, to: clicked });
+                }
+            }
+        }
+    }
+}
+
+/// The side of the unit on `tile` as the viewer sees it: enemies hidden in
+/// the fog are treated as empty ground.
+fn visible_side(
+    tile: GridPosition,
+    index: &UnitIndex,
+    vision: &FactionVision,
+    viewer: &Viewer,
+    factions: &Query<&Faction, With<Unit>>,
+) -> Option<Faction> {
+    let entity = index.at(tile)?;
+    let faction = *factions.get(entity).ok()?;
+    vision.can_see(viewer.0, entity, faction).then_some(faction)
+}
+
+/// Uses the ability being aimed on the tile picked, when it can be used
+/// there, and goes back to the main menu.
+pub fn aim_ability_system(
+    mut actions: MessageReader<GameAction>,
+    mut mode: ResMut<ActionMenuMode>,
+    selected: Res<SelectedUnit>,
+    vision: Res<FactionVision>,
+    viewer: Res<Viewer>,
+    map: Res<GridMap>,
+    abilities: Res<AbilityCatalog>,
+    index: Res<UnitIndex>,
+    units: Query<(&UnitId, &GridPosition, &Faction, &UnitClass), With<Unit>>,
+    factions: Query<&Faction, With<Unit>>,
+    mut orders: MessageWriter<GameCommand>,
+) {
+    let Some(slot) = mode.aiming() else {
+        return;
+    };
+    let Some((&unit, &from, &faction, &class)) =
+        selected.0.and_then(|entity| units.get(entity).ok())
+    else {
+        return;
+    };
+    let Some((_, ability)) = abilities.slot(class, slot) else {
+        return;
+    };
+    for action in actions.read() {
+        let GameAction::SelectAt(at) = *action else {
+            continue;
+        };
+        let target = visible_side(at, &index, &vision, &viewer, &factions);
+        if check_target(ability, &map, from, faction, at, target).is_ok() {
+            orders.write(GameCommand::UseAbility { unit, slot, at });
+            *mode = ActionMenuMode::Main;
+            return;
+        }
+    }
+}
+
+pub fn end_turn_input_system(
+    mut actions: MessageReader<GameAction>,
+    turn: Res<CurrentTurn>,
+    outcome: Res<BattleOutcome>,
+    viewer: Res<Viewer>,
+    mut selected: ResMut<SelectedUnit>,
+    mut orders: MessageWriter<GameCommand>,
+) {
+    let end_turn = actions.read().any(|action| *action == GameAction::EndTurn);
+    if end_turn && player_can_act(&turn, &outcome, &viewer) {
+        selected.0 = None;
+        orders.write(GameCommand::EndTurn);
+    }
+}
+
+pub fn clear_selection(mut selected: ResMut<SelectedUnit>) {
+    selected.0 = None;
+}
+
+pub fn setup_selection_ring(mut commands: Commands) {
+    let half = TILE_SIZE / 2.0 - SELECTION_CORNER_WIDTH / 2.0;
+    let arm = SELECTION_CORNER_LENGTH / 2.0 - SELECTION_CORNER_WIDTH / 2.0;
+    commands
+        .spawn((
+            SelectionRing,
+            Transform::from_xyz(0.0, 0.0, SELECTION_Z),
+            Visibility::Hidden,
+        ))
+        .with_children(|ring| {
+            // An L-shaped bracket in each corner of the tile.
+            for (sx, sy) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+                let corner = Vec2::new(sx * half, sy * half);
+                let bars = [
+                    (
+                        Vec2::new(corner.x - sx * arm, corner.y),
+                        Vec2::new(SELECTION_CORNER_LENGTH, SELECTION_CORNER_WIDTH),
+                    ),
+                    (
+                        Vec2::new(corner.x, corner.y - sy * arm),
+                        Vec2::new(SELECTION_CORNER_WIDTH, SELECTION_CORNER_LENGTH),
+                    ),
+                ];
+                for (center, size) in bars {
+                    ring.spawn((
+                        Thickened(size),
+                        Sprite::from_color(SELECTION_COLOR, size),
+                        Transform::from_translation(center.extend(0.0)),
+                    ));
+                }
+            }
+        });
+}
+
+pub fn has_selection(selected: Res<SelectedUnit>) -> bool {
+    selected.0.is_some()
+}
+
+/// Keeps the selection indicator on the selected unit, pulsing and turning
+/// it as set in the constants.
+pub fn update_selection_ring(
+    time: Res<Time>,
+    selected: Res<SelectedUnit>,
+    units: Query<&Transform, With<Unit>>,
+    mut ring: Single<(&mut Transform, &mut Visibility), (With<SelectionRing>, Without<Unit>)>,
+) {
+    let (transform, visibility) = &mut *ring;
+    match selected.0.and_then(|entity| units.get(entity).ok()) {
+        Some(unit) => {
+            let elapsed = time.elapsed_secs();
+            let pulse = (elapsed * TAU / SELECTION_PULSE_SECONDS).sin() * 0.5 + 0.5;
+            transform.translation = unit.translation.with_z(SELECTION_Z);
+            transform.scale = Vec3::splat(1.0 + SELECTION_PULSE_SCALE * pulse);
+            transform.rotation = Quat::from_rotation_z(elapsed * SELECTION_SPIN_SPEED);
+            **visibility = Visibility::Visible;
+        }
+        None => **visibility = Visibility::Hidden,
+    }
+}
+
+pub fn setup_hover_highlight(mut commands: Commands) {
+    commands.spawn((
+        HoverHighlight,
+        Bordered,
+        Sprite::from_color(HOVER_COLOR, Vec2::splat(TILE_SIZE)),
+        Transform::from_xyz(0.0, 0.0, HOVER_Z),
+        Visibility::Hidden,
+    ));
+}
+
+/// Moves the hover tint onto the cursor's tile, hiding it off the board or
+/// while the mouse is over a UI button.
+pub fn update_hover_highlight(
+    cursor: Res<GridCursor>,
+    map: Res<GridMap>,
+    interactions: Query<&Interaction>,
+    mut highlight: Single<(&mut Transform, &mut Visibility), With<HoverHighlight>>,
+) {
+    let (transform, visibility) = &mut *highlight;
+    let hovered = cursor
+        .0
+        .filter(|pos| map.tile(*pos).is_some() && !pointer_over_ui(&interactions));
+    match hovered {
+        Some(pos) => {
+            transform.translation = pos.to_world(HOVER_Z);
+            visibility.set_if_neq(Visibility::Visible);
+        }
+        None => {
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+    }
+}
+
+/// Redraws the movement, attack, and heal overlays whenever the selection or the
+/// board changes. They make way for the ability overlay while one is aimed.
+pub fn highlight_movement_system(
+    mut commands: Commands,
+    selected: Res<SelectedUnit>,
+    mode: Res<ActionMenuMode>,
+    map: Res<GridMap>,
+    catalog: Res<ItemCatalog>,
+    index: Res<UnitIndex>,
+    mut paths: ResMut<PathCache>,
+    units: Query<
+        (
+            Entity,
+            &UnitId,
+            &GridPosition,
+            &Faction,
+            &Stats,
+            &TurnStatus,
+            &MoveRange,
+            &AttackRange,
+            &Equipment,
+            &StatusEffects,
+            &AuraBonus,
+            &UnitClass,
+            &Rescue,
+        ),
+        With<Unit>,
+    >,
+    changed: Query<
+        (),
+        (
+            With<Unit>,
+            Or<(
+                Changed<GridPosition>,
+                Changed<TurnStatus>,
+                Changed<Stats>,
+                Changed<Equipment>,
+                Changed<StatusEffects>,
+                Changed<AuraBonus>,
+                Changed<Rescue>,
+            )>,
+        ),
+    >,
+    mut highlights: HighlightPool<MovementHighlight>,
+    (vision, palette): (Res<FactionVision>, Res<FactionPalette>),
+) {
+    if !selected.is_changed()
+        && !mode.is_changed()
+        && changed.is_empty()
+        && !vision.is_changed()
+        && !palette.is_changed()
+    {
+        return;
+    }
+
+    let mut tiles = Vec::new();
+    let shown = selected.0.filter(|_| mode.aiming().is_none());
+    if let Some(Ok((
+        entity,
+        &id,
+        &from,
+        &faction,
+        stats,
+        status,
+        move_range,
+        &attack_range,
+        equipment,
+        effects,
+        aura,
+        class,
+        rescue,
+    ))) = shown.map(|entity| units.get(entity))
+    {
+        let (_, attack_range) =
+            effective_stats(stats, attack_range, equipment, effects, aura, &catalog);
+        let armed = can_attack(*class, equipment);
+
+        if !status.has_moved {
+            let range = rescue.move_range(*move_range);
+            for &pos in paths.reachable(&map, &index, id, from, range) {
+                if pos != from {
+                    tiles.push((pos, palette.moves));
+                }
+            }
+        }
+        if !status.has_acted {
+            for (other, _, &pos, &other_faction, .., other_rescue) in &units {
+                if other_faction != faction
+                    && armed
+                    && !other_rescue.is_carried()
+                    && vision.can_see(faction, other, other_faction)
+                    && from.distance(pos) <= attack_range.0
+                {
+                    tiles.push((pos, palette.attacks));
+                }
+            }
+            if let Some(heal_range) = class.heal_range() {
+                for (other, _, &pos, &other_faction, other_stats, .., other_rescue) in &units {
+                    let wounded = other_stats.hp < other_stats.max_hp;
+                    if other != entity
+                        && other_faction == faction
+                        && !other_rescue.is_carried()
+                        && wounded
+                        && from.distance(pos) <= heal_range
+                    {
+                        tiles.push((pos, palette.heals));
+                    }
+                }
+            }
+        }
+    }
+    draw_highlights(&mut commands, &mut highlights, tiles);
+}
+
+/// Shows where the ability being aimed can be used, and the area it would
+/// cover used on the tile under the cursor.
+pub fn highlight_ability_system(
+    mut commands: Commands,
+    mode: Res<ActionMenuMode>,
+    selected: Res<SelectedUnit>,
+    cursor: Res<GridCursor>,
+    vision: Res<FactionVision>,
+    viewer: Res<Viewer>,
+    map: Res<GridMap>,
+    abilities: Res<AbilityCatalog>,
+    index: Res<UnitIndex>,
+    units: Query<(&GridPosition, &Faction, &UnitClass), With<Unit>>,
+    factions: Query<&Faction, With<Unit>>,
+    mut highlights: HighlightPool<AbilityHighlight>,
+) {
+    let aimed = mode.aiming().zip(selected.0).and_then(|(slot, entity)| {
+        let (&from, &faction, &class) = units.get(entity).ok()?;
+        let (_, ability) = abilities.slot(class, slot)?;
+        Some((from, faction, ability))
+    });
+    let Some((from, faction, ability)) = aimed else {
+        draw_highlights(&mut commands, &mut highlights, []);
+        return;
+    };
+
+    let usable = |at: GridPosition| {
+        let target = visible_side(at, &index, &vision, &viewer, &factions);
+        check_target(ability, &map, from, faction, at, target).is_ok()
+    };
+    let reach = ability.range as i32;
+    let mut tiles: Vec<(GridPosition, Color)> = (-reach..=reach)
+        .flat_map(|dy| (-reach..=reach).map(move |dx| GridPosition::new(from.x + dx, from.y + dy)))
+        .chain([from])
+        .filter(|&at| usable(at))
+        .map(|at| (at, ABILITY_RANGE_COLOR))
+        .collect();
+    if let Some(at) = cursor.0.filter(|&at| usable(at)) {
+        tiles.extend(
+            area(ability.shape, &map, from, at)
+                .into_iter()
+                .map(|pos| (pos, ABILITY_AREA_COLOR)),
+        );
+    }
+    draw_highlights(&mut commands, &mut highlights, tiles);
+}
+
+/// Previews where the enemy under the cursor could move and attack next
+/// turn, using the same reachable-tile search as the player's own overlay.
+pub fn highlight_enemy_range_system(
+    mut commands: Commands,
+    mut shown: Local<Option<Entity>>,
+    cursor: Res<GridCursor>,
+    map: Res<GridMap>,
+    catalog: Res<ItemCatalog>,
+    index: Res<UnitIndex>,
+    mut paths: ResMut<PathCache>,
+    vision: Res<FactionVision>,
+    viewer: Res<Viewer>,
+    units: Query<
+        (
+            Entity,
+            &UnitId,
+            &GridPosition,
+            &Faction,
+            &Stats,
+            &MoveRange,
+            &AttackRange,
+            &Equipment,
+            &StatusEffects,
+            &AuraBonus,
+            &UnitClass,
+            &Rescue,
+        ),
+        With<Unit>,
+    >,
+    changed: Query<
+        (),
+        (
+            With<Unit>,
+            Or<(
+                Changed<GridPosition>,
+                Changed<Stats>,
+                Changed<Equipment>,
+                Changed<StatusEffects>,
+                Changed<AuraBonus>,
+                Changed<Rescue>,
+            )>,
+        ),
+    >,
+    mut highlights: HighlightPool<EnemyRangeHighlight>,
+) {
+    let hovered = cursor
+        .0
+        .and_then(|cursor| index.at(cursor))
+        .filter(|&entity| {
+            units.get(entity).is_ok_and(|(_, _, _, &faction, ..)| {
+                faction != viewer.0 && vision.can_see(viewer.0, entity, faction)
+            })
+        });
+    if hovered == *shown && changed.is_empty() && !map.is_changed() && !viewer.is_changed() {
+        return;
+    }
+    *shown = hovered;
+
+    let Some(Ok((
+        _,
+        &id,
+        &from,
+        _,
+        stats,
+        move_range,
+        &attack_range,
+        equipment,
+        effects,
+        aura,
+        class,
+        rescue,
+    ))) = hovered.map(|entity| units.get(entity))
+    else {
+        draw_highlights(&mut commands, &mut highlights, []);
+        return;
+    };
+    let (_, attack_range) =
+        effective_stats(stats, attack_range, equipment, effects, aura, &catalog);
+
+    let reachable = paths.reachable(&map, &index, id, from, rescue.move_range(*move_range));
+    let mut threatened = HashSet::new();
+    if can_attack(*class, equipment) {
+        let reach = attack_range.0 as i32;
+        for tile in reachable {
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    let target = GridPosition::new(tile.x + dx, tile.y + dy);
+                    if map.in_bounds(target)
+                        && tile.distance(target) <= attack_range.0
+                        && !reachable.contains(&target)
+                    {
+                        threatened.insert(target);
+                    }
+                }
+            }
+        }
+    }
+
+    let tiles = reachable
+        .iter()
+        .filter(|tile| **tile != from)
+        .map(|tile| (*tile, ENEMY_MOVE_HIGHLIGHT_COLOR))
+        .chain(
+            threatened
+                .into_iter()
+                .map(|tile| (tile, ENEMY_ATTACK_HIGHLIGHT_COLOR)),
+        );
+    draw_highlights(&mut commands, &mut highlights, tiles);
+}
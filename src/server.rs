@@ -0,0 +1,303 @@
+//! The dedicated server: the battle run on its own, with both players
+//! joining it as they would join a host.
+//!
+//! `bevy-game-server --port 7777` waits for two players to `--join` it,
+//! seats the first at the player's side and the second at the enemy's, and
+//! keeps the authoritative copy of the battle. A command is only taken from
+//! the player whose turn it is, and goes through the same executor, and so
+//! the same rules, as on their own copy; every command it accepts goes to
+//! the other player. At the end of each turn both are sent the digest of
+//! the server's board, so a copy that drifted finds out, and any player who
+//! asks gets the whole board back. If a player drops, the AI takes over
+//! their side here and plays it against the one who is left, until they
+//! join again with the session token they were given and are caught up to
+//! take it back.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::TcpListener;
+
+use bevy::prelude::*;
+
+use crate::ai::AiLevel;
+use crate::command::{execute_commands, CommandLog, GameCommand};
+use crate::lobby::MatchSetup;
+use crate::net::{session_token, NetLink, NetMessage, Rejoining};
+use crate::plugins::GameSet;
+use crate::seats::{Controller, Seats};
+use crate::snapshot::{SyncSnapshot, SyncSources};
+use crate::state::AppState;
+use crate::turn::CurrentTurn;
+use crate::unit::Faction;
+
+/// A player connected to the server.
+pub struct Client {
+    link: NetLink,
+    side: Faction,
+    ready: bool,
+    /// Digests the player has sent that haven't been checked against the
+    /// server's board yet, oldest first.
+    checksums: VecDeque<(usize, u64)>,
+    /// The length of the log at the last end of turn where the player's
+    /// board matched the server's; it won't ask for a board before then.
+    acked: usize,
+}
+
+impl Client {
+    fn new(link: NetLink, side: Faction, ready: bool, acked: usize) -> Self {
+        Self {
+            link,
+            side,
+            ready,
+            checksums: VecDeque::new(),
+            acked,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Server {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    /// Whether the players have been told to begin.
+    begun: bool,
+    /// How far into the [`CommandLog`] the players have been sent, and whose
+    /// turn the next command in it was issued on.
+    shared: usize,
+    side: Faction,
+    /// The board at the end of each turn, by the length of the log there,
+    /// for players whose digest doesn't match. Only the latest and those
+    /// some player hasn't matched yet are kept.
+    boards: HashMap<usize, SyncSnapshot>,
+    /// The token each side's player has to show to rejoin.
+    sessions: HashMap<Faction, u64>,
+    rejoining: Rejoining,
+}
+
+impl Server {
+    /// Waits on `port` for a player for each side, telling each the seed.
+    pub fn open(port: u16, seed: u64) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let mut clients = Vec::new();
+        let mut sessions = HashMap::new();
+        for side in [Faction::Player, Faction::Enemy] {
+            let session = session_token();
+            sessions.insert(side, session);
+            let mut link = NetLink::accept(&listener, seed, session)?;
+            // Each player is told the setup that seats them at this side,
+            // and that the host is ready.
+            link.send(NetMessage::Setup(setup_for(side)))?;
+            link.send(NetMessage::Ready(true))?;
+            clients.push(Client::new(link, side, false, 0));
+        }
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            clients,
+            begun: false,
+            shared: 0,
+            side: Faction::Player,
+            boards: HashMap::new(),
+            sessions,
+            rejoining: Rejoining::default(),
+        })
+    }
+
+    /// Sends `message` to every player but the one at `except`, dropping any
+    /// whose connection has failed.
+    fn send_all(&mut self, message: &NetMessage, except: Option<Faction>, seats: &mut Seats) {
+        self.clients.retain_mut(|client| {
+            if Some(client.side) == except {
+                return true;
+            }
+            match client.link.send(message.clone()) {
+                Ok(()) => true,
+                Err(err) => {
+                    hand_to_ai(seats, client.side, &err);
+                    false
+                }
+            }
+        });
+    }
+
+    /// Checks the digests the players have sent against the server's boards,
+    /// then forgets the boards every player has matched, but the latest.
+    fn forget_matched_boards(&mut self) {
+        for client in &mut self.clients {
+            while let Some(&(at, digest)) = client.checksums.front() {
+                match self.boards.get(&at) {
+                    Some(board) if board.digest() == digest => client.acked = at,
+                    // A player whose board drifted asks for the server's.
+                    Some(_) => break,
+                    // The server hasn't reached the end of that turn yet.
+                    None if at > self.shared => break,
+                    None => {}
+                }
+                client.checksums.pop_front();
+            }
+        }
+        let Some(acked) = self.clients.iter().map(|client| client.acked).min() else {
+            return;
+        };
+        let latest = self.boards.keys().max().copied();
+        self.boards
+            .retain(|&at, _| at > acked || Some(at) == latest);
+    }
+}
+
+/// The setup that seats a player at `side`: the host, as they see it,
+/// plays the other.
+fn setup_for(side: Faction) -> MatchSetup {
+    MatchSetup {
+        host_side: side.opponent(),
+        ..default()
+    }
+}
+
+fn hand_to_ai(seats: &mut Seats, side: Faction, err: &io::Error) {
+    warn!("Lost the {side:?} player ({err}); the AI takes over their side");
+    seats.set(side, Controller::Ai(AiLevel::default()));
+}
+
+/// Running the battle for the players connected to the [`Server`].
+pub struct ServerPlugin;
+
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        let serving = resource_exists::<Server>.and(in_state(AppState::GamePlay));
+        app.add_systems(
+            Update,
+            serve_clients_system
+                .run_if(serving.clone())
+                .in_set(GameSet::Input),
+        )
+        .add_systems(
+            Update,
+            broadcast_commands_system
+                .run_if(serving)
+                .after(execute_commands)
+                .in_set(GameSet::Logic),
+        );
+    }
+}
+
+/// Takes each player's commands on their own turn, passes chat between
+/// them, answers players whose boards have drifted, tells both to begin
+/// once they are ready, and seats players rejoining at the side left empty.
+pub fn serve_clients_system(
+    mut server: ResMut<Server>,
+    mut seats: ResMut<Seats>,
+    log: Res<CommandLog>,
+    turn: Res<CurrentTurn>,
+    mut orders: MessageWriter<GameCommand>,
+    mut exit: MessageWriter<AppExit>,
+) {
+    let server = &mut *server;
+    let mut chat = Vec::new();
+    server.clients.retain_mut(|client| {
+        let messages = match client.link.receive() {
+            Ok(messages) => messages,
+            Err(err) => {
+                hand_to_ai(&mut seats, client.side, &err);
+                return false;
+            }
+        };
+        for message in messages {
+            match message {
+                NetMessage::Ready(ready) => client.ready = ready,
+                NetMessage::Command(command) if server.begun && turn.faction == client.side => {
+                    orders.write(command);
+                }
+                NetMessage::Command(command) => {
+                    warn!(
+                        "Ignored {command:?} from the {:?} player out of turn",
+                        client.side
+                    );
+                }
+                NetMessage::Checksum { at, digest } => client.checksums.push_back((at, digest)),
+                NetMessage::Board { at, .. } => {
+                    error!(
+                        "The {:?} player's board differs from the server's after {at} commands",
+                        client.side
+                    );
+                    if let Some(board) = server.boards.get(&at) {
+                        let reply = NetMessage::Board {
+                            at,
+                            board: board.to_ron(),
+                        };
+                        if let Err(err) = client.link.send(reply) {
+                            hand_to_ai(&mut seats, client.side, &err);
+                            return false;
+                        }
+                    }
+                }
+                NetMessage::Chat(line) => chat.push((client.side, NetMessage::Chat(line))),
+                _ => {}
+            }
+        }
+        true
+    });
+    for (from, line) in chat {
+        server.send_all(&line, Some(from), &mut seats);
+    }
+
+    let empty = [Faction::Player, Faction::Enemy]
+        .into_iter()
+        .find(|&side| server.clients.iter().all(|client| client.side != side));
+    if let Some(side) = empty.filter(|_| server.begun) {
+        let session = server.sessions[&side];
+        let polled = server
+            .rejoining
+            .poll(&server.listener, session, setup_for(side), &log);
+        match polled {
+            Ok(Some(link)) => {
+                info!("A player rejoined at the {side:?} side");
+                seats.set(side, Controller::Remote);
+                // Caught up on the whole log, they only check what follows.
+                server
+                    .clients
+                    .push(Client::new(link, side, true, log.commands.len()));
+            }
+            Ok(None) => {}
+            Err(err) => warn!("A player failed to rejoin: {err}"),
+        }
+    }
+
+    if server.clients.is_empty() {
+        info!("Both players have left");
+        exit.write(AppExit::Success);
+        return;
+    }
+    if !server.begun && server.clients.iter().all(|client| client.ready) {
+        server.begun = true;
+        server.send_all(&NetMessage::Begin, None, &mut seats);
+    }
+}
+
+/// Sends every command the executor accepted to the player who didn't issue
+/// it, and the board's digest to both at the end of each turn, keeping the
+/// board until both have matched it or a later one.
+pub fn broadcast_commands_system(
+    mut server: ResMut<Server>,
+    mut seats: ResMut<Seats>,
+    log: Res<CommandLog>,
+    sources: SyncSources,
+) {
+    while let Some(&command) = log.commands.get(server.shared) {
+        let side = server.side;
+        server.send_all(&NetMessage::Command(command), Some(side), &mut seats);
+        if command == GameCommand::EndTurn {
+            server.side = side.opponent();
+        }
+        server.shared += 1;
+    }
+
+    let at = log.commands.len();
+    if log.commands.last() == Some(&GameCommand::EndTurn) && !server.boards.contains_key(&at) {
+        let board = sources.snapshot();
+        let digest = board.digest();
+        server.send_all(&NetMessage::Checksum { at, digest }, None, &mut seats);
+        server.boards.insert(at, board);
+    }
+    server.forget_matched_boards();
+}
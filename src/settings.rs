@@ -0,0 +1,342 @@
+//! Player settings, persisted to the platform config directory.
+//!
+//! Settings are loaded once at startup and written back to disk whenever the
+//! [`Settings`] resource changes, so adjustments survive restarts.
+
+use std::fs;
+use std::path::PathBuf;
+
+use bevy::audio::{GlobalVolume, Volume};
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::ai::AiPacing;
+use crate::bindings::{ActionInput, InputAction, InputMap};
+use crate::constants::{ENEMY_ACTION_DELAY, ENEMY_TURN_DELAY, PHASE_BANNER_SECONDS};
+use crate::contrast::HighContrast;
+use crate::launch::LaunchOptions;
+use crate::locale::{Translations, ENGLISH};
+use crate::unit::FactionPalette;
+
+const MIN_UI_SCALE: f32 = 0.5;
+const MAX_UI_SCALE: f32 = 2.0;
+/// Vision range multipliers the skirmish setting cycles through.
+const VISION_SCALES: [f32; 5] = [0.5, 0.75, 1.0, 1.25, 1.5];
+/// Text size multipliers the text size setting cycles through.
+const TEXT_SCALES: [f32; 5] = [0.8, 1.0, 1.25, 1.5, 2.0];
+
+/// The set of faction colors units, structures, and the turn banner are
+/// drawn in, along with the range highlights. Each colorblind palette keeps
+/// the two sides, and moves and attacks, apart for one form of color
+/// blindness.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Classic,
+    /// Red and green look alike. Settings saved when this was the only
+    /// colorblind palette call it `Colorblind`.
+    #[serde(alias = "Colorblind")]
+    Deuteranopia,
+    /// Red and green look alike, and red looks dim.
+    Protanopia,
+    /// Blue and yellow look alike.
+    Tritanopia,
+}
+
+impl Palette {
+    pub fn next(self) -> Self {
+        match self {
+            Palette::Classic => Palette::Deuteranopia,
+            Palette::Deuteranopia => Palette::Protanopia,
+            Palette::Protanopia => Palette::Tritanopia,
+            Palette::Tritanopia => Palette::Classic,
+        }
+    }
+}
+
+/// Where written announcements of what happens in battle go.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnnouncementMode {
+    #[default]
+    Off,
+    /// A log on screen.
+    OnScreen,
+    /// The log on screen, read out by the system's screen reader as well.
+    ScreenReader,
+}
+
+impl AnnouncementMode {
+    pub fn next(self) -> Self {
+        match self {
+            AnnouncementMode::Off => AnnouncementMode::OnScreen,
+            AnnouncementMode::OnScreen => AnnouncementMode::ScreenReader,
+            AnnouncementMode::ScreenReader => AnnouncementMode::Off,
+        }
+    }
+}
+
+/// How long the game pauses at turn changes and for the AI, in seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pacing {
+    /// How long the AI waits as its turn begins.
+    pub enemy_turn: f32,
+    /// How long the phase banner stays up as a turn begins; 0 hides it.
+    pub banner: f32,
+    /// How long the AI waits before each of its actions.
+    pub ai_action: f32,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Self {
+            enemy_turn: ENEMY_TURN_DELAY,
+            banner: PHASE_BANNER_SECONDS,
+            ai_action: ENEMY_ACTION_DELAY,
+        }
+    }
+}
+
+impl Pacing {
+    /// The pacings the pace setting cycles through, by name.
+    const PRESETS: [(&'static str, Pacing); 3] = [
+        (
+            "Slow",
+            Pacing {
+                enemy_turn: ENEMY_TURN_DELAY * 2.0,
+                banner: PHASE_BANNER_SECONDS * 2.0,
+                ai_action: ENEMY_ACTION_DELAY * 2.5,
+            },
+        ),
+        (
+            "Normal",
+            Pacing {
+                enemy_turn: ENEMY_TURN_DELAY,
+                banner: PHASE_BANNER_SECONDS,
+                ai_action: ENEMY_ACTION_DELAY,
+            },
+        ),
+        (
+            "Fast",
+            Pacing {
+                enemy_turn: ENEMY_TURN_DELAY * 0.25,
+                banner: PHASE_BANNER_SECONDS * 0.5,
+                ai_action: ENEMY_ACTION_DELAY * 0.25,
+            },
+        ),
+    ];
+
+    /// The preset this pacing is, or `"Custom"` when it was set by hand in
+    /// the settings file.
+    pub fn name(self) -> &'static str {
+        Self::PRESETS
+            .into_iter()
+            .find(|(_, preset)| *preset == self)
+            .map_or("Custom", |(name, _)| name)
+    }
+
+    /// The AI's waits, never negative.
+    pub fn ai(self) -> AiPacing {
+        AiPacing {
+            turn_start: self.enemy_turn.max(0.0),
+            per_action: self.ai_action.max(0.0),
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Linear master volume, `0.0..=1.0`.
+    pub master_volume: f32,
+    /// Multiplier applied to all UI layout and text.
+    pub ui_scale: f32,
+    /// Multiplier applied to UI text alone, on top of `ui_scale`.
+    pub text_scale: f32,
+    /// Whether new battles hide what each side's units can't see. Each
+    /// battle keeps its own choice in its [`MatchSetup`](crate::lobby::MatchSetup).
+    pub fog_of_war: bool,
+    /// Multiplier applied to every unit's vision range.
+    pub vision_scale: f32,
+    pub palette: Palette,
+    /// Whether the board is drawn in high contrast.
+    pub high_contrast: bool,
+    /// Whether resting the cursor at the window edge pans the camera.
+    pub edge_scroll: bool,
+    /// Whether the camera glides to each enemy unit as it acts.
+    pub camera_follow: bool,
+    pub announcements: AnnouncementMode,
+    /// Whether each sound cue also flashes up on screen.
+    pub sound_indicators: bool,
+    pub pacing: Pacing,
+    /// The language menus and the HUD are shown in, by the name it calls
+    /// itself.
+    pub language: String,
+    /// Actions the player has rebound away from their defaults.
+    pub bindings: InputMap,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            ui_scale: 1.0,
+            text_scale: 1.0,
+            fog_of_war: true,
+            vision_scale: 1.0,
+            palette: Palette::default(),
+            high_contrast: false,
+            edge_scroll: true,
+            camera_follow: true,
+            announcements: AnnouncementMode::default(),
+            sound_indicators: false,
+            pacing: Pacing::default(),
+            language: ENGLISH.to_string(),
+            bindings: InputMap::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Moves on to the next vision range multiplier, wrapping around.
+    pub fn cycle_vision_scale(&mut self) {
+        self.vision_scale = VISION_SCALES
+            .into_iter()
+            .find(|scale| *scale > self.vision_scale)
+            .unwrap_or(VISION_SCALES[0]);
+    }
+
+    /// Moves on to the next pacing preset, wrapping around, or to the first
+    /// from a pacing set by hand.
+    pub fn cycle_pacing(&mut self) {
+        let presets = Pacing::PRESETS;
+        let current = presets
+            .iter()
+            .position(|(_, preset)| *preset == self.pacing);
+        self.pacing = presets[current.map_or(0, |index| (index + 1) % presets.len())].1;
+    }
+
+    /// Moves on to the next text size multiplier, wrapping around.
+    pub fn cycle_text_scale(&mut self) {
+        self.text_scale = TEXT_SCALES
+            .into_iter()
+            .find(|scale| *scale > self.text_scale)
+            .unwrap_or(TEXT_SCALES[0]);
+    }
+
+    /// Moves on to the next language in `translations`, wrapping around to
+    /// English.
+    pub fn cycle_language(&mut self, translations: &Translations) {
+        let mut names = translations
+            .names()
+            .skip_while(|name| *name != self.language);
+        self.language = names.nth(1).unwrap_or(ENGLISH).to_string();
+    }
+}
+
+/// `<config dir>/bevy-game/settings.ron`, e.g. `~/.config/bevy-game/settings.ron` on Linux.
+pub fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("bevy-game").join("settings.ron"))
+}
+
+/// Reads settings from disk, falling back to defaults if the file is missing or invalid.
+pub fn load_settings() -> Settings {
+    let Some(path) = settings_path() else {
+        return Settings::default();
+    };
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return Settings::default();
+    };
+    ron::from_str(&contents).unwrap_or_else(|err| {
+        warn!("Ignoring invalid settings file {}: {err}", path.display());
+        Settings::default()
+    })
+}
+
+pub fn save_settings(settings: &Settings) -> Result<(), String> {
+    let path = settings_path().ok_or("no config directory on this platform")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+    let contents = ron::ser::to_string_pretty(settings, PrettyConfig::default())
+        .map_err(|err| err.to_string())?;
+    fs::write(&path, contents).map_err(|err| err.to_string())
+}
+
+/// Shrinks and grows the UI and lowers and raises the master volume, on
+/// `-`/`=` and `[`/`]` by default.
+pub fn settings_hotkeys_system(input: ActionInput, mut settings: ResMut<Settings>) {
+    if input.just_pressed(InputAction::UiSmaller) {
+        settings.ui_scale = (settings.ui_scale - 0.1).max(MIN_UI_SCALE);
+    }
+    if input.just_pressed(InputAction::UiLarger) {
+        settings.ui_scale = (settings.ui_scale + 0.1).min(MAX_UI_SCALE);
+    }
+    if input.just_pressed(InputAction::VolumeDown) {
+        settings.master_volume = (settings.master_volume - 0.1).max(0.0);
+    }
+    if input.just_pressed(InputAction::VolumeUp) {
+        settings.master_volume = (settings.master_volume + 0.1).min(1.0);
+    }
+}
+
+pub fn apply_settings_system(
+    settings: Res<Settings>,
+    mut ui_scale: ResMut<UiScale>,
+    mut volume: ResMut<GlobalVolume>,
+    (mut palette, mut contrast): (ResMut<FactionPalette>, ResMut<HighContrast>),
+    mut bindings: ResMut<InputMap>,
+    (mut pacing, launch): (ResMut<AiPacing>, Res<LaunchOptions>),
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    // Headless runs keep the AI acting without a pause.
+    if !launch.headless {
+        pacing.set_if_neq(settings.pacing.ai());
+    }
+    ui_scale.0 = settings.ui_scale;
+    volume.volume = Volume::Linear(settings.master_volume);
+    contrast.set_if_neq(HighContrast(settings.high_contrast));
+    palette.set_if_neq(FactionPalette::new(settings.palette).with_contrast(*contrast));
+    bindings.set_if_neq(settings.bindings.clone());
+}
+
+pub fn save_settings_system(settings: Res<Settings>) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+    if let Err(err) = save_settings(&settings) {
+        error!("Failed to save settings: {err}");
+    }
+}
+
+/// The size a piece of UI text was spawned with, before the text size
+/// setting was applied to it.
+#[derive(Component)]
+pub struct BaseFontSize(pub f32);
+
+/// Sizes all UI text by the text size setting: new text as it appears, and
+/// all of it when the setting changes. Text is spawned at its normal size
+/// and never needs to know about the setting.
+pub fn scale_text_system(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut new: Query<(Entity, &mut TextFont), (With<Text>, Without<BaseFontSize>)>,
+    mut sized: Query<(&BaseFontSize, &mut TextFont), With<Text>>,
+) {
+    let scale = settings.text_scale;
+    for (entity, mut font) in &mut new {
+        commands.entity(entity).insert(BaseFontSize(font.font_size));
+        font.font_size *= scale;
+    }
+    if settings.is_changed() {
+        for (base, mut font) in &mut sized {
+            let size = base.0 * scale;
+            if font.font_size != size {
+                font.font_size = size;
+            }
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! A canonical picture of the battle for tests, and the two ends of an
+//! online battle, to compare.
+//!
+//! A [`BoardSnapshot`] holds the turn, the outcome, and every unit on the
+//! board in id order, and prints as RON with one field per line, so a
+//! stored snapshot diffs cleanly against a new one and a change anywhere on
+//! the board shows up as the lines that moved. Its [digest](BoardSnapshot::digest)
+//! stands in for the whole board where only sameness matters.
+//!
+//! Online battles compare a [`SyncSnapshot`] instead, which adds the rest of
+//! what commands change: gold, the dice, the next unit id, structures,
+//! terrain, fires, flares, traps, chests, and each unit's items, effects,
+//! facing, ranges, and other state. Tests leave those out
+//! so their stored snapshots stay short enough to read.
+
+use bevy::ecs::system::{SystemParam, SystemState};
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::ability::{AbilityId, Cooldowns};
+use crate::chest::Chest;
+use crate::class::{Experience, UnitClass};
+use crate::economy::{NextUnitId, Treasury};
+use crate::facing::Facing;
+use crate::fire::Fires;
+use crate::grid::{GridMap, GridPosition};
+use crate::items::{Equipment, Inventory};
+use crate::rescue::Rescue;
+use crate::rules::GameRng;
+use crate::status::StatusEffects;
+use crate::stealth::Stealth;
+use crate::structure::Structure;
+use crate::trap::Trap;
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::{AttackRange, Faction, MoveRange, Stats, TurnStatus, Unit, UnitId};
+use crate::vision::Flares;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoardSnapshot {
+    pub turn: u32,
+    pub acting: Faction,
+    pub winner: Option<Faction>,
+    pub units: Vec<UnitSnapshot>,
+}
+
+/// The units as systems taking snapshots query them.
+pub type SnapshotUnits<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static UnitId,
+        &'static Faction,
+        &'static UnitClass,
+        &'static GridPosition,
+        &'static Stats,
+        &'static TurnStatus,
+    ),
+    With<Unit>,
+>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnitSnapshot {
+    pub id: UnitId,
+    pub faction: Faction,
+    pub class: UnitClass,
+    pub pos: GridPosition,
+    pub stats: Stats,
+    pub moved: bool,
+    pub acted: bool,
+}
+
+impl UnitSnapshot {
+    pub fn new(
+        (&id, &faction, &class, &pos, &stats, status): (
+            &UnitId,
+            &Faction,
+            &UnitClass,
+            &GridPosition,
+            &Stats,
+            &TurnStatus,
+        ),
+    ) -> Self {
+        Self {
+            id,
+            faction,
+            class,
+            pos,
+            stats,
+            moved: status.has_moved,
+            acted: status.has_acted,
+        }
+    }
+}
+
+impl BoardSnapshot {
+    /// A snapshot of the battle with `units` on the board, in any order.
+    pub fn new(
+        turn: &CurrentTurn,
+        outcome: &BattleOutcome,
+        units: impl IntoIterator<Item = UnitSnapshot>,
+    ) -> Self {
+        let mut units: Vec<UnitSnapshot> = units.into_iter().collect();
+        units.sort_by_key(|unit| unit.id);
+        Self {
+            turn: turn.number,
+            acting: turn.faction,
+            winner: outcome.0,
+            units,
+        }
+    }
+
+    /// Takes a snapshot of the battle in `world`. Fallen units are left out.
+    pub fn capture(world: &mut World) -> Self {
+        let units: Vec<UnitSnapshot> = world
+            .query_filtered::<(
+                &UnitId,
+                &Faction,
+                &UnitClass,
+                &GridPosition,
+                &Stats,
+                &TurnStatus,
+            ), With<Unit>>()
+            .iter(world)
+            .map(UnitSnapshot::new)
+            .collect();
+        Self::new(
+            world.resource::<CurrentTurn>(),
+            world.resource::<BattleOutcome>(),
+            units,
+        )
+    }
+
+    /// A 64-bit FNV-1a hash of the snapshot's RON, which unlike the standard
+    /// library's hashers is the same on every platform and build.
+    pub fn digest(&self) -> u64 {
+        fnv1a(&self.to_ron())
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .expect("board snapshots always serialize")
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(text)
+    }
+}
+
+/// The whole state of an online battle, for the two ends to compare.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SyncSnapshot {
+    pub board: BoardSnapshot,
+    pub treasury: Treasury,
+    pub rng: GameRng,
+    pub next_unit_id: NextUnitId,
+    pub map: GridMap,
+    pub fires: Fires,
+    pub flares: Flares,
+    /// Structures, traps, and chests in position order.
+    pub structures: Vec<(GridPosition, Structure)>,
+    pub traps: Vec<(GridPosition, Trap)>,
+    pub chests: Vec<(GridPosition, Chest)>,
+    /// The rest of each unit's state, in the same order as the board's units.
+    pub units: Vec<UnitState>,
+}
+
+/// What a unit carries and has been through, beyond its [`UnitSnapshot`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct UnitState {
+    pub id: UnitId,
+    pub inventory: Inventory,
+    pub equipment: Equipment,
+    pub effects: StatusEffects,
+    pub experience: Experience,
+    pub stealth: Stealth,
+    pub rescue: Rescue,
+    pub facing: Facing,
+    /// Its base ranges, before equipment and bonuses.
+    pub move_range: MoveRange,
+    pub attack_range: AttackRange,
+    /// Cooldowns sorted by ability, since their map has no fixed order.
+    pub cooldowns: Vec<(AbilityId, u32)>,
+}
+
+/// Everything a [`SyncSnapshot`] is taken from.
+#[derive(SystemParam)]
+pub struct SyncSources<'w, 's> {
+    turn: Res<'w, CurrentTurn>,
+    outcome: Res<'w, BattleOutcome>,
+    treasury: Res<'w, Treasury>,
+    rng: Res<'w, GameRng>,
+    next_unit_id: Res<'w, NextUnitId>,
+    map: Res<'w, GridMap>,
+    fires: Res<'w, Fires>,
+    flares: Res<'w, Flares>,
+    units: SnapshotUnits<'w, 's>,
+    states: Query<
+        'w,
+        's,
+        (
+            &'static UnitId,
+            &'static Inventory,
+            &'static Equipment,
+            &'static StatusEffects,
+            &'static Experience,
+            &'static Stealth,
+            &'static Rescue,
+            &'static Facing,
+            &'static MoveRange,
+            &'static AttackRange,
+            &'static Cooldowns,
+        ),
+        With<Unit>,
+    >,
+    structures: Query<'w, 's, (&'static GridPosition, &'static Structure)>,
+    traps: Query<'w, 's, (&'static GridPosition, &'static Trap)>,
+    chests: Query<'w, 's, (&'static GridPosition, &'static Chest)>,
+}
+
+impl SyncSources<'_, '_> {
+    pub fn snapshot(&self) -> SyncSnapshot {
+        let mut units: Vec<UnitState> = self
+            .states
+            .iter()
+            .map(
+                |(
+                    &id,
+                    inventory,
+                    equipment,
+                    effects,
+                    &experience,
+                    &stealth,
+                    &rescue,
+                    &facing,
+                    &move_range,
+                    &attack_range,
+                    cooldowns,
+                )| {
+                    let mut cooldowns: Vec<(AbilityId, u32)> = cooldowns
+                        .0
+                        .iter()
+                        .map(|(ability, &turns)| (ability.clone(), turns))
+                        .collect();
+                    cooldowns.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0));
+                    UnitState {
+                        id,
+                        inventory: inventory.clone(),
+                        equipment: equipment.clone(),
+                        effects: effects.clone(),
+                        experience,
+                        stealth,
+                        rescue,
+                        facing,
+                        move_range,
+                        attack_range,
+                        cooldowns,
+                    }
+                },
+            )
+            .collect();
+        units.sort_by_key(|unit| unit.id);
+        SyncSnapshot {
+            board: BoardSnapshot::new(
+                &self.turn,
+                &self.outcome,
+                self.units.iter().map(UnitSnapshot::new),
+            ),
+            treasury: *self.treasury,
+            rng: self.rng.clone(),
+            next_unit_id: *self.next_unit_id,
+            map: self.map.clone(),
+            fires: self.fires.clone(),
+            flares: self.flares.clone(),
+            structures: by_position(self.structures.iter()),
+            traps: by_position(self.traps.iter()),
+            chests: by_position(self.chests.iter()),
+            units,
+        }
+    }
+}
+
+impl SyncSnapshot {
+    /// Takes a snapshot of the battle in `world`, as [`SyncSources`] would.
+    pub fn capture(world: &mut World) -> Self {
+        SystemState::<SyncSources>::new(world).get(world).snapshot()
+    }
+
+    /// A 64-bit FNV-1a hash of the snapshot's RON, like
+    /// [`BoardSnapshot::digest`].
+    pub fn digest(&self) -> u64 {
+        fnv1a(&self.to_ron())
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .expect("sync snapshots always serialize")
+    }
+}
+
+fn by_position<'a, T: Clone + 'a>(
+    items: impl Iterator<Item = (&'a GridPosition, &'a T)>,
+) -> Vec<(GridPosition, T)> {
+    let mut items: Vec<(GridPosition, T)> = items.map(|(&pos, item)| (pos, item.clone())).collect();
+    items.sort_by_key(|(pos, _)| (pos.x, pos.y));
+    items
+}
+
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
@@ -0,0 +1,354 @@
+//! Sprite-sheet art for tiles and units.
+//!
+//! When `assets/textures/tiles.png` and `assets/textures/units.png` exist,
+//! they are cut into [`ATLAS_CELL`]-pixel frames and tiles and units draw
+//! from them. The tile sheet is a single row; the unit sheet has a row per
+//! class holding its animation frames. Without them everything keeps its
+//! flat colored quad, so the game still runs from a checkout with no art.
+//! A mod can bring its own copy of either sheet, or replace a single class's
+//! row with `textures/units/<class>.png`, e.g. `textures/units/knight.png`,
+//! a row of that class's frames drawn in place of its row of the sheet.
+//!
+//! A tile type opts into animation through [`tile_animation`]: it cycles
+//! through extra frames at the end of the tile sheet, or shimmers its flat
+//! color when there is no sheet.
+//!
+//! The board is drawn in chunks, which sample an array texture rather than
+//! an atlas, so once the tile sheet has loaded its frames are restacked into
+//! one, a frame per layer. See [`TileSet`].
+
+use std::f32::consts::TAU;
+
+use bevy::image::TextureFormatPixelInfo;
+use bevy::prelude::*;
+use bevy::render::render_resource::{
+    Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension,
+};
+
+use std::collections::HashMap;
+
+use crate::class::UnitClass;
+use crate::grid::{GridPosition, TileType};
+use crate::loading::Preload;
+use crate::mods::resolve_asset;
+
+const TILE_ATLAS: &str = "textures/tiles.png";
+const UNIT_ATLAS: &str = "textures/units.png";
+/// Size in pixels of one frame in either sheet.
+const ATLAS_CELL: UVec2 = UVec2::splat(32);
+const TILE_FRAMES: u32 = 10;
+/// Animation frames in each class's row of the unit sheet.
+const UNIT_COLUMNS: u32 = 8;
+const UNIT_ROWS: u32 = 10;
+
+/// The frame of the tile sheet drawn for a tile. Custom tiles have none
+/// and keep their flat color.
+pub fn tile_frame(tile: TileType) -> Option<usize> {
+    Some(match tile {
+        TileType::Grass => 0,
+        TileType::Forest => 1,
+        TileType::Water => 2,
+        TileType::Mountain => 3,
+        TileType::Door { open: false } => 4,
+        TileType::Door { open: true } => 5,
+        TileType::Bridge { lowered: false } => 6,
+        TileType::Bridge { lowered: true } => 7,
+        TileType::Lever => 8,
+        TileType::Custom(_) => return None,
+    })
+}
+
+/// How an animated tile type moves.
+#[derive(Clone, Copy, Debug)]
+pub struct TileAnimation {
+    /// Tile sheet frames to cycle through, starting with its usual one.
+    pub frames: &'static [usize],
+    pub frame_seconds: f32,
+    /// How much lighter its flat color gets at the peak of a shimmer.
+    pub shimmer: f32,
+}
+
+impl TileAnimation {
+    /// Neighbouring tiles run a step apart so a body of water ripples
+    /// rather than blinking all at once.
+    fn phase(pos: GridPosition) -> usize {
+        (pos.x + pos.y).rem_euclid(8) as usize
+    }
+
+    /// The frame a tile at `pos` shows `elapsed` seconds in.
+    pub fn frame(&self, pos: GridPosition, elapsed: f32) -> usize {
+        let step = (elapsed / self.frame_seconds) as usize + Self::phase(pos);
+        self.frames[step % self.frames.len()]
+    }
+
+    /// Its flat `color` at `pos`, `elapsed` seconds in.
+    pub fn tint(&self, color: Color, pos: GridPosition, elapsed: f32) -> Color {
+        let cycle = self.frame_seconds * self.frames.len() as f32;
+        let angle = elapsed / cycle * TAU + Self::phase(pos) as f32;
+        color.lighter(self.shimmer * (angle.sin() * 0.5 + 0.5))
+    }
+}
+
+/// The animation a tile type plays, if any.
+pub fn tile_animation(tile: TileType) -> Option<TileAnimation> {
+    match tile {
+        TileType::Water => Some(TileAnimation {
+            frames: &[2, 9],
+            frame_seconds: 0.6,
+            shimmer: 0.06,
+        }),
+        _ => None,
+    }
+}
+
+/// Where a class's own sprite row, replacing its row of the unit sheet, is
+/// looked for.
+fn class_sprite_path(class: UnitClass) -> String {
+    format!("textures/units/{}.png", format!("{class:?}").to_lowercase())
+}
+
+/// The frame of the unit sheet in `column` of a class's row.
+pub fn unit_frame(class: UnitClass, column: usize) -> usize {
+    let row = match class {
+        UnitClass::Knight => 0,
+        UnitClass::Archer => 1,
+        UnitClass::Mage => 2,
+        UnitClass::Healer => 3,
+        UnitClass::Paladin => 4,
+        UnitClass::General => 5,
+        UnitClass::Sniper => 6,
+        UnitClass::Ranger => 7,
+        UnitClass::Sage => 8,
+        UnitClass::Bishop => 9,
+    };
+    row * UNIT_COLUMNS as usize + column
+}
+
+/// A loaded sprite sheet and how it is cut into frames.
+#[derive(Clone, Debug)]
+pub struct Atlas {
+    image: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+}
+
+impl Atlas {
+    /// Loads the sheet at `path` under `assets/`, or a mod's copy of it, or
+    /// returns `None` when neither is there.
+    fn load(
+        path: &str,
+        (columns, rows): (u32, u32),
+        asset_server: &AssetServer,
+        layouts: &mut Assets<TextureAtlasLayout>,
+    ) -> Option<Self> {
+        let (source, file) = resolve_asset(path);
+        if !file.exists() {
+            return None;
+        }
+        Some(Self {
+            image: asset_server.load(source),
+            layout: layouts.add(TextureAtlasLayout::from_grid(
+                ATLAS_CELL, columns, rows, None, None,
+            )),
+        })
+    }
+
+    /// Points `sprite` at frame `index` of this sheet, only touching it when
+    /// it shows something else so change detection stays quiet.
+    pub fn apply(&self, sprite: &mut Mut<Sprite>, index: usize) {
+        if sprite.image != self.image {
+            sprite.image = self.image.clone();
+        }
+        let current = sprite.texture_atlas.as_ref().map(|atlas| atlas.index);
+        if current != Some(index) {
+            sprite.texture_atlas = Some(TextureAtlas {
+                layout: self.layout.clone(),
+                index,
+            });
+        }
+    }
+}
+
+/// The sprite sheets found at startup. A missing sheet leaves its sprites
+/// as colored quads.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct SpriteAtlases {
+    pub tiles: Option<Atlas>,
+    pub units: Option<Atlas>,
+    /// Classes with a sprite row of their own.
+    pub classes: HashMap<UnitClass, Atlas>,
+}
+
+impl SpriteAtlases {
+    /// The sheet and frame `column` of `class`'s animation is drawn from:
+    /// its own row if it has one, or its row of the unit sheet.
+    pub fn unit(&self, class: UnitClass, column: usize) -> Option<(&Atlas, usize)> {
+        match self.classes.get(&class) {
+            Some(atlas) => Some((atlas, column)),
+            None => Some((self.units.as_ref()?, unit_frame(class, column))),
+        }
+    }
+}
+
+pub fn load_sprite_atlases(
+    asset_server: Res<AssetServer>,
+    mut layouts: ResMut<Assets<TextureAtlasLayout>>,
+    mut atlases: ResMut<SpriteAtlases>,
+    mut preload: ResMut<Preload>,
+) {
+    let mut load = |path: &str, grid| {
+        let atlas = Atlas::load(path, grid, &asset_server, &mut layouts);
+        if atlas.is_none() {
+            info!("No sprite sheet at {path}, drawing flat colors");
+        }
+        atlas
+    };
+    let tiles = load(TILE_ATLAS, (TILE_FRAMES, 1));
+    let units = load(UNIT_ATLAS, (UNIT_COLUMNS, UNIT_ROWS));
+    let classes = UnitClass::ALL
+        .into_iter()
+        .filter_map(|class| {
+            let atlas = Atlas::load(
+                &class_sprite_path(class),
+                (UNIT_COLUMNS, 1),
+                &asset_server,
+                &mut layouts,
+            )?;
+            Some((class, atlas))
+        })
+        .collect();
+    *atlases = SpriteAtlases {
+        tiles,
+        units,
+        classes,
+    };
+    let loaded = [&atlases.tiles, &atlases.units]
+        .into_iter()
+        .flatten()
+        .chain(atlases.classes.values());
+    for atlas in loaded {
+        preload.0.push(atlas.image.clone().untyped());
+    }
+}
+
+/// Falls back to flat colors for any sheet that failed to load, rather
+/// than drawing placeholders.
+pub fn drop_failed_atlases_system(
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<SpriteAtlases>,
+) {
+    let failed = |atlas: &Atlas| asset_server.load_state(&atlas.image).is_failed();
+    let SpriteAtlases {
+        tiles,
+        units,
+        classes,
+    } = &mut *atlases;
+    for (atlas, path) in [(tiles, TILE_ATLAS), (units, UNIT_ATLAS)] {
+        if atlas.as_ref().is_some_and(failed) {
+            warn!("Failed to load {path}, drawing flat colors");
+            *atlas = None;
+        }
+    }
+    classes.retain(|&class, atlas| {
+        if failed(atlas) {
+            warn!(
+                "Failed to load {}, drawing the unit sheet",
+                class_sprite_path(class)
+            );
+        }
+        !failed(atlas)
+    });
+}
+
+/// The array textures board chunks draw from. `white` is a single white
+/// layer that chunks tint with flat colors; `tiles` holds the tile sheet's
+/// frames once it has loaded, and stays `None` without one.
+#[derive(Resource, Clone, Debug)]
+pub struct TileSet {
+    pub white: Handle<Image>,
+    pub tiles: Option<Handle<Image>>,
+}
+
+impl FromWorld for TileSet {
+    fn from_world(world: &mut World) -> Self {
+        let white = Image::new_fill(
+            Extent3d::default(),
+            TextureDimension::D2,
+            &[255; 4],
+            TextureFormat::Rgba8UnormSrgb,
+            default(),
+        );
+        Self {
+            white: world.resource_mut::<Assets<Image>>().add(as_array(white)),
+            tiles: None,
+        }
+    }
+}
+
+/// Marks `image` to be sampled as an array texture, even with one layer.
+fn as_array(mut image: Image) -> Image {
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..default()
+    });
+    image
+}
+
+/// Cuts a sheet laid out as a single row of `frames` cells into an array
+/// texture with a frame per layer. `None` if the sheet isn't that shape.
+fn stack_frames(sheet: &Image, frames: u32) -> Option<Image> {
+    let data = sheet.data.as_ref()?;
+    let pixel = sheet.texture_descriptor.format.pixel_size().ok()?;
+    let (width, height) = (sheet.width(), sheet.height());
+    if width != ATLAS_CELL.x * frames || height != ATLAS_CELL.y {
+        return None;
+    }
+    let row = (ATLAS_CELL.x as usize) * pixel;
+    let mut stacked = Vec::with_capacity(data.len());
+    for frame in 0..frames as usize {
+        for y in 0..height as usize {
+            let start = y * width as usize * pixel + frame * row;
+            stacked.extend_from_slice(&data[start..start + row]);
+        }
+    }
+    let size = Extent3d {
+        width: ATLAS_CELL.x,
+        height: ATLAS_CELL.y,
+        depth_or_array_layers: frames,
+    };
+    let mut image = Image::new(
+        size,
+        TextureDimension::D2,
+        stacked,
+        sheet.texture_descriptor.format,
+        sheet.asset_usage,
+    );
+    image.sampler = sheet.sampler.clone();
+    Some(as_array(image))
+}
+
+/// Restacks the tile sheet for [`TileSet`] once it has loaded. A sheet that
+/// isn't a single row of [`TILE_FRAMES`] cells is dropped, and the board
+/// keeps its flat colors.
+pub fn stack_tile_sheet_system(
+    mut atlases: ResMut<SpriteAtlases>,
+    mut tileset: ResMut<TileSet>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if tileset.tiles.is_some() {
+        return;
+    }
+    let Some(sheet) = atlases
+        .tiles
+        .as_ref()
+        .and_then(|atlas| images.get(&atlas.image))
+    else {
+        return;
+    };
+    match stack_frames(sheet, TILE_FRAMES) {
+        Some(stacked) => tileset.tiles = Some(images.add(stacked)),
+        None => {
+            warn!("{TILE_ATLAS} is not a single row of {TILE_FRAMES} frames, drawing flat colors");
+            atlases.tiles = None;
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! Top-level application states.
+
+use bevy::prelude::*;
+
+#[derive(States, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub enum AppState {
+    /// Waiting on the game's assets, before the main menu.
+    #[default]
+    Loading,
+    MainMenu,
+    /// Setting up a hotseat or online match.
+    Lobby,
+    /// A live battle driven by player input and the AI.
+    GamePlay,
+    /// Playback of a recorded battle through the same command executor.
+    Replay,
+}
@@ -0,0 +1,157 @@
+//! Status effects with durations, their start-of-turn upkeep, and the small
+//! icons drawn next to affected units.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "render")]
+use crate::constants::UNIT_SIZE;
+use crate::items::StatBonus;
+#[cfg(feature = "render")]
+use crate::unit::Unit;
+use crate::unit::{Stats, TurnStatus};
+
+#[cfg(feature = "render")]
+const ICON_SIZE: f32 = 12.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub enum StatusKind {
+    /// Loses this much HP at the start of each of the unit's turns. Poison
+    /// never drops a unit below 1 HP.
+    Poison(u32),
+    /// Skips the unit's turns while it lasts.
+    Stun,
+    /// The unit can't move on its turns while it lasts, but can still act.
+    Snare,
+    /// Temporary stat change, e.g. attack up.
+    Boost(StatBonus),
+}
+
+impl StatusKind {
+    /// Poison, stun, and snares can be cured; boosts can't.
+    pub fn is_harmful(self) -> bool {
+        !matches!(self, StatusKind::Boost(_))
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StatusKind::Poison(_) => "poison",
+            StatusKind::Stun => "stun",
+            StatusKind::Snare => "snare",
+            StatusKind::Boost(_) => "boost",
+        }
+    }
+
+    #[cfg(feature = "render")]
+    fn icon(self) -> (&'static str, Color) {
+        match self {
+            StatusKind::Poison(_) => ("P", Color::srgb(0.45, 0.85, 0.3)),
+            StatusKind::Stun => ("S", Color::srgb(0.95, 0.85, 0.25)),
+            StatusKind::Snare => ("#", Color::srgb(0.75, 0.5, 0.3)),
+            StatusKind::Boost(_) => ("+", Color::srgb(0.4, 0.7, 1.0)),
+        }
+    }
+}
+
+/// A status effect that wears off at the start of the unit's `turns`-th turn
+/// after it was applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub turns: u32,
+}
+
+#[derive(Component, Clone, Debug, Default, PartialEq, Serialize, Reflect)]
+#[reflect(Component)]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+
+/// What start-of-turn upkeep did to a unit, for the battle log.
+#[derive(Default)]
+pub struct Upkeep {
+    pub poison_damage: u32,
+    pub stunned: bool,
+}
+
+impl StatusEffects {
+    pub fn bonuses(&self) -> impl Iterator<Item = StatBonus> + '_ {
+        self.0.iter().filter_map(|effect| match effect.kind {
+            StatusKind::Boost(bonus) => Some(bonus),
+            _ => None,
+        })
+    }
+
+    /// Removes poison, stun, and snares, returning whether there was anything to cure.
+    pub fn cure(&mut self) -> bool {
+        let before = self.0.len();
+        self.0.retain(|effect| !effect.kind.is_harmful());
+        self.0.len() != before
+    }
+
+    /// Runs at the start of the owner's turn: applies poison, spends the turn
+    /// if stunned, then counts every effect down and drops the expired ones.
+    pub fn upkeep(&mut self, stats: &mut Stats, status: &mut TurnStatus) -> Upkeep {
+        let mut upkeep = Upkeep::default();
+        for effect in &mut self.0 {
+            match effect.kind {
+                StatusKind::Poison(damage) => {
+                    let damage = damage.min(stats.hp.saturating_sub(1));
+                    stats.hp -= damage;
+                    upkeep.poison_damage += damage;
+                }
+                StatusKind::Stun => {
+                    status.has_moved = true;
+                    status.has_acted = true;
+                    upkeep.stunned = true;
+                }
+                StatusKind::Snare => status.has_moved = true,
+                StatusKind::Boost(_) => {}
+            }
+            effect.turns = effect.turns.saturating_sub(1);
+        }
+        self.0.retain(|effect| effect.turns > 0);
+        upkeep
+    }
+}
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct StatusIcon;
+
+/// Redraws the row of status icons above a unit whenever its effects change.
+#[cfg(feature = "render")]
+pub fn update_status_icons_system(
+    mut commands: Commands,
+    units: Query<(Entity, &StatusEffects, Option<&Children>), (With<Unit>, Changed<StatusEffects>)>,
+    icons: Query<(), With<StatusIcon>>,
+) {
+    for (unit, effects, children) in &units {
+        for &child in children.into_iter().flatten() {
+            if icons.contains(child) {
+                commands.entity(child).despawn();
+            }
+        }
+
+        let top = UNIT_SIZE / 2.0 - ICON_SIZE / 2.0;
+        let left = -UNIT_SIZE / 2.0 + ICON_SIZE / 2.0;
+        commands.entity(unit).with_children(|parent| {
+            for (index, effect) in effects.0.iter().enumerate() {
+                let (glyph, color) = effect.kind.icon();
+                parent
+                    .spawn((
+                        StatusIcon,
+                        Sprite::from_color(color, Vec2::splat(ICON_SIZE)),
+                        Transform::from_xyz(left + index as f32 * ICON_SIZE, top, 0.2),
+                    ))
+                    .with_child((
+                        Text2d::new(glyph),
+                        TextFont {
+                            font_size: ICON_SIZE * 0.8,
+                            ..default()
+                        },
+                        TextColor(Color::BLACK),
+                        Transform::from_xyz(0.0, 0.0, 0.1),
+                    ));
+            }
+        });
+    }
+}
@@ -0,0 +1,42 @@
+//! Stealth: units that slip out of the enemy's sight entirely.
+//!
+//! Archers, snipers, and rangers start every battle hidden. A hidden unit is
+//! never spotted by the other side, fog or no fog, so it can't be attacked
+//! or chosen as a target. It is revealed once it attacks or a unit of the
+//! other side comes within that unit's detection range: next to it, or up
+//! to three tiles for mages and sages with true sight. It can spend its
+//! action to hide again while no enemy would notice it.
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+#[cfg(feature = "render")]
+use crate::unit::FactionPalette;
+#[cfg(feature = "render")]
+use crate::unit::{Faction, Unit};
+
+/// How opaque a hidden unit is drawn for its own side.
+#[cfg(feature = "render")]
+const HIDDEN_ALPHA: f32 = 0.45;
+
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Serialize, Reflect)]
+#[reflect(Component)]
+pub struct Stealth {
+    pub hidden: bool,
+}
+
+/// Tints units in their faction's color, fading out the player's own
+/// hidden units so they can tell who is in stealth.
+#[cfg(feature = "render")]
+pub fn update_stealth_visuals_system(
+    palette: Res<FactionPalette>,
+    mut units: Query<(&Faction, Ref<Stealth>, &mut Sprite), With<Unit>>,
+) {
+    for (faction, stealth, mut sprite) in &mut units {
+        if !palette.is_changed() && !stealth.is_changed() {
+            continue;
+        }
+        let alpha = if stealth.hidden { HIDDEN_ALPHA } else { 1.0 };
+        sprite.color = palette.color(*faction).with_alpha(alpha);
+    }
+}
@@ -0,0 +1,48 @@
+//! A crowded battle for profiling.
+//!
+//! `--stress 200` fights 200 units a side on a board generated big enough to
+//! deploy them all, with terrain scattered over it at the usual density.
+//! Together with `--headless --autoplay` it times pathfinding and the AI
+//! under load; on its own it shows how drawing holds up.
+
+use crate::class::UnitClass;
+use crate::constants::*;
+use crate::grid::{GridMap, GridPosition};
+use crate::unit::Faction;
+
+/// The classes a stress army cycles through.
+const STRESS_CLASSES: [UnitClass; 4] = [
+    UnitClass::Knight,
+    UnitClass::Archer,
+    UnitClass::Mage,
+    UnitClass::Healer,
+];
+
+/// An empty board with room for `units` a side in the deployment columns,
+/// and as wide as it is tall so there is ground to cross between them.
+pub fn stress_map(units: u32) -> GridMap {
+    let height = (units.div_ceil(DEPLOY_COLUMNS as u32) as i32).max(GRID_HEIGHT);
+    GridMap::new(height.max(GRID_WIDTH), height)
+}
+
+/// Where each side deploys: the player filling the left columns and the
+/// enemy the right ones, bottom to top, skipping tiles nobody can stand on.
+pub fn stress_roster(map: &GridMap, units: u32) -> Vec<(Faction, UnitClass, GridPosition)> {
+    let mut roster = Vec::new();
+    for (faction, columns) in [
+        (Faction::Player, 0..DEPLOY_COLUMNS),
+        (Faction::Enemy, map.width - DEPLOY_COLUMNS..map.width),
+    ] {
+        let tiles = (0..map.height)
+            .flat_map(|y| columns.clone().map(move |x| GridPosition::new(x, y)))
+            .filter(|&pos| map.is_walkable(pos));
+        let classes = STRESS_CLASSES.into_iter().cycle();
+        roster.extend(
+            tiles
+                .zip(classes)
+                .take(units as usize)
+                .map(|(pos, class)| (faction, class, pos)),
+        );
+    }
+    roster
+}
@@ -0,0 +1,206 @@
+//! Capturable structures standing on map tiles.
+//!
+//! A unit standing on a structure its side doesn't own can spend its action
+//! capturing it; after enough captures the structure changes hands. Seizing
+//! the opponent's throne wins the battle, forts heal their owner's units, and
+//! every owned structure pays income at the start of its owner's turn.
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+#[cfg(feature = "render")]
+use crate::constants::*;
+use crate::grid::{GridMap, GridPosition, TerrainBonus};
+use crate::state::AppState;
+#[cfg(feature = "render")]
+use crate::unit::FactionPalette;
+use crate::unit::{Faction, UnitId};
+
+#[cfg(feature = "render")]
+const NEUTRAL_COLOR: Color = Color::srgb(0.55, 0.55, 0.55);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Reflect)]
+pub enum StructureKind {
+    /// Seizing the opponent's throne wins the battle. Heals like a fort.
+    Throne,
+    /// Heals its owner's units standing on it, and recruits new ones.
+    Fort,
+    /// Pays the most income but does nothing else.
+    Village,
+}
+
+impl StructureKind {
+    /// Capture actions needed to take the structure.
+    pub fn capture_turns(self) -> u32 {
+        match self {
+            StructureKind::Throne => 2,
+            StructureKind::Fort | StructureKind::Village => 1,
+        }
+    }
+
+    /// Gold paid to the owner at the start of each of its turns.
+    pub fn income(self) -> u32 {
+        match self {
+            StructureKind::Throne | StructureKind::Village => 100,
+            StructureKind::Fort => 50,
+        }
+    }
+
+    /// Percent of max HP restored to a unit of the owner standing on the
+    /// structure at the start of its turn.
+    pub fn heal_percent(self) -> u32 {
+        match self {
+            StructureKind::Throne | StructureKind::Fort => 20,
+            StructureKind::Village => 0,
+        }
+    }
+
+    /// Whether units can be recruited on this structure.
+    pub fn can_recruit(self) -> bool {
+        matches!(self, StructureKind::Throne | StructureKind::Fort)
+    }
+
+    /// Defense granted to a unit standing on the structure.
+    pub fn defense_bonus(self) -> u32 {
+        match self {
+            StructureKind::Throne => 3,
+            StructureKind::Fort => 2,
+            StructureKind::Village => 0,
+        }
+    }
+
+    #[cfg(feature = "render")]
+    fn glyph(self) -> &'static str {
+        match self {
+            StructureKind::Throne => "T",
+            StructureKind::Fort => "F",
+            StructureKind::Village => "V",
+        }
+    }
+}
+
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Reflect)]
+#[reflect(Component)]
+pub struct Structure {
+    pub kind: StructureKind,
+    pub owner: Option<Faction>,
+    /// Who owned the structure when the battle started.
+    pub home: Option<Faction>,
+    /// The unit currently capturing the structure and how many capture
+    /// actions it has put in so far.
+    pub capture: Option<(UnitId, u32)>,
+}
+
+impl Structure {
+    /// Whether the opponent of its original owner has taken it.
+    pub fn is_seized(&self) -> bool {
+        self.home.is_some() && self.owner != self.home
+    }
+}
+
+/// Combat modifiers for a unit at `pos`: the tile's own bonus plus the
+/// defense of the structure standing there, if any.
+pub fn terrain_at(
+    map: &GridMap,
+    structure: Option<StructureKind>,
+    pos: GridPosition,
+) -> TerrainBonus {
+    let mut bonus = map.tile(pos).map(|tile| tile.bonus()).unwrap_or_default();
+    bonus.defense += structure.map_or(0, StructureKind::defense_bonus);
+    bonus
+}
+
+/// Layout of the default battlefield's structures.
+pub const STRUCTURES: [(StructureKind, Option<Faction>, GridPosition); 5] = [
+    (
+        StructureKind::Throne,
+        Some(Faction::Enemy),
+        GridPosition::new(11, 6),
+    ),
+    (StructureKind::Fort, None, GridPosition::new(5, 1)),
+    (StructureKind::Fort, None, GridPosition::new(6, 6)),
+    (StructureKind::Village, None, GridPosition::new(3, 7)),
+    (StructureKind::Village, None, GridPosition::new(8, 0)),
+];
+
+/// Tiles the default battlefield's structures stand on.
+pub fn structure_sites() -> impl Iterator<Item = GridPosition> {
+    STRUCTURES.into_iter().map(|(_, _, pos)| pos)
+}
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct StructureLabel;
+
+pub fn spawn_structures(mut commands: Commands, state: Res<State<AppState>>) {
+    for (kind, owner, pos) in STRUCTURES {
+        commands.spawn((
+            Structure {
+                kind,
+                owner,
+                home: owner,
+                capture: None,
+            },
+            pos,
+            DespawnOnExit(*state.get()),
+        ));
+    }
+}
+
+/// Gives newly placed structures their sprite and glyph.
+#[cfg(feature = "render")]
+pub fn add_structure_sprites_system(
+    mut commands: Commands,
+    structures: Query<(Entity, &Structure, &GridPosition), Added<Structure>>,
+) {
+    for (entity, structure, pos) in &structures {
+        commands
+            .entity(entity)
+            .insert((
+                Sprite::from_color(NEUTRAL_COLOR, Vec2::splat(TILE_SIZE * 0.9)),
+                Transform::from_translation(pos.to_world(STRUCTURE_Z)),
+            ))
+            .with_child((
+                StructureLabel,
+                Text2d::new(structure.kind.glyph()),
+                TextFont {
+                    font_size: TILE_SIZE * 0.3,
+                    ..default()
+                },
+                TextColor(Color::BLACK),
+                Transform::from_xyz(0.0, TILE_SIZE * 0.3, 0.1),
+            ));
+    }
+}
+
+/// Tints structures by owner and shows capture progress next to the glyph.
+#[cfg(feature = "render")]
+pub fn update_structure_visuals_system(
+    palette: Res<FactionPalette>,
+    mut structures: Query<(Ref<Structure>, &mut Sprite, &Children)>,
+    mut labels: Query<&mut Text2d, With<StructureLabel>>,
+) {
+    for (structure, mut sprite, children) in &mut structures {
+        if !palette.is_changed() && !structure.is_changed() {
+            continue;
+        }
+        let color = structure.owner.map_or(NEUTRAL_COLOR, |owner| {
+            palette.color(owner).mix(&NEUTRAL_COLOR, 0.5)
+        });
+        sprite.color = color;
+
+        let text = match structure.capture {
+            Some((_, progress)) => format!(
+                "{} {progress}/{}",
+                structure.kind.glyph(),
+                structure.kind.capture_turns()
+            ),
+            None => structure.kind.glyph().to_string(),
+        };
+        for child in children {
+            if let Ok(mut label) = labels.get_mut(*child) {
+                label.0 = text.clone();
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Suspend saves: quitting mid-battle writes the battle's command log, its
+//! seats, and its match setup to disk, and "Continue" rebuilds the exact
+//! same battle by re-executing the log with the same people and AIs at
+//! each side.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::window::WindowCloseRequested;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "render")]
+use crate::bindings::{ActionInput, InputAction};
+use crate::command::CommandLog;
+use crate::lobby::MatchSetup;
+use crate::seats::Seats;
+#[cfg(feature = "render")]
+use crate::state::AppState;
+#[cfg(feature = "render")]
+use crate::turn::BattleOutcome;
+
+/// A suspended battle waiting to be re-executed when gameplay starts.
+#[derive(Resource)]
+pub struct PendingResume(pub CommandLog);
+
+/// Everything a suspend save holds.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Suspend {
+    pub log: CommandLog,
+    pub seats: Seats,
+    pub setup: MatchSetup,
+}
+
+/// `<data dir>/bevy-game/suspend.ron`.
+pub fn suspend_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("bevy-game").join("suspend.ron"))
+}
+
+pub fn has_suspend_save() -> bool {
+    suspend_path().is_some_and(|path| path.exists())
+}
+
+pub fn save_suspend(suspend: &Suspend, path: &Path) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+    let contents = ron::ser::to_string_pretty(suspend, PrettyConfig::default())
+        .map_err(|err| err.to_string())?;
+    fs::write(path, contents).map_err(|err| err.to_string())
+}
+
+pub fn load_suspend(path: &Path) -> Result<Suspend, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    ron::from_str(&contents).map_err(|err| err.to_string())
+}
+
+#[cfg(feature = "render")]
+fn write_suspend_save(suspend: &Suspend) {
+    let Some(path) = suspend_path() else {
+        return;
+    };
+    match save_suspend(suspend, &path) {
+        Ok(()) => info!("Suspended battle to {}", path.display()),
+        Err(err) => error!("Failed to write suspend save {}: {err}", path.display()),
+    }
+}
+
+/// Loads the suspend save and deletes it, so a battle can only be resumed once.
+pub fn take_suspend_save() -> Option<Suspend> {
+    let path = suspend_path()?;
+    let suspend = load_suspend(&path)
+        .inspect_err(|err| error!("Failed to read suspend save {}: {err}", path.display()))
+        .ok();
+    if let Err(err) = fs::remove_file(&path) {
+        warn!("Failed to remove suspend save {}: {err}", path.display());
+    }
+    suspend
+}
+
+/// F10 suspends the battle and returns to the main menu; closing the window
+/// mid-battle suspends it as well.
+#[cfg(feature = "render")]
+pub fn suspend_on_quit_system(
+    input: ActionInput,
+    mut close_requests: MessageReader<WindowCloseRequested>,
+    log: Res<CommandLog>,
+    outcome: Res<BattleOutcome>,
+    seats: Res<Seats>,
+    setup: Res<MatchSetup>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let quit_to_menu = input.just_pressed(InputAction::Suspend);
+    let closing = close_requests.read().count() > 0;
+    if !quit_to_menu && !closing {
+        return;
+    }
+
+    // A finished battle has nothing left to resume, and one shared with
+    // another player goes on with them rather than here.
+    if outcome.0.is_none() && !seats.online() {
+        write_suspend_save(&Suspend {
+            log: log.clone(),
+            seats: *seats,
+            setup: *setup,
+        });
+    }
+    if quit_to_menu {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::AiLevel;
+    use crate::command::GameCommand;
+    use crate::seats::Controller;
+
+    #[test]
+    fn a_suspended_lobby_match_keeps_its_seats_and_fog() {
+        let path = std::env::temp_dir()
+            .join(format!("bevy-game-{}", std::process::id()))
+            .join("suspend.ron");
+        let seats = Seats {
+            player: Controller::Ai(AiLevel::Hard),
+            enemy: Controller::Local,
+        };
+        let suspend = Suspend {
+            log: CommandLog {
+                seed: 9,
+                commands: vec![GameCommand::EndTurn],
+                ..default()
+            },
+            seats,
+            setup: MatchSetup {
+                sides: seats,
+                fog_of_war: false,
+                ..default()
+            },
+        };
+
+        save_suspend(&suspend, &path).unwrap();
+        let loaded = load_suspend(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, Ok(suspend));
+    }
+}
@@ -0,0 +1,264 @@
+//! A headless battle for tests to drive.
+//!
+//! [`TestGame`] runs [`BattlePlugin`] on the default map without a window,
+//! the AI, or the campaign profile. A test places the units it needs, plays
+//! both sides through clicks and [`GameCommand`]s, and checks the board with
+//! the `assert_*` helpers or against a whole [`BoardSnapshot`]. Rolls come
+//! from [`TEST_SEED`], so a test plays out the same way every run.
+//!
+//! [`BattlePlugin`] draws nothing, so this works in every build, and the
+//! tests under `tests/` run with a plain `cargo test`. With the `render`
+//! feature fallen units are left for an animation to take off the board;
+//! they lose their [`Unit`] at once all the same, so the helpers never see
+//! them.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+
+use crate::class::UnitClass;
+use crate::command::{CommandLog, CommandRejected, GameCommand, Rejection};
+use crate::economy::NextUnitId;
+use crate::grid::{GridMap, GridPosition, PathCache};
+use crate::items::ItemCatalog;
+use crate::plugins::BattlePlugin;
+use crate::snapshot::BoardSnapshot;
+use crate::state::AppState;
+use crate::suspend::PendingResume;
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::{spawn_unit, Faction, MoveRange, Stats, TurnStatus, Unit, UnitId, UnitIndex};
+
+/// Seed every test battle is fought with.
+pub const TEST_SEED: u64 = 0;
+
+/// A live battle on the default map, on the player's first turn.
+pub struct TestGame {
+    pub app: App,
+    /// The player unit the last click selected.
+    selected: Option<UnitId>,
+}
+
+impl Default for TestGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestGame {
+    /// Starts the battle with its structures, traps, and chests but none of
+    /// the starting roster, so the board holds only the units a test spawns.
+    /// A side with no units left loses, so spawn both before acting.
+    pub fn new() -> Self {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin, BattlePlugin))
+            .insert_resource(PendingResume(CommandLog {
+                seed: TEST_SEED,
+                ..default()
+            }));
+        app.world_mut()
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::GamePlay);
+        app.update();
+
+        let world = app.world_mut();
+        let roster: Vec<Entity> = world
+            .query_filtered::<Entity, With<Unit>>()
+            .iter(world)
+            .collect();
+        for entity in roster {
+            world.despawn(entity);
+        }
+        Self {
+            app,
+            selected: None,
+        }
+    }
+
+    /// Places a new unit on the board, ready to act, and returns its id.
+    pub fn spawn_unit(&mut self, faction: Faction, class: UnitClass, pos: GridPosition) -> UnitId {
+        let world = self.app.world_mut();
+        let id = {
+            let mut next_id = world.resource_mut::<NextUnitId>();
+            next_id.0 += 1;
+            UnitId(next_id.0 - 1)
+        };
+        world.resource_scope(|world, catalog: Mut<ItemCatalog>| {
+            let mut commands = world.commands();
+            spawn_unit(
+                &mut commands,
+                &catalog,
+                AppState::GamePlay,
+                id,
+                faction,
+                class,
+                pos,
+            );
+        });
+        world.flush();
+        id
+    }
+
+    /// Sets a unit's hp, for a test that needs it wounded.
+    pub fn set_hp(&mut self, unit: UnitId, hp: u32) {
+        let world = self.app.world_mut();
+        let mut stats = world
+            .query::<(&UnitId, &mut Stats)>()
+            .iter_mut(world)
+            .find(|(id, _)| **id == unit)
+            .map(|(_, stats)| stats)
+            .unwrap_or_else(|| panic!("{unit:?} has fallen"));
+        stats.hp = hp;
+    }
+
+    /// Places a player knight at `pos`.
+    pub fn spawn_player_unit(&mut self, pos: GridPosition) -> UnitId {
+        self.spawn_unit(Faction::Player, UnitClass::Knight, pos)
+    }
+
+    /// Places an enemy knight at `pos`.
+    pub fn spawn_enemy_unit(&mut self, pos: GridPosition) -> UnitId {
+        self.spawn_unit(Faction::Enemy, UnitClass::Knight, pos)
+    }
+
+    /// Runs one frame.
+    pub fn update(&mut self) {
+        self.app.update();
+    }
+
+    /// Hands a command to the executor and runs the frame that applies it.
+    pub fn send(&mut self, command: GameCommand) {
+        self.app.world_mut().write_message(command);
+        self.update();
+    }
+
+    /// Clicks a tile the way the player would: a click on a player unit that
+    /// can still act selects it, and any other click sends the selected unit
+    /// to attack the unit there or to move to it. The executor has the final
+    /// say, as it does for real clicks.
+    pub fn click_tile(&mut self, pos: GridPosition) {
+        let world = self.app.world_mut();
+        let clicked = world
+            .query_filtered::<(&UnitId, &GridPosition, &Faction, &TurnStatus), With<Unit>>()
+            .iter(world)
+            .find(|(_, unit_pos, ..)| **unit_pos == pos)
+            .map(|(&id, _, &faction, status)| (id, faction, status.has_acted));
+
+        let command = match (clicked, self.selected) {
+            (Some((id, Faction::Player, false)), _) => {
+                self.selected = Some(id);
+                return;
+            }
+            (_, None) => return,
+            (Some((target, Faction::Enemy, _)), Some(attacker)) => {
+                GameCommand::Attack { attacker, target }
+            }
+            (Some(_), Some(_)) => return,
+            (None, Some(unit)) => GameCommand::Move { unit, to: pos },
+        };
+        self.send(command);
+    }
+
+    /// Ends the turn of whichever side is acting and drops the selection.
+    pub fn advance_turn(&mut self) {
+        self.selected = None;
+        self.send(GameCommand::EndTurn);
+    }
+
+    /// Where a unit stands, or `None` once it has fallen.
+    pub fn position(&mut self, unit: UnitId) -> Option<GridPosition> {
+        let world = self.app.world_mut();
+        world
+            .query_filtered::<(&UnitId, &GridPosition), With<Unit>>()
+            .iter(world)
+            .find(|(id, _)| **id == unit)
+            .map(|(_, pos)| *pos)
+    }
+
+    /// A unit's current stats, or `None` once it has fallen.
+    pub fn stats(&mut self, unit: UnitId) -> Option<Stats> {
+        let world = self.app.world_mut();
+        world
+            .query_filtered::<(&UnitId, &Stats), With<Unit>>()
+            .iter(world)
+            .find(|(id, _)| **id == unit)
+            .map(|(_, stats)| *stats)
+    }
+
+    /// The tiles a unit can walk to, as the [`PathCache`] has them: worked
+    /// out now if nothing is cached, or as cached from an earlier call.
+    pub fn reachable(&mut self, unit: UnitId) -> HashSet<GridPosition> {
+        let world = self.app.world_mut();
+        let (start, range) = world
+            .query_filtered::<(&UnitId, &GridPosition, &MoveRange), With<Unit>>()
+            .iter(world)
+            .find(|(id, ..)| **id == unit)
+            .map(|(_, pos, range)| (*pos, range.0))
+            .expect("the unit is on the board");
+        world.resource_scope(|world, mut paths: Mut<PathCache>| {
+            let map = world.resource::<GridMap>();
+            let index = world.resource::<UnitIndex>();
+            paths.reachable(map, index, unit, start, range).clone()
+        })
+    }
+
+    /// Why the executor turned down each command it rejected last frame.
+    pub fn rejections(&self) -> Vec<Rejection> {
+        self.app
+            .world()
+            .resource::<Messages<CommandRejected>>()
+            .iter_current_update_messages()
+            .map(|rejected| rejected.reason)
+            .collect()
+    }
+
+    pub fn turn(&self) -> &CurrentTurn {
+        self.app.world().resource::<CurrentTurn>()
+    }
+
+    pub fn assert_unit_at(&mut self, unit: UnitId, pos: GridPosition) {
+        assert_eq!(self.position(unit), Some(pos), "{unit:?} is not at {pos:?}");
+    }
+
+    pub fn assert_unit_fallen(&mut self, unit: UnitId) {
+        assert_eq!(self.position(unit), None, "{unit:?} is still standing");
+    }
+
+    pub fn assert_hp(&mut self, unit: UnitId, hp: u32) {
+        let stats = self
+            .stats(unit)
+            .unwrap_or_else(|| panic!("{unit:?} has fallen"));
+        assert_eq!(stats.hp, hp, "{unit:?} has the wrong hp");
+    }
+
+    /// Checks whose turn it is and which turn of the battle.
+    pub fn assert_turn(&self, faction: Faction, number: u32) {
+        let turn = self.turn();
+        assert_eq!((turn.faction, turn.number), (faction, number), "wrong turn");
+    }
+
+    pub fn snapshot(&mut self) -> BoardSnapshot {
+        BoardSnapshot::capture(self.app.world_mut())
+    }
+
+    /// Checks the whole board against a snapshot saved with
+    /// [`BoardSnapshot::to_ron`], failing with both side by side.
+    pub fn assert_snapshot(&mut self, expected: &str) {
+        let expected = BoardSnapshot::from_ron(expected)
+            .unwrap_or_else(|err| panic!("invalid snapshot: {err}"));
+        assert_eq!(
+            self.snapshot().to_ron(),
+            expected.to_ron(),
+            "board differs from the snapshot"
+        );
+    }
+
+    /// Checks who has won, with `None` for a battle still going.
+    pub fn assert_winner(&self, winner: Option<Faction>) {
+        assert_eq!(
+            self.app.world().resource::<BattleOutcome>().0,
+            winner,
+            "wrong outcome"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Tile kinds beyond the built-in ones, defined in `assets/data/tiles.ron`
+//! or registered by plugins.
+//!
+//! Each kind in the [`TileRegistry`] has a name and everything the rules
+//! need to know about it: whether units can walk on it and what a step onto
+//! it costs, how it blocks or thins out sight, the cover it gives, and the
+//! flat color it is drawn in. Laying out a battlefield scatters a kind's
+//! `scatter` tiles over the middle of the map, as it does water and
+//! mountains.
+//!
+//! A tile on the board is a [`TileType::Custom`] carrying a copy of its
+//! kind's rules, so nothing that asks whether a tile is walkable needs the
+//! registry; only its name does. The registry is read when a battlefield is
+//! laid out, so changes to it apply from the next battle.
+
+use std::borrow::Cow;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::data::{load_data_file, Layered};
+use crate::grid::{TerrainBonus, TileType};
+
+/// What the rules know about a custom tile, carried by every tile of its
+/// kind on the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Reflect)]
+pub struct CustomTile {
+    /// Where its kind is in the [`TileRegistry`].
+    pub id: u16,
+    pub walkable: bool,
+    pub move_cost: u32,
+    pub blocks_vision: bool,
+    pub sight_cost: u32,
+    pub bonus: TerrainBonus,
+    /// The flat color it is drawn in, as 8-bit sRGB. The tile sheet has no
+    /// frames for custom tiles.
+    pub color: [u8; 3],
+}
+
+fn default_cost() -> u32 {
+    1
+}
+
+fn default_walkable() -> bool {
+    true
+}
+
+/// A tile kind, as written in `tiles.ron`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct TileKind {
+    pub name: Cow<'static, str>,
+    #[serde(default = "default_walkable")]
+    pub walkable: bool,
+    /// Movement used up stepping onto it; grass costs 1.
+    #[serde(default = "default_cost")]
+    pub move_cost: u32,
+    #[serde(default)]
+    pub blocks_vision: bool,
+    /// Sight range used up looking through it; grass costs 1.
+    #[serde(default = "default_cost")]
+    pub sight_cost: u32,
+    #[serde(default)]
+    pub defense: u32,
+    /// Percent chance that an attack on a unit standing on it misses.
+    #[serde(default)]
+    pub avoid: u32,
+    /// Damage taken off attacks from more than one tile away.
+    #[serde(default)]
+    pub ranged_cover: u32,
+    pub color: [u8; 3],
+    /// How many of these each battlefield scatters over its middle.
+    #[serde(default)]
+    pub scatter: u32,
+}
+
+/// Every custom tile kind, in the order they were defined.
+#[derive(Resource, Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TileRegistry {
+    pub kinds: Vec<TileKind>,
+}
+
+impl TileRegistry {
+    /// Adds `kind`, replacing any kind of the same name, and returns the
+    /// tile it lays.
+    pub fn register(&mut self, kind: TileKind) -> TileType {
+        let id = match self.kinds.iter().position(|known| known.name == kind.name) {
+            Some(id) => {
+                warn!("Tile kind {:?} was registered twice", kind.name);
+                self.kinds[id] = kind;
+                id
+            }
+            None => {
+                self.kinds.push(kind);
+                self.kinds.len() - 1
+            }
+        };
+        self.tile(id as u16).expect("the kind was just added")
+    }
+
+    /// The tile of the kind at `id`.
+    pub fn tile(&self, id: u16) -> Option<TileType> {
+        let kind = self.kinds.get(id as usize)?;
+        Some(TileType::Custom(CustomTile {
+            id,
+            walkable: kind.walkable,
+            move_cost: kind.move_cost.max(1),
+            blocks_vision: kind.blocks_vision,
+            sight_cost: kind.sight_cost,
+            bonus: TerrainBonus {
+                defense: kind.defense,
+                avoid: kind.avoid,
+                ranged_cover: kind.ranged_cover,
+            },
+            color: kind.color,
+        }))
+    }
+
+    /// The tile called `name`: plain built-in terrain, or a registered
+    /// kind.
+    pub fn find(&self, name: &str) -> Option<TileType> {
+        let builtin = [
+            TileType::Grass,
+            TileType::Forest,
+            TileType::Water,
+            TileType::Mountain,
+        ];
+        if let Some(tile) = builtin.into_iter().find(|tile| tile.label() == name) {
+            return Some(tile);
+        }
+        let id = self.kinds.iter().position(|kind| kind.name == name)?;
+        self.tile(id as u16)
+    }
+
+    /// The name shown for `tile`.
+    pub fn label(&self, tile: TileType) -> &str {
+        match tile {
+            TileType::Custom(custom) => self
+                .kinds
+                .get(custom.id as usize)
+                .map_or("Unknown terrain", |kind| &kind.name),
+            _ => tile.label(),
+        }
+    }
+
+    /// Each kind's tile and how many of it a battlefield scatters.
+    pub fn scattered(&self) -> impl Iterator<Item = (TileType, u32)> + '_ {
+        (0..self.kinds.len() as u16).filter_map(|id| {
+            let count = self.kinds[id as usize].scatter;
+            Some((self.tile(id)?, count)).filter(|_| count > 0)
+        })
+    }
+}
+
+impl Layered for TileRegistry {
+    fn ids(&self) -> Vec<String> {
+        self.kinds
+            .iter()
+            .map(|kind| kind.name.to_string())
+            .collect()
+    }
+
+    fn overlay(&mut self, layer: Self) {
+        for kind in layer.kinds {
+            match self.kinds.iter_mut().find(|known| known.name == kind.name) {
+                Some(known) => *known = kind,
+                None => self.kinds.push(kind),
+            }
+        }
+    }
+}
+
+pub fn load_tile_registry() -> TileRegistry {
+    load_data_file("tiles.ron")
+}
+
+/// Registering tile kinds from a plugin.
+pub trait RegisterTileKind {
+    fn register_tile_kind(&mut self, kind: TileKind) -> &mut Self;
+}
+
+impl RegisterTileKind for App {
+    fn register_tile_kind(&mut self, kind: TileKind) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(load_tile_registry)
+            .register(kind);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> TileRegistry {
+        ron::from_str(
+            r#"(kinds: [
+                (name: "Swamp", move_cost: 3, color: (60, 80, 40), scatter: 4),
+                (name: "Ice", move_cost: 0, color: (200, 220, 255)),
+            ])"#,
+        )
+        .unwrap()
+    }
+
+    fn kind(name: &str, move_cost: u32) -> TileKind {
+        let kind = format!("(name: {name:?}, move_cost: {move_cost}, color: (0, 0, 0))");
+        ron::from_str(&kind).unwrap()
+    }
+
+    #[test]
+    fn registering_a_name_again_replaces_the_kind_and_keeps_its_id() {
+        let mut tiles = registry();
+        let TileType::Custom(swamp) = tiles.register(kind("Swamp", 2)) else {
+            panic!("expected a custom tile");
+        };
+        assert_eq!((swamp.id, swamp.move_cost), (0, 2));
+        assert_eq!(tiles.kinds.len(), 2);
+        assert_eq!(tiles.find("Swamp"), Some(TileType::Custom(swamp)));
+    }
+
+    #[test]
+    fn built_in_names_are_found_before_registered_ones() {
+        let mut tiles = registry();
+        tiles.register(kind("Forest", 5));
+        assert_eq!(tiles.find("Forest"), Some(TileType::Forest));
+        assert_eq!(tiles.find("Ice").map(|ice| tiles.label(ice)), Some("Ice"));
+        assert_eq!(tiles.find("Lava"), None);
+    }
+
+    #[test]
+    fn a_free_step_still_costs_one() {
+        let ice = registry().find("Ice").unwrap();
+        assert_eq!(ice.move_cost(), 1);
+    }
+
+    #[test]
+    fn a_layer_replaces_kinds_by_name_and_adds_new_ones() {
+        let mut tiles = registry();
+        let layer = TileRegistry {
+            kinds: vec![kind("Ice", 2), kind("Lava", 4)],
+        };
+        tiles.overlay(layer);
+        assert_eq!(tiles.ids(), ["Swamp", "Ice", "Lava"]);
+        assert_eq!(tiles.find("Ice").unwrap().move_cost(), 2);
+    }
+
+    #[test]
+    fn only_kinds_with_a_scatter_count_are_scattered() {
+        let tiles = registry();
+        let scattered: Vec<(TileType, u32)> = tiles.scattered().collect();
+        assert_eq!(scattered, [(tiles.find("Swamp").unwrap(), 4)]);
+    }
+}
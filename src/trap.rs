@@ -0,0 +1,124 @@
+//! Hidden traps laid on the battlefield.
+//!
+//! A trap stays invisible until an opposing unit steps on it or a unit with
+//! a keen eye ends its move close by. Springing a trap uses it up.
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+#[cfg(feature = "render")]
+use crate::constants::*;
+use crate::grid::GridPosition;
+use crate::state::AppState;
+use crate::unit::Faction;
+
+/// Tiles around them that archers and snipers spot traps in.
+pub const TRAP_SENSE_RADIUS: u32 = 2;
+
+#[cfg(feature = "render")]
+const TRAP_COLOR: Color = Color::srgba(0.75, 0.35, 0.10, 0.6);
+#[cfg(feature = "render")]
+const SPRUNG_COLOR: Color = Color::srgba(0.35, 0.35, 0.35, 0.5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Reflect)]
+pub enum TrapKind {
+    /// Deals this much damage, but never drops the unit below 1 HP.
+    Spikes(u32),
+    /// Holds the unit in place through its next turn.
+    Snare,
+    /// Rallies the trap owner's units with a short attack boost.
+    Alarm,
+}
+
+impl TrapKind {
+    #[cfg(feature = "render")]
+    fn glyph(self) -> &'static str {
+        match self {
+            TrapKind::Spikes(_) => "^",
+            TrapKind::Snare => "#",
+            TrapKind::Alarm => "!",
+        }
+    }
+}
+
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Reflect)]
+#[reflect(Component)]
+pub struct Trap {
+    pub kind: TrapKind,
+    /// The side that laid the trap. Its own units never set it off.
+    pub owner: Faction,
+    pub revealed: bool,
+    pub sprung: bool,
+}
+
+/// Layout of the default battlefield's traps.
+const TRAPS: [(TrapKind, Faction, GridPosition); 3] = [
+    (TrapKind::Spikes(5), Faction::Enemy, GridPosition::new(6, 3)),
+    (TrapKind::Snare, Faction::Enemy, GridPosition::new(4, 5)),
+    (TrapKind::Alarm, Faction::Enemy, GridPosition::new(7, 1)),
+];
+
+/// Tiles the default battlefield's traps are laid on.
+pub fn trap_sites() -> impl Iterator<Item = GridPosition> {
+    TRAPS.into_iter().map(|(_, _, pos)| pos)
+}
+
+pub fn spawn_traps(mut commands: Commands, state: Res<State<AppState>>) {
+    for (kind, owner, pos) in TRAPS {
+        commands.spawn((
+            Trap {
+                kind,
+                owner,
+                revealed: false,
+                sprung: false,
+            },
+            pos,
+            DespawnOnExit(*state.get()),
+        ));
+    }
+}
+
+/// Gives newly laid traps their sprite and glyph, hidden until revealed.
+#[cfg(feature = "render")]
+pub fn add_trap_sprites_system(
+    mut commands: Commands,
+    traps: Query<(Entity, &Trap, &GridPosition), Added<Trap>>,
+) {
+    for (entity, trap, pos) in &traps {
+        commands
+            .entity(entity)
+            .insert((
+                Sprite::from_color(TRAP_COLOR, Vec2::splat(TILE_SIZE * 0.5)),
+                Transform::from_translation(pos.to_world(STRUCTURE_Z + 0.1)),
+                Visibility::Hidden,
+            ))
+            .with_child((
+                Text2d::new(trap.kind.glyph()),
+                TextFont {
+                    font_size: TILE_SIZE * 0.3,
+                    ..default()
+                },
+                TextColor(Color::BLACK),
+                Transform::from_xyz(0.0, 0.0, 0.1),
+            ));
+    }
+}
+
+/// Shows traps once they are revealed and greys them out once sprung.
+#[cfg(feature = "render")]
+pub fn update_trap_visuals_system(
+    mut traps: Query<(&Trap, &mut Sprite, &mut Visibility), Changed<Trap>>,
+) {
+    for (trap, mut sprite, mut visibility) in &mut traps {
+        *visibility = if trap.revealed {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        sprite.color = if trap.sprung {
+            SPRUNG_COLOR
+        } else {
+            TRAP_COLOR
+        };
+    }
+}
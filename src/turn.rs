@@ -0,0 +1,239 @@
+//! Turn order, day and night, and the turn indicator. Who wins is decided in
+//! [`victory`](crate::victory).
+
+#[cfg(feature = "render")]
+use std::fmt::{Debug, Write};
+
+use bevy::prelude::*;
+
+#[cfg(feature = "render")]
+use crate::bindings::InputAction;
+#[cfg(feature = "render")]
+use crate::constants::*;
+#[cfg(feature = "render")]
+use crate::grid::GridMap;
+#[cfg(feature = "render")]
+use crate::hud::{HudEvent, HudLabel, HudSources};
+#[cfg(feature = "render")]
+use crate::locale::Locale;
+#[cfg(feature = "render")]
+use crate::settings::Settings;
+#[cfg(feature = "render")]
+use crate::state::AppState;
+use crate::unit::Faction;
+#[cfg(feature = "render")]
+use crate::unit::FactionPalette;
+
+/// Turns in each stretch of daylight and of darkness.
+pub const DAY_LENGTH: u32 = 3;
+/// Percent chance for any attack to miss at night, on top of terrain avoid.
+pub const NIGHT_MISS_CHANCE: u32 = 10;
+
+#[cfg(feature = "render")]
+const NIGHT_TINT: Color = Color::srgba(0.05, 0.05, 0.25, 0.35);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeOfDay {
+    Day,
+    Night,
+}
+
+impl TimeOfDay {
+    /// Battles open in daylight; day and night then take turns every
+    /// [`DAY_LENGTH`] turns.
+    pub fn of_turn(number: u32) -> Self {
+        if (number.saturating_sub(1) / DAY_LENGTH).is_multiple_of(2) {
+            TimeOfDay::Day
+        } else {
+            TimeOfDay::Night
+        }
+    }
+}
+
+/// Whose turn it is. Only the command executor advances this, so live play
+/// and replays always agree on the turn sequence.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Reflect)]
+#[reflect(Resource)]
+pub struct CurrentTurn {
+    pub faction: Faction,
+    pub number: u32,
+}
+
+impl Default for CurrentTurn {
+    fn default() -> Self {
+        Self {
+            faction: Faction::Player,
+            number: 1,
+        }
+    }
+}
+
+impl CurrentTurn {
+    /// Derived from the turn number, so it advances with the turn and
+    /// replays always agree on it.
+    pub fn time_of_day(&self) -> TimeOfDay {
+        TimeOfDay::of_turn(self.number)
+    }
+}
+
+/// The winning faction, once a victory condition or the scenario script has
+/// decided the battle.
+#[derive(Resource, Clone, Copy, Debug, Default, Reflect)]
+#[reflect(Resource)]
+pub struct BattleOutcome(pub Option<Faction>);
+
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct TurnText;
+
+/// The banner across the middle of the screen naming the side whose phase
+/// has just begun, taken down once its time is up.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct PhaseBanner(Timer);
+
+/// Translucent layer over the battlefield that darkens it at night.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct NightOverlay;
+
+#[cfg(feature = "render")]
+pub fn setup_turn_ui(mut commands: Commands) {
+    commands.spawn((
+        TurnText,
+        HudLabel {
+            events: &[
+                HudEvent::Turn,
+                HudEvent::Gold,
+                HudEvent::Outcome,
+                HudEvent::Palette,
+                HudEvent::Bindings,
+                HudEvent::Language,
+            ],
+            write: write_turn_banner,
+        },
+        Text::new(""),
+        TextFont {
+            font_size: 22.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        },
+    ));
+}
+
+/// The turn, time of day, phase, and gold, with the end turn key on this
+/// device's turns, or the outcome once the battle is decided.
+#[cfg(feature = "render")]
+fn write_turn_banner(hud: &HudSources, text: &mut String, color: &mut Color) {
+    // Lightened so the banner stays readable over the board.
+    let faction = hud.outcome.0.unwrap_or(hud.turn.faction);
+    *color = hud.palette.color(faction).mix(&Color::WHITE, 0.5);
+    // In hotseat neither side is this device's own, so the banner names the
+    // winner and shows the gold of the side taking its turn.
+    let hotseat = hud.seats.hotseat();
+    let home = hud.seats.home();
+    let purse = if hotseat { hud.turn.faction } else { home };
+    let locale = &hud.locale;
+    let name = |value: &dyn Debug| locale.tr(&format!("{value:?}")).to_string();
+    *text += &match hud.outcome.0 {
+        Some(winner) if hotseat => locale.fill("{} side wins!", &[&name(&winner)]),
+        Some(winner) if winner == home => locale.tr("Victory!").to_string(),
+        Some(_) => locale.tr("Defeat...").to_string(),
+        None => locale.fill(
+            "Turn {} ({}) - {} Phase  Gold: {}",
+            &[
+                &hud.turn.number,
+                &name(&hud.turn.time_of_day()),
+                &name(&hud.turn.faction),
+                &hud.treasury.get(purse),
+            ],
+        ),
+    };
+    if hud.outcome.0.is_none() && hud.seats.is_local(hud.turn.faction) {
+        let _ = write!(
+            text,
+            "  [{}] {}",
+            hud.bindings.hint(InputAction::EndTurn),
+            locale.tr("End turn")
+        );
+    }
+}
+
+/// Puts up the phase banner as each turn begins, for as long as the pacing
+/// setting keeps it up, and takes it down again.
+#[cfg(feature = "render")]
+pub fn phase_banner_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    turn: Res<CurrentTurn>,
+    outcome: Res<BattleOutcome>,
+    settings: Res<Settings>,
+    (palette, locale): (Res<FactionPalette>, Res<Locale>),
+    mut banners: Query<(Entity, &mut PhaseBanner)>,
+) {
+    for (banner, mut timer) in &mut banners {
+        if turn.is_changed() || timer.0.tick(time.delta()).is_finished() {
+            commands.entity(banner).despawn();
+        }
+    }
+    let seconds = settings.pacing.banner;
+    if !turn.is_changed() || outcome.0.is_some() || seconds <= 0.0 {
+        return;
+    }
+    let faction = turn.faction;
+    commands.spawn((
+        PhaseBanner(Timer::from_seconds(seconds, TimerMode::Once)),
+        Text::new(locale.fill("{} Phase", &[&locale.tr(&format!("{faction:?}"))])),
+        TextFont {
+            font_size: 40.0,
+            ..default()
+        },
+        TextColor(palette.color(faction).mix(&Color::WHITE, 0.5)),
+        TextLayout::new_with_justify(Justify::Center),
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            top: Val::Percent(40.0),
+            padding: UiRect::vertical(Val::Px(12.0)),
+            ..default()
+        },
+        BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+        DespawnOnExit(AppState::GamePlay),
+    ));
+}
+
+#[cfg(feature = "render")]
+pub fn setup_night_overlay(mut commands: Commands, map: Res<GridMap>) {
+    let size = map.world_size();
+    commands.spawn((
+        NightOverlay,
+        Sprite::from_color(NIGHT_TINT, size),
+        Transform::from_translation(map.center().extend(NIGHT_Z)),
+        Visibility::Hidden,
+    ));
+}
+
+/// Shows the night tint during battles whenever it is night.
+#[cfg(feature = "render")]
+pub fn update_night_overlay_system(
+    turn: Res<CurrentTurn>,
+    state: Res<State<AppState>>,
+    mut overlay: Single<&mut Visibility, With<NightOverlay>>,
+) {
+    if !turn.is_changed() && !state.is_changed() {
+        return;
+    }
+    let in_battle = matches!(state.get(), AppState::GamePlay | AppState::Replay);
+    let visibility = if in_battle && turn.time_of_day() == TimeOfDay::Night {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    overlay.set_if_neq(visibility);
+}
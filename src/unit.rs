@@ -0,0 +1,404 @@
+//! Unit components and the starting roster.
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ability::Cooldowns;
+#[cfg(feature = "render")]
+use crate::animation::UnitAnimation;
+use crate::class::{Experience, UnitClass};
+#[cfg(feature = "render")]
+use crate::constants::*;
+#[cfg(feature = "render")]
+use crate::contrast::HighContrast;
+use crate::economy::NextUnitId;
+use crate::facing::Facing;
+use crate::grid::{GridMap, GridPosition};
+use crate::items::ItemCatalog;
+use crate::launch::LaunchOptions;
+use crate::leader::{AuraBonus, Leader, STARTING_LEADERS};
+use crate::rescue::Rescue;
+#[cfg(feature = "render")]
+use crate::settings::Palette;
+use crate::state::AppState;
+use crate::status::StatusEffects;
+use crate::stealth::Stealth;
+use crate::stress::stress_roster;
+
+/// Marker for every combat unit on the board.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Unit;
+
+/// Stable identifier for a unit. Unlike `Entity`, ids are assigned in spawn
+/// order and therefore stay the same between a live battle and its replay.
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    Reflect,
+)]
+#[reflect(Component)]
+pub struct UnitId(pub u32);
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
+pub enum Faction {
+    Player,
+    Enemy,
+}
+
+impl Faction {
+    pub fn opponent(self) -> Faction {
+        match self {
+            Faction::Player => Faction::Enemy,
+            Faction::Enemy => Faction::Player,
+        }
+    }
+}
+
+/// The color each faction is drawn in, and the selected unit's range
+/// highlights, picked from the player's [`Palette`] setting.
+#[cfg(feature = "render")]
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct FactionPalette {
+    pub player: Color,
+    pub enemy: Color,
+    /// Tiles the selected unit can move to.
+    pub moves: Color,
+    /// Enemies it can attack.
+    pub attacks: Color,
+    /// Allies it can heal.
+    pub heals: Color,
+}
+
+#[cfg(feature = "render")]
+impl FactionPalette {
+    pub fn new(palette: Palette) -> Self {
+        match palette {
+            Palette::Classic => Self {
+                player: PLAYER_COLOR,
+                enemy: ENEMY_COLOR,
+                moves: MOVE_HIGHLIGHT_COLOR,
+                attacks: ATTACK_HIGHLIGHT_COLOR,
+                heals: HEAL_HIGHLIGHT_COLOR,
+            },
+            Palette::Deuteranopia => {
+                Self::colorblind(DEUTERANOPIA_PLAYER_COLOR, DEUTERANOPIA_ENEMY_COLOR)
+            }
+            Palette::Protanopia => {
+                Self::colorblind(PROTANOPIA_PLAYER_COLOR, PROTANOPIA_ENEMY_COLOR)
+            }
+            Palette::Tritanopia => {
+                Self::colorblind(TRITANOPIA_PLAYER_COLOR, TRITANOPIA_ENEMY_COLOR)
+            }
+        }
+    }
+
+    /// A palette whose move and attack highlights are tints of the two
+    /// sides' colors, which are already told apart.
+    fn colorblind(player: Color, enemy: Color) -> Self {
+        Self {
+            player,
+            enemy,
+            moves: player.with_alpha(0.35),
+            attacks: enemy.with_alpha(0.35),
+            heals: COLORBLIND_HEAL_HIGHLIGHT_COLOR,
+        }
+    }
+
+    /// This palette as drawn with `contrast`.
+    pub fn with_contrast(self, contrast: HighContrast) -> Self {
+        Self {
+            player: contrast.apply(self.player),
+            enemy: contrast.apply(self.enemy),
+            moves: contrast.overlay(self.moves),
+            attacks: contrast.overlay(self.attacks),
+            heals: contrast.overlay(self.heals),
+        }
+    }
+
+    pub fn color(&self, faction: Faction) -> Color {
+        match faction {
+            Faction::Player => self.player,
+            Faction::Enemy => self.enemy,
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+impl Default for FactionPalette {
+    fn default() -> Self {
+        Self::new(Palette::default())
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+#[reflect(Component)]
+pub struct Stats {
+    pub hp: u32,
+    pub max_hp: u32,
+    pub attack: u32,
+    pub defense: u32,
+}
+
+impl Stats {
+    pub fn is_alive(&self) -> bool {
+        self.hp > 0
+    }
+
+    /// Restores up to `amount` HP without going over the maximum, returning
+    /// how much was actually healed.
+    pub fn heal(&mut self, amount: u32) -> u32 {
+        let healed = amount.min(self.max_hp.saturating_sub(self.hp));
+        self.hp += healed;
+        healed
+    }
+}
+
+/// How many tiles a unit can walk per turn.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Reflect)]
+#[reflect(Component)]
+pub struct MoveRange(pub u32);
+
+/// How many tiles away a unit can strike.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Reflect)]
+#[reflect(Component)]
+pub struct AttackRange(pub u32);
+
+/// What a unit has already done this turn.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct TurnStatus {
+    pub has_moved: bool,
+    pub has_acted: bool,
+}
+
+/// Which unit stands on each tile, kept in step with the board by
+/// [`update_unit_index_system`] so lookups don't scan every unit. Carried
+/// units are left out: their carrier holds the tile.
+///
+/// The index is refreshed once each frame's commands are applied, so input
+/// and the AI see the board as the last frame left it. The executor tracks
+/// positions itself while it applies a batch.
+#[derive(Resource, Default, Debug)]
+pub struct UnitIndex {
+    tiles: HashMap<GridPosition, Entity>,
+    units: HashMap<Entity, (GridPosition, Faction)>,
+    factions: HashMap<Faction, HashSet<Entity>>,
+}
+
+impl UnitIndex {
+    /// The unit standing at `pos`, if any.
+    pub fn at(&self, pos: GridPosition) -> Option<Entity> {
+        self.tiles.get(&pos).copied()
+    }
+
+    pub fn is_occupied(&self, pos: GridPosition) -> bool {
+        self.tiles.contains_key(&pos)
+    }
+
+    /// Every occupied tile.
+    pub fn occupied(&self) -> HashSet<GridPosition> {
+        self.tiles.keys().copied().collect()
+    }
+
+    /// Every occupied tile but `pos`, for a unit at `pos` that is about to
+    /// move.
+    pub fn occupied_except(&self, pos: GridPosition) -> HashSet<GridPosition> {
+        self.tiles
+            .keys()
+            .copied()
+            .filter(|tile| *tile != pos)
+            .collect()
+    }
+
+    /// The units of `faction` on the board.
+    pub fn faction(&self, faction: Faction) -> impl Iterator<Item = Entity> + '_ {
+        self.factions.get(&faction).into_iter().flatten().copied()
+    }
+
+    fn insert(&mut self, entity: Entity, pos: GridPosition, faction: Faction) {
+        self.tiles.insert(pos, entity);
+        self.units.insert(entity, (pos, faction));
+        self.factions.entry(faction).or_default().insert(entity);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        let Some((pos, faction)) = self.units.remove(&entity) else {
+            return;
+        };
+        // Another unit may already have stepped onto the tile.
+        if self.tiles.get(&pos) == Some(&entity) {
+            self.tiles.remove(&pos);
+        }
+        if let Some(members) = self.factions.get_mut(&faction) {
+            members.remove(&entity);
+        }
+    }
+}
+
+/// Updates the [`UnitIndex`] for units that moved, were picked up or set
+/// down, joined the board, or left it.
+pub fn update_unit_index_system(
+    mut index: ResMut<UnitIndex>,
+    changed: Query<
+        (Entity, &GridPosition, &Faction, &Rescue),
+        (
+            With<Unit>,
+            Or<(Added<Unit>, Changed<GridPosition>, Changed<Rescue>)>,
+        ),
+    >,
+    mut removed: RemovedComponents<Unit>,
+) {
+    for entity in removed.read() {
+        index.remove(entity);
+    }
+    // Clear every changed unit before placing any, so two units swapping
+    // tiles don't erase each other.
+    for (entity, ..) in &changed {
+        index.remove(entity);
+    }
+    for (entity, pos, faction, rescue) in &changed {
+        if !rescue.is_carried() {
+            index.insert(entity, *pos, *faction);
+        }
+    }
+}
+
+/// Starting positions for both sides. Spawn order defines each unit's id.
+pub const STARTING_ROSTER: [(Faction, UnitClass, GridPosition); 9] = [
+    (Faction::Player, UnitClass::Knight, GridPosition::new(2, 3)),
+    (Faction::Player, UnitClass::Knight, GridPosition::new(2, 5)),
+    (Faction::Player, UnitClass::Archer, GridPosition::new(1, 2)),
+    (Faction::Player, UnitClass::Mage, GridPosition::new(1, 5)),
+    (Faction::Player, UnitClass::Healer, GridPosition::new(0, 4)),
+    (Faction::Enemy, UnitClass::Knight, GridPosition::new(9, 4)),
+    (Faction::Enemy, UnitClass::Archer, GridPosition::new(10, 2)),
+    (Faction::Enemy, UnitClass::Mage, GridPosition::new(10, 5)),
+    (Faction::Enemy, UnitClass::Healer, GridPosition::new(11, 4)),
+];
+
+pub fn spawn_units(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    launch: Res<LaunchOptions>,
+    map: Res<GridMap>,
+    catalog: Res<ItemCatalog>,
+    mut next_id: ResMut<NextUnitId>,
+) {
+    let roster = match launch.stress {
+        Some(units) => stress_roster(&map, units),
+        None => STARTING_ROSTER.to_vec(),
+    };
+    for (index, &(faction, class, pos)) in roster.iter().enumerate() {
+        let id = UnitId(index as u32);
+        let entity = spawn_unit(
+            &mut commands,
+            &catalog,
+            *state.get(),
+            id,
+            faction,
+            class,
+            pos,
+        );
+        if launch.stress.is_none() && STARTING_LEADERS.contains(&id) {
+            commands.entity(entity).insert(Leader::default());
+        }
+    }
+    next_id.0 = roster.len() as u32;
+}
+
+/// The shape in the corner of a unit that tells the sides apart whatever
+/// the palette: a square for the player's side, a diamond for the enemy's.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct FactionMarker;
+
+#[cfg(feature = "render")]
+fn faction_marker(faction: Faction) -> impl Bundle {
+    let corner = UNIT_SIZE / 2.0 - FACTION_MARKER_SIZE / 2.0;
+    let turn = match faction {
+        Faction::Player => 0.0,
+        Faction::Enemy => std::f32::consts::FRAC_PI_4,
+    };
+    (
+        FactionMarker,
+        Sprite::from_color(Color::BLACK, Vec2::splat(FACTION_MARKER_SIZE)),
+        Transform::from_xyz(corner, -corner, 0.15).with_rotation(Quat::from_rotation_z(turn)),
+        children![(
+            Sprite::from_color(Color::WHITE, Vec2::splat(FACTION_MARKER_SIZE * 0.6)),
+            Transform::from_xyz(0.0, 0.0, 0.01),
+        )],
+    )
+}
+
+/// Spawns a fresh level 1 unit with its class's stats and starting items.
+/// Its sprite picks up its faction's color from the [`FactionPalette`] once
+/// it is on the board, and a [`FactionMarker`] shows its side by shape.
+pub fn spawn_unit(
+    commands: &mut Commands,
+    catalog: &ItemCatalog,
+    state: AppState,
+    id: UnitId,
+    faction: Faction,
+    class: UnitClass,
+    pos: GridPosition,
+) -> Entity {
+    let unit = commands
+        .spawn((
+            Unit,
+            id,
+            faction,
+            class,
+            pos,
+            class.base_stats(),
+            class.move_range(),
+            class.attack_range(),
+            class.vision(),
+            catalog.loadout(class),
+            (
+                TurnStatus::default(),
+                Experience::default(),
+                StatusEffects::default(),
+                AuraBonus::default(),
+                Rescue::default(),
+                Stealth {
+                    hidden: class.stealthy(),
+                },
+                Facing::for_faction(faction),
+                Cooldowns::default(),
+            ),
+            DespawnOnExit(state),
+        ))
+        .id();
+    #[cfg(feature = "render")]
+    commands
+        .entity(unit)
+        .insert((
+            UnitAnimation::default(),
+            Sprite::from_color(Color::WHITE, Vec2::splat(UNIT_SIZE * class.sprite_scale())),
+            Transform::from_translation(pos.to_world(UNIT_Z)),
+        ))
+        .with_child((
+            Text2d::new(class.glyph()),
+            TextFont {
+                font_size: UNIT_SIZE * 0.6,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            Transform::from_xyz(0.0, 0.0, 0.1),
+        ))
+        .with_child(faction_marker(faction));
+    unit
+}
@@ -0,0 +1,659 @@
+//! Checking the maps, data files, and scenario scripts for mistakes before
+//! a battle trips over them.
+//!
+//! [`validate`] loads everything the way a battle would, lays out every
+//! map, and reports what it finds as [`Diagnostic`]s: units starting off
+//! the map or on water, objectives no one can walk to, chests and loadouts
+//! handing out items `items.ron` doesn't define, classes given abilities
+//! `abilities.ron` doesn't define, scripts that don't compile or spawn
+//! classes that don't exist. `--validate` prints the report for the
+//! game as installed, mods included, and fails if it has any errors. Each
+//! battle also logs the problems on its own map when it starts.
+//!
+//! Reachability ignores units and counts doors and drawbridges as open,
+//! since a side can always open them on the way.
+
+use std::cmp::Reverse;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+
+use bevy::prelude::*;
+
+use crate::ability::{AbilityCatalog, AbilityEffect, AbilityId};
+use crate::chest::{ChestLayout, Loot};
+use crate::class::UnitClass;
+use crate::command::CommandLog;
+use crate::constants::{GRID_HEIGHT, GRID_WIDTH};
+use crate::data::{data_path, read_data_file, script_path, Layered};
+use crate::grid::{lay_out, BattleMap, GridMap, GridPosition, TileType};
+use crate::items::{ItemCatalog, ItemId};
+use crate::launch::LaunchOptions;
+use crate::structure::{StructureKind, STRUCTURES};
+use crate::tiles::TileRegistry;
+use crate::unit::{Faction, STARTING_ROSTER};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Something that works but probably isn't what was meant.
+    Warning,
+    /// Something that breaks a battle.
+    Error,
+}
+
+/// What is wrong.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// A data file or script that couldn't be read or parsed.
+    Unreadable(String),
+    /// A unit or objective placed outside the map.
+    OffMap {
+        what: String,
+    },
+    /// A unit starting on a tile it can't stand on.
+    Blocked {
+        what: String,
+        terrain: String,
+    },
+    /// Two units starting on the same tile.
+    Overlap {
+        what: String,
+        other: String,
+    },
+    /// An objective `side`, or either side if `None`, has no way of walking
+    /// to.
+    Unreachable {
+        what: String,
+        side: Option<Faction>,
+    },
+    UnknownClass(String),
+    UnknownSide(String),
+    UnknownItem(ItemId),
+    UnknownAbility(AbilityId),
+    UnknownTerrain(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Unreadable(err) => write!(f, "{err}"),
+            Problem::OffMap { what } => write!(f, "{what} is off the map"),
+            Problem::Blocked { what, terrain } => write!(f, "{what} starts on {terrain}"),
+            Problem::Overlap { what, other } => {
+                write!(f, "{what} starts on the same tile as {other}")
+            }
+            Problem::Unreachable {
+                what,
+                side: Some(side),
+            } => write!(f, "{what} can't be reached by {side:?}"),
+            Problem::Unreachable { what, side: None } => {
+                write!(f, "{what} can't be reached by either side")
+            }
+            Problem::UnknownClass(class) => write!(f, "unknown class {class:?}"),
+            Problem::UnknownSide(side) => write!(f, "unknown side {side:?}"),
+            Problem::UnknownItem(id) => write!(f, "unknown item {:?}", id.0),
+            Problem::UnknownAbility(id) => write!(f, "unknown ability {:?}", id.0),
+            Problem::UnknownTerrain(name) => write!(f, "unknown terrain {name:?}"),
+        }
+    }
+}
+
+/// One problem found, and where.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The map or file it was found in.
+    pub origin: String,
+    /// The tile it is about, on a map.
+    pub at: Option<GridPosition>,
+    /// The line it is on, in a script.
+    pub line: Option<usize>,
+    pub problem: Problem,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, origin: impl Into<String>, problem: Problem) -> Self {
+        Self {
+            severity,
+            origin: origin.into(),
+            at: None,
+            line: None,
+            problem,
+        }
+    }
+
+    fn at(mut self, pos: GridPosition) -> Self {
+        self.at = Some(pos);
+        self
+    }
+
+    fn line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity}: {}", self.origin)?;
+        if let Some(at) = self.at {
+            write!(f, " ({}, {})", at.x, at.y)?;
+        }
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        write!(f, ": {}", self.problem)
+    }
+}
+
+/// Reads a data file for checking, noting it if it can't be.
+fn read_checked<T: Layered + Default>(name: &str, diagnostics: &mut Vec<Diagnostic>) -> T {
+    checked(name, read_data_file(name), diagnostics)
+}
+
+/// The data file `name` as read, or, noting why it couldn't be, its
+/// default.
+pub fn checked<T: Default>(
+    name: &str,
+    read: Result<T, String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> T {
+    read.unwrap_or_else(|err| {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            data_path(name).display().to_string(),
+            Problem::Unreadable(err),
+        ));
+        T::default()
+    })
+}
+
+/// Checks every map, data file, and scenario script, errors first.
+pub fn validate() -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let catalog: ItemCatalog = read_checked("items.ron", &mut diagnostics);
+    let chests: ChestLayout = read_checked("chests.ron", &mut diagnostics);
+    let tiles: TileRegistry = read_checked("tiles.ron", &mut diagnostics);
+    let abilities: AbilityCatalog = read_checked("abilities.ron", &mut diagnostics);
+
+    diagnostics.extend(validate_items(&catalog, &chests));
+    diagnostics.extend(validate_abilities(&abilities, &tiles));
+    for battle_map in BattleMap::ALL {
+        let mut map = GridMap::new(GRID_WIDTH, GRID_HEIGHT);
+        lay_out(&mut map, &chests, &tiles, battle_map);
+        diagnostics.extend(validate_map(battle_map, &map, &chests));
+        diagnostics.extend(validate_script(battle_map));
+    }
+    diagnostics.sort_by_key(|diagnostic| Reverse(diagnostic.severity));
+    diagnostics
+}
+
+/// Checks that every item a loadout or chest hands out is defined.
+pub fn validate_items(catalog: &ItemCatalog, chests: &ChestLayout) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (class, items) in &catalog.loadouts {
+        for id in items.iter().filter(|id| catalog.get(id).is_none()) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                format!("{}, {class:?} loadout", data_path("items.ron").display()),
+                Problem::UnknownItem(id.clone()),
+            ));
+        }
+    }
+    for chest in &chests.chests {
+        if let Loot::Item(id) = &chest.loot {
+            if catalog.get(id).is_none() {
+                diagnostics.push(
+                    Diagnostic::new(
+                        Severity::Error,
+                        data_path("chests.ron").display().to_string(),
+                        Problem::UnknownItem(id.clone()),
+                    )
+                    .at(chest.at),
+                );
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Checks that every ability a class lists is defined, and that every
+/// terrain an ability lays exists.
+pub fn validate_abilities(abilities: &AbilityCatalog, tiles: &TileRegistry) -> Vec<Diagnostic> {
+    let origin = data_path("abilities.ron").display().to_string();
+    let mut diagnostics = Vec::new();
+    for (class, ids) in &abilities.classes {
+        for id in ids.iter().filter(|id| abilities.get(id).is_none()) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                format!("{origin}, {class:?} abilities"),
+                Problem::UnknownAbility(id.clone()),
+            ));
+        }
+    }
+    for (id, ability) in &abilities.abilities {
+        for effect in &ability.effects {
+            if let AbilityEffect::ModifyTerrain(name) = effect {
+                if tiles.find(name).is_none() {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Error,
+                        format!("{origin}, {:?}", id.0),
+                        Problem::UnknownTerrain(name.clone()),
+                    ));
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Whether a side can get onto `tile`, opening what's in its way.
+fn passable(tile: TileType) -> bool {
+    tile.is_walkable() || matches!(tile, TileType::Door { .. } | TileType::Bridge { .. })
+}
+
+/// Every tile reachable on foot from any of `starts`, however far.
+fn flood(map: &GridMap, starts: impl IntoIterator<Item = GridPosition>) -> HashSet<GridPosition> {
+    let mut seen: HashSet<GridPosition> = starts
+        .into_iter()
+        .filter(|&pos| map.tile(pos).is_some_and(passable))
+        .collect();
+    let mut frontier: VecDeque<GridPosition> = seen.iter().copied().collect();
+    while let Some(pos) = frontier.pop_front() {
+        for next in pos.neighbors() {
+            if map.tile(next).is_some_and(passable) && seen.insert(next) {
+                frontier.push_back(next);
+            }
+        }
+    }
+    seen
+}
+
+/// Checks the starting roster and objectives of `battle_map`, laid out on
+/// `map`.
+pub fn validate_map(battle_map: BattleMap, map: &GridMap, chests: &ChestLayout) -> Vec<Diagnostic> {
+    let origin = format!("{battle_map:?}");
+    let mut diagnostics = Vec::new();
+    let mut report = |severity, at, problem| {
+        diagnostics.push(Diagnostic::new(severity, origin.clone(), problem).at(at));
+    };
+
+    let mut spawns: Vec<(GridPosition, String)> = Vec::new();
+    for (index, &(faction, class, pos)) in STARTING_ROSTER.iter().enumerate() {
+        let what = format!("{faction:?} {class:?} (unit {index})");
+        match map.tile(pos) {
+            None => report(Severity::Error, pos, Problem::OffMap { what: what.clone() }),
+            Some(tile) if !tile.is_walkable() => report(
+                Severity::Error,
+                pos,
+                Problem::Blocked {
+                    what: what.clone(),
+                    terrain: tile.label().to_string(),
+                },
+            ),
+            Some(_) => {}
+        }
+        if let Some((_, other)) = spawns.iter().find(|(at, _)| *at == pos) {
+            report(
+                Severity::Error,
+                pos,
+                Problem::Overlap {
+                    what: what.clone(),
+                    other: other.clone(),
+                },
+            );
+        }
+        spawns.push((pos, what));
+    }
+
+    let reach = |side: Faction| {
+        flood(
+            map,
+            STARTING_ROSTER
+                .iter()
+                .filter(|(faction, ..)| *faction == side)
+                .map(|&(.., pos)| pos),
+        )
+    };
+    let reachable = [
+        (Faction::Player, reach(Faction::Player)),
+        (Faction::Enemy, reach(Faction::Enemy)),
+    ];
+    let mut objectives: Vec<(String, GridPosition, Vec<Faction>, Severity)> = STRUCTURES
+        .iter()
+        .map(|&(kind, owner, pos)| {
+            let what = format!("{kind:?}");
+            if kind == StructureKind::Throne {
+                // Whoever doesn't hold the throne has to be able to take it.
+                let takers = [Faction::Player, Faction::Enemy]
+                    .into_iter()
+                    .filter(|&side| Some(side) != owner)
+                    .collect();
+                (what, pos, takers, Severity::Error)
+            } else {
+                (what, pos, vec![], Severity::Warning)
+            }
+        })
+        .collect();
+    objectives.extend(
+        chests
+            .chests
+            .iter()
+            .map(|chest| ("Chest".to_string(), chest.at, vec![], Severity::Warning)),
+    );
+    for (what, pos, needed_by, severity) in objectives {
+        if !map.in_bounds(pos) {
+            report(Severity::Error, pos, Problem::OffMap { what });
+            continue;
+        }
+        let cut_off: Vec<Faction> = reachable
+            .iter()
+            .filter(|(_, tiles)| !tiles.contains(&pos))
+            .map(|(side, _)| *side)
+            .collect();
+        // Anyone reaching the rest will do.
+        let missing = if needed_by.is_empty() {
+            (cut_off.len() == reachable.len()).then_some(None)
+        } else {
+            needed_by
+                .into_iter()
+                .find(|side| cut_off.contains(side))
+                .map(Some)
+        };
+        if let Some(side) = missing {
+            report(severity, pos, Problem::Unreachable { what, side });
+        }
+    }
+    diagnostics
+}
+
+/// Whether `start` in `source` falls in a `//` comment or a string
+/// literal, judging by the rest of its line.
+fn commented_or_quoted(source: &str, start: usize) -> bool {
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let mut chars = source[line_start..start].chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if quoted => {
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            '/' if !quoted && chars.peek() == Some(&'/') => return true,
+            _ => {}
+        }
+    }
+    quoted
+}
+
+/// The arguments of every `spawn_unit(...)` call in `source`, with the line
+/// each starts on. Only arguments written out as string literals are
+/// returned; anything worked out at run time is `None`. Calls in `//`
+/// comments and strings, and functions whose names merely end in
+/// `spawn_unit`, are skipped.
+fn spawn_calls(source: &str) -> Vec<(usize, Vec<Option<&str>>)> {
+    const CALL: &str = "spawn_unit(";
+    let mut calls = Vec::new();
+    for (start, _) in source.match_indices(CALL) {
+        let named = source[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if named || commented_or_quoted(source, start) {
+            continue;
+        }
+        let line = source[..start].matches('\n').count() + 1;
+        let rest = &source[start + CALL.len()..];
+        let mut args = Vec::new();
+        let (mut depth, mut quoted, mut escaped, mut from) = (0, false, false, 0);
+        for (i, c) in rest.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if quoted => escaped = true,
+                '"' => quoted = !quoted,
+                _ if quoted => {}
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' if depth > 0 => depth -= 1,
+                ',' | ')' if depth == 0 => {
+                    let arg = rest[from..i].trim();
+                    args.push(arg.strip_prefix('"').and_then(|arg| arg.strip_suffix('"')));
+                    from = i + 1;
+                    if c == ')' {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        calls.push((line, args));
+    }
+    calls
+}
+
+/// Checks that `battle_map`'s scenario script, if it has one, compiles and
+/// only spawns classes and sides that exist.
+pub fn validate_script(battle_map: BattleMap) -> Vec<Diagnostic> {
+    let path = script_path(&battle_map.script_name());
+    if !path.exists() {
+        return Vec::new();
+    }
+    let origin = path.display().to_string();
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(err) => {
+            return vec![Diagnostic::new(
+                Severity::Error,
+                origin,
+                Problem::Unreadable(err.to_string()),
+            )];
+        }
+    };
+    let mut diagnostics = Vec::new();
+
+    #[cfg(feature = "scripting")]
+    if let Err(err) = rhai::Engine::new().compile(&source) {
+        let diagnostic = Diagnostic::new(
+            Severity::Error,
+            origin.clone(),
+            Problem::Unreadable(err.0.to_string()),
+        );
+        diagnostics.push(match err.1.line() {
+            Some(line) => diagnostic.line(line),
+            None => diagnostic,
+        });
+    }
+
+    for (line, args) in spawn_calls(&source) {
+        if let Some(Some(side)) = args.first() {
+            if ron::from_str::<Faction>(side).is_err() {
+                diagnostics.push(
+                    Diagnostic::new(
+                        Severity::Error,
+                        origin.clone(),
+                        Problem::UnknownSide(side.to_string()),
+                    )
+                    .line(line),
+                );
+            }
+        }
+        if let Some(Some(class)) = args.get(1) {
+            if ron::from_str::<UnitClass>(class).is_err() {
+                diagnostics.push(
+                    Diagnostic::new(
+                        Severity::Error,
+                        origin.clone(),
+                        Problem::UnknownClass(class.to_string()),
+                    )
+                    .line(line),
+                );
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Logs the problems with the map a battle is starting on, as laid out
+/// with the data and tile kinds it is fought with.
+pub fn report_map_problems(
+    log: Res<CommandLog>,
+    launch: Res<LaunchOptions>,
+    map: Res<GridMap>,
+    chests: Res<ChestLayout>,
+    catalog: Res<ItemCatalog>,
+) {
+    // A stress test fights a generated roster on its own board.
+    if launch.stress.is_some() {
+        return;
+    }
+    let diagnostics = validate_items(&catalog, &chests)
+        .into_iter()
+        .chain(validate_map(log.map, &map, &chests))
+        .chain(validate_script(log.map));
+    for diagnostic in diagnostics {
+        match diagnostic.severity {
+            Severity::Warning => warn!("{diagnostic}"),
+            Severity::Error => error!("{diagnostic}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chest::ChestDef;
+
+    const THRONE: GridPosition = GridPosition::new(11, 6);
+
+    fn open_field() -> GridMap {
+        GridMap::new(GRID_WIDTH, GRID_HEIGHT)
+    }
+
+    /// Fills column `x` with water from top to bottom.
+    fn river(map: &mut GridMap, x: i32) {
+        for y in 0..GRID_HEIGHT {
+            map.set_tile(GridPosition::new(x, y), TileType::Water);
+        }
+    }
+
+    fn check(map: &GridMap, chests: Vec<ChestDef>) -> Vec<Diagnostic> {
+        validate_map(BattleMap::default(), map, &ChestLayout { chests })
+    }
+
+    #[test]
+    fn literal_arguments_are_read_and_computed_ones_are_not() {
+        let calls =
+            spawn_calls("spawn_unit(\"Enemy\", \"Knight\", 3, 4);\nspawn_unit(side, class, 1, 1);");
+
+        assert_eq!(
+            calls,
+            vec![
+                (1, vec![Some("Enemy"), Some("Knight"), None, None]),
+                (2, vec![None, None, None, None]),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_parentheses_stay_inside_their_argument() {
+        let calls = spawn_calls("spawn_unit(pick(\"Enemy\", 1), \"Mage\", max(1, (x)), 2)");
+
+        assert_eq!(calls, vec![(1, vec![None, Some("Mage"), None, None])]);
+    }
+
+    #[test]
+    fn commented_out_and_quoted_calls_are_skipped() {
+        let source = r#"// spawn_unit("Dragon", "Knight", 0, 0);
+let x = 1; // spawn_unit("Dragon")
+print("spawn_unit(\"Dragon\")");
+my_spawn_unit("Dragon", "Knight", 0, 0);
+spawn_unit("Enemy", "Say \"hi\", then go", 0, 0);"#;
+
+        assert_eq!(
+            spawn_calls(source),
+            vec![(
+                5,
+                vec![Some("Enemy"), Some(r#"Say \"hi\", then go"#), None, None]
+            )]
+        );
+    }
+
+    #[test]
+    fn an_open_field_has_nothing_to_report() {
+        assert_eq!(check(&open_field(), Vec::new()), Vec::new());
+    }
+
+    #[test]
+    fn units_starting_on_water_are_blocked() {
+        let mut map = open_field();
+        let (_, _, at) = STARTING_ROSTER[0];
+        map.set_tile(at, TileType::Water);
+
+        let diagnostics = check(&map, Vec::new());
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].at, Some(at));
+        assert!(matches!(diagnostics[0].problem, Problem::Blocked { .. }));
+    }
+
+    #[test]
+    fn a_throne_walled_off_from_its_challenger_is_an_error() {
+        let mut map = open_field();
+        river(&mut map, 7);
+
+        let diagnostics = check(&map, Vec::new());
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(
+                Severity::Error,
+                "Crossroads",
+                Problem::Unreachable {
+                    what: "Throne".to_string(),
+                    side: Some(Faction::Player),
+                },
+            )
+            .at(THRONE)]
+        );
+    }
+
+    #[test]
+    fn a_closed_door_does_not_cut_a_side_off() {
+        let mut map = open_field();
+        river(&mut map, 7);
+        map.set_tile(GridPosition::new(7, 3), TileType::Door { open: false });
+
+        assert_eq!(check(&map, Vec::new()), Vec::new());
+    }
+
+    #[test]
+    fn a_chest_neither_side_can_reach_is_a_warning() {
+        let mut map = open_field();
+        let at = GridPosition::new(5, 7);
+        for wall in [
+            GridPosition::new(4, 7),
+            GridPosition::new(6, 7),
+            GridPosition::new(5, 6),
+        ] {
+            map.set_tile(wall, TileType::Mountain);
+        }
+        let chests = vec![ChestDef {
+            at,
+            loot: Loot::Gold(50),
+        }];
+
+        let diagnostics = check(&map, chests);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].at, Some(at));
+    }
+}
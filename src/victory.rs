@@ -0,0 +1,351 @@
+//! How battles are won: the [`VictoryCondition`]s in play, checked as the
+//! board changes, and the objectives panel listing them.
+//!
+//! Every condition in the [`VictoryConditions`] registry is checked in the
+//! order it was added, and the first to name a winner decides the battle.
+//! The built-in ones are seizing the enemy throne, which comes first, and
+//! wiping out the other side. Plugins add their own with
+//! [`App::add_victory_condition`](AddVictoryCondition::add_victory_condition);
+//! scenario scripts can still end a battle on their own terms with `win`.
+//!
+//! Conditions are checked whenever units, structures, or the turn change.
+//! They must only read the board, and decide the same way from the same
+//! board, so that replays and both ends of an online match agree on who
+//! won.
+
+use std::fmt::Display;
+#[cfg(feature = "render")]
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use crate::battle_log::{BattleEvent, BattleLog};
+#[cfg(feature = "render")]
+use crate::hud::{HudEvent, HudLabel, HudSources};
+#[cfg(feature = "render")]
+use crate::locale::Locale;
+use crate::structure::{Structure, StructureKind};
+use crate::turn::{BattleOutcome, CurrentTurn};
+use crate::unit::{Faction, Stats, UnitIndex};
+
+/// A way for a side to win the battle.
+pub trait VictoryCondition: Send + Sync + 'static {
+    /// What has to be done, e.g. "Seize the enemy throne".
+    fn name(&self) -> &str;
+
+    /// The side that has won by this condition, if either has.
+    fn evaluate(&self, world: &World) -> Option<Faction>;
+
+    /// How far along the battle is toward it, e.g. "Enemy units left: 3".
+    /// Nothing when there is nothing to show.
+    fn progress(&self, world: &World) -> Progress;
+}
+
+/// How far along the battle is toward a condition, as English
+/// [`Locale::fill`](crate::locale::Locale::fill) templates and the values
+/// that go in them, shown one after another. The values are looked up in
+/// the player's language too, so faction names get translated and numbers
+/// stay as they are.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress(pub Vec<(String, Vec<String>)>);
+
+impl Progress {
+    /// Adds `template`, filled with `args`, to the end.
+    pub fn push(&mut self, template: &str, args: &[&dyn Display]) {
+        let args = args.iter().map(ToString::to_string).collect();
+        self.0.push((template.to_string(), args));
+    }
+
+    /// The progress in `locale`'s language, its parts separated by commas.
+    #[cfg(feature = "render")]
+    pub fn localize(&self, locale: &Locale) -> String {
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|(template, args)| {
+                let args: Vec<&str> = args.iter().map(|arg| locale.tr(arg)).collect();
+                let args: Vec<&dyn Display> = args.iter().map(|arg| arg as &dyn Display).collect();
+                locale.fill(template, &args)
+            })
+            .collect();
+        parts.join(", ")
+    }
+}
+
+/// Every victory condition in play, in the order they are checked.
+#[derive(Resource, Default)]
+pub struct VictoryConditions(Vec<Box<dyn VictoryCondition>>);
+
+impl VictoryConditions {
+    pub fn add(&mut self, condition: impl VictoryCondition) {
+        self.0.push(Box::new(condition));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn VictoryCondition> {
+        self.0.iter().map(|condition| condition.as_ref())
+    }
+}
+
+/// Adding victory conditions from a plugin.
+pub trait AddVictoryCondition {
+    fn add_victory_condition(&mut self, condition: impl VictoryCondition) -> &mut Self;
+}
+
+impl AddVictoryCondition for App {
+    fn add_victory_condition(&mut self, condition: impl VictoryCondition) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_init::<VictoryConditions>()
+            .add(condition);
+        self
+    }
+}
+
+/// Each condition's name and progress as of the last check, for the
+/// objectives panel.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Objectives(pub Vec<(String, Progress)>);
+
+/// Won by the side that takes the other's throne.
+pub struct SeizeThrone;
+
+impl VictoryCondition for SeizeThrone {
+    fn name(&self) -> &str {
+        "Seize the enemy throne"
+    }
+
+    fn evaluate(&self, world: &World) -> Option<Faction> {
+        let mut structures = world.try_query::<&Structure>()?;
+        structures
+            .iter(world)
+            .find(|structure| structure.kind == StructureKind::Throne && structure.is_seized())
+            .and_then(|throne| throne.owner)
+    }
+
+    fn progress(&self, world: &World) -> Progress {
+        let mut progress = Progress::default();
+        let Some(mut structures) = world.try_query::<&Structure>() else {
+            return progress;
+        };
+        let thrones = structures
+            .iter(world)
+            .filter(|structure| structure.kind == StructureKind::Throne);
+        for throne in thrones {
+            let Some(home) = throne.home else {
+                continue;
+            };
+            let home = format!("{home:?}");
+            match (throne.owner, throne.capture) {
+                (Some(owner), _) if throne.is_seized() => {
+                    progress.push("{} throne taken by {}", &[&home, &format!("{owner:?}")]);
+                }
+                (_, Some((_, done))) => progress.push(
+                    "{} throne under capture ({}/{})",
+                    &[&home, &done, &throne.kind.capture_turns()],
+                ),
+                _ => progress.push("{} throne held", &[&home]),
+            }
+        }
+        progress
+    }
+}
+
+/// Won by the last side with units standing.
+pub struct Rout;
+
+impl Rout {
+    fn standing(world: &World, faction: Faction) -> usize {
+        let Some(index) = world.get_resource::<UnitIndex>() else {
+            return 0;
+        };
+        index
+            .faction(faction)
+            .filter(|&unit| world.get::<Stats>(unit).is_some_and(Stats::is_alive))
+            .count()
+    }
+}
+
+impl VictoryCondition for Rout {
+    fn name(&self) -> &str {
+        "Defeat every enemy unit"
+    }
+
+    fn evaluate(&self, world: &World) -> Option<Faction> {
+        match (
+            Self::standing(world, Faction::Player),
+            Self::standing(world, Faction::Enemy),
+        ) {
+            (0, 0) => None,
+            (_, 0) => Some(Faction::Player),
+            (0, _) => Some(Faction::Enemy),
+            _ => None,
+        }
+    }
+
+    fn progress(&self, world: &World) -> Progress {
+        let mut progress = Progress::default();
+        for faction in [Faction::Player, Faction::Enemy] {
+            let left = Self::standing(world, faction);
+            progress.push("{} units left: {}", &[&format!("{faction:?}"), &left]);
+        }
+        progress
+    }
+}
+
+/// Checks every victory condition, ending the battle on the first one met,
+/// and brings the objectives up to date.
+pub fn check_victory_system(world: &mut World) {
+    let (winner, objectives) = {
+        let Some(conditions) = world.get_resource::<VictoryConditions>() else {
+            return;
+        };
+        let world: &World = world;
+        let winner = conditions
+            .iter()
+            .find_map(|condition| condition.evaluate(world));
+        let objectives: Vec<(String, Progress)> = conditions
+            .iter()
+            .map(|condition| (condition.name().to_string(), condition.progress(world)))
+            .collect();
+        (winner, objectives)
+    };
+    world
+        .resource_mut::<Objectives>()
+        .set_if_neq(Objectives(objectives));
+
+    let decided = world.resource::<BattleOutcome>().0.is_some();
+    if let Some(winner) = winner.filter(|_| !decided) {
+        let turn = world.resource::<CurrentTurn>().number;
+        world
+            .resource_mut::<BattleLog>()
+            .record(turn, BattleEvent::BattleEnded { winner });
+        world.resource_mut::<BattleOutcome>().0 = Some(winner);
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn setup_objectives_ui(mut commands: Commands) {
+    commands.spawn((
+        HudLabel {
+            events: &[HudEvent::Objectives, HudEvent::Language],
+            write: write_objectives,
+        },
+        Text::new(""),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(40.0),
+            left: Val::Px(10.0),
+            ..default()
+        },
+    ));
+}
+
+/// Every objective with its progress, a line each.
+#[cfg(feature = "render")]
+fn write_objectives(hud: &HudSources, text: &mut String, _color: &mut Color) {
+    for (name, progress) in &hud.objectives.0 {
+        let name = hud.locale.tr(name);
+        let _ = if progress.0.is_empty() {
+            writeln!(text, "{name}")
+        } else {
+            writeln!(text, "{name}: {}", progress.localize(&hud.locale))
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Always names the same winner, or none.
+    struct Declares(&'static str, Option<Faction>);
+
+    impl VictoryCondition for Declares {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn evaluate(&self, _world: &World) -> Option<Faction> {
+            self.1
+        }
+
+        fn progress(&self, _world: &World) -> Progress {
+            Progress::default()
+        }
+    }
+
+    /// An app with the resources victory is checked against and no units.
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Objectives>()
+            .init_resource::<BattleOutcome>()
+            .init_resource::<CurrentTurn>()
+            .init_resource::<BattleLog>()
+            .init_resource::<UnitIndex>();
+        app
+    }
+
+    #[test]
+    fn the_first_condition_to_name_a_winner_decides() {
+        let mut app = app();
+        app.add_victory_condition(Declares("Nobody yet", None))
+            .add_victory_condition(Declares("Enemy first", Some(Faction::Enemy)))
+            .add_victory_condition(Declares("Player second", Some(Faction::Player)));
+        check_victory_system(app.world_mut());
+
+        assert_eq!(
+            app.world().resource::<BattleOutcome>().0,
+            Some(Faction::Enemy)
+        );
+        assert_eq!(
+            app.world().resource::<BattleLog>().entries,
+            [(
+                1,
+                BattleEvent::BattleEnded {
+                    winner: Faction::Enemy
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn a_rout_with_no_units_on_either_side_names_nobody() {
+        let app = app();
+        assert_eq!(Rout.evaluate(app.world()), None);
+    }
+
+    #[test]
+    fn a_decided_battle_is_never_overwritten() {
+        let mut app = app();
+        app.add_victory_condition(Declares("Player wins", Some(Faction::Player)));
+        app.world_mut().resource_mut::<BattleOutcome>().0 = Some(Faction::Enemy);
+        check_victory_system(app.world_mut());
+
+        assert_eq!(
+            app.world().resource::<BattleOutcome>().0,
+            Some(Faction::Enemy)
+        );
+        assert!(app.world().resource::<BattleLog>().entries.is_empty());
+    }
+
+    #[test]
+    fn an_added_condition_is_listed_in_the_objectives() {
+        let mut app = app();
+        app.add_victory_condition(Rout)
+            .add_victory_condition(Declares("Hold the bridge", None));
+        check_victory_system(app.world_mut());
+
+        let names: Vec<&str> = app
+            .world()
+            .resource::<Objectives>()
+            .0
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, ["Defeat every enemy unit", "Hold the bridge"]);
+    }
+}
@@ -0,0 +1,381 @@
+//! Fog of war: what each side can currently see.
+//!
+//! Every unit sees the tiles within its [`Vision`] range that are in line of
+//! sight: mountains and closed doors block it, and forests take an extra
+//! tile of range to see through. During a live battle the player only sees
+//! enemies standing in allied sight. Tiles the player has seen before stay
+//! dimmed once out of sight, tiles never seen are blacked out, and enemies
+//! on either aren't drawn. Units in [`Stealth`] are never spotted by the
+//! other side, and scouting flares light up an area on top of what units
+//! see. Replays show the whole field. Vision never feeds into battle
+//! outcomes, so it is recomputed outside the command executor.
+
+#[cfg(feature = "render")]
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+#[cfg(feature = "render")]
+use bevy::sprite_render::{AlphaMode2d, TileData, TilemapChunkTileData};
+use serde::Serialize;
+
+#[cfg(feature = "render")]
+use crate::constants::*;
+use crate::grid::GridPosition;
+#[cfg(feature = "render")]
+use crate::grid::{same_tiles, Chunk, GridMap, TileType};
+#[cfg(feature = "render")]
+use crate::lobby::MatchSetup;
+#[cfg(feature = "render")]
+use crate::rescue::Rescue;
+#[cfg(feature = "render")]
+use crate::seats::Viewer;
+#[cfg(feature = "render")]
+use crate::settings::Settings;
+#[cfg(feature = "render")]
+use crate::sprites::TileSet;
+#[cfg(feature = "render")]
+use crate::state::AppState;
+#[cfg(feature = "render")]
+use crate::stealth::Stealth;
+#[cfg(feature = "render")]
+use crate::turn::{CurrentTurn, TimeOfDay};
+use crate::unit::Faction;
+#[cfg(feature = "render")]
+use crate::unit::{Stats, Unit, UnitIndex};
+
+/// Tiles of sight lost at night.
+pub const NIGHT_VISION_PENALTY: u32 = 1;
+/// A unit in a forest is only spotted by enemies this close.
+pub const FOREST_SPOT_RANGE: u32 = 1;
+
+/// Shade over explored tiles that are out of sight.
+#[cfg(feature = "render")]
+const FOG_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.45);
+/// Shade over tiles that have never been seen.
+#[cfg(feature = "render")]
+const SHROUD_COLOR: Color = Color::srgb(0.0, 0.0, 0.0);
+
+/// How many tiles away a unit can see.
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+#[reflect(Component)]
+pub struct Vision {
+    pub range: u32,
+}
+
+/// An area lit up by a scouting flare.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Flare {
+    pub faction: Faction,
+    pub at: GridPosition,
+    pub radius: u32,
+    /// Turns of `faction`'s that still start with the flare lit.
+    pub turns: u32,
+}
+
+/// Every lit flare. Flares are placed by the command executor, so replays
+/// light them at the same moments.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Flares(pub Vec<Flare>);
+
+impl Flares {
+    /// Burns down `faction`'s flares at the start of its turn, putting out
+    /// the spent ones.
+    pub fn burn_down(&mut self, faction: Faction) {
+        self.0.retain_mut(|flare| {
+            if flare.faction == faction {
+                flare.turns = flare.turns.saturating_sub(1);
+            }
+            flare.turns > 0
+        });
+    }
+}
+
+/// What one side can see: the tiles in its sight and the opposing units it
+/// has spotted, plus every tile it has seen at some point this battle.
+#[cfg(feature = "render")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sight {
+    pub tiles: HashSet<GridPosition>,
+    pub units: HashSet<Entity>,
+    pub explored: HashSet<GridPosition>,
+}
+
+#[cfg(feature = "render")]
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct FactionVision {
+    pub player: Sight,
+    pub enemy: Sight,
+}
+
+#[cfg(feature = "render")]
+impl FactionVision {
+    pub fn sight(&self, faction: Faction) -> &Sight {
+        match faction {
+            Faction::Player => &self.player,
+            Faction::Enemy => &self.enemy,
+        }
+    }
+
+    /// Whether `viewer`'s side can see the unit `entity` of side `faction`.
+    /// A side always sees its own units.
+    pub fn can_see(&self, viewer: Faction, entity: Entity, faction: Faction) -> bool {
+        viewer == faction || self.sight(viewer).units.contains(&entity)
+    }
+}
+
+#[cfg(feature = "render")]
+fn sight_for(
+    faction: Faction,
+    map: &GridMap,
+    night: bool,
+    flares: &Flares,
+    fog_of_war: bool,
+    settings: &Settings,
+    units: &[(Entity, GridPosition, Faction, u32, bool)],
+) -> Sight {
+    // Without fog, both sides see everything short of stealth.
+    if !fog_of_war {
+        return Sight {
+            tiles: (0..map.height)
+                .flat_map(|y| (0..map.width).map(move |x| GridPosition::new(x, y)))
+                .collect(),
+            units: units
+                .iter()
+                .filter(|&&(_, _, unit_faction, _, hidden)| unit_faction != faction && !hidden)
+                .map(|&(entity, ..)| entity)
+                .collect(),
+            explored: HashSet::new(),
+        };
+    }
+
+    let viewers: Vec<(GridPosition, u32)> = units
+        .iter()
+        .filter(|(_, _, unit_faction, ..)| *unit_faction == faction)
+        .map(|&(_, pos, _, range, _)| {
+            let range = (range as f32 * settings.vision_scale).round() as u32;
+            let range = if night {
+                range.saturating_sub(NIGHT_VISION_PENALTY).max(1)
+            } else {
+                range
+            };
+            (pos, range)
+        })
+        .collect();
+
+    // Looking past a tile costs its sight cost, and vision-blocking tiles
+    // hide everything behind them.
+    let in_sight = |viewer: GridPosition, range: u32, tile: GridPosition| {
+        if viewer.distance(tile) > range {
+            return false;
+        }
+        let mut cost = 1;
+        for between in viewer.line_between(tile) {
+            let Some(terrain) = map.tile(between) else {
+                return false;
+            };
+            if terrain.blocks_vision() {
+                return false;
+            }
+            cost += terrain.sight_cost();
+        }
+        cost <= range
+    };
+
+    // Only tiles in range of a viewer or a flare are looked at, so sight
+    // costs the same however large the map is.
+    let mut sight = Sight::default();
+    let lit = flares
+        .0
+        .iter()
+        .filter(|flare| flare.faction == faction)
+        .flat_map(|flare| flare.at.within(flare.radius));
+    let seen = viewers.iter().flat_map(|&(pos, range)| {
+        pos.within(range)
+            .filter(move |&tile| tile == pos || in_sight(pos, range, tile))
+    });
+    sight
+        .tiles
+        .extend(lit.chain(seen).filter(|&tile| map.in_bounds(tile)));
+    for &(entity, pos, unit_faction, _, hidden) in units {
+        if unit_faction == faction || hidden || !sight.tiles.contains(&pos) {
+            continue;
+        }
+        let in_forest = map.tile(pos) == Some(TileType::Forest);
+        if !in_forest
+            || viewers
+                .iter()
+                .any(|(viewer, _)| viewer.distance(pos) <= FOREST_SPOT_RANGE)
+        {
+            sight.units.insert(entity);
+        }
+    }
+    sight
+}
+
+/// Whether anything sight depends on has changed: a unit moving, joining,
+/// leaving, or being carried off the board, a unit's sight, stealth, or
+/// health, the terrain, the time of day, flares, the match's fog of war, or
+/// the settings.
+#[cfg(feature = "render")]
+pub fn sight_changed(
+    state: Res<State<AppState>>,
+    map: Res<GridMap>,
+    turn: Res<CurrentTurn>,
+    flares: Res<Flares>,
+    setup: Res<MatchSetup>,
+    settings: Res<Settings>,
+    index: Res<UnitIndex>,
+    units: Query<
+        (),
+        (
+            With<Unit>,
+            Or<(Changed<Vision>, Changed<Stealth>, Changed<Stats>)>,
+        ),
+    >,
+) -> bool {
+    state.is_changed()
+        || map.is_changed()
+        || turn.is_changed()
+        || flares.is_changed()
+        || setup.is_changed()
+        || settings.is_changed()
+        || index.is_changed()
+        || !units.is_empty()
+}
+
+/// Recomputes both sides' sight from where their units stand.
+#[cfg(feature = "render")]
+pub fn update_vision_system(
+    map: Res<GridMap>,
+    turn: Res<CurrentTurn>,
+    flares: Res<Flares>,
+    setup: Res<MatchSetup>,
+    settings: Res<Settings>,
+    units: Query<
+        (
+            Entity,
+            &GridPosition,
+            &Faction,
+            &Vision,
+            &Stealth,
+            &Stats,
+            &Rescue,
+        ),
+        With<Unit>,
+    >,
+    mut vision: ResMut<FactionVision>,
+) {
+    let night = turn.time_of_day() == TimeOfDay::Night;
+    let on_board: Vec<(Entity, GridPosition, Faction, u32, bool)> = units
+        .iter()
+        .filter(|(.., stats, rescue)| stats.is_alive() && !rescue.is_carried())
+        .map(|(entity, &pos, &faction, vision, stealth, ..)| {
+            (entity, pos, faction, vision.range, stealth.hidden)
+        })
+        .collect();
+    let fog = setup.fog_of_war;
+    let mut player = sight_for(
+        Faction::Player,
+        &map,
+        night,
+        &flares,
+        fog,
+        &settings,
+        &on_board,
+    );
+    let mut enemy = sight_for(
+        Faction::Enemy,
+        &map,
+        night,
+        &flares,
+        fog,
+        &settings,
+        &on_board,
+    );
+    for (sight, previous) in [(&mut player, &vision.player), (&mut enemy, &vision.enemy)] {
+        sight.explored = previous.explored.union(&sight.tiles).copied().collect();
+    }
+    vision.set_if_neq(FactionVision { player, enemy });
+}
+
+/// Forgets what both sides explored in the previous battle.
+#[cfg(feature = "render")]
+pub fn reset_vision(mut vision: ResMut<FactionVision>) {
+    *vision = FactionVision::default();
+}
+
+/// Hides carried units, and enemies the side on screen can't see during a
+/// live battle.
+#[cfg(feature = "render")]
+pub fn update_unit_visibility_system(
+    state: Res<State<AppState>>,
+    vision: Res<FactionVision>,
+    viewer: Res<Viewer>,
+    mut units: Query<(Entity, &Faction, &Rescue, &mut Visibility), With<Unit>>,
+) {
+    let fogged = *state.get() == AppState::GamePlay;
+    for (entity, &faction, rescue, mut visibility) in &mut units {
+        let hidden = rescue.is_carried() || (fogged && !vision.can_see(viewer.0, entity, faction));
+        visibility.set_if_neq(if hidden {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        });
+    }
+}
+
+/// Marker for the chunks that shade fogged tiles.
+#[cfg(feature = "render")]
+#[derive(Component)]
+pub struct FogChunk;
+
+/// The shade over a tile, or `None` while it is in sight.
+#[cfg(feature = "render")]
+fn fog_tile(map: &GridMap, sight: &Sight, pos: GridPosition) -> Option<TileData> {
+    if !map.in_bounds(pos) || sight.tiles.contains(&pos) {
+        return None;
+    }
+    let color = if sight.explored.contains(&pos) {
+        FOG_COLOR
+    } else {
+        SHROUD_COLOR
+    };
+    Some(TileData { color, ..default() })
+}
+
+#[cfg(feature = "render")]
+pub fn setup_fog(mut commands: Commands, map: Res<GridMap>, tileset: Res<TileSet>) {
+    for chunk in Chunk::covering(&map) {
+        let tiles = vec![None; chunk.tiles().count()];
+        commands.spawn((
+            FogChunk,
+            chunk.bundle(tileset.white.clone(), AlphaMode2d::Blend, FOG_Z, tiles),
+        ));
+    }
+}
+
+/// Shades the tiles outside the sight of the side on screen during a live
+/// battle: dimmed where it has explored, black where it never has.
+#[cfg(feature = "render")]
+pub fn update_fog_system(
+    state: Res<State<AppState>>,
+    map: Res<GridMap>,
+    vision: Res<FactionVision>,
+    viewer: Res<Viewer>,
+    mut fog: Query<(&Chunk, &mut TilemapChunkTileData), With<FogChunk>>,
+) {
+    if !vision.is_changed() && !state.is_changed() && !viewer.is_changed() {
+        return;
+    }
+    let fogged = *state.get() == AppState::GamePlay;
+    let sight = vision.sight(viewer.0);
+    for (&chunk, mut data) in &mut fog {
+        let tiles: Vec<_> = chunk
+            .tiles()
+            .map(|pos| fog_tile(&map, sight, pos).filter(|_| fogged))
+            .collect();
+        if !same_tiles(&data, &tiles) {
+            data.0 = tiles;
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Whole battles played through [`TestGame`].
+
+use bevy_game::command::{CommandLog, GameCommand, Rejection};
+use bevy_game::economy::Treasury;
+use bevy_game::grid::GridPosition;
+use bevy_game::test_utils::TestGame;
+use bevy_game::unit::Faction;
+
+#[test]
+fn clicking_a_unit_then_a_tile_moves_it() {
+    let mut game = TestGame::new();
+    let knight = game.spawn_player_unit(GridPosition::new(1, 1));
+    game.spawn_enemy_unit(GridPosition::new(10, 4));
+
+    game.click_tile(GridPosition::new(1, 1));
+    game.click_tile(GridPosition::new(2, 1));
+
+    game.assert_unit_at(knight, GridPosition::new(2, 1));
+    game.assert_turn(Faction::Player, 1);
+}
+
+#[test]
+fn an_attack_can_fell_a_unit_and_win() {
+    let mut game = TestGame::new();
+    let knight = game.spawn_player_unit(GridPosition::new(1, 1));
+    let enemy = game.spawn_enemy_unit(GridPosition::new(2, 1));
+    game.set_hp(enemy, 1);
+
+    game.click_tile(GridPosition::new(1, 1));
+    game.click_tile(GridPosition::new(2, 1));
+    game.update();
+
+    game.assert_unit_fallen(enemy);
+    game.assert_hp(knight, 14);
+    game.assert_winner(Some(Faction::Player));
+}
+
+#[test]
+fn ending_a_turn_collects_income_and_heals_on_owned_structures() {
+    let mut game = TestGame::new();
+    game.spawn_player_unit(GridPosition::new(1, 1));
+    // The enemy starts out owning the throne.
+    let enemy = game.spawn_enemy_unit(GridPosition::new(11, 6));
+    game.set_hp(enemy, 5);
+    let gold = game.app.world().resource::<Treasury>().enemy;
+
+    game.advance_turn();
+
+    game.assert_turn(Faction::Enemy, 1);
+    assert_eq!(game.app.world().resource::<Treasury>().enemy, gold + 100);
+    game.assert_hp(enemy, 7);
+}
+
+#[test]
+fn a_unit_cannot_act_out_of_turn() {
+    let mut game = TestGame::new();
+    game.spawn_player_unit(GridPosition::new(1, 1));
+    let enemy = game.spawn_enemy_unit(GridPosition::new(10, 4));
+
+    game.send(GameCommand::Move {
+        unit: enemy,
+        to: GridPosition::new(9, 4),
+    });
+
+    assert_eq!(game.rejections(), [Rejection::OutOfTurn]);
+    game.assert_unit_at(enemy, GridPosition::new(10, 4));
+    assert!(game
+        .app
+        .world()
+        .resource::<CommandLog>()
+        .commands
+        .is_empty());
+}
+
+#[test]
+fn a_move_refreshes_the_tiles_other_units_can_reach() {
+    let mut game = TestGame::new();
+    let mover = game.spawn_player_unit(GridPosition::new(1, 1));
+    let other = game.spawn_player_unit(GridPosition::new(3, 1));
+    game.spawn_enemy_unit(GridPosition::new(10, 4));
+    let between = GridPosition::new(2, 1);
+    assert!(game.reachable(other).contains(&between));
+
+    game.send(GameCommand::Move {
+        unit: mover,
+        to: between,
+    });
+
+    game.assert_unit_at(mover, between);
+    assert!(!game.reachable(other).contains(&between));
+}
@@ -0,0 +1,158 @@
+//! Battles checked against whole stored [`BoardSnapshot`]s.
+
+use bevy::ecs::component::Mutable;
+use bevy::prelude::*;
+use bevy_game::economy::{NextUnitId, Treasury};
+use bevy_game::facing::Facing;
+use bevy_game::grid::GridPosition;
+use bevy_game::rules::GameRng;
+use bevy_game::snapshot::{BoardSnapshot, SyncSnapshot};
+use bevy_game::test_utils::TestGame;
+use bevy_game::unit::{AttackRange, Faction, MoveRange, UnitId};
+use bevy_game::vision::{Flare, Flares};
+
+#[test]
+fn a_charge_then_end_turn_matches_the_stored_board() {
+    let mut game = TestGame::new();
+    let knight = game.spawn_player_unit(GridPosition::new(1, 1));
+    let foe = game.spawn_enemy_unit(GridPosition::new(3, 1));
+
+    game.click_tile(GridPosition::new(1, 1));
+    game.click_tile(GridPosition::new(2, 1));
+    game.click_tile(GridPosition::new(3, 1));
+    game.advance_turn();
+
+    // A knight charging in hits 2 harder. Ending the turn readies both.
+    game.assert_snapshot(&format!(
+        "(
+    turn: 1,
+    acting: Enemy,
+    winner: None,
+    units: [
+        (
+            id: ({knight}),
+            faction: Player,
+            class: Knight,
+            pos: (
+                x: 2,
+                y: 1,
+            ),
+            stats: (
+                hp: 14,
+                max_hp: 14,
+                attack: 6,
+                defense: 4,
+            ),
+            moved: false,
+            acted: false,
+        ),
+        (
+            id: ({foe}),
+            faction: Enemy,
+            class: Knight,
+            pos: (
+                x: 3,
+                y: 1,
+            ),
+            stats: (
+                hp: 9,
+                max_hp: 14,
+                attack: 6,
+                defense: 4,
+            ),
+            moved: false,
+            acted: false,
+        ),
+    ],
+)",
+        knight = knight.0,
+        foe = foe.0,
+    ));
+}
+
+#[test]
+fn snapshots_survive_a_round_trip_through_ron() {
+    let mut game = TestGame::new();
+    game.spawn_player_unit(GridPosition::new(1, 1));
+    game.spawn_enemy_unit(GridPosition::new(3, 1));
+
+    let snapshot = game.snapshot();
+    assert_eq!(BoardSnapshot::from_ron(&snapshot.to_ron()), Ok(snapshot));
+}
+
+#[test]
+fn the_sync_digest_covers_what_the_board_leaves_out() {
+    let mut game = TestGame::new();
+    game.spawn_player_unit(GridPosition::new(1, 1));
+    game.spawn_enemy_unit(GridPosition::new(3, 1));
+    let board = game.snapshot();
+    let sync = SyncSnapshot::capture(game.app.world_mut());
+    assert_eq!(sync.board, board);
+    assert_eq!(
+        SyncSnapshot::capture(game.app.world_mut()).digest(),
+        sync.digest()
+    );
+
+    game.app.world_mut().resource_mut::<Treasury>().player += 50;
+
+    assert_eq!(game.snapshot().digest(), board.digest());
+    assert_ne!(
+        SyncSnapshot::capture(game.app.world_mut()).digest(),
+        sync.digest()
+    );
+}
+
+#[test]
+fn every_part_of_the_sync_state_changes_the_digest() {
+    let mut game = TestGame::new();
+    let knight = game.spawn_player_unit(GridPosition::new(1, 1));
+    game.spawn_enemy_unit(GridPosition::new(3, 1));
+
+    let changes: [(&str, Change); 6] = [
+        ("the dice", |world, _| {
+            world.resource_mut::<GameRng>().next_u64();
+        }),
+        ("the next unit id", |world, _| {
+            world.resource_mut::<NextUnitId>().0 += 1;
+        }),
+        ("the flares", |world, _| {
+            world.resource_mut::<Flares>().0.push(Flare {
+                faction: Faction::Player,
+                at: GridPosition::new(5, 5),
+                radius: 2,
+                turns: 1,
+            });
+        }),
+        ("a unit's facing", |world, unit| {
+            *unit_part::<Facing>(world, unit) = Facing::Up;
+        }),
+        ("a unit's move range", |world, unit| {
+            unit_part::<MoveRange>(world, unit).0 += 1;
+        }),
+        ("a unit's attack range", |world, unit| {
+            unit_part::<AttackRange>(world, unit).0 += 1;
+        }),
+    ];
+    for (part, change) in changes {
+        let before = SyncSnapshot::capture(game.app.world_mut()).digest();
+        change(game.app.world_mut(), knight);
+        assert_ne!(
+            SyncSnapshot::capture(game.app.world_mut()).digest(),
+            before,
+            "changing {part} left the digest as it was"
+        );
+    }
+}
+
+/// Changes one thing about the battle, given the unit to change it on.
+type Change = fn(&mut World, UnitId);
+
+fn unit_part<T: Component<Mutability = Mutable>>(world: &mut World, unit: UnitId) -> Mut<'_, T> {
+    let entity = world
+        .query::<(Entity, &UnitId)>()
+        .iter(world)
+        .find(|(_, id)| **id == unit)
+        .map(|(entity, _)| entity)
+        .unwrap_or_else(|| panic!("{unit:?} has fallen"));
+    world.get_mut::<T>(entity).expect("units have every part")
+}